@@ -0,0 +1,185 @@
+#!/usr/bin/env python3
+# Writes a large, valid dex file for bench/parallel.sh, for when no real
+# one is at hand: CLASSES classes of METHODS static methods, each a loop
+# of arithmetic, calls and string constants.
+#
+#   bench/gen_dex.py OUT.dex [CLASSES] [METHODS] [REPEAT]
+import hashlib
+import struct
+import sys
+import zlib
+
+NO_INDEX = 0xffffffff
+
+
+def uleb128(n):
+    out = bytearray()
+    while True:
+        b = n & 0x7f
+        n >>= 7
+        if n:
+            out.append(b | 0x80)
+        else:
+            out.append(b)
+            return bytes(out)
+
+
+def align(buf, n=4):
+    while len(buf) % n:
+        buf.append(0)
+
+
+def method_body(k, methods, repeat, method_idx, string_idx, length_idx):
+    # static int m<k>(int p), p in v4
+    units = [0x0012, 0x0112]                          # const/4 v0, 0; const/4 v1, 0
+    loop = len(units)
+    units += [0x0035 | (4 << 12) | (1 << 8), 0]       # if-ge v1, v4, :end
+    for r in range(repeat):
+        units += [0x0090, 0x0100]                     # add-int v0, v0, v1
+        units += [0x02d8, 0x0301]                     # add-int/lit8 v2, v1, 3
+        units += [0x20b7]                             # xor-int/2addr v0, v2
+        units += [0x1071, method_idx((k + r + 1) % methods), 0x0001]  # invoke-static {v1}
+        units += [0x030a]                             # move-result v3
+        units += [0x30b0]                             # add-int/2addr v0, v3
+        units += [0x031a, string_idx((k + r) % methods)]  # const-string v3
+        units += [0x106e, length_idx, 0x0003]         # invoke-virtual {v3}, String.length()I
+        units += [0x030a]                             # move-result v3
+        units += [0x30b0]                             # add-int/2addr v0, v3
+    units += [0x01d8, 0x0101]                         # add-int/lit8 v1, v1, 1
+    goto = len(units)
+    units += [0x0029, (loop - goto) & 0xffff]         # goto/16 :loop
+    end = len(units)
+    units[loop + 1] = end - loop
+    units += [0x000f]                                 # return v0
+
+    code = struct.pack("<HHHHII", 5, 1, 1, 0, 0, len(units))
+    return code + struct.pack("<%dH" % len(units), *units)
+
+
+def main():
+    if len(sys.argv) < 2:
+        sys.exit("usage: %s OUT.dex [CLASSES] [METHODS] [REPEAT]" % sys.argv[0])
+    path = sys.argv[1]
+    classes = int(sys.argv[2]) if len(sys.argv) > 2 else 3000
+    methods = int(sys.argv[3]) if len(sys.argv) > 3 else 20
+    repeat = int(sys.argv[4]) if len(sys.argv) > 4 else 3
+    if classes * methods + 1 > 0x10000:
+        sys.exit("at most 65535 methods fit in one dex file")
+
+    class_names = ["Lbench/C%d;" % n for n in range(classes)]
+    method_names = ["m%d" % k for k in range(methods)]
+    constants = ["s%d" % k for k in range(methods)]
+    strings = sorted(set(["I", "II", "Ljava/lang/Object;", "Ljava/lang/String;", "length"]
+                         + class_names + method_names + constants))
+    sidx = {s: i for i, s in enumerate(strings)}
+
+    types = sorted(["I", "Ljava/lang/Object;", "Ljava/lang/String;"] + class_names, key=lambda t: sidx[t])
+    tidx = {t: i for i, t in enumerate(types)}
+
+    # ()I before (I)I
+    protos = [("I", []), ("II", ["I"])]
+
+    method_ids = [(tidx["Ljava/lang/String;"], 0, sidx["length"])]
+    method_ids += [(tidx[c], 1, sidx[m]) for c in class_names for m in method_names]
+    method_ids.sort(key=lambda m: (m[0], m[2], m[1]))
+    midx = {m: i for i, m in enumerate(method_ids)}
+    length_idx = midx[(tidx["Ljava/lang/String;"], 0, sidx["length"])]
+
+    header_size = 0x70
+    offset = header_size
+    string_ids_off = offset; offset += 4 * len(strings)
+    type_ids_off = offset; offset += 4 * len(types)
+    proto_ids_off = offset; offset += 12 * len(protos)
+    method_ids_off = offset; offset += 8 * len(method_ids)
+    class_defs_off = offset; offset += 32 * classes
+    data_off = offset
+
+    data = bytearray()
+
+    def here():
+        return data_off + len(data)
+
+    code_items_off = here()
+    code_offs = {}
+    for c in class_names:
+        for k, m in enumerate(method_names):
+            align(data)
+            code_offs[(c, m)] = here()
+            data += method_body(k, methods, repeat,
+                                lambda j: midx[(tidx[c], 1, sidx[method_names[j]])],
+                                lambda j: sidx[constants[j]], length_idx)
+
+    string_data_off = here()
+    string_offs = []
+    for s in strings:
+        string_offs.append(here())
+        data += uleb128(len(s)) + s.encode() + b"\0"
+
+    align(data)
+    type_lists_off = here()
+    int_list_off = here()
+    data += struct.pack("<IH", 1, tidx["I"])
+
+    class_data_off = here()
+    class_data_offs = []
+    for c in class_names:
+        class_data_offs.append(here())
+        ids = sorted((midx[(tidx[c], 1, sidx[m])], m) for m in method_names)
+        data += uleb128(0) + uleb128(0) + uleb128(len(ids)) + uleb128(0)
+        previous = 0
+        for i, m in ids:
+            data += uleb128(i - previous) + uleb128(0x9) + uleb128(code_offs[(c, m)])
+            previous = i
+
+    align(data)
+    map_off = here()
+    sections = [
+        (0x0000, 1, 0),
+        (0x0001, len(strings), string_ids_off),
+        (0x0002, len(types), type_ids_off),
+        (0x0003, len(protos), proto_ids_off),
+        (0x0005, len(method_ids), method_ids_off),
+        (0x0006, classes, class_defs_off),
+        (0x2001, classes * methods, code_items_off),
+        (0x2002, len(strings), string_data_off),
+        (0x1001, 1, type_lists_off),
+        (0x2000, classes, class_data_off),
+        (0x1000, 1, map_off),
+    ]
+    data += struct.pack("<I", len(sections))
+    for kind, size, off in sections:
+        data += struct.pack("<HHII", kind, 0, size, off)
+
+    ids = bytearray()
+    ids += b"".join(struct.pack("<I", o) for o in string_offs)
+    ids += b"".join(struct.pack("<I", sidx[t]) for t in types)
+    for shorty, params in protos:
+        ids += struct.pack("<III", sidx[shorty], tidx["I"], int_list_off if params else 0)
+    ids += b"".join(struct.pack("<HHI", *m) for m in method_ids)
+    for n, c in enumerate(class_names):
+        ids += struct.pack("<IIIIIIII", tidx[c], 0x1, tidx["Ljava/lang/Object;"], 0, NO_INDEX, 0,
+                           class_data_offs[n], 0)
+    assert header_size + len(ids) == data_off
+
+    file_size = data_off + len(data)
+    header = bytearray(b"dex\n035\0" + bytes(24))
+    header += struct.pack("<II", file_size, header_size)
+    header += struct.pack("<I", 0x12345678)
+    header += struct.pack("<III", 0, 0, map_off)
+    header += struct.pack("<II", len(strings), string_ids_off)
+    header += struct.pack("<II", len(types), type_ids_off)
+    header += struct.pack("<II", len(protos), proto_ids_off)
+    header += struct.pack("<II", 0, 0)
+    header += struct.pack("<II", len(method_ids), method_ids_off)
+    header += struct.pack("<II", classes, class_defs_off)
+    header += struct.pack("<II", len(data), data_off)
+
+    dex = header + ids + data
+    dex[12:32] = hashlib.sha1(dex[32:]).digest()
+    dex[8:12] = struct.pack("<I", zlib.adler32(dex[12:]))
+    with open(path, "wb") as f:
+        f.write(dex)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,34 @@
+#!/usr/bin/env bash
+# Times the commands that --jobs speeds up, on one thread and on N (one per
+# CPU by default), and checks that both print the same thing. Without a
+# real file at hand, bench/gen_dex.py writes a large one.
+#
+#   bench/parallel.sh framework.dex [N]
+set -e
+
+if [ -z "$1" ]; then
+    echo "usage: $0 FILE.dex [JOBS]" >&2
+    exit 1
+fi
+input=$1
+jobs=${2:-0}
+
+# the warnings only matter if the build fails
+cargo build --release --quiet 2> /dev/null || cargo build --release
+ddex=target/release/ddex
+out=$(mktemp -d)
+trap 'rm -rf "$out"' EXIT
+TIMEFORMAT=%R
+
+# a speedup needs more than one CPU, with one the extra threads only cost
+echo "CPUs: $(nproc)"
+printf "%-32s %10s %10s %8s\n" command "--jobs 1" "--jobs $jobs" speedup
+for command in "stats" "verify-code" "ssa" "call-graph" "grep invoke-virtual" "xrefs --type java.lang.String" "disassemble" "disassemble --types"; do
+    one=$( { time $ddex --jobs 1 -i "$input" $command > "$out/one" 2>&1; } 2>&1 )
+    many=$( { time $ddex --jobs "$jobs" -i "$input" $command > "$out/many" 2>&1; } 2>&1 )
+    speedup=$(awk "BEGIN { printf \"%.2fx\", $one / $many }")
+    if ! cmp -s "$out/one" "$out/many"; then
+        speedup="$speedup (output differs!)"
+    fi
+    printf "%-32s %9ss %9ss %8s\n" "$command" "$one" "$many" "$speedup"
+done
@@ -1,142 +1,383 @@
 
+use crate::dex_types::CodeItem;
 use crate::instructions::*;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use serde::Serialize;
 
-#[derive(Debug, Clone)]
-pub struct Block {
-    pub instructions: Vec<Instruction>,
+#[derive(Debug, Clone, Serialize)]
+pub struct Block<'a> {
+    pub instructions: Vec<Instruction<'a>>,
     pub starting_addr: usize,
 }
 
-#[derive(Debug)]
+// `edges` holds normal control flow, `exception_edges` the edges from a
+// throwing instruction inside a try range to its catch handlers.
+#[derive(Debug, Serialize)]
 pub struct Graph<T> {
     pub nodes: Vec<T>,
     pub edges: Vec<(usize, usize)>,
+    pub exception_edges: Vec<(usize, usize)>,
+    // both kinds of edge by node, without duplicates, built once by `new`
+    #[serde(skip)]
+    successors: Vec<Vec<usize>>,
+    #[serde(skip)]
+    predecessors: Vec<Vec<usize>>,
+    #[serde(skip)]
+    exception_successors: Vec<Vec<usize>>,
 }
 
-pub fn control_flow_graph(ins: &Vec<Instruction>) -> Graph<Block> {
+impl<T> Graph<T> {
+    pub fn new(nodes: Vec<T>, edges: Vec<(usize, usize)>, exception_edges: Vec<(usize, usize)>) -> Graph<T> {
+        let mut successors: Vec<Vec<usize>> = vec![Vec::new(); nodes.len()];
+        let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); nodes.len()];
+        let mut exception_successors: Vec<Vec<usize>> = vec![Vec::new(); nodes.len()];
+        let mut seen: HashSet<(usize, usize)> = HashSet::new();
+        for (a, b) in edges.iter().chain(exception_edges.iter()) {
+            if seen.insert((*a, *b)) {
+                successors[*a].push(*b);
+                predecessors[*b].push(*a);
+            }
+        }
+        for (a, b) in exception_edges.iter() {
+            exception_successors[*a].push(*b);
+        }
+        Graph { nodes, edges, exception_edges, successors, predecessors, exception_successors }
+    }
+
+    pub fn successors(&self, i: usize) -> &[usize] {
+        &self.successors[i]
+    }
+
+    pub fn predecessors(&self, i: usize) -> &[usize] {
+        &self.predecessors[i]
+    }
+
+    pub fn exception_successors(&self, i: usize) -> &[usize] {
+        &self.exception_successors[i]
+    }
+}
+
+pub fn control_flow_graph<'a>(code: &CodeItem<'a>) -> Graph<Block<'a>> {
     let mut nodes: Vec<Block> = Vec::new();
     let mut edges: Vec<(usize, usize)> = Vec::new();
+    let mut exception_edges: Vec<(usize, usize)> = Vec::new();
+    let mut seen: HashSet<(usize, usize)> = HashSet::new();
+    let mut seen_exceptions: HashSet<(usize, usize)> = HashSet::new();
     let mut node_lookup: HashMap<usize, usize> = HashMap::new();
 
-    let mut block_start_addrs: Vec<usize> = Vec::new();
-
-    let mut flag = true;
-    for i in ins.iter() {
+    // payloads are data embedded in the instruction stream, not code
+    let ins = code.instructions.iter()
+        .filter(|i| !is_payload(&i.kind))
+        .collect::<Vec<&Instruction>>();
 
-        if flag {
-            //println!("ADDR: {}", i.addr);
-            block_start_addrs.push(i.addr);
-            flag = false;
-        }
-
-        match i.kind {
-              InstructionKind::GoTo(o)
-            | InstructionKind::GoTo16(o)
-            | InstructionKind::GoTo32(o)
-            | InstructionKind::IfEq(_,_,o)
-            | InstructionKind::IfNe(_,_,o)
-            | InstructionKind::IfLt(_,_,o)
-            | InstructionKind::IfGe(_,_,o)
-            | InstructionKind::IfGt(_,_,o)
-            | InstructionKind::IfLe(_,_,o)
-            | InstructionKind::IfEqZ(_,o)
-            | InstructionKind::IfNeZ(_,o)
-            | InstructionKind::IfLtZ(_,o)
-            | InstructionKind::IfGeZ(_,o)
-            | InstructionKind::IfGtZ(_,o)
-            | InstructionKind::IfLeZ(_,o) => {
-                block_start_addrs.push((i.addr as i32 + o) as usize);
-                //println!("{} {:?}", i.addr as i64 + (o as i64 * 2), i.kind);
-                flag = true;
-            }
-            InstructionKind::ReturnVoid => {
-                //println!("{:?}", i.kind);
-                flag = true;
-            }
-            _ => {}
-        };
+    if ins.is_empty() {
+        return Graph::new(nodes, edges, exception_edges);
     }
 
-    block_start_addrs.sort_unstable();
-    block_start_addrs.dedup();
-    block_start_addrs.reverse();
+    let mut block_start_addrs: BTreeSet<usize> = BTreeSet::new();
+    block_start_addrs.insert(ins[0].addr);
 
-    let mut buffer: Vec<Instruction> = Vec::new();
-    for inst in ins {
-        //println!("addr: {}, {}", inst.addr, block_start_addrs.last().unwrap());
+    for i in ins.iter() {
+        let next_addr = i.addr + i.bytecode.len();
+        let targets = branch_targets(code, i);
+        if !targets.is_empty() || !falls_through(&i.kind) {
+            block_start_addrs.extend(targets);
+            block_start_addrs.insert(next_addr);
+        }
 
-        if inst.addr > *block_start_addrs.last().unwrap() {
-            break;
+        // anything that can throw inside a try ends its block, so the
+        // exception edge leaves from a well-defined point
+        if can_throw(&i.kind) && !catch_handlers(code, i.addr).is_empty() {
+            block_start_addrs.insert(next_addr);
         }
+    }
+
+    for t in code.tries.iter() {
+        block_start_addrs.insert(t.start_addr);
+        block_start_addrs.insert(t.end_addr);
+        block_start_addrs.extend(t.handlers.iter().map(|h| h.addr));
+        block_start_addrs.extend(t.catch_all_addr);
+    }
 
-        if inst.addr == *block_start_addrs.last().unwrap() {
-            let addr = *block_start_addrs.last().unwrap();
-            let block = Block {
+    let mut buffer: Vec<Instruction> = Vec::new();
+    for inst in ins.iter() {
+        if block_start_addrs.contains(&inst.addr) && !buffer.is_empty() {
+            let addr = buffer[0].addr;
+            node_lookup.insert(addr, nodes.len());
+            nodes.push(Block {
                 starting_addr: addr,
                 instructions: buffer,
-            };
-            node_lookup.insert(addr, nodes.len());
-            nodes.push(block);
+            });
             buffer = Vec::new();
+        }
+        buffer.push((*inst).clone());
+    }
+    let addr = buffer[0].addr;
+    node_lookup.insert(addr, nodes.len());
+    nodes.push(Block {
+        starting_addr: addr,
+        instructions: buffer,
+    });
+
+    for (i, n) in nodes.iter().enumerate() {
+        let last_instruction = n.instructions.last().unwrap();
 
-            if block_start_addrs.len() == 0 {
-                break;
+        for target in branch_targets(code, last_instruction) {
+            if let Some(idx) = node_lookup.get(&target) {
+                if seen.insert((i, *idx)) {
+                    edges.push((i, *idx));
+                }
             }
+        }
 
-            let _ = block_start_addrs.pop().unwrap();
-        } else {
-            buffer.push(inst.clone());
+        if falls_through(&last_instruction.kind) && i + 1 < nodes.len() && seen.insert((i, i + 1)) {
+            edges.push((i, i + 1));
+        }
+
+        let throwing = n.instructions.iter().filter(|x| can_throw(&x.kind));
+        for x in throwing {
+            for handler in catch_handlers(code, x.addr) {
+                if let Some(idx) = node_lookup.get(&handler) {
+                    if seen_exceptions.insert((i, *idx)) {
+                        exception_edges.push((i, *idx));
+                    }
+                }
+            }
         }
     }
 
-    let mut misses: Vec<Instruction> = Vec::new();
-    for (i,n) in nodes.iter().enumerate() {
-        if n.instructions.len() == 0 {
-            continue;
+    Graph::new(nodes, edges, exception_edges)
+}
+
+// Explicit jump destinations of a goto, if-* or switch, as absolute addresses.
+pub fn branch_targets(code: &CodeItem, i: &Instruction) -> Vec<usize> {
+    match i.kind {
+          InstructionKind::GoTo(o)
+        | InstructionKind::GoTo16(o)
+        | InstructionKind::GoTo32(o)
+        | InstructionKind::IfEq(_,_,o)
+        | InstructionKind::IfNe(_,_,o)
+        | InstructionKind::IfLt(_,_,o)
+        | InstructionKind::IfGe(_,_,o)
+        | InstructionKind::IfGt(_,_,o)
+        | InstructionKind::IfLe(_,_,o)
+        | InstructionKind::IfEqZ(_,o)
+        | InstructionKind::IfNeZ(_,o)
+        | InstructionKind::IfLtZ(_,o)
+        | InstructionKind::IfGeZ(_,o)
+        | InstructionKind::IfGtZ(_,o)
+        | InstructionKind::IfLeZ(_,o) => vec![(i.addr as i64 + o as i64) as usize],
+          InstructionKind::PackedSwitch(_, o)
+        | InstructionKind::SparseSwitch(_, o) => {
+            let payload_addr = (i.addr as i64 + o as i64 * 2) as usize;
+            let payload = code.instructions.iter().find(|x| x.addr == payload_addr);
+            match payload.map(|x| &x.kind) {
+                  Some(InstructionKind::PackedSwitchPayload(_, targets))
+                | Some(InstructionKind::SparseSwitchPayload(_, targets)) => {
+                    targets.iter().map(|t| (i.addr as i64 + *t as i64 * 2) as usize).collect()
+                }
+                _ => Vec::new(),
+            }
         }
+        _ => Vec::new(),
+    }
+}
 
+pub fn falls_through(kind: &InstructionKind) -> bool {
+    !matches!(kind,
+          InstructionKind::GoTo(_)
+        | InstructionKind::GoTo16(_)
+        | InstructionKind::GoTo32(_)
+        | InstructionKind::ReturnVoid
+        | InstructionKind::ReturnVoidNoBarrier
+        | InstructionKind::Return(_)
+        | InstructionKind::ReturnWide(_)
+        | InstructionKind::ReturnObject(_)
+        | InstructionKind::Throw(_))
+}
 
-        let last_instruction = n.instructions.last().unwrap();
-        match last_instruction.kind {
-              InstructionKind::GoTo(o)
-            | InstructionKind::GoTo16(o)
-            | InstructionKind::GoTo32(o)
-            | InstructionKind::IfEq(_,_,o)
-            | InstructionKind::IfNe(_,_,o)
-            | InstructionKind::IfLt(_,_,o)
-            | InstructionKind::IfGe(_,_,o)
-            | InstructionKind::IfGt(_,_,o)
-            | InstructionKind::IfLe(_,_,o)
-            | InstructionKind::IfEqZ(_,o)
-            | InstructionKind::IfNeZ(_,o)
-            | InstructionKind::IfLtZ(_,o)
-            | InstructionKind::IfGeZ(_,o)
-            | InstructionKind::IfGtZ(_,o)
-            | InstructionKind::IfLeZ(_,o) => {
-                let destination = last_instruction.addr as i32 + o;
-                let destination_idx = node_lookup.get(&(destination as usize));
-                match destination_idx {
-                    Some(idx) => {
-                        edges.push((i, *idx));
-                    }
-                    None => {
-                        misses.push(last_instruction.clone());
-                    }
+pub fn is_payload(kind: &InstructionKind) -> bool {
+    matches!(kind,
+          InstructionKind::PackedSwitchPayload(..)
+        | InstructionKind::SparseSwitchPayload(..)
+        | InstructionKind::FillArrayDataPayload(..))
+}
+
+pub fn can_throw(kind: &InstructionKind) -> bool {
+    match kind {
+          InstructionKind::ConstString(..)
+        | InstructionKind::ConstStringJumbo(..)
+        | InstructionKind::ConstClass(..)
+        | InstructionKind::MonitorEnter(..)
+        | InstructionKind::MonitorExit(..)
+        | InstructionKind::CheckCast(..)
+        | InstructionKind::InstanceOf(..)
+        | InstructionKind::ArrayLength(..)
+        | InstructionKind::NewInstance(..)
+        | InstructionKind::NewArray(..)
+        | InstructionKind::FilledNewArray(..)
+        | InstructionKind::FilledNewArrayRange(..)
+        | InstructionKind::FillArrayData(..)
+        | InstructionKind::Throw(..)
+        | InstructionKind::DivInt(..)
+        | InstructionKind::RemInt(..)
+        | InstructionKind::DivLong(..)
+        | InstructionKind::RemLong(..)
+        | InstructionKind::DivInt2Addr(..)
+        | InstructionKind::RemInt2Addr(..)
+        | InstructionKind::DivLong2Addr(..)
+        | InstructionKind::RemLong2Addr(..)
+        | InstructionKind::DivIntLit16(..)
+        | InstructionKind::RemIntLit16(..)
+        | InstructionKind::DivIntLit8(..)
+        | InstructionKind::RemIntLit8(..)
+        | InstructionKind::InvokePolymorphic(..)
+        | InstructionKind::InvokePolymorphicRange(..)
+        | InstructionKind::InvokeCustom(..)
+        | InstructionKind::InvokeCustomRange(..)
+        | InstructionKind::ConstMethodHandle(..)
+        | InstructionKind::ConstMethodType(..) => true,
+        k => is_invoke(k) || is_field_access(k) || is_array_access(k),
+    }
+}
+
+pub fn is_invoke(kind: &InstructionKind) -> bool {
+    matches!(kind,
+          InstructionKind::InvokeVirtual(..)
+        | InstructionKind::InvokeSuper(..)
+        | InstructionKind::InvokeDirect(..)
+        | InstructionKind::InvokeStatic(..)
+        | InstructionKind::InvokeInterface(..)
+        | InstructionKind::InvokeVirtualRange(..)
+        | InstructionKind::InvokeSuperRange(..)
+        | InstructionKind::InvokeDirectRange(..)
+        | InstructionKind::InvokeStaticRange(..)
+        | InstructionKind::InvokeInterfaceRange(..)
+        | InstructionKind::InvokeVirtualQuick(..)
+        | InstructionKind::InvokeVirtualQuickRange(..))
+}
+
+fn is_field_access(kind: &InstructionKind) -> bool {
+    let opcode = kind.mnemonic();
+    opcode.starts_with("iget") || opcode.starts_with("iput") || opcode.starts_with("sget") || opcode.starts_with("sput")
+}
+
+fn is_array_access(kind: &InstructionKind) -> bool {
+    let opcode = kind.mnemonic();
+    opcode.starts_with("aget") || opcode.starts_with("aput")
+}
+
+// Handler addresses for an instruction, in the order the runtime tries them.
+pub fn catch_handlers(code: &CodeItem, addr: usize) -> Vec<usize> {
+    let mut result: Vec<usize> = Vec::new();
+    for t in code.tries.iter().filter(|t| addr >= t.start_addr && addr < t.end_addr) {
+        result.extend(t.handlers.iter().map(|h| h.addr));
+        result.extend(t.catch_all_addr);
+    }
+    result
+}
+
+pub fn reverse_postorder<T>(graph: &Graph<T>, entry: usize) -> Vec<usize> {
+    let mut visited: HashSet<usize> = HashSet::new();
+    let mut postorder: Vec<usize> = Vec::new();
+    let mut stack: Vec<(usize, Vec<usize>)> = Vec::new();
+
+    if entry >= graph.nodes.len() {
+        return postorder;
+    }
+
+    visited.insert(entry);
+    stack.push((entry, graph.successors(entry).to_vec()));
+    while let Some((node, mut pending)) = stack.pop() {
+        match pending.pop() {
+            Some(next) => {
+                stack.push((node, pending));
+                if visited.insert(next) {
+                    stack.push((next, graph.successors(next).to_vec()));
                 }
             }
-            _ => {}
+            None => postorder.push(node),
         }
     }
 
-    for x in misses {
-        println!("{:?}", x);
+    postorder.reverse();
+    postorder
+}
+
+// Immediate dominators (Cooper, Harvey & Kennedy). The entry dominates itself;
+// nodes unreachable from the entry have no dominator.
+pub fn dominators<T>(graph: &Graph<T>, entry: usize) -> Vec<Option<usize>> {
+    let mut idom: Vec<Option<usize>> = vec![None; graph.nodes.len()];
+    let rpo = reverse_postorder(graph, entry);
+    if rpo.is_empty() {
+        return idom;
+    }
+
+    let mut order: Vec<usize> = vec![usize::MAX; graph.nodes.len()];
+    for (position, node) in rpo.iter().enumerate() {
+        order[*node] = position;
+    }
+    idom[entry] = Some(entry);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for node in rpo.iter().skip(1) {
+            let mut new_idom: Option<usize> = None;
+            for p in graph.predecessors(*node).iter() {
+                if idom[*p].is_none() {
+                    continue;
+                }
+                new_idom = match new_idom {
+                    None => Some(*p),
+                    Some(current) => {
+                        let (mut a, mut b) = (*p, current);
+                        while a != b {
+                            while order[a] > order[b] { a = idom[a].unwrap(); }
+                            while order[b] > order[a] { b = idom[b].unwrap(); }
+                        }
+                        Some(a)
+                    }
+                };
+            }
+            if new_idom.is_some() && idom[*node] != new_idom {
+                idom[*node] = new_idom;
+                changed = true;
+            }
+        }
+    }
+
+    idom
+}
+
+pub fn dominance_frontiers<T>(graph: &Graph<T>, idom: &[Option<usize>]) -> Vec<Vec<usize>> {
+    let mut frontiers: Vec<Vec<usize>> = vec![Vec::new(); graph.nodes.len()];
+    for node in 0..graph.nodes.len() {
+        let node_idom = match idom[node] {
+            Some(x) => x,
+            None => continue,
+        };
+        let predecessors = graph.predecessors(node).iter()
+            .filter(|p| idom[**p].is_some())
+            .cloned()
+            .collect::<Vec<usize>>();
+        if predecessors.len() < 2 {
+            continue;
+        }
+        for p in predecessors {
+            let mut runner = p;
+            while runner != node_idom {
+                if !frontiers[runner].contains(&node) {
+                    frontiers[runner].push(node);
+                }
+                match idom[runner] {
+                    Some(x) if x != runner => runner = x,
+                    _ => break,
+                }
+            }
+        }
     }
 
-    return Graph {
-        nodes,
-        edges,
-    };
+    frontiers
 }
 
 
@@ -160,4 +401,87 @@ fn walk(i: usize, edges: &Vec<(usize, usize)>) -> Vec<(usize, usize)> {
     //println!("returning {:?}", result);
 
     return result;
-}
\ No newline at end of file
+}
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::binary_parser::BinaryParser;
+    use crate::dex_types::TryItem;
+
+    pub(crate) fn code_units(units: &[u16]) -> Vec<u8> {
+        units.iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+
+    // A code item whose instructions start at address 0, so an address is
+    // twice the code unit index.
+    pub(crate) fn code_item(bytes: &[u8], registers_size: u16, ins_size: u16, tries: Vec<TryItem>) -> CodeItem<'_> {
+        let instructions = parse_bytecode(&mut BinaryParser::new(bytes), 0, bytes.len() / 2).unwrap();
+        CodeItem {
+            addr: 0,
+            instructions_addr: 0,
+            registers_size,
+            ins_size,
+            outs_size: 0,
+            tries_size: tries.len() as u16,
+            debug_info_offset: 0,
+            instructions_size: (bytes.len() / 2) as u32,
+            instructions,
+            tries,
+            parameter_names: Vec::new(),
+        }
+    }
+
+    // Catches anything thrown by the code units in `start..end` at `handler`.
+    pub(crate) fn catch_all(start: usize, end: usize, handler: usize) -> TryItem {
+        TryItem { start_addr: start * 2, end_addr: end * 2, handlers: Vec::new(), catch_all_addr: Some(handler * 2) }
+    }
+
+    fn starts(graph: &Graph<Block>) -> Vec<usize> {
+        graph.nodes.iter().map(|b| b.starting_addr / 2).collect()
+    }
+
+    #[test]
+    fn switch_edges() {
+        let bytes = code_units(&[
+            0x002b, 8, 0,   // packed-switch v0, +8
+            0x000e,         // return-void
+            0x000e,         // return-void
+            0x000e,         // return-void
+            0x0000,         // nop, to align the payload
+            0x0000,         // nop
+            0x0100, 2,      // packed-switch-payload, 2 targets
+            0, 0,           // first key 0
+            4, 0, 5, 0,     // +4, +5
+        ]);
+        let code = code_item(&bytes, 1, 0, Vec::new());
+        let graph = control_flow_graph(&code);
+
+        assert_eq!(starts(&graph), vec![0, 3, 4, 5, 6]);
+        assert_eq!(graph.successors(0), &[2, 3, 1]);
+        assert!(graph.exception_edges.is_empty());
+        for target in 1..4 {
+            assert!(graph.successors(target).is_empty());
+            assert_eq!(graph.predecessors(target), &[0]);
+        }
+    }
+
+    #[test]
+    fn exception_edges() {
+        let bytes = code_units(&[
+            0x1012,         // const/4 v0, 1
+            0x10b3,         // div-int/2addr v0, v1
+            0x10b3,         // div-int/2addr v0, v1
+            0x000f,         // return v0
+            0x000d,         // move-exception v0
+            0x000f,         // return v0
+        ]);
+        let code = code_item(&bytes, 2, 1, vec![catch_all(1, 3, 4)]);
+        let graph = control_flow_graph(&code);
+
+        // each throwing instruction in the try ends its block
+        assert_eq!(starts(&graph), vec![0, 1, 2, 3, 4]);
+        assert_eq!(graph.edges, vec![(0, 1), (1, 2), (2, 3)]);
+        assert_eq!(graph.exception_edges, vec![(1, 4), (2, 4)]);
+        assert_eq!(graph.predecessors(4), &[1, 2]);
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt;
+use std::fmt::Display;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+// Something in the file that does not fit it: a section past the end, an
+// index out of range, a size no file could hold. Packers produce these on
+// purpose to break tools. With --hardened, parsing reports them and carries
+// on with what it could read instead of giving up at the first one.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Anomaly {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#x}: {}", self.offset, self.message)
+    }
+}
+
+// A crafted file can have one anomaly per byte; past this many only the
+// count is kept.
+const MAX_ANOMALIES: usize = 1000;
+
+// Where the anomalies found while parsing one input go. A parser and
+// everything decoded from it later share one, so that class data and code
+// read on demand are held to the same rules as the header.
+#[derive(Debug)]
+pub struct Anomalies {
+    hardened: bool,
+    // whether anomalies are printed to stderr as they are found
+    echo: bool,
+    found: Mutex<(Vec<Anomaly>, usize)>,
+}
+
+// For parsers that were not asked to be hardened: the first anomaly is an
+// error and nothing is collected.
+pub static STRICT: Anomalies = Anomalies::strict();
+
+impl Anomalies {
+    pub const fn strict() -> Anomalies {
+        Anomalies { hardened: false, echo: false, found: Mutex::new((Vec::new(), 0)) }
+    }
+
+    pub const fn hardened(echo: bool) -> Anomalies {
+        Anomalies { hardened: true, echo, found: Mutex::new((Vec::new(), 0)) }
+    }
+
+    pub fn is_hardened(&self) -> bool {
+        self.hardened
+    }
+
+    pub fn report(&self, offset: usize, message: String) {
+        let anomaly = Anomaly { offset, message };
+        let mut found = self.found.lock().unwrap();
+        // a second pass over the same code finds the same problems
+        if found.0.contains(&anomaly) {
+            return;
+        }
+        found.1 += 1;
+        if found.0.len() < MAX_ANOMALIES {
+            if self.echo {
+                eprintln!("warning: {}", anomaly);
+            }
+            found.0.push(anomaly);
+        }
+    }
+
+    // In hardened mode, reports an anomaly and returns true so the caller
+    // skips or clamps what it was about to read. Otherwise returns false and
+    // parsing goes on as it always has.
+    pub fn reject(&self, offset: usize, message: impl FnOnce() -> String) -> bool {
+        if !self.hardened {
+            return false;
+        }
+        self.report(offset, message());
+        true
+    }
+
+    // The result of parsing one item. In hardened mode an error in it, such
+    // as a read past the end of the file, is reported against `what` and
+    // gives None so the item is left out; otherwise the error is passed on.
+    pub fn recover<T>(&self, offset: usize, what: impl FnOnce() -> String, result: Result<T, String>) -> Result<Option<T>, String> {
+        match result {
+            Ok(x) => Ok(Some(x)),
+            Err(e) if self.hardened => {
+                self.report(offset, format!("{}: {}", what(), e));
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // The anomalies reported so far, in the order found, and how many
+    // there were in all.
+    pub fn take(&self) -> (Vec<Anomaly>, usize) {
+        std::mem::take(&mut *self.found.lock().unwrap())
+    }
+}
@@ -1,66 +1,114 @@
 #![allow(dead_code, unused_variables)]
 
-
-use crate::util::{decode_uleb128};
-
-pub struct BinaryParser {
-    buffer: Vec<u8>,
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::anomaly::{Anomalies, STRICT};
+use crate::util::{decode_uleb128, decode_sleb128};
+
+// Reads from a borrowed buffer, usually a memory-mapped file; reads return
+// slices of it rather than copies. Offsets and sizes come from the file, so
+// every read can fail, and what the callers do about it is up to the
+// anomalies it carries: a strict parser gives up on the file, a hardened
+// one reports the item and carries on.
+pub struct BinaryParser<'a> {
+    buffer: &'a [u8],
     next: usize,
+    // where offsets into the data section count from: the start of the
+    // buffer for standard dex, the (possibly shared) data section for cdex
+    data_base: usize,
+    anomalies: &'a Anomalies,
 }
 
-impl BinaryParser {
-    pub fn new(buffer: Vec<u8>) -> BinaryParser {
+impl<'a> BinaryParser<'a> {
+    pub fn new(buffer: &'a [u8]) -> BinaryParser<'a> {
+        BinaryParser::with_anomalies(buffer, &STRICT)
+    }
+
+    pub fn with_anomalies(buffer: &'a [u8], anomalies: &'a Anomalies) -> BinaryParser<'a> {
         BinaryParser {
             buffer,
             next: 0,
+            data_base: 0,
+            anomalies,
         }
     }
 
-    pub fn next(&mut self) -> u8 {
-        let x = self.buffer[self.next];
-        self.next += 1;
-        return x;
+    pub fn next(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
     }
 
-    pub fn expect(&mut self, i: u8) {
-        let a = self.next();
+    pub fn expect(&mut self, i: u8) -> Result<(), String> {
+        let a = self.next()?;
         if a != i {
-            panic!("Expected {}, got {}", i, a);
+            return Err(format!("Expected {}, got {}", i, a));
         }
+        Ok(())
     }
 
-    pub fn expect_many(&mut self, ls: Vec<u8>) {
+    pub fn expect_many(&mut self, ls: &[u8]) -> Result<(), String> {
         for i in ls {
-            let a = self.next();
-            if a != i {
-                panic!("Expected {}, got {}", i, a);
-            }
+            self.expect(*i)?;
         }
+        Ok(())
     }
 
-    pub fn take(&mut self, n: usize) -> Vec<u8> {
-        let x = self.buffer[self.next..self.next + n].to_vec();
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let x = self.peek(n)?;
         self.next += n;
-        return x;
+        Ok(x)
     }
 
-    pub fn take_until(&mut self, x: u8) -> Vec<u8> {
-        let mut acc = Vec::new();
-        loop {
-            let p = self.peek(1)[0];
-            if p == x {
-                break;
-            }
+    pub fn take_until(&mut self, x: u8) -> Result<&'a [u8], String> {
+        let rest = self.buffer.get(self.next..).unwrap_or(&[]);
+        let n = rest.iter().position(|b| *b == x)
+            .ok_or_else(|| format!("Expected {} after {:#x}", x, self.next))?;
+        self.take(n)
+    }
 
-            let n = self.next();
-            acc.push(n);
-        }
+    pub fn peek(&self, n: usize) -> Result<&'a [u8], String> {
+        self.next.checked_add(n)
+            .and_then(|end| self.buffer.get(self.next..end))
+            .ok_or_else(|| format!("Read of {} bytes at {:#x} runs past the end of the file", n, self.next))
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, String> {
+        Ok(LittleEndian::read_u16(self.take(2)?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, String> {
+        Ok(LittleEndian::read_u32(self.take(4)?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, String> {
+        Ok(LittleEndian::read_u64(self.take(8)?))
+    }
+
+    pub fn read_i8(&mut self) -> Result<i8, String> {
+        Ok(self.next()? as i8)
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16, String> {
+        Ok(LittleEndian::read_i16(self.take(2)?))
+    }
 
-        return acc;
+    pub fn read_i32(&mut self) -> Result<i32, String> {
+        Ok(LittleEndian::read_i32(self.take(4)?))
     }
 
-    pub fn peek(&self, n: usize) -> Vec<u8> {
-        self.buffer[self.next..self.next + n].to_vec()
+    pub fn read_i64(&mut self) -> Result<i64, String> {
+        Ok(LittleEndian::read_i64(self.take(8)?))
+    }
+
+    pub fn buffer(&self) -> &'a [u8] {
+        self.buffer
+    }
+
+    pub fn anomalies(&self) -> &'a Anomalies {
+        self.anomalies
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
     }
 
     pub fn current_location(&self) -> usize {
@@ -71,17 +119,33 @@ impl BinaryParser {
         self.next = i;
     }
 
-    pub fn parse_uleb128(&mut self) -> u32 {
-        let mut result: Vec<u8> = Vec::new();
+    pub fn data_base(&self) -> usize {
+        self.data_base
+    }
 
-        loop {
-            let x = self.next();
-            result.push(x);
-            if x & 0x80 == 0 {
-                break;
-            }
-        }
+    pub fn set_data_base(&mut self, base: usize) {
+        self.data_base = base;
+    }
+
+    // Seeks to an offset stored in the file that points into the data section.
+    pub fn seek_to_data(&mut self, offset: usize) {
+        self.next = self.data_base.saturating_add(offset);
+    }
+
+    pub fn parse_uleb128(&mut self) -> Result<u32, String> {
+        Ok(decode_uleb128(self.take_leb128()?))
+    }
+
+    pub fn parse_sleb128(&mut self) -> Result<i32, String> {
+        Ok(decode_sleb128(self.take_leb128()?))
+    }
 
-        return decode_uleb128(&result);
+    // The bytes of one leb128 value: all but the last have the high bit set,
+    // and a 32-bit value never needs more than 5.
+    fn take_leb128(&mut self) -> Result<&'a [u8], String> {
+        let rest = self.buffer.get(self.next..).unwrap_or(&[]);
+        let n = rest.iter().take(5).position(|b| b & 0x80 == 0)
+            .ok_or_else(|| format!("Unterminated leb128 at {:#x}", self.next))?;
+        self.take(n + 1)
     }
-}
\ No newline at end of file
+}
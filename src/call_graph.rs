@@ -0,0 +1,173 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_json::json;
+
+use crate::class_hierarchy::ClassHierarchy;
+use crate::dex_types::*;
+use crate::instructions::*;
+use crate::parallel;
+
+// Nodes are method signatures rather than method indices so that calls
+// between the files of a multidex app end up on the same node.
+pub struct CallGraph {
+    pub methods: Vec<String>,
+    pub edges: Vec<(usize, usize)>,
+    lookup: HashMap<String, usize>,
+}
+
+impl CallGraph {
+    fn add_method(&mut self, signature: String) -> usize {
+        if let Some(idx) = self.lookup.get(&signature) {
+            return *idx;
+        }
+        let idx = self.methods.len();
+        self.lookup.insert(signature.clone(), idx);
+        self.methods.push(signature);
+        idx
+    }
+
+    // Accepts a full signature, or `Lcom/example/Foo;->name` to match every
+    // overload.
+    pub fn find(&self, query: &str) -> Vec<usize> {
+        if let Some(idx) = self.lookup.get(query) {
+            return vec![*idx];
+        }
+        let prefix = format!("{}(", query);
+        (0..self.methods.len())
+            .filter(|i| self.methods[*i].starts_with(&prefix))
+            .collect()
+    }
+
+    pub fn callers_of(&self, method: usize) -> Vec<usize> {
+        self.edges.iter().filter(|(_, b)| *b == method).map(|(a, _)| *a).collect()
+    }
+
+    pub fn callees_of(&self, method: usize) -> Vec<usize> {
+        self.edges.iter().filter(|(a, _)| *a == method).map(|(_, b)| *b).collect()
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut result = String::from("digraph calls {\n");
+        for (i, m) in self.methods.iter().enumerate() {
+            result.push_str(&format!("    n{} [label=\"{}\"];\n", i, m.replace('\\', "\\\\").replace('"', "\\\"")));
+        }
+        for (a, b) in self.edges.iter() {
+            result.push_str(&format!("    n{} -> n{};\n", a, b));
+        }
+        result.push_str("}\n");
+        result
+    }
+
+    pub fn to_json(&self) -> String {
+        let calls = self.edges.iter()
+            .map(|(a, b)| json!({ "caller": self.methods[*a], "callee": self.methods[*b] }))
+            .collect::<Vec<_>>();
+        json!({ "methods": self.methods, "calls": calls }).to_string()
+    }
+}
+
+pub fn build_call_graph(dex_files: &[DexFile]) -> Result<CallGraph, String> {
+    let hierarchy = ClassHierarchy::new(dex_files)?;
+    let mut graph = CallGraph {
+        methods: Vec::new(),
+        edges: Vec::new(),
+        lookup: HashMap::new(),
+    };
+    let mut edges: HashSet<(usize, usize)> = HashSet::new();
+    // a method is called from many places, and from every file of a multidex app
+    let mut resolved: HashMap<Call, Vec<String>> = HashMap::new();
+
+    for dex in dex_files.iter() {
+        let calls = parallel::map_methods(&dex.classes, |_, m| {
+            let callees = match m.code_item()? {
+                Some(code) => code.instructions.iter()
+                    .filter_map(|i| call(dex, &i.kind))
+                    .collect::<Vec<Call>>(),
+                None => Vec::new(),
+            };
+            Ok((dex.method_signature(m.method_idx), callees))
+        })?;
+
+        // resolving the targets is the slow part; nodes are numbered after,
+        // in the same order as without --jobs
+        let unresolved = calls.iter()
+            .flat_map(|(_, callees)| callees.iter())
+            .filter(|c| !resolved.contains_key(*c))
+            .cloned()
+            .collect::<HashSet<Call>>()
+            .into_iter()
+            .collect::<Vec<Call>>();
+        let targets = parallel::map(&unresolved, |c| call_targets(&hierarchy, c));
+        resolved.extend(unresolved.into_iter().zip(targets));
+
+        for (caller, callees) in calls {
+            let caller = graph.add_method(caller);
+            for callee in callees.iter().flat_map(|c| resolved[c].iter()) {
+                let callee = graph.add_method(callee.clone());
+                if edges.insert((caller, callee)) {
+                    graph.edges.push((caller, callee));
+                }
+            }
+        }
+    }
+
+    Ok(graph)
+}
+
+// An invoke, by the class and method it names.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Call {
+    class: String,
+    // `name(args)ret`
+    method: String,
+    // virtual and interface calls go to whichever override the receiver has
+    dispatched: bool,
+    interface: bool,
+}
+
+fn call(dex: &DexFile, kind: &InstructionKind) -> Option<Call> {
+    let (method_idx, dispatched, interface) = match *kind {
+          InstructionKind::InvokeVirtual(_, m)
+        | InstructionKind::InvokeVirtualRange(_, _, m) => (m, true, false),
+          InstructionKind::InvokeInterface(_, m)
+        | InstructionKind::InvokeInterfaceRange(_, _, m) => (m, true, true),
+          InstructionKind::InvokeSuper(_, m)
+        | InstructionKind::InvokeDirect(_, m)
+        | InstructionKind::InvokeStatic(_, m)
+        | InstructionKind::InvokeSuperRange(_, _, m)
+        | InstructionKind::InvokeDirectRange(_, _, m)
+        | InstructionKind::InvokeStaticRange(_, _, m) => (m, false, false),
+        _ => return None,
+    };
+
+    let class = dex.type_descriptor(dex.methods.get(method_idx)?.class_idx).to_string();
+    let method = dex.method_name_and_proto(method_idx);
+    Some(Call { class, method, dispatched, interface })
+}
+
+fn call_targets(hierarchy: &ClassHierarchy, call: &Call) -> Vec<String> {
+    let (class, method) = (&call.class, &call.method);
+    let mut targets: Vec<String> = Vec::new();
+    if call.dispatched {
+        // class hierarchy analysis: any concrete subtype may be the receiver
+        let mut seen: HashSet<&str> = HashSet::new();
+        for s in hierarchy.subtypes_of(class).iter().filter(|s| hierarchy.is_concrete(&s.name)) {
+            if let Some(declaring) = hierarchy.dispatch(&s.name, method) {
+                if seen.insert(&declaring.name) {
+                    targets.push(format!("{}->{}", declaring.name, method));
+                }
+            }
+        }
+    }
+
+    if targets.is_empty() {
+        let resolved = if call.interface {
+            hierarchy.resolve_interface_method(class, method)
+        } else {
+            hierarchy.resolve_method(class, method)
+        };
+        let declaring = resolved.map(|c| c.name.as_str()).unwrap_or(class);
+        targets.push(format!("{}->{}", declaring, method));
+    }
+    targets
+}
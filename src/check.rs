@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use crate::anomaly::Anomalies;
+use crate::dex_types::*;
+use crate::instructions::IndexReference;
+use crate::parallel;
+
+// Superclass and source file indexes that are absent.
+const NO_INDEX: usize = 0xffffffff;
+
+// What `check` looks for beyond what parsing itself reports: sections that
+// do not fit the file or overlap, indexes between the tables that are out
+// of range, and superclass chains that loop. Every class's data and code
+// is decoded as well, so that what is wrong in them is reported too.
+pub fn check_dex_file(dex: &DexFile, file_len: usize, anomalies: &Anomalies) {
+    check_sections(dex, file_len, anomalies);
+    check_ids(dex, anomalies);
+    check_hierarchy(dex, anomalies);
+    check_code(dex, anomalies);
+}
+
+fn check_sections(dex: &DexFile, file_len: usize, anomalies: &Anomalies) {
+    let h = &dex.header;
+    if h.file_size as usize != file_len {
+        anomalies.report(0x20, format!("the header gives a file size of {} bytes but the file is {}", h.file_size, file_len));
+    }
+
+    // (name, offset, length); the ids are at absolute offsets in every
+    // format, the map only in standard dex
+    let mut sections: Vec<(&str, usize, usize)> = vec![
+        ("string_id_item", h.string_ids_offset as usize, h.string_ids_size as usize * 4),
+        ("type_id_item", h.type_ids_offset as usize, h.type_ids_size as usize * 4),
+        ("proto_id_item", h.proto_ids_offset as usize, h.proto_ids_size as usize * 12),
+        ("field_id_item", h.field_ids_offset as usize, h.field_ids_size as usize * 8),
+        ("method_id_item", h.method_ids_offset as usize, h.method_ids_size as usize * 8),
+        ("class_def_item", h.class_defs_offset as usize, h.class_defs_size as usize * 32),
+    ];
+    if h.compact.is_none() {
+        for m in dex.map.iter() {
+            let length = match m.type_code {
+                // already covered by the header
+                0x0000..=0x0006 => continue,
+                0x0007 => m.size as usize * 4,
+                0x0008 => m.size as usize * 8,
+                0x1000 => 4 + m.size as usize * 12,
+                // variable sized, so only where they start is known
+                _ => 0,
+            };
+            sections.push((m.type_name(), m.offset as usize, length));
+        }
+    }
+    sections.retain(|s| s.2 > 0 || s.1 != 0);
+
+    for (name, offset, length) in sections.iter() {
+        if *offset < h.header_size as usize {
+            anomalies.report(*offset, format!("{} section overlaps the header", name));
+        }
+        if offset + length > file_len {
+            anomalies.report(*offset, format!("{} section of {} bytes runs past the end of the file", name, length));
+        }
+    }
+    sections.sort_by_key(|s| s.1);
+    for pair in sections.windows(2) {
+        let ((a, a_offset, a_length), (b, b_offset, _)) = (pair[0], pair[1]);
+        if a_offset + a_length > b_offset {
+            anomalies.report(b_offset, format!("{} section overlaps the {} section at {:#x}", b, a, a_offset));
+        }
+    }
+}
+
+fn check_ids(dex: &DexFile, anomalies: &Anomalies) {
+    let h = &dex.header;
+    let (strings, types, protos, fields, methods) = (dex.strings.len(), dex.types.len(), dex.protos.len(), dex.fields.len(), dex.methods.len());
+    let check = |offset: usize, what: &str, idx: usize, size: usize| {
+        if idx >= size {
+            anomalies.report(offset, format!("{} index {} is out of range", what, idx));
+        }
+    };
+
+    for (i, p) in dex.protos.iter().enumerate() {
+        let offset = h.proto_ids_offset as usize + i * 12;
+        check(offset, "proto shorty string", p.shorty_idx, strings);
+        check(offset, "proto return type", p.return_type_idx, types);
+        for t in p.parameter_type_idx_list.iter() {
+            check(offset, "proto parameter type", *t, types);
+        }
+    }
+    for (i, f) in dex.fields.iter().enumerate() {
+        let offset = h.field_ids_offset as usize + i * 8;
+        check(offset, "field class", f.class_idx, types);
+        check(offset, "field type", f.type_idx, types);
+        check(offset, "field name string", f.name_idx, strings);
+    }
+    for (i, m) in dex.methods.iter().enumerate() {
+        let offset = h.method_ids_offset as usize + i * 8;
+        check(offset, "method class", m.class_idx, types);
+        check(offset, "method proto", m.proto_idx, protos);
+        check(offset, "method name string", m.name_idx, strings);
+    }
+    for c in dex.classes.iter() {
+        let offset = c.offset;
+        if c.superclass_idx != NO_INDEX {
+            check(offset, "superclass type", c.superclass_idx, types);
+        }
+        if c.source_file_idx != NO_INDEX {
+            check(offset, "source file string", c.source_file_idx, strings);
+        }
+        for t in c.interfaces.iter() {
+            check(offset, "interface type", *t, types);
+        }
+    }
+    let method_handles = dex.map.iter().find(|m| m.type_code == 0x0008).map(|m| m.offset as usize).unwrap_or(0);
+    for (i, m) in dex.method_handles.iter().enumerate() {
+        let size = if m.kind.is_field_accessor() { fields } else { methods };
+        check(method_handles + i * 8, "method handle member", m.field_or_method_idx, size);
+    }
+}
+
+// A class that is its own superclass, directly or further up, would send
+// anything that walks the chain around forever.
+fn check_hierarchy(dex: &DexFile, anomalies: &Anomalies) {
+    let defined = dex.classes.iter().map(|c| (c.class_idx, c)).collect::<HashMap<TypeIndex, &DexClassDef>>();
+    for c in dex.classes.iter() {
+        let mut current = defined.get(&c.superclass_idx);
+        // a chain longer than the number of classes must loop somewhere,
+        // which is reported when the walk starts on the loop itself
+        for _ in 0..dex.classes.len() {
+            let s = match current {
+                Some(s) => s,
+                None => break,
+            };
+            if s.class_idx == c.class_idx {
+                anomalies.report(c.offset, format!("class {} is its own superclass", dex.types[c.class_idx].raw));
+                break;
+            }
+            current = defined.get(&s.superclass_idx);
+        }
+    }
+}
+
+fn check_code(dex: &DexFile, anomalies: &Anomalies) {
+    let sizes = (dex.strings.len(), dex.types.len(), dex.fields.len(), dex.methods.len());
+    let decoded = parallel::map_methods(&dex.classes, |_, m| {
+        let code = match m.decode_code_item()? {
+            Some(code) => code,
+            None => return Ok(()),
+        };
+        for i in code.instructions.iter() {
+            let (what, idx, size) = match i.kind.index_reference() {
+                Some(IndexReference::String(x)) => ("string", x, sizes.0),
+                Some(IndexReference::Type(x)) => ("type", x, sizes.1),
+                Some(IndexReference::Field(x)) => ("field", x, sizes.2),
+                Some(IndexReference::Method(x)) => ("method", x, sizes.3),
+                None => continue,
+            };
+            if idx >= size {
+                anomalies.report(i.addr, format!("{} refers to {} index {}, which is out of range", i.kind.mnemonic(), what, idx));
+            }
+        }
+        Ok(())
+    });
+    // a hardened parser reports what it cannot decode and leaves it out, so
+    // this only fails for a strict one
+    if let Err(e) = decoded {
+        anomalies.report(0, e);
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use crate::dex_types::*;
+use serde::Serialize;
+
+const OBJECT: &str = "Ljava/lang/Object;";
+
+// What java.lang.Object declares, with the access flags, for when the
+// program does not define it.
+const OBJECT_METHODS: [(&str, u32); 12] = [
+    ("<init>()V", 0x10001),
+    ("clone()Ljava/lang/Object;", 0x104),
+    ("equals(Ljava/lang/Object;)Z", 0x1),
+    ("finalize()V", 0x4),
+    ("getClass()Ljava/lang/Class;", 0x111),
+    ("hashCode()I", 0x101),
+    ("notify()V", 0x111),
+    ("notifyAll()V", 0x111),
+    ("toString()Ljava/lang/String;", 0x1),
+    ("wait()V", 0x11),
+    ("wait(J)V", 0x111),
+    ("wait(JI)V", 0x11),
+];
+
+#[derive(Debug, Serialize)]
+pub struct ClassNode {
+    pub name: String,
+    pub access_flags: u32,
+    pub superclass: Option<String>,
+    pub interfaces: Vec<String>,
+    // `name(args)ret` of each declared method, with its access flags
+    pub methods: BTreeMap<String, u32>,
+    // referenced by the program but defined elsewhere, usually the framework
+    pub phantom: bool,
+}
+
+impl ClassNode {
+    pub fn is_interface(&self) -> bool {
+        self.access_flags & ClassAccessLevel::Interface as u32 != 0
+    }
+
+    fn declares(&self, method: &str) -> bool {
+        self.methods.contains_key(method)
+    }
+
+    // A phantom class is assumed to declare whatever is asked of it, except
+    // java.lang.Object, whose methods are known.
+    fn may_declare(&self, method: &str) -> bool {
+        (self.phantom && self.name != OBJECT) || self.declares(method)
+    }
+
+    fn may_declare_overridable(&self, method: &str) -> bool {
+        (self.phantom && self.name != OBJECT) || self.declares_overridable(method)
+    }
+
+    // Declares a body for the method, as opposed to an abstract declaration.
+    fn implements(&self, method: &str) -> bool {
+        match self.methods.get(method) {
+            Some(flags) => flags & MethodAccessLevel::Abstract as u32 == 0,
+            None => false,
+        }
+    }
+
+    fn declares_overridable(&self, method: &str) -> bool {
+        let not_overridable = MethodAccessLevel::Private as u32 | MethodAccessLevel::Static as u32 | MethodAccessLevel::Constructor as u32;
+        match self.methods.get(method) {
+            Some(flags) => flags & not_overridable == 0,
+            None => false,
+        }
+    }
+}
+
+// Every class defined across the input files plus phantom nodes for the
+// classes they extend or implement but don't define.
+pub struct ClassHierarchy {
+    pub classes: Vec<ClassNode>,
+    lookup: HashMap<String, usize>,
+    // direct subclasses and implementors of each class or interface
+    subtypes: HashMap<String, Vec<String>>,
+}
+
+impl ClassHierarchy {
+    pub fn new(dex_files: &[DexFile]) -> Result<ClassHierarchy, String> {
+        let mut hierarchy = ClassHierarchy {
+            classes: Vec::new(),
+            lookup: HashMap::new(),
+            subtypes: HashMap::new(),
+        };
+
+        for dex in dex_files.iter() {
+            for c in dex.classes.iter() {
+                let name = dex.type_descriptor(c.class_idx).to_string();
+                if hierarchy.lookup.contains_key(&name) {
+                    continue; // the first definition wins, as it does at runtime
+                }
+
+                let superclass = dex.types.get(c.superclass_idx).map(|t| t.raw.clone());
+                let interfaces = c.interfaces.iter()
+                    .map(|i| dex.type_descriptor(*i).to_string())
+                    .collect::<Vec<String>>();
+                let methods = c.methods()?
+                    .map(|m| (dex.method_name_and_proto(m.method_idx), m.access_flags))
+                    .collect::<BTreeMap<String, u32>>();
+
+                hierarchy.add(ClassNode { name, access_flags: c.access_flags, superclass, interfaces, methods, phantom: false });
+            }
+        }
+
+        hierarchy.link();
+        Ok(hierarchy)
+    }
+
+    // Adds phantom nodes for the supertypes the program names but doesn't
+    // define, then indexes the subtypes of every node. A phantom that is
+    // only ever implemented is an interface.
+    fn link(&mut self) {
+        let superclasses = self.classes.iter()
+            .flat_map(|c| c.superclass.iter())
+            .cloned()
+            .collect::<HashSet<String>>();
+        let referenced = self.classes.iter()
+            .flat_map(|c| c.superclass.iter().chain(c.interfaces.iter()))
+            .cloned()
+            .chain(std::iter::once(OBJECT.to_string()))
+            .collect::<Vec<String>>();
+        for name in referenced {
+            if !self.lookup.contains_key(&name) {
+                let (superclass, methods) = if name == OBJECT {
+                    (None, OBJECT_METHODS.iter().map(|(m, flags)| (m.to_string(), *flags)).collect())
+                } else {
+                    (Some(OBJECT.to_string()), BTreeMap::new())
+                };
+                let access_flags = if superclasses.contains(&name) {
+                    0
+                } else {
+                    ClassAccessLevel::Interface as u32 | ClassAccessLevel::Abstract as u32
+                };
+                self.add(ClassNode {
+                    name,
+                    access_flags,
+                    superclass,
+                    interfaces: Vec::new(),
+                    methods,
+                    phantom: true,
+                });
+            }
+        }
+
+        for c in self.classes.iter() {
+            for s in c.superclass.iter().chain(c.interfaces.iter()) {
+                self.subtypes.entry(s.clone()).or_default().push(c.name.clone());
+            }
+        }
+    }
+
+    fn add(&mut self, node: ClassNode) {
+        self.lookup.insert(node.name.clone(), self.classes.len());
+        self.classes.push(node);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ClassNode> {
+        self.lookup.get(name).map(|i| &self.classes[*i])
+    }
+
+    // Superclasses from the direct one up to the root.
+    pub fn superclass_chain(&self, name: &str) -> Vec<&ClassNode> {
+        let mut result: Vec<&ClassNode> = Vec::new();
+        let mut current = self.get(name).and_then(|c| c.superclass.as_ref());
+        while let Some(s) = current {
+            let node = match self.get(s) {
+                Some(node) => node,
+                None => break,
+            };
+            if result.iter().any(|x| x.name == node.name) || node.name == name {
+                break; // cyclic hierarchy
+            }
+            result.push(node);
+            current = node.superclass.as_ref();
+        }
+        result
+    }
+
+    // Every interface the class implements, including those inherited from
+    // superclasses and superinterfaces.
+    pub fn interfaces_of(&self, name: &str) -> Vec<&ClassNode> {
+        let mut pending: VecDeque<&String> = VecDeque::new();
+        if let Some(c) = self.get(name) {
+            pending.extend(c.interfaces.iter());
+        }
+        for s in self.superclass_chain(name) {
+            pending.extend(s.interfaces.iter());
+        }
+
+        let mut result: Vec<&ClassNode> = Vec::new();
+        while let Some(i) = pending.pop_front() {
+            if let Some(node) = self.get(i) {
+                if !result.iter().any(|x| x.name == node.name) {
+                    result.push(node);
+                    pending.extend(node.interfaces.iter());
+                }
+            }
+        }
+        result
+    }
+
+    // Classes that name this one as their superclass.
+    pub fn subclasses_of(&self, name: &str) -> Vec<&ClassNode> {
+        match self.subtypes.get(name) {
+            Some(subtypes) => subtypes.iter()
+                .filter_map(|s| self.get(s))
+                .filter(|s| s.superclass.as_deref() == Some(name))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // The class itself plus everything that extends or implements it,
+    // directly or indirectly.
+    pub fn subtypes_of(&self, name: &str) -> Vec<&ClassNode> {
+        let mut result: Vec<&ClassNode> = Vec::new();
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut pending: VecDeque<&str> = VecDeque::new();
+        pending.push_back(name);
+
+        while let Some(n) = pending.pop_front() {
+            if !seen.insert(n) {
+                continue;
+            }
+            if let Some(node) = self.get(n) {
+                result.push(node);
+            }
+            if let Some(subtypes) = self.subtypes.get(n) {
+                pending.extend(subtypes.iter().map(|s| s.as_str()));
+            }
+        }
+        result
+    }
+
+    pub fn is_concrete(&self, name: &str) -> bool {
+        let abstract_flags = ClassAccessLevel::Abstract as u32 | ClassAccessLevel::Interface as u32;
+        match self.get(name) {
+            Some(c) => !c.phantom && c.access_flags & abstract_flags == 0,
+            None => false,
+        }
+    }
+
+    // Resolves a method reference on a class the way the runtime does: the
+    // class and its superclasses first, then its interfaces.
+    pub fn resolve_method(&self, class: &str, method: &str) -> Option<&ClassNode> {
+        let start = self.get(class)?;
+        for c in std::iter::once(start).chain(self.superclass_chain(class)) {
+            if c.may_declare(method) {
+                return Some(c);
+            }
+        }
+        self.interfaces_of(class).into_iter().find(|i| i.declares(method))
+    }
+
+    // Resolves a method reference on an interface: the interface, its
+    // superinterfaces, then java.lang.Object.
+    pub fn resolve_interface_method(&self, interface: &str, method: &str) -> Option<&ClassNode> {
+        let start = self.get(interface)?;
+        if start.may_declare(method) {
+            return Some(start);
+        }
+        let inherited = self.interfaces_of(interface).into_iter().find(|i| i.may_declare(method));
+        inherited.or_else(|| self.get(OBJECT).filter(|o| o.may_declare(method)))
+    }
+
+    // The implementation a virtual or interface call runs when the receiver
+    // is exactly `class`: the nearest non-abstract declaration up the
+    // superclass chain, or else a default method from an interface. Private
+    // and static methods with the same signature take no part in dispatch.
+    pub fn dispatch(&self, class: &str, method: &str) -> Option<&ClassNode> {
+        let start = self.get(class)?;
+        for c in std::iter::once(start).chain(self.superclass_chain(class)) {
+            if (c.phantom && c.name != OBJECT) || (c.implements(method) && c.declares_overridable(method)) {
+                return Some(c);
+            }
+        }
+        self.interfaces_of(class).into_iter().find(|i| i.implements(method) && i.declares_overridable(method))
+    }
+
+    // The superclass or interface declaration that `class`'s method overrides.
+    pub fn overridden_method(&self, class: &str, method: &str) -> Option<&ClassNode> {
+        if !self.get(class)?.declares_overridable(method) {
+            return None;
+        }
+        let from_superclass = self.superclass_chain(class).into_iter().find(|c| c.may_declare_overridable(method));
+        from_superclass.or_else(|| self.interfaces_of(class).into_iter().find(|i| i.may_declare(method)))
+    }
+
+    // Subtypes of `class` that declare their own version of its method.
+    pub fn overriding_methods(&self, class: &str, method: &str) -> Vec<&ClassNode> {
+        self.subtypes_of(class).into_iter()
+            .filter(|c| c.name != class && c.declares_overridable(method))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBLIC: u32 = MethodAccessLevel::Public as u32;
+    const PRIVATE: u32 = MethodAccessLevel::Private as u32;
+    const STATIC: u32 = MethodAccessLevel::Static as u32;
+    const ABSTRACT: u32 = MethodAccessLevel::Abstract as u32;
+    const INTERFACE: u32 = ClassAccessLevel::Interface as u32 | ClassAccessLevel::Abstract as u32;
+
+    fn class(name: &str, access_flags: u32, superclass: &str, interfaces: &[&str], methods: &[(&str, u32)]) -> ClassNode {
+        ClassNode {
+            name: name.to_string(),
+            access_flags,
+            superclass: Some(superclass.to_string()),
+            interfaces: interfaces.iter().map(|i| i.to_string()).collect(),
+            methods: methods.iter().map(|(m, flags)| (m.to_string(), *flags)).collect(),
+            phantom: false,
+        }
+    }
+
+    fn hierarchy(classes: Vec<ClassNode>) -> ClassHierarchy {
+        let mut hierarchy = ClassHierarchy { classes: Vec::new(), lookup: HashMap::new(), subtypes: HashMap::new() };
+        for c in classes {
+            hierarchy.add(c);
+        }
+        hierarchy.link();
+        hierarchy
+    }
+
+    fn name(node: Option<&ClassNode>) -> Option<&str> {
+        node.map(|c| c.name.as_str())
+    }
+
+    #[test]
+    fn resolution() {
+        let h = hierarchy(vec![
+            class("LI;", INTERFACE, OBJECT, &[], &[("i()V", PUBLIC | ABSTRACT)]),
+            class("LA;", 0, OBJECT, &["LI;"], &[("m()V", PUBLIC)]),
+            class("LB;", 0, "LA;", &[], &[]),
+        ]);
+        assert_eq!(name(h.resolve_method("LB;", "m()V")), Some("LA;"));
+        assert_eq!(name(h.resolve_method("LB;", "i()V")), Some("LI;"));
+        assert_eq!(name(h.resolve_method("LB;", "hashCode()I")), Some(OBJECT));
+        // a phantom Object declares only what java.lang.Object does
+        assert_eq!(name(h.resolve_method("LB;", "x()V")), None);
+        assert_eq!(name(h.resolve_interface_method("LI;", "i()V")), Some("LI;"));
+        assert_eq!(name(h.resolve_interface_method("LI;", "hashCode()I")), Some(OBJECT));
+        assert_eq!(name(h.resolve_method("LMissing;", "m()V")), None);
+    }
+
+    #[test]
+    fn dispatch() {
+        let h = hierarchy(vec![
+            class("LI;", INTERFACE, OBJECT, &[], &[("d()V", PUBLIC)]),
+            class("LA;", 0, OBJECT, &["LI;"], &[("m()V", PUBLIC), ("n()V", PUBLIC | ABSTRACT)]),
+            class("LB;", 0, "LA;", &[], &[("n()V", PUBLIC)]),
+            // same signatures, but neither overrides anything
+            class("LC;", 0, "LB;", &[], &[("m()V", PRIVATE), ("n()V", STATIC), ("d()V", PRIVATE)]),
+        ]);
+        assert_eq!(name(h.dispatch("LB;", "m()V")), Some("LA;"));
+        assert_eq!(name(h.dispatch("LB;", "n()V")), Some("LB;"));
+        assert_eq!(name(h.dispatch("LC;", "m()V")), Some("LA;"));
+        assert_eq!(name(h.dispatch("LC;", "n()V")), Some("LB;"));
+        assert_eq!(name(h.dispatch("LC;", "d()V")), Some("LI;"));
+        assert_eq!(name(h.dispatch("LA;", "n()V")), None);
+        assert_eq!(name(h.dispatch("LC;", "toString()Ljava/lang/String;")), Some(OBJECT));
+        assert!(h.overridden_method("LC;", "m()V").is_none());
+        assert_eq!(h.overriding_methods("LA;", "n()V").iter().map(|c| c.name.as_str()).collect::<Vec<&str>>(), vec!["LB;"]);
+    }
+
+    #[test]
+    fn default_methods() {
+        let h = hierarchy(vec![
+            class("LI;", INTERFACE, OBJECT, &[], &[("d()V", PUBLIC)]),
+            class("LJ;", INTERFACE, OBJECT, &["LI;"], &[]),
+            class("LA;", 0, "Lframework/Base;", &["LJ;"], &[]),
+        ]);
+        // the phantom superclass comes first and may declare anything
+        assert_eq!(name(h.dispatch("LA;", "d()V")), Some("Lframework/Base;"));
+
+        let h = hierarchy(vec![
+            class("LI;", INTERFACE, OBJECT, &[], &[("d()V", PUBLIC)]),
+            class("LJ;", INTERFACE, OBJECT, &["LI;"], &[]),
+            class("LA;", 0, "LBase;", &["LJ;"], &[]),
+            class("LBase;", 0, OBJECT, &[], &[]),
+            class("LK;", INTERFACE, OBJECT, &[], &[("d()V", PUBLIC | ABSTRACT)]),
+            class("LB;", 0, OBJECT, &["LK;"], &[]),
+        ]);
+        assert_eq!(name(h.dispatch("LA;", "d()V")), Some("LI;"));
+        assert_eq!(name(h.dispatch("LB;", "d()V")), None);
+        assert_eq!(name(h.resolve_method("LB;", "d()V")), Some("LK;"));
+    }
+
+    #[test]
+    fn phantoms() {
+        let h = hierarchy(vec![
+            class("LA;", 0, "Landroid/app/Activity;", &["Ljava/lang/Runnable;"], &[("onStart()V", PUBLIC)]),
+            class("LB;", 0, OBJECT, &["Ljava/lang/Runnable;"], &[("run()V", PUBLIC), ("hashCode()I", PUBLIC)]),
+        ]);
+        let activity = h.get("Landroid/app/Activity;").unwrap();
+        assert!(activity.phantom);
+        assert!(!activity.is_interface());
+        assert_eq!(activity.superclass.as_deref(), Some(OBJECT));
+        let runnable = h.get("Ljava/lang/Runnable;").unwrap();
+        assert!(runnable.phantom);
+        assert!(runnable.is_interface());
+        assert!(!h.is_concrete("Ljava/lang/Runnable;"));
+        assert!(h.get(OBJECT).unwrap().phantom);
+        assert!(h.get(OBJECT).unwrap().superclass.is_none());
+        assert!(!h.is_concrete("Landroid/app/Activity;"));
+        assert!(h.is_concrete("LA;"));
+
+        let chain = h.superclass_chain("LA;").iter().map(|c| c.name.as_str()).collect::<Vec<&str>>();
+        assert_eq!(chain, vec!["Landroid/app/Activity;", OBJECT]);
+        assert_eq!(name(h.resolve_method("LA;", "onCreate()V")), Some("Landroid/app/Activity;"));
+        assert_eq!(name(h.dispatch("LA;", "run()V")), Some("Landroid/app/Activity;"));
+        let subclasses = h.subclasses_of("Landroid/app/Activity;").iter().map(|c| c.name.as_str()).collect::<Vec<&str>>();
+        assert_eq!(subclasses, vec!["LA;"]);
+
+        // phantoms may declare whatever is overridden, as they may for resolution
+        assert_eq!(name(h.overridden_method("LA;", "onStart()V")), Some("Landroid/app/Activity;"));
+        assert_eq!(name(h.overridden_method("LB;", "run()V")), Some("Ljava/lang/Runnable;"));
+        assert_eq!(name(h.overridden_method("LB;", "hashCode()I")), Some(OBJECT));
+    }
+}
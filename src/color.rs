@@ -0,0 +1,97 @@
+use std::io::IsTerminal;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Opcode,
+    Register,
+    Literal,
+    String,
+    Comment,
+    Label,
+}
+
+impl Style {
+    fn code(self) -> &'static str {
+        match self {
+            Style::Opcode   => "1;34",
+            Style::Register => "33",
+            Style::Literal  => "35",
+            Style::String   => "32",
+            Style::Comment  => "90",
+            Style::Label    => "1;36",
+        }
+    }
+}
+
+// Color is used when stdout is a terminal, unless turned off with
+// --no-color or the NO_COLOR environment variable.
+pub fn use_color(no_color: bool) -> bool {
+    !no_color && std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+}
+
+pub fn paint(style: Style, s: &str) -> String {
+    format!("\x1b[{}m{}\x1b[0m", style.code(), s)
+}
+
+// Styles an instruction as printed by the disassembler, e.g.
+// `invoke-direct {v0}, Ljava/lang/Object;-><init>()V` or `if-eqz v2 +3`.
+// The first word is the opcode; the rest is split into tokens and styled
+// by what they look like, so it works for raw and symbolic operands alike.
+pub fn paint_instruction(text: &str) -> String {
+    let mut result = String::with_capacity(text.len() * 2);
+    let chars = text.char_indices().collect::<Vec<(usize, char)>>();
+    let mut first = true;
+    let mut i = 0;
+    while i < chars.len() {
+        let (start, c) = chars[i];
+        if c == ' ' || c == ',' || c == '{' || c == '}' {
+            result.push(c);
+            i += 1;
+            continue;
+        }
+
+        let mut j = i + 1;
+        if c == '"' {
+            while j < chars.len() && chars[j].1 != '"' {
+                j += if chars[j].1 == '\\' { 2 } else { 1 };
+            }
+            j = (j + 1).min(chars.len());
+        } else {
+            while j < chars.len() && !matches!(chars[j].1, ' ' | ',' | '{' | '}') {
+                j += 1;
+            }
+        }
+        let end = chars.get(j).map(|x| x.0).unwrap_or_else(|| text.len());
+        let token = &text[start..end];
+
+        let style = if first {
+            Some(Style::Opcode)
+        } else if c == '"' {
+            Some(Style::String)
+        } else if c == ':' {
+            Some(Style::Label)
+        } else if is_register(token) {
+            Some(Style::Register)
+        } else if is_literal(token) {
+            Some(Style::Literal)
+        } else {
+            None
+        };
+        match style {
+            Some(s) => result.push_str(&paint(s, token)),
+            None => result.push_str(token),
+        }
+        first = false;
+        i = j;
+    }
+    result
+}
+
+fn is_register(token: &str) -> bool {
+    token.len() > 1 && token.starts_with('v') && token[1..].bytes().all(|b| b.is_ascii_digit())
+}
+
+fn is_literal(token: &str) -> bool {
+    let digits = token.trim_start_matches(['-', '+']);
+    !digits.is_empty() && (digits.starts_with("0x") || digits.bytes().all(|b| b.is_ascii_digit()))
+}
@@ -0,0 +1,455 @@
+use std::borrow::Cow;
+
+use crate::binary_parser::BinaryParser;
+use crate::dex_parser::parse_dex_file;
+use crate::dex_types::DexFile;
+use crate::instructions::InstructionKind;
+use crate::util::to_utf8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContainerKind {
+    Vdex,
+    Oat,
+}
+
+// A dex or cdex file found inside a container.
+pub struct EmbeddedDex<'a> {
+    // `classes.dex`, `classes2.dex`, ... in the order they appear
+    pub name: String,
+    pub offset: usize,
+    // borrowed from the container, unless quickened instructions were restored
+    pub bytes: Cow<'a, [u8]>,
+}
+
+// Since version 027 a vdex starts with a table of sections.
+const VDEX_SECTIONED_VERSION: u32 = 27;
+const VDEX_DEX_SECTION: u32 = 1;
+// The only version whose quickening info layout is read.
+const VDEX_QUICKENING_VERSION: u32 = 6;
+
+pub fn container_kind(buffer: &[u8]) -> Option<ContainerKind> {
+    match buffer.get(0..4)? {
+        b"vdex" => Some(ContainerKind::Vdex),
+        b"\x7fELF" => Some(ContainerKind::Oat),
+        _ => None,
+    }
+}
+
+// Every dex file in a vdex or oat file, empty if it only holds compiled
+// code or verification data for dex files kept in the apk. With
+// `unquicken`, quickened instructions in a vdex are restored to the ones
+// they replaced.
+pub fn extract_dex_files(buffer: &[u8], unquicken: bool) -> Vec<EmbeddedDex<'_>> {
+    let mut p = BinaryParser::new(buffer);
+    let FoundDexFiles { ranges, quickening_info } = match find_dex_files(&mut p, unquicken) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("warning: cannot read the container: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut result: Vec<EmbeddedDex> = Vec::new();
+    for (i, (offset, size)) in ranges.into_iter().enumerate() {
+        match offset.checked_add(size).and_then(|end| buffer.get(offset..end)) {
+            Some(bytes) => result.push(EmbeddedDex { name: dex_file_name(i), offset, bytes: Cow::Borrowed(bytes) }),
+            None => eprintln!("warning: {} at {:#x} runs past the end of the container", dex_file_name(i), offset),
+        }
+    }
+    if let Some(info) = quickening_info {
+        restore_quickened(&mut result, info);
+    }
+    result
+}
+
+// The offset and size of each dex file, and the quickening info if it is
+// to be restored.
+struct FoundDexFiles<'a> {
+    ranges: Vec<(usize, usize)>,
+    quickening_info: Option<&'a [u8]>,
+}
+
+fn find_dex_files<'a>(p: &mut BinaryParser<'a>, unquicken: bool) -> Result<FoundDexFiles<'a>, String> {
+    let len = p.len();
+    let mut quickening_info = None;
+    let found = match container_kind(p.buffer()) {
+        Some(ContainerKind::Vdex) => {
+            p.seek_to(4);
+            let version = to_utf8(p.take(3)?).parse::<u32>().unwrap_or(0);
+            if unquicken && version == VDEX_QUICKENING_VERSION {
+                match find_quickening_info(p) {
+                    Ok(info) => quickening_info = Some(info),
+                    Err(e) => eprintln!("warning: cannot read the quickening info: {}", e),
+                }
+            } else if unquicken && version < VDEX_SECTIONED_VERSION {
+                eprintln!("warning: quickened instructions can only be restored from vdex version {:03}, not {:03}",
+                    VDEX_QUICKENING_VERSION, version);
+            }
+            if version >= VDEX_SECTIONED_VERSION {
+                p.seek_to(8);
+                let sections = p.read_u32()?;
+                let mut dex_section = None;
+                for _ in 0..sections {
+                    let kind = p.read_u32()?;
+                    let offset = p.read_u32()? as usize;
+                    let size = p.read_u32()? as usize;
+                    if kind == VDEX_DEX_SECTION {
+                        dex_section = Some((offset, offset.saturating_add(size)));
+                    }
+                }
+                match dex_section {
+                    Some((start, end)) => scan_dex_files(p, start, end.min(len)),
+                    None => Vec::new(),
+                }
+            } else {
+                // older versions differ in what comes between the header and
+                // the dex files, but the dex files themselves are 4-byte aligned
+                scan_dex_files(p, 12, len)
+            }
+        }
+        Some(ContainerKind::Oat) => match elf_section(p, ".rodata") {
+            Some((start, end)) => scan_dex_files(p, start, end.min(len)),
+            None => scan_dex_files(p, 0, len),
+        },
+        None => Vec::new(),
+    };
+    Ok(FoundDexFiles { ranges: found, quickening_info })
+}
+
+fn find_quickening_info<'a>(p: &mut BinaryParser<'a>) -> Result<&'a [u8], String> {
+    // number_of_dex_files, dex_size, verifier_deps_size,
+    // quickening_info_size, then a checksum per dex file
+    p.seek_to(8);
+    let dex_files = p.read_u32()? as usize;
+    let dex_size = p.read_u32()? as usize;
+    let verifier_deps_size = p.read_u32()? as usize;
+    let quickening_info_size = p.read_u32()? as usize;
+    let start = dex_files.checked_mul(4)
+        .and_then(|x| x.checked_add(0x18 + dex_size))
+        .and_then(|x| x.checked_add(verifier_deps_size))
+        .ok_or("it starts past the end of the file")?;
+    p.seek_to(start);
+    p.take(quickening_info_size)
+}
+
+// The standard opcode a quickened one replaced.
+fn unquickened_opcode(opcode: u8) -> Option<u8> {
+    let x = match opcode {
+        0x73 => 0x0e, // return-void-no-barrier
+        0xe3 => 0x52, // iget-quick
+        0xe4 => 0x53,
+        0xe5 => 0x54,
+        0xe6 => 0x59, // iput-quick
+        0xe7 => 0x5a,
+        0xe8 => 0x5b,
+        0xe9 => 0x6e, // invoke-virtual-quick
+        0xea => 0x74,
+        0xeb => 0x5c, // iput-boolean-quick
+        0xec => 0x5d,
+        0xed => 0x5e,
+        0xee => 0x5f,
+        0xef => 0x55, // iget-boolean-quick
+        0xf0 => 0x56,
+        0xf1 => 0x57,
+        0xf2 => 0x58,
+        _ => return None,
+    };
+    Some(x)
+}
+
+// The quickening info holds, for every method with code in class def
+// order, direct methods first, a u32 size and then (dex_pc, index) uleb128
+// pairs: the field or method index each quickened instruction replaced.
+// A check-cast that was found redundant and replaced by two nops has two
+// pairs at its dex_pc, its register and then its type index.
+fn restore_quickened(dex_files: &mut [EmbeddedDex<'_>], info: &[u8]) {
+    let mut info = BinaryParser::new(info);
+    for e in dex_files.iter_mut() {
+        // the parsed file borrows the bytes, so patch a copy
+        let mut patched = e.bytes.to_vec();
+        let result = match parse_dex_file(&mut BinaryParser::new(&e.bytes)) {
+            Ok(dex) => restore_quickened_dex(&mut patched, &dex, &mut info),
+            Err(e) => Err(e),
+        };
+        if let Err(err) = result {
+            eprintln!("warning: cannot restore the quickened instructions of {}: {}", e.name, err);
+            return;
+        }
+        e.bytes = Cow::Owned(patched);
+    }
+}
+
+fn restore_quickened_dex(bytes: &mut [u8], dex: &DexFile, info: &mut BinaryParser) -> Result<(), String> {
+    for c in dex.classes.iter() {
+        for m in c.methods()? {
+            let code = match m.code_item()? {
+                Some(code) => code,
+                None => continue,
+            };
+            if info.current_location() + 4 > info.len() {
+                return Err(format!("the quickening info ends before {}", dex.method_signature(m.method_idx)));
+            }
+
+            let size = info.read_u32()? as usize;
+            let end = info.current_location() + size;
+            while info.current_location() < end {
+                let dex_pc = info.parse_uleb128()? as usize;
+                let index = info.parse_uleb128()? as u16;
+                let addr = code.instructions_addr as usize + dex_pc * 2;
+                if addr + 4 > bytes.len() {
+                    eprintln!("warning: no quickened instruction at {:#x} in {}", addr, dex.method_signature(m.method_idx));
+                    continue;
+                }
+                match bytes[addr] {
+                    0x00 => {
+                        let type_pc = info.parse_uleb128()? as usize;
+                        let type_idx = info.parse_uleb128()? as u16;
+                        if type_pc != dex_pc || index > 0xff {
+                            eprintln!("warning: no check-cast to restore at {:#x} in {}", addr, dex.method_signature(m.method_idx));
+                            continue;
+                        }
+                        bytes[addr] = 0x1f;
+                        bytes[addr + 1] = index as u8;
+                        bytes[addr + 2..addr + 4].copy_from_slice(&type_idx.to_le_bytes());
+                    }
+                    x => match unquickened_opcode(x) {
+                        Some(opcode) => {
+                            bytes[addr] = opcode;
+                            bytes[addr + 2..addr + 4].copy_from_slice(&index.to_le_bytes());
+                        }
+                        None => eprintln!("warning: no quickened instruction at {:#x} in {}", addr, dex.method_signature(m.method_idx)),
+                    },
+                }
+            }
+            info.seek_to(end);
+
+            // return-void-no-barrier has no entry, it only ever replaces return-void
+            for i in code.instructions.iter() {
+                if i.kind == InstructionKind::ReturnVoidNoBarrier {
+                    bytes[i.addr] = 0x0e;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn dex_file_name(i: usize) -> String {
+    match i {
+        0 => "classes.dex".to_string(),
+        i => format!("classes{}.dex", i + 1),
+    }
+}
+
+// The offset and size of every dex file header at a 4-byte aligned
+// position in [start, end) whose sizes are consistent with the buffer.
+fn scan_dex_files(p: &mut BinaryParser, start: usize, end: usize) -> Vec<(usize, usize)> {
+    let mut result: Vec<(usize, usize)> = Vec::new();
+    let mut offset = (start + 3) & !3;
+    while offset + 0x70 <= end {
+        match dex_file_size(p, offset, end) {
+            Some((file_size, size)) => {
+                result.push((offset, size));
+                offset = (offset + file_size + 3) & !3;
+            }
+            None => offset += 4,
+        }
+    }
+    result
+}
+
+// The file_size in the header, which is where the next dex file can start,
+// and how many bytes from `offset` the file needs, which for a cdex reaches
+// on into the data section it shares with the ones after it.
+fn dex_file_size(p: &mut BinaryParser, offset: usize, end: usize) -> Option<(usize, usize)> {
+    p.seek_to(offset);
+    let magic = p.take(8).ok()?;
+    let is_compact = match &magic[0..4] {
+        b"dex\n" => false,
+        b"cdex" => true,
+        _ => return None,
+    };
+    if !magic[4..7].iter().all(|b| b.is_ascii_digit()) || magic[7] != 0 {
+        return None;
+    }
+
+    p.seek_to(offset + 0x20);
+    let file_size = p.read_u32().ok()? as usize;
+    let header_size = p.read_u32().ok()? as usize;
+    if header_size < 0x70 || file_size < header_size || offset + file_size > end {
+        return None;
+    }
+
+    // the data section of a cdex in a vdex is shared with the other dex
+    // files and comes after all of them, at data_off from this header
+    if is_compact {
+        p.seek_to(offset + 0x68);
+        let data_size = p.read_u32().ok()? as usize;
+        let data_offset = p.read_u32().ok()? as usize;
+        let data_end = data_offset + data_size;
+        if data_end > file_size && offset + data_end <= p.len() {
+            return Some((file_size, data_end));
+        }
+    }
+    Some((file_size, file_size))
+}
+
+// The file offset range of a section in a little endian ELF file.
+fn elf_section(p: &mut BinaryParser, name: &str) -> Option<(usize, usize)> {
+    p.seek_to(4);
+    let is_64 = match p.next().ok()? {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    let word = |p: &mut BinaryParser| -> Option<usize> {
+        if is_64 {
+            let low = p.read_u32().ok()? as u64;
+            let high = p.read_u32().ok()? as u64;
+            Some((high << 32 | low) as usize)
+        } else {
+            Some(p.read_u32().ok()? as usize)
+        }
+    };
+    let half = |p: &mut BinaryParser| -> Option<usize> { Some(p.read_u16().ok()? as usize) };
+
+    p.seek_to(if is_64 { 0x28 } else { 0x20 });
+    let section_headers = word(p)?;
+    p.seek_to(if is_64 { 0x3a } else { 0x2e });
+    let entry_size = half(p)?;
+    let count = half(p)?;
+    let names_index = half(p)?;
+    let end = section_headers.checked_add(entry_size * count)?;
+    if section_headers == 0 || end > p.len() {
+        return None;
+    }
+
+    // sh_name, sh_type, sh_flags, sh_addr, sh_offset, sh_size
+    let section = |p: &mut BinaryParser, i: usize| -> Option<(usize, usize, usize)> {
+        p.seek_to(section_headers + i * entry_size);
+        let name = p.read_u32().ok()? as usize;
+        let _type = p.take(4).ok()?;
+        let _flags = word(p)?;
+        let _addr = word(p)?;
+        let offset = word(p)?;
+        let size = word(p)?;
+        Some((name, offset, size))
+    };
+    let (_, names_offset, _) = section(p, names_index)?;
+    for i in 0..count {
+        let (name_offset, offset, size) = section(p, i)?;
+        p.seek_to(names_offset.checked_add(name_offset)?);
+        if to_utf8(p.take_until(0).ok()?) == name {
+            return Some((offset, offset.checked_add(size)?));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::InstructionKind;
+
+    fn write_u32(buffer: &mut [u8], offset: usize, value: u32) {
+        buffer[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    // A cdex header that is all of its file_size, with data_off pointing
+    // at the data section shared by every cdex in the container.
+    fn write_cdex(buffer: &mut [u8], offset: usize, data: usize, data_size: u32) {
+        buffer[offset..offset + 8].copy_from_slice(b"cdex001\0");
+        write_u32(buffer, offset + 0x20, 0x70);
+        write_u32(buffer, offset + 0x24, 0x70);
+        write_u32(buffer, offset + 0x68, data_size);
+        write_u32(buffer, offset + 0x6c, (data - offset) as u32);
+    }
+
+    // A dex file with one class whose one method has `code` as its body,
+    // with no map and only the ids the method needs.
+    fn dex_with_code(code: &[u16]) -> Vec<u8> {
+        let mut dex = vec![0u8; 0xc0];
+        dex[0..8].copy_from_slice(b"dex\n035\0");
+        write_u32(&mut dex, 0x24, 0x70);
+        write_u32(&mut dex, 0x28, 0x12345678);
+        for (n, (size, offset)) in [(1, 0x70), (1, 0x74), (1, 0x78), (0, 0), (1, 0x84), (1, 0x8c)].iter().enumerate() {
+            write_u32(&mut dex, 0x38 + n * 8, *size);
+            write_u32(&mut dex, 0x3c + n * 8, *offset);
+        }
+        write_u32(&mut dex, 0x70, 0xac);
+        // class def: LA;, no superclass, class data at 0xb4
+        write_u32(&mut dex, 0x8c, 0);
+        write_u32(&mut dex, 0x94, 0xffffffff);
+        write_u32(&mut dex, 0x9c, 0xffffffff);
+        write_u32(&mut dex, 0xa4, 0xb4);
+        dex[0xac..0xb1].copy_from_slice(b"\x03LA;\0");
+        // one direct method, public, code at 0xc0
+        dex[0xb4..0xbb].copy_from_slice(&[0, 0, 1, 0, 0, 1, 0xc0]);
+        dex[0xbb] = 0x01;
+
+        let mut item = [1u16, 0, 0, 0, 0, 0, code.len() as u16, 0].to_vec();
+        item.extend_from_slice(code);
+        dex.extend(item.iter().flat_map(|x| x.to_le_bytes()));
+        let len = dex.len() as u32;
+        write_u32(&mut dex, 0x20, len);
+        dex
+    }
+
+    #[test]
+    fn unquicken() {
+        let dex = dex_with_code(&[
+            0x10e3, 0x0008,         // iget-quick v0, v1, [obj+0x8]
+            0x10e9, 0x0002, 0x0001, // invoke-virtual-quick {v1}, vtable@2
+            0x10e6, 0x000c,         // iput-quick v0, v1, [obj+0xc]
+            0x0000, 0x0000,         // check-cast v2, type@4, elided
+            0x0073,                 // return-void-no-barrier
+        ]);
+        // (dex_pc, index) pairs, the check-cast has its register and type
+        let info = [0, 5, 2, 7, 5, 3, 7, 2, 7, 4];
+
+        let mut vdex = vec![0u8; 0x1c];
+        vdex[0..8].copy_from_slice(b"vdex006\0");
+        write_u32(&mut vdex, 8, 1);
+        write_u32(&mut vdex, 12, dex.len() as u32);
+        write_u32(&mut vdex, 20, 4 + info.len() as u32);
+        vdex.extend_from_slice(&dex);
+        vdex.extend_from_slice(&(info.len() as u32).to_le_bytes());
+        vdex.extend_from_slice(&info);
+
+        let found = extract_dex_files(&vdex, true);
+        assert_eq!(found.len(), 1);
+        let restored = parse_dex_file(&mut BinaryParser::new(&found[0].bytes)).unwrap();
+        let methods = restored.classes[0].methods().unwrap().collect::<Vec<_>>();
+        let code = methods[0].code_item().unwrap().unwrap();
+        let kinds = code.instructions.iter().map(|i| &i.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![
+            &InstructionKind::IGet(0, 1, 5),
+            &InstructionKind::InvokeVirtual(vec![1], 7),
+            &InstructionKind::IPut(0, 1, 3),
+            &InstructionKind::CheckCast(2, 4),
+            &InstructionKind::ReturnVoid,
+        ]);
+
+        // without --unquicken the bytes are left as they are
+        let found = extract_dex_files(&vdex, false);
+        assert_eq!(&found[0].bytes[..], &dex[..]);
+    }
+
+    #[test]
+    fn compact_multidex() {
+        // a version 027 vdex whose one section is the dex files, two cdex
+        // headers and the 0x10 bytes of data after them
+        let mut vdex = vec![0u8; 0x110];
+        vdex[0..8].copy_from_slice(b"vdex027\0");
+        write_u32(&mut vdex, 8, 1);
+        write_u32(&mut vdex, 12, VDEX_DEX_SECTION);
+        write_u32(&mut vdex, 16, 0x20);
+        write_u32(&mut vdex, 20, 0xf0);
+        write_cdex(&mut vdex, 0x20, 0x100, 0x10);
+        write_cdex(&mut vdex, 0x90, 0x100, 0x10);
+
+        let found = extract_dex_files(&vdex, false);
+        let found = found.iter().map(|e| (e.name.as_str(), e.offset, e.bytes.len())).collect::<Vec<_>>();
+        assert_eq!(found, vec![("classes.dex", 0x20, 0xf0), ("classes2.dex", 0x90, 0x80)]);
+    }
+}
@@ -0,0 +1,2012 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fmt::Display;
+
+use crate::analysis::{control_flow_graph, dominators, Block, Graph};
+use crate::dex_parser::parse_type_descriptor;
+use crate::dex_types::*;
+use crate::instructions::*;
+use crate::printer::get_type_descriptor_string;
+use crate::type_inference::{RegisterType, RegisterTypes, TypeInference};
+
+// Turns method bodies back into Java source. Each block is translated into
+// statements, folding single-use registers into the expressions that read
+// them, and the control flow graph is then structured into if/while/for/
+// switch/try using dominators and post-dominators. Anything that does not
+// fit those shapes is reported as an error so the caller can fall back to
+// disassembly.
+pub struct Decompiler<'a> {
+    strings: &'a [String],
+    types: &'a [DexType],
+    protos: &'a [DexProto],
+    fields: &'a [DexField],
+    methods: &'a [DexMethod],
+    inference: TypeInference<'a>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Var(String),
+    Literal(String),
+    Unary(&'static str, Box<Expr>),
+    Cast(String, Box<Expr>),
+    Binary(Box<Expr>, &'static str, Box<Expr>),
+    InstanceOf(Box<Expr>, String),
+    Field(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Length(Box<Expr>),
+    // no receiver for `super(...)` and `this(...)` in constructors
+    Call(Option<Box<Expr>>, String, Vec<Expr>),
+    New(String, Vec<Expr>),
+    // the array type and its length
+    NewArray(String, Box<Expr>),
+    ArrayInit(String, Vec<Expr>),
+    // the result of a cmp-* instruction, usually folded into an if-*z
+    Compare(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Statement {
+    Expr(Expr),
+    Assign(Expr, Expr),
+    Return(Option<Expr>),
+    Throw(Expr),
+    If(Expr, Vec<Statement>, Vec<Statement>),
+    While(Expr, Vec<Statement>),
+    DoWhile(Vec<Statement>, Expr),
+    For(Box<Statement>, Expr, Box<Statement>, Vec<Statement>),
+    Switch(Expr, Vec<Case>),
+    Try(Vec<Statement>, Vec<Catch>),
+    // a local's type in front of its first assignment
+    Declare(String, Box<Statement>),
+    Break,
+    Continue,
+    Comment(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Case {
+    keys: Vec<i32>,
+    default: bool,
+    body: Vec<Statement>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Catch {
+    exception_type: String,
+    variable: String,
+    body: Vec<Statement>,
+}
+
+// How control leaves a translated block.
+#[derive(Debug, Clone)]
+enum Terminator {
+    Next(usize),
+    // condition, block when it holds, block when it does not
+    Branch(Expr, usize, usize),
+    Switch(Expr, Vec<(i32, usize)>, usize),
+    Exit,
+}
+
+struct TranslatedBlock {
+    statements: Vec<Statement>,
+    terminator: Terminator,
+}
+
+// A register written by an instruction, as far as inlining is concerned.
+struct Def {
+    register: Register,
+    // every register the instruction overwrites, both halves for wide values
+    kills: Vec<Register>,
+    reads: Vec<Register>,
+    pure: bool,
+    // an <init> call completing a new-instance; it defines the receiver
+    init: bool,
+    // where the type inference records the written type
+    type_addr: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    // single use in the same block: fold into the reader
+    Defer,
+    // never read
+    Dead,
+    Assign,
+}
+
+struct Pending {
+    expr: Expr,
+    index: usize,
+    pure: bool,
+}
+
+#[derive(Clone, Default)]
+struct Context {
+    stops: Vec<usize>,
+    loop_header: Option<usize>,
+    loop_follow: Option<usize>,
+    tries: Vec<usize>,
+}
+
+struct Loop {
+    nodes: HashSet<usize>,
+    follow: Option<usize>,
+}
+
+// Context for decompiling a single method.
+struct MethodDecompiler<'a, 'b> {
+    d: &'b Decompiler<'a>,
+    code: &'b CodeItem<'a>,
+    graph: Graph<Block<'a>>,
+    types: Option<RegisterTypes>,
+    class_idx: TypeIndex,
+    is_constructor: bool,
+    return_type: String,
+    // `this` and the parameters
+    names: HashMap<Register, String>,
+    // invoke or filled-new-array address to the register of the move-result after it
+    results: HashMap<usize, Register>,
+    // move-result instructions folded into the instruction before them
+    skip: HashSet<usize>,
+    block_of_addr: HashMap<usize, usize>,
+    defs: Vec<Vec<Option<Def>>>,
+    absorbed: Vec<HashSet<usize>>,
+    live_out: Vec<HashSet<Register>>,
+    declared: BTreeMap<Register, String>,
+    blocks: Vec<TranslatedBlock>,
+    ipdom: Vec<Option<usize>>,
+    loops: HashMap<usize, Loop>,
+    emitted: HashSet<usize>,
+}
+
+impl<'a> Decompiler<'a> {
+    pub fn new(
+        strings: &'a [String],
+        types: &'a [DexType],
+        protos: &'a [DexProto],
+        fields: &'a [DexField],
+        methods: &'a [DexMethod],
+        classes: &'a [DexClassDef],
+        call_sites: &'a [Vec<EncodedValue>],
+    ) -> Decompiler<'a> {
+        let inference = TypeInference::new(strings, types, protos, fields, methods, classes, call_sites);
+        Decompiler { strings, types, protos, fields, methods, inference }
+    }
+
+    // The body of a method as lines of Java, without the enclosing braces.
+    pub fn decompile(&self, method: &EncodedMethod) -> Result<Vec<String>, String> {
+        // not cached, `decompile` reads every method once
+        let code = match method.decode_code_item()? {
+            Some(code) => code,
+            None => return Ok(Vec::new()),
+        };
+        let code = &*code;
+        let graph = control_flow_graph(code);
+        if graph.nodes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut m = MethodDecompiler::new(self, method, code, graph)?;
+        m.plan()?;
+        for b in 0..m.graph.nodes.len() {
+            let block = m.translate_block(b)?;
+            m.blocks.push(block);
+        }
+        m.find_loops();
+
+        let (mut statements, _) = m.region(Some(0), &Context::default())?;
+        let reachable = reachable(&m.graph);
+        if let Some(b) = reachable.iter().find(|b| !m.emitted.contains(b)) {
+            return Err(format!("could not structure the block at {:#x}", m.graph.nodes[*b].starting_addr));
+        }
+
+        statements = simplify(statements);
+        if statements.last() == Some(&Statement::Return(None)) {
+            statements.pop();
+        }
+
+        let mut lines: Vec<String> = Vec::new();
+        for (r, t) in m.declared.iter() {
+            if !declare_at_first_assignment(&mut statements, t, &m.name(*r)) {
+                lines.push(format!("{} {};", t, m.name(*r)));
+            }
+        }
+        render(&statements, 0, &mut lines);
+        Ok(lines)
+    }
+
+    fn type_name(&self, idx: TypeIndex) -> String {
+        match self.types.get(idx) {
+            Some(t) => get_type_descriptor_string(&t.parsed),
+            None => format!("type@{:#x}", idx),
+        }
+    }
+
+    fn descriptor(&self, idx: TypeIndex) -> Result<&str, String> {
+        match self.types.get(idx) {
+            Some(t) => Ok(&t.raw),
+            None => Err(format!("type@{:#x} is out of range", idx)),
+        }
+    }
+
+    // The method id and its prototype.
+    fn method(&self, idx: MethodIndex) -> Result<(&DexMethod, &DexProto), String> {
+        match self.methods.get(idx).and_then(|m| Some((m, self.protos.get(m.proto_idx)?))) {
+            Some(x) => Ok(x),
+            None => Err(format!("method@{:#x} is out of range", idx)),
+        }
+    }
+
+    fn method_name(&self, idx: MethodIndex) -> Option<&str> {
+        self.methods.get(idx).and_then(|m| self.strings.get(m.name_idx)).map(|x| x.as_str())
+    }
+}
+
+impl<'a, 'b> MethodDecompiler<'a, 'b> {
+    fn new(d: &'b Decompiler<'a>, method: &'b EncodedMethod, code: &'b CodeItem<'a>, graph: Graph<Block<'a>>) -> Result<MethodDecompiler<'a, 'b>, String> {
+        let (method_id, proto) = d.method(method.method_idx)?;
+        let is_static = method.access_flags & MethodAccessLevel::Static as u32 != 0;
+
+        let mut names: HashMap<Register, String> = HashMap::new();
+        let mut r = code.registers_size.saturating_sub(code.ins_size) as Register;
+        if !is_static {
+            names.insert(r, "this".to_string());
+            r += 1;
+        }
+        for (n, p) in proto.parameter_type_idx_list.iter().enumerate() {
+            let name = match code.parameter_names.get(n) {
+                Some(Some(idx)) if *idx < d.strings.len() => d.strings[*idx].clone(),
+                _ => format!("arg{}", n),
+            };
+            names.insert(r, name);
+            r += if is_wide(d.descriptor(*p)?) { 2 } else { 1 };
+        }
+
+        let instructions = code.instructions.iter()
+            .filter(|i| !crate::analysis::is_payload(&i.kind))
+            .collect::<Vec<&Instruction>>();
+        let mut results: HashMap<usize, Register> = HashMap::new();
+        let mut skip: HashSet<usize> = HashSet::new();
+        for pair in instructions.windows(2) {
+            let register = match pair[1].kind {
+                  InstructionKind::MoveResult(r)
+                | InstructionKind::MoveResultWide(r)
+                | InstructionKind::MoveResultObject(r) => r,
+                _ => continue,
+            };
+            if invoke_parts(&pair[0].kind).is_some() || matches!(pair[0].kind, InstructionKind::FilledNewArray(..) | InstructionKind::FilledNewArrayRange(..)) {
+                results.insert(pair[0].addr, register);
+                skip.insert(pair[1].addr);
+            }
+        }
+
+        let block_of_addr = graph.nodes.iter().enumerate()
+            .map(|(n, b)| (b.starting_addr, n))
+            .collect::<HashMap<usize, usize>>();
+
+        Ok(MethodDecompiler {
+            d,
+            code,
+            types: d.inference.infer(method)?,
+            class_idx: method_id.class_idx,
+            is_constructor: d.method_name(method.method_idx) == Some("<init>"),
+            return_type: d.descriptor(proto.return_type_idx)?.to_string(),
+            names,
+            results,
+            skip,
+            block_of_addr,
+            defs: Vec::new(),
+            absorbed: Vec::new(),
+            live_out: Vec::new(),
+            declared: BTreeMap::new(),
+            blocks: Vec::new(),
+            ipdom: Vec::new(),
+            loops: HashMap::new(),
+            emitted: HashSet::new(),
+            graph,
+        })
+    }
+
+    fn name(&self, r: Register) -> String {
+        match self.names.get(&r) {
+            Some(name) => name.clone(),
+            None => format!("v{}", r),
+        }
+    }
+
+    fn var(&self, r: Register) -> Expr {
+        Expr::Var(self.name(r))
+    }
+
+    // Finds what every instruction defines, then the registers live out of
+    // each block, so translation knows which definitions can be folded.
+    fn plan(&mut self) -> Result<(), String> {
+        for b in 0..self.graph.nodes.len() {
+            let (defs, absorbed) = self.block_defs(b)?;
+            self.defs.push(defs);
+            self.absorbed.push(absorbed);
+        }
+
+        let count = self.graph.nodes.len();
+        let mut used: Vec<HashSet<Register>> = vec![HashSet::new(); count];
+        let mut killed: Vec<HashSet<Register>> = vec![HashSet::new(); count];
+        for b in 0..count {
+            for j in 0..self.graph.nodes[b].instructions.len() {
+                for r in self.uses(b, j) {
+                    if !killed[b].contains(&r) {
+                        used[b].insert(r);
+                    }
+                }
+                killed[b].extend(self.kills(b, j));
+            }
+        }
+
+        let mut live_in: Vec<HashSet<Register>> = used.clone();
+        self.live_out = vec![HashSet::new(); count];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for b in (0..count).rev() {
+                let mut out: HashSet<Register> = HashSet::new();
+                for s in self.graph.successors(b) {
+                    out.extend(live_in[*s].iter().cloned());
+                }
+                let mut inn = used[b].clone();
+                inn.extend(out.iter().filter(|r| !killed[b].contains(r)).cloned());
+                if inn != live_in[b] || out != self.live_out[b] {
+                    live_in[b] = inn;
+                    self.live_out[b] = out;
+                    changed = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn block_defs(&self, b: usize) -> Result<(Vec<Option<Def>>, HashSet<usize>), String> {
+        let ins = &self.graph.nodes[b].instructions;
+        let mut defs: Vec<Option<Def>> = Vec::new();
+        for i in ins.iter() {
+            defs.push(self.def_of(i)?);
+        }
+
+        // a new-instance and its <init> call become one `new T(...)`
+        let mut absorbed: HashSet<usize> = HashSet::new();
+        for (k, i) in ins.iter().enumerate() {
+            let r = match i.kind {
+                InstructionKind::NewInstance(r, _) => r,
+                _ => continue,
+            };
+            for (j, x) in ins.iter().enumerate().skip(k + 1) {
+                if let Some((regs, m, "direct")) = invoke_parts(&x.kind) {
+                    if regs.first() == Some(&r) && self.d.method_name(m) == Some("<init>") {
+                        defs[j] = Some(Def {
+                            register: r,
+                            kills: vec![r],
+                            reads: x.kind.used_registers(),
+                            pure: false,
+                            init: true,
+                            type_addr: x.addr,
+                        });
+                        absorbed.insert(k);
+                        break;
+                    }
+                }
+                if x.kind.used_registers().contains(&r) || x.kind.defined_registers().contains(&r) {
+                    break;
+                }
+            }
+        }
+        Ok((defs, absorbed))
+    }
+
+    fn def_of(&self, i: &Instruction) -> Result<Option<Def>, String> {
+        if self.skip.contains(&i.addr) {
+            return Ok(None);
+        }
+        let (register, kills, type_addr) = match self.results.get(&i.addr) {
+            Some(r) => {
+                let next = match self.code.instructions.iter().find(|x| x.addr == i.addr + i.bytecode.len()) {
+                    Some(x) => x,
+                    None => return Err(format!("no move-result after {:#x}", i.addr)),
+                };
+                (*r, next.kind.defined_registers(), next.addr)
+            }
+            None => {
+                if invoke_parts(&i.kind).is_some() {
+                    return Ok(None);
+                }
+                let kills = i.kind.defined_registers();
+                match kills.first() {
+                    Some(r) => (*r, kills.clone(), i.addr),
+                    None => return Ok(None),
+                }
+            }
+        };
+        if matches!(i.kind, InstructionKind::NewInstance(..)) {
+            return Ok(None);
+        }
+        Ok(Some(Def { register, kills, reads: i.kind.used_registers(), pure: is_pure(&i.kind), init: false, type_addr }))
+    }
+
+    fn uses(&self, b: usize, j: usize) -> Vec<Register> {
+        let i = &self.graph.nodes[b].instructions[j];
+        if self.skip.contains(&i.addr) || self.absorbed[b].contains(&j) {
+            return Vec::new();
+        }
+        let mut used = i.kind.used_registers();
+        if let Some(Some(def)) = self.defs[b].get(j) {
+            if def.init {
+                // the receiver of a folded <init> is the object being built
+                if let Some(p) = used.iter().position(|r| *r == def.register) {
+                    used.remove(p);
+                }
+            }
+        }
+        used
+    }
+
+    fn kills(&self, b: usize, j: usize) -> Vec<Register> {
+        let i = &self.graph.nodes[b].instructions[j];
+        if self.skip.contains(&i.addr) {
+            return Vec::new();
+        }
+        match &self.defs[b][j] {
+            Some(def) => def.kills.clone(),
+            None => i.kind.defined_registers(),
+        }
+    }
+
+    fn is_pure_at(&self, b: usize, j: usize) -> bool {
+        let i = &self.graph.nodes[b].instructions[j];
+        if self.skip.contains(&i.addr) || self.absorbed[b].contains(&j) || matches!(i.kind, InstructionKind::Nop) {
+            return true;
+        }
+        match &self.defs[b][j] {
+            Some(def) => def.pure,
+            None => false,
+        }
+    }
+
+    fn action(&self, b: usize, k: usize) -> Result<Action, String> {
+        let def = self.def(b, k)?;
+        let r = def.register;
+        let count = self.graph.nodes[b].instructions.len();
+
+        let mut uses = 0;
+        let mut first_use: Option<usize> = None;
+        let mut killed = false;
+        for j in k + 1..count {
+            let n = self.uses(b, j).iter().filter(|x| **x == r).count();
+            if n > 0 && first_use.is_none() {
+                first_use = Some(j);
+            }
+            uses += n;
+            if self.kills(b, j).contains(&r) {
+                killed = true;
+                break;
+            }
+        }
+
+        let live = !killed && self.live_out[b].contains(&r);
+        if uses == 0 && !live {
+            return Ok(Action::Dead);
+        }
+        if uses != 1 || live || self.names.contains_key(&r) {
+            return Ok(Action::Assign);
+        }
+
+        let u = match first_use {
+            Some(u) => u,
+            None => return Err(format!("v{} is used once but nowhere", r)),
+        };
+        for j in k + 1..u {
+            if self.kills(b, j).iter().any(|x| def.reads.contains(x)) {
+                return Ok(Action::Assign);
+            }
+            if !def.pure && !self.is_pure_at(b, j) {
+                return Ok(Action::Assign);
+            }
+        }
+        Ok(Action::Defer)
+    }
+
+    // What the instruction at `k` in block `b` defines, which the caller
+    // knows it does.
+    fn def(&self, b: usize, k: usize) -> Result<&Def, String> {
+        match self.defs.get(b).and_then(|x| x.get(k)) {
+            Some(Some(def)) => Ok(def),
+            _ => Err(format!("the instruction at {:#x} defines nothing", self.graph.nodes[b].instructions[k].addr)),
+        }
+    }
+
+    fn type_written(&self, addr: usize, r: Register) -> Option<RegisterType> {
+        let annotations = self.types.as_ref()?.annotations.get(&addr)?;
+        annotations.iter().rev().find(|(x, _)| *x == r).map(|(_, t)| t.clone())
+    }
+
+    fn type_before(&self, addr: usize, r: Register) -> Option<RegisterType> {
+        self.types.as_ref()?.before.get(&addr)?.get(r as usize).cloned()
+    }
+
+    fn read(&self, pending: &mut HashMap<Register, Pending>, r: Register) -> Expr {
+        match pending.remove(&r) {
+            Some(p) => p.expr,
+            None => self.var(r),
+        }
+    }
+
+    fn define(&mut self, st: &mut BlockState, k: usize, expr: Expr) -> Result<(), String> {
+        let (r, pure, type_addr) = {
+            let def = self.def(st.block, k)?;
+            (def.register, def.pure, def.type_addr)
+        };
+        let written = self.type_written(type_addr, r);
+        let expr = coerce(expr, written.as_ref().map(hint_of_register_type).unwrap_or(Hint::Other));
+        match self.action(st.block, k)? {
+            Action::Defer => {
+                st.pending.insert(r, Pending { expr, index: k, pure });
+            }
+            Action::Dead => {
+                if matches!(expr, Expr::Call(..) | Expr::New(..)) {
+                    st.statements.push(Statement::Expr(expr));
+                }
+            }
+            Action::Assign => {
+                if !self.names.contains_key(&r) && !self.declared.contains_key(&r) {
+                    let type_string = match &written {
+                        Some(t) => java_type(t),
+                        None => "int".to_string(),
+                    };
+                    self.declared.insert(r, type_string);
+                }
+                st.statements.push(Statement::Assign(self.var(r), expr));
+            }
+        }
+        Ok(())
+    }
+
+    fn block_of(&self, addr: usize) -> Result<usize, String> {
+        match self.block_of_addr.get(&addr) {
+            Some(b) => Ok(*b),
+            None => Err(format!("no block starts at {:#x}", addr)),
+        }
+    }
+
+    fn normal_successors(&self, b: usize) -> Vec<usize> {
+        self.graph.edges.iter().filter(|(a, _)| *a == b).map(|(_, x)| *x).collect()
+    }
+
+    fn translate_block(&mut self, b: usize) -> Result<TranslatedBlock, String> {
+        let ins = self.graph.nodes[b].instructions.clone();
+        let mut st = BlockState { block: b, pending: HashMap::new(), statements: Vec::new(), allocations: HashMap::new() };
+        let mut terminator: Option<Terminator> = None;
+
+        for (k, i) in ins.iter().enumerate() {
+            if self.skip.contains(&i.addr) {
+                continue;
+            }
+
+            // two folded calls read by one instruction would be evaluated in
+            // operand order rather than program order, so spell them out
+            let used = self.uses(b, k);
+            let mut impure = st.pending.iter()
+                .filter(|(r, p)| !p.pure && used.contains(r))
+                .map(|(r, p)| (p.index, *r))
+                .collect::<Vec<(usize, Register)>>();
+            if impure.len() > 1 {
+                impure.sort_unstable();
+                for (_, r) in impure {
+                    let p = match st.pending.remove(&r) {
+                        Some(p) => p,
+                        None => continue,
+                    };
+                    if !self.names.contains_key(&r) && !self.declared.contains_key(&r) {
+                        let type_addr = self.def(b, p.index)?.type_addr;
+                        let type_string = self.type_written(type_addr, r).map(|t| java_type(&t)).unwrap_or_else(|| "int".to_string());
+                        self.declared.insert(r, type_string);
+                    }
+                    st.statements.push(Statement::Assign(self.var(r), p.expr));
+                }
+            }
+
+            if let Some(t) = self.translate_instruction(&mut st, k, i)? {
+                terminator = Some(t);
+            }
+        }
+
+        if let Some(r) = st.pending.keys().next() {
+            return Err(format!("value of v{} is never read", r));
+        }
+        let terminator = match terminator {
+            Some(t) => t,
+            None => match self.normal_successors(b).first() {
+                Some(n) => Terminator::Next(*n),
+                None => Terminator::Exit,
+            },
+        };
+        Ok(TranslatedBlock { statements: st.statements, terminator })
+    }
+
+    fn translate_instruction(&mut self, st: &mut BlockState, k: usize, i: &Instruction) -> Result<Option<Terminator>, String> {
+        let d = self.d;
+        let b = st.block;
+        let next_block = |s: &Self| s.block_of(i.addr + i.bytecode.len());
+
+        if let Some((op, _, lhs, rhs)) = binary_operation(&i.kind) {
+            let left = self.read(&mut st.pending, lhs);
+            let right = match rhs {
+                Operand::Register(r) => self.read(&mut st.pending, r),
+                Operand::Literal(x) => Expr::Literal(x.to_string()),
+                _ => unreachable!(),
+            };
+            let expr = match i.kind {
+                  InstructionKind::RSubIntLit16(..)
+                | InstructionKind::RSubIntLit8(..) => Expr::Binary(Box::new(right), "-", Box::new(left)),
+                _ => Expr::Binary(Box::new(left), op, Box::new(right)),
+            };
+            self.define(st, k, expr)?;
+            return Ok(None);
+        }
+
+        if let Some((op, _, source)) = unary_operation(&i.kind) {
+            let operand = self.read(&mut st.pending, source);
+            let expr = if op.starts_with('(') {
+                Expr::Cast(op[1..op.len() - 1].to_string(), Box::new(operand))
+            } else {
+                Expr::Unary(op, Box::new(operand))
+            };
+            self.define(st, k, expr)?;
+            return Ok(None);
+        }
+
+        if let Some((regs, m, kind)) = invoke_parts(&i.kind) {
+            return self.translate_invoke(st, k, i, regs, m, kind).map(|_| None);
+        }
+
+        match &i.kind {
+            InstructionKind::Nop => {}
+              InstructionKind::Move(_, s)
+            | InstructionKind::MoveFrom16(_, s)
+            | InstructionKind::Move16(_, s)
+            | InstructionKind::MoveWide(_, s)
+            | InstructionKind::MoveWideFrom16(_, s)
+            | InstructionKind::MoveWide16(_, s)
+            | InstructionKind::MoveObject(_, s)
+            | InstructionKind::MoveObjectFrom16(_, s)
+            | InstructionKind::MoveObject16(_, s) => {
+                let e = self.read(&mut st.pending, *s);
+                self.define(st, k, e)?;
+            }
+            InstructionKind::MoveException(_) => {
+                self.define(st, k, Expr::Var("e".to_string()))?;
+            }
+              InstructionKind::MoveResult(_)
+            | InstructionKind::MoveResultWide(_)
+            | InstructionKind::MoveResultObject(_) => {
+                return Err(format!("move-result at {:#x} does not follow an invoke", i.addr));
+            }
+            InstructionKind::ReturnVoid | InstructionKind::ReturnVoidNoBarrier => {
+                st.statements.push(Statement::Return(None));
+                return Ok(Some(Terminator::Exit));
+            }
+              InstructionKind::Return(r)
+            | InstructionKind::ReturnWide(r)
+            | InstructionKind::ReturnObject(r) => {
+                let e = self.read(&mut st.pending, *r);
+                st.statements.push(Statement::Return(Some(coerce(e, hint_of_descriptor(&self.return_type)))));
+                return Ok(Some(Terminator::Exit));
+            }
+              InstructionKind::Const4(_, x)
+            | InstructionKind::Const16(_, x)
+            | InstructionKind::Const(_, x)
+            | InstructionKind::ConstHigh16(_, x) => self.define(st, k, Expr::Literal(x.to_string()))?,
+              InstructionKind::ConstWide16(_, x)
+            | InstructionKind::ConstWide32(_, x)
+            | InstructionKind::ConstWide(_, x)
+            | InstructionKind::ConstWideHigh16(_, x) => self.define(st, k, Expr::Literal(format!("{}L", x)))?,
+              InstructionKind::ConstString(_, s)
+            | InstructionKind::ConstStringJumbo(_, s) => {
+                let literal = match d.strings.get(*s) {
+                    Some(s) => format!("{:?}", s),
+                    None => format!("string@{:#x}", s),
+                };
+                self.define(st, k, Expr::Literal(literal))?;
+            }
+            InstructionKind::ConstClass(_, t) => self.define(st, k, Expr::Literal(format!("{}.class", self.class_reference(*t))))?,
+            InstructionKind::MonitorEnter(r) | InstructionKind::MonitorExit(r) => {
+                let e = self.read(&mut st.pending, *r);
+                st.statements.push(Statement::Comment(format!("{}({})", i.kind.mnemonic(), e)));
+            }
+            InstructionKind::CheckCast(r, t) => {
+                let e = self.read(&mut st.pending, *r);
+                self.define(st, k, Expr::Cast(d.type_name(*t), Box::new(e)))?;
+            }
+            InstructionKind::InstanceOf(_, r, t) => {
+                let e = self.read(&mut st.pending, *r);
+                self.define(st, k, Expr::InstanceOf(Box::new(e), d.type_name(*t)))?;
+            }
+            InstructionKind::ArrayLength(_, r) => {
+                let e = self.read(&mut st.pending, *r);
+                self.define(st, k, Expr::Length(Box::new(e)))?;
+            }
+            InstructionKind::NewInstance(r, t) => {
+                if !self.absorbed[b].contains(&k) {
+                    return Err(format!("new-instance at {:#x} has no constructor call in its block", i.addr));
+                }
+                st.allocations.insert(*r, *t);
+            }
+            InstructionKind::NewArray(_, size, t) => {
+                let e = self.read(&mut st.pending, *size);
+                self.define(st, k, Expr::NewArray(d.type_name(*t), Box::new(e)))?;
+            }
+            InstructionKind::FilledNewArray(..) | InstructionKind::FilledNewArrayRange(..) => {
+                let (regs, t) = match &i.kind {
+                    InstructionKind::FilledNewArray(regs, t) => (regs.clone(), *t),
+                    InstructionKind::FilledNewArrayRange(first, count, t) => (register_range(*first, *count), *t),
+                    _ => unreachable!(),
+                };
+                let elements = regs.iter().map(|r| self.read(&mut st.pending, *r)).collect::<Vec<Expr>>();
+                if self.results.contains_key(&i.addr) {
+                    self.define(st, k, Expr::ArrayInit(d.type_name(t), elements))?;
+                }
+            }
+            InstructionKind::FillArrayData(r, o) => {
+                let payload_addr = (i.addr as i64 + *o as i64 * 2) as usize;
+                let payload = self.code.instructions.iter().find(|x| x.addr == payload_addr).map(|x| &x.kind);
+                let (width, data) = match payload {
+                    Some(InstructionKind::FillArrayDataPayload(width, data)) => (*width as usize, data),
+                    _ => return Err(format!("fill-array-data at {:#x} has no payload", i.addr)),
+                };
+                let array_type = match self.type_before(i.addr, *r) {
+                    Some(RegisterType::Reference(t)) => get_type_descriptor_string(&parse_type_descriptor(t)),
+                    _ => "int[]".to_string(),
+                };
+                let elements = array_elements(&array_type, width, data);
+                let array = self.read(&mut st.pending, *r);
+                match st.statements.last_mut() {
+                    Some(Statement::Assign(Expr::Var(name), e @ Expr::NewArray(..))) if Expr::Var(name.clone()) == array => {
+                        *e = Expr::ArrayInit(array_type, elements);
+                    }
+                    _ => {
+                        for (n, x) in elements.into_iter().enumerate() {
+                            let target = Expr::Index(Box::new(array.clone()), Box::new(Expr::Literal(n.to_string())));
+                            st.statements.push(Statement::Assign(target, x));
+                        }
+                    }
+                }
+            }
+            InstructionKind::Throw(r) => {
+                let e = self.read(&mut st.pending, *r);
+                st.statements.push(Statement::Throw(e));
+                return Ok(Some(Terminator::Exit));
+            }
+            InstructionKind::GoTo(o) | InstructionKind::GoTo16(o) | InstructionKind::GoTo32(o) => {
+                let target = self.block_of((i.addr as i64 + *o as i64) as usize)?;
+                return Ok(Some(Terminator::Next(target)));
+            }
+            InstructionKind::PackedSwitch(r, o) | InstructionKind::SparseSwitch(r, o) => {
+                let e = self.read(&mut st.pending, *r);
+                let payload_addr = (i.addr as i64 + *o as i64 * 2) as usize;
+                let payload = self.code.instructions.iter().find(|x| x.addr == payload_addr).map(|x| &x.kind);
+                let (keys, targets): (Vec<i32>, &Vec<i32>) = match payload {
+                    Some(InstructionKind::PackedSwitchPayload(first, targets)) => ((0..targets.len() as i32).map(|n| first + n).collect(), targets),
+                    Some(InstructionKind::SparseSwitchPayload(keys, targets)) => (keys.clone(), targets),
+                    _ => return Err(format!("switch at {:#x} has no payload", i.addr)),
+                };
+                let mut cases: Vec<(i32, usize)> = Vec::new();
+                for (key, t) in keys.iter().zip(targets.iter()) {
+                    cases.push((*key, self.block_of((i.addr as i64 + *t as i64 * 2) as usize)?));
+                }
+                return Ok(Some(Terminator::Switch(e, cases, next_block(self)?)));
+            }
+              InstructionKind::CmpLFloat(_, x, y)
+            | InstructionKind::CmpGFloat(_, x, y)
+            | InstructionKind::CmpLDouble(_, x, y)
+            | InstructionKind::CmpGDouble(_, x, y)
+            | InstructionKind::CmpLong(_, x, y) => {
+                let class = match i.kind {
+                    InstructionKind::CmpLong(..) => "Long",
+                    InstructionKind::CmpLDouble(..) | InstructionKind::CmpGDouble(..) => "Double",
+                    _ => "Float",
+                };
+                let a = self.read(&mut st.pending, *x);
+                let c = self.read(&mut st.pending, *y);
+                self.define(st, k, Expr::Compare(class, Box::new(a), Box::new(c)))?;
+            }
+              InstructionKind::IfEq(x, y, o)
+            | InstructionKind::IfNe(x, y, o)
+            | InstructionKind::IfLt(x, y, o)
+            | InstructionKind::IfGe(x, y, o)
+            | InstructionKind::IfGt(x, y, o)
+            | InstructionKind::IfLe(x, y, o) => {
+                let a = self.read(&mut st.pending, *x);
+                let c = self.read(&mut st.pending, *y);
+                let cond = Expr::Binary(Box::new(a), comparison(&i.kind), Box::new(c));
+                return self.branch(i, cond, *o);
+            }
+              InstructionKind::IfEqZ(x, o)
+            | InstructionKind::IfNeZ(x, o)
+            | InstructionKind::IfLtZ(x, o)
+            | InstructionKind::IfGeZ(x, o)
+            | InstructionKind::IfGtZ(x, o)
+            | InstructionKind::IfLeZ(x, o) => {
+                let op = comparison(&i.kind);
+                let e = self.read(&mut st.pending, *x);
+                let cond = match (e, self.type_before(i.addr, *x)) {
+                    (Expr::Compare(_, a, c), _) => Expr::Binary(a, op, c),
+                    (e, Some(RegisterType::Boolean)) if op == "==" => negate(e),
+                    (e, Some(RegisterType::Boolean)) if op == "!=" => e,
+                    (e, Some(RegisterType::Reference(_))) => Expr::Binary(Box::new(e), op, Box::new(Expr::Literal("null".to_string()))),
+                    (e, _) => Expr::Binary(Box::new(e), op, Box::new(Expr::Literal("0".to_string()))),
+                };
+                return self.branch(i, cond, *o);
+            }
+              InstructionKind::AGet(_, a, x)
+            | InstructionKind::AGetWide(_, a, x)
+            | InstructionKind::AGetObject(_, a, x)
+            | InstructionKind::AGetBoolean(_, a, x)
+            | InstructionKind::AGetByte(_, a, x)
+            | InstructionKind::AGetChar(_, a, x)
+            | InstructionKind::AGetShort(_, a, x) => {
+                let array = self.read(&mut st.pending, *a);
+                let index = self.read(&mut st.pending, *x);
+                self.define(st, k, Expr::Index(Box::new(array), Box::new(index)))?;
+            }
+              InstructionKind::APut(v, a, x)
+            | InstructionKind::APutWide(v, a, x)
+            | InstructionKind::APutObject(v, a, x)
+            | InstructionKind::APutBoolean(v, a, x)
+            | InstructionKind::APutByte(v, a, x)
+            | InstructionKind::APutChar(v, a, x)
+            | InstructionKind::APutShort(v, a, x) => {
+                let array = self.read(&mut st.pending, *a);
+                let index = self.read(&mut st.pending, *x);
+                let value = self.read(&mut st.pending, *v);
+                let hint = match i.kind {
+                    InstructionKind::APutObject(..) => Hint::Reference,
+                    InstructionKind::APutBoolean(..) => Hint::Boolean,
+                    _ => Hint::Other,
+                };
+                st.statements.push(Statement::Assign(Expr::Index(Box::new(array), Box::new(index)), coerce(value, hint)));
+            }
+              InstructionKind::IGet(_, o, f)
+            | InstructionKind::IGetWide(_, o, f)
+            | InstructionKind::IGetObject(_, o, f)
+            | InstructionKind::IGetBoolean(_, o, f)
+            | InstructionKind::IGetByte(_, o, f)
+            | InstructionKind::IGetChar(_, o, f)
+            | InstructionKind::IGetShort(_, o, f) => {
+                let object = self.read(&mut st.pending, *o);
+                self.define(st, k, Expr::Field(Box::new(object), self.field_name(*f)))?;
+            }
+              InstructionKind::IPut(v, o, f)
+            | InstructionKind::IPutWide(v, o, f)
+            | InstructionKind::IPutObject(v, o, f)
+            | InstructionKind::IPutBoolean(v, o, f)
+            | InstructionKind::IPutByte(v, o, f)
+            | InstructionKind::IPutChar(v, o, f)
+            | InstructionKind::IPutShort(v, o, f) => {
+                let object = self.read(&mut st.pending, *o);
+                let value = self.read(&mut st.pending, *v);
+                let target = Expr::Field(Box::new(object), self.field_name(*f));
+                st.statements.push(Statement::Assign(target, coerce(value, self.field_hint(*f))));
+            }
+              InstructionKind::SGet(_, f)
+            | InstructionKind::SGetWide(_, f)
+            | InstructionKind::SGetObject(_, f)
+            | InstructionKind::SGetBoolean(_, f)
+            | InstructionKind::SGetByte(_, f)
+            | InstructionKind::SGetChar(_, f)
+            | InstructionKind::SGetShort(_, f) => {
+                let e = self.static_field(*f);
+                self.define(st, k, e)?;
+            }
+              InstructionKind::SPut(v, f)
+            | InstructionKind::SPutWide(v, f)
+            | InstructionKind::SPutObject(v, f)
+            | InstructionKind::SPutBoolean(v, f)
+            | InstructionKind::SPutByte(v, f)
+            | InstructionKind::SPutChar(v, f)
+            | InstructionKind::SPutShort(v, f) => {
+                let value = self.read(&mut st.pending, *v);
+                st.statements.push(Statement::Assign(self.static_field(*f), coerce(value, self.field_hint(*f))));
+            }
+            kind => return Err(format!("{} at {:#x} is not supported", kind.mnemonic(), i.addr)),
+        }
+        Ok(None)
+    }
+
+    fn branch(&self, i: &Instruction, cond: Expr, offset: i32) -> Result<Option<Terminator>, String> {
+        let taken = self.block_of((i.addr as i64 + offset as i64) as usize)?;
+        let fall = self.block_of(i.addr + i.bytecode.len())?;
+        if taken == fall {
+            return Ok(Some(Terminator::Next(taken)));
+        }
+        Ok(Some(Terminator::Branch(cond, taken, fall)))
+    }
+
+    fn translate_invoke(&mut self, st: &mut BlockState, k: usize, i: &Instruction, regs: Vec<Register>, m: MethodIndex, kind: &str) -> Result<(), String> {
+        let d = self.d;
+        let (method, proto) = d.method(m)?;
+        let name = d.method_name(m).unwrap_or("?").to_string();
+
+        let mut position = 0;
+        let receiver = if kind == "static" {
+            None
+        } else {
+            position = 1;
+            regs.first().cloned()
+        };
+        let mut parameters: Vec<(Register, Hint)> = Vec::new();
+        for p in proto.parameter_type_idx_list.iter() {
+            let descriptor = d.descriptor(*p)?;
+            match regs.get(position) {
+                Some(r) => parameters.push((*r, hint_of_descriptor(descriptor))),
+                None => return Err(format!("too few registers for the invoke at {:#x}", i.addr)),
+            }
+            position += if is_wide(descriptor) { 2 } else { 1 };
+        }
+
+        if name == "<init>" {
+            if let Some(t) = receiver.and_then(|r| st.allocations.remove(&r)) {
+                let args = self.arguments(st, &parameters);
+                self.define(st, k, Expr::New(d.type_name(t), args))?;
+                return Ok(());
+            }
+        }
+
+        let object = receiver.map(|r| self.read(&mut st.pending, r));
+        let args = self.arguments(st, &parameters);
+        let call = match (kind, object) {
+            ("static", _) => Expr::Call(Some(Box::new(Expr::Var(self.class_reference(method.class_idx)))), name, args),
+            ("super", _) => Expr::Call(Some(Box::new(Expr::Var("super".to_string()))), name, args),
+            ("direct", Some(Expr::Var(this))) if name == "<init>" && this == "this" && self.is_constructor => {
+                let keyword = if method.class_idx == self.class_idx { "this" } else { "super" };
+                Expr::Call(None, keyword.to_string(), args)
+            }
+            (_, object) => Expr::Call(object.map(Box::new), name, args),
+        };
+
+        if self.results.contains_key(&i.addr) {
+            self.define(st, k, call)?;
+        } else {
+            st.statements.push(Statement::Expr(call));
+        }
+        Ok(())
+    }
+
+    fn arguments(&self, st: &mut BlockState, parameters: &[(Register, Hint)]) -> Vec<Expr> {
+        parameters.iter()
+            .map(|(r, hint)| coerce(self.read(&mut st.pending, *r), *hint))
+            .collect()
+    }
+
+    // Classes are written out in full, except the one being decompiled.
+    fn class_reference(&self, t: TypeIndex) -> String {
+        let name = self.d.type_name(t);
+        if t == self.class_idx {
+            return name.rsplit('.').next().unwrap_or(&name).to_string();
+        }
+        name
+    }
+
+    fn field_name(&self, f: FieldIndex) -> String {
+        match self.d.fields.get(f) {
+            Some(field) if field.name_idx < self.d.strings.len() => self.d.strings[field.name_idx].clone(),
+            Some(_) => format!("field@{:#x}", f),
+            None => format!("field@{:#x}", f),
+        }
+    }
+
+    fn field_hint(&self, f: FieldIndex) -> Hint {
+        match self.d.fields.get(f).and_then(|x| self.d.descriptor(x.type_idx).ok()) {
+            Some(descriptor) => hint_of_descriptor(descriptor),
+            None => Hint::Other,
+        }
+    }
+
+    fn static_field(&self, f: FieldIndex) -> Expr {
+        let class = match self.d.fields.get(f) {
+            Some(field) => self.class_reference(field.class_idx),
+            None => "?".to_string(),
+        };
+        Expr::Field(Box::new(Expr::Var(class)), self.field_name(f))
+    }
+
+    // Loops are natural loops around back edges, merged per header. The
+    // follow is where the loop exits to, preferring exits from the header.
+    fn find_loops(&mut self) {
+        let count = self.graph.nodes.len();
+        let idom = dominators(&self.graph, 0);
+        let dominates = |a: usize, mut b: usize| loop {
+            if a == b {
+                return true;
+            }
+            match idom[b] {
+                Some(x) if x != b => b = x,
+                _ => return false,
+            }
+        };
+
+        for (p, h) in self.graph.edges.iter() {
+            if !dominates(*h, *p) {
+                continue;
+            }
+            let mut nodes: HashSet<usize> = self.loops.remove(h).map(|l| l.nodes).unwrap_or_default();
+            nodes.insert(*h);
+            let mut work = vec![*p];
+            while let Some(n) = work.pop() {
+                if nodes.insert(n) {
+                    work.extend(self.graph.predecessors(n));
+                }
+            }
+            self.loops.insert(*h, Loop { nodes, follow: None });
+        }
+
+        let graph = &self.graph;
+        for (h, l) in self.loops.iter_mut() {
+            let exits_from = |b: usize| graph.edges.iter()
+                .filter(|(a, x)| *a == b && !l.nodes.contains(x))
+                .map(|(_, x)| *x)
+                .collect::<Vec<usize>>();
+            let header_exits = exits_from(*h);
+            let mut exits = l.nodes.iter().flat_map(|b| exits_from(*b)).collect::<Vec<usize>>();
+            exits.sort_unstable();
+            exits.dedup();
+            l.follow = if header_exits.len() == 1 {
+                Some(header_exits[0])
+            } else {
+                exits.iter().max_by_key(|x| graph.nodes[**x].starting_addr).cloned()
+            };
+        }
+
+        // post-dominators over normal edges, with a virtual exit after
+        // every block that leaves the method
+        let mut edges = self.graph.edges.iter().map(|(a, b)| (*b, *a)).collect::<Vec<(usize, usize)>>();
+        for b in 0..count {
+            if self.normal_successors(b).is_empty() {
+                edges.push((count, b));
+            }
+        }
+        let reversed: Graph<()> = Graph::new(vec![(); count + 1], edges, Vec::new());
+        self.ipdom = dominators(&reversed, count).into_iter()
+            .take(count)
+            .map(|x| x.filter(|x| *x != count))
+            .collect();
+    }
+
+    // Emits blocks from `start` until reaching a stop, the enclosing loop's
+    // header or follow, or the end of the method. Returns where it stopped.
+    fn region(&mut self, start: Option<usize>, ctx: &Context) -> Result<(Vec<Statement>, Option<usize>), String> {
+        let mut out: Vec<Statement> = Vec::new();
+        let mut current = start;
+        while let Some(b) = current {
+            if ctx.stops.contains(&b) {
+                break;
+            }
+            if ctx.loop_header == Some(b) {
+                out.push(Statement::Continue);
+                return Ok((out, Some(b)));
+            }
+            if ctx.loop_follow == Some(b) {
+                out.push(Statement::Break);
+                return Ok((out, Some(b)));
+            }
+            current = self.node(b, ctx, &mut out)?;
+        }
+        Ok((out, current))
+    }
+
+    fn node(&mut self, b: usize, ctx: &Context, out: &mut Vec<Statement>) -> Result<Option<usize>, String> {
+        if self.emitted.contains(&b) {
+            return Err(format!("the block at {:#x} is reached twice", self.graph.nodes[b].starting_addr));
+        }
+
+        let try_index = self.try_starting_at(b, ctx);
+        let is_new_loop = self.loops.contains_key(&b) && ctx.loop_header != Some(b);
+        if let Some(t) = try_index {
+            let inside = self.try_blocks(t);
+            if !is_new_loop || self.loops[&b].nodes.iter().all(|n| inside.contains(n)) {
+                return self.try_region(b, t, ctx, out);
+            }
+        }
+        if is_new_loop {
+            return self.loop_region(b, ctx, out);
+        }
+
+        self.emitted.insert(b);
+        out.extend(self.blocks[b].statements.iter().cloned());
+        match self.blocks[b].terminator.clone() {
+            Terminator::Next(n) => Ok(Some(n)),
+            Terminator::Exit => Ok(None),
+            Terminator::Branch(cond, taken, fall) => {
+                let follow = self.follow(b, ctx);
+                let mut inner = ctx.clone();
+                inner.stops.extend(follow);
+                if Some(taken) == follow {
+                    let (then, _) = self.region(Some(fall), &inner)?;
+                    out.push(Statement::If(negate(cond), then, Vec::new()));
+                } else if Some(fall) == follow {
+                    let (then, _) = self.region(Some(taken), &inner)?;
+                    out.push(Statement::If(cond, then, Vec::new()));
+                } else {
+                    let (then, _) = self.region(Some(fall), &inner)?;
+                    let (otherwise, _) = self.region(Some(taken), &inner)?;
+                    out.push(Statement::If(negate(cond), then, otherwise));
+                }
+                Ok(follow)
+            }
+            Terminator::Switch(e, cases, default) => self.switch_region(b, e, cases, default, ctx, out),
+        }
+    }
+
+    // The immediate post-dominator, unless it lies outside the loop being
+    // structured; leaving the loop is left to break and continue.
+    fn follow(&self, b: usize, ctx: &Context) -> Option<usize> {
+        let follow = self.ipdom[b]?;
+        match ctx.loop_header {
+            Some(h) if h != follow && !self.loops[&h].nodes.contains(&follow) => None,
+            _ => Some(follow),
+        }
+    }
+
+    fn loop_region(&mut self, h: usize, ctx: &Context, out: &mut Vec<Statement>) -> Result<Option<usize>, String> {
+        let follow = self.loops[&h].follow;
+        let inner = Context { stops: Vec::new(), loop_header: Some(h), loop_follow: follow, tries: ctx.tries.clone() };
+        let mut body: Vec<Statement> = Vec::new();
+        let next = self.node(h, &inner, &mut body)?;
+        let (rest, _) = self.region(next, &inner)?;
+        body.extend(rest);
+        out.push(Statement::While(Expr::Literal("true".to_string()), body));
+        Ok(follow)
+    }
+
+    fn switch_region(
+        &mut self,
+        b: usize,
+        e: Expr,
+        cases: Vec<(i32, usize)>,
+        default: usize,
+        ctx: &Context,
+        out: &mut Vec<Statement>,
+    ) -> Result<Option<usize>, String> {
+        let follow = self.follow(b, ctx);
+
+        // keys sharing a target become one case with several labels
+        let mut groups: Vec<(Vec<i32>, bool, usize)> = Vec::new();
+        for (key, target) in cases {
+            match groups.iter_mut().find(|g| g.2 == target) {
+                Some(g) => g.0.push(key),
+                None => groups.push((vec![key], false, target)),
+            }
+        }
+        match groups.iter_mut().find(|g| g.2 == default) {
+            Some(g) => g.1 = true,
+            None if Some(default) != follow => groups.push((Vec::new(), true, default)),
+            None => {}
+        }
+        groups.sort_by_key(|g| self.graph.nodes[g.2].starting_addr);
+
+        let targets = groups.iter().map(|g| g.2).collect::<Vec<usize>>();
+        let mut result: Vec<Case> = Vec::new();
+        for (n, (keys, is_default, target)) in groups.iter().enumerate() {
+            let mut inner = ctx.clone();
+            inner.stops.extend(follow);
+            inner.stops.extend(targets.iter().filter(|t| *t != target));
+            let (mut body, stopped) = self.region(Some(*target), &inner)?;
+            if contains_loop_jump(&body) {
+                return Err("break or continue inside a switch".to_string());
+            }
+            if stopped.is_some() && stopped == follow {
+                body.push(Statement::Break);
+            } else if stopped.is_some() && stopped != targets.get(n + 1).cloned() {
+                return Err("switch case falls through out of order".to_string());
+            }
+            result.push(Case { keys: keys.clone(), default: *is_default, body });
+        }
+
+        // a default that does not fall through reads better last
+        if let Some(p) = result.iter().position(|c| c.default && c.keys.is_empty()) {
+            let jumps = |c: &Case| c.body.last().map(is_jump).unwrap_or(false);
+            if p + 1 < result.len() && jumps(&result[p]) && (p == 0 || jumps(&result[p - 1])) {
+                let case = result.remove(p);
+                result.push(case);
+            }
+        }
+
+        out.push(Statement::Switch(e, result));
+        Ok(follow)
+    }
+
+    fn try_starting_at(&self, b: usize, ctx: &Context) -> Option<usize> {
+        let addr = self.graph.nodes[b].starting_addr;
+        self.code.tries.iter().enumerate()
+            .filter(|(n, t)| t.start_addr == addr && !ctx.tries.contains(n))
+            .max_by_key(|(_, t)| t.end_addr)
+            .map(|(n, _)| n)
+    }
+
+    fn try_blocks(&self, t: usize) -> HashSet<usize> {
+        let t = &self.code.tries[t];
+        self.graph.nodes.iter().enumerate()
+            .filter(|(_, b)| b.starting_addr >= t.start_addr && b.starting_addr < t.end_addr)
+            .map(|(n, _)| n)
+            .collect()
+    }
+
+    fn try_region(&mut self, b: usize, t: usize, ctx: &Context, out: &mut Vec<Statement>) -> Result<Option<usize>, String> {
+        let inside = self.try_blocks(t);
+        let mut exits = inside.iter()
+            .flat_map(|x| self.normal_successors(*x))
+            .filter(|x| !inside.contains(x) && Some(*x) != ctx.loop_header && Some(*x) != ctx.loop_follow)
+            .collect::<Vec<usize>>();
+        exits.sort_unstable();
+        exits.dedup();
+        if exits.len() > 1 {
+            return Err(format!("the try block at {:#x} has several exits", self.graph.nodes[b].starting_addr));
+        }
+        let follow = exits.first().cloned();
+
+        let mut inner = ctx.clone();
+        inner.stops.extend(follow);
+        inner.tries.push(t);
+        let mut body: Vec<Statement> = Vec::new();
+        let next = self.node(b, &inner, &mut body)?;
+        let (rest, _) = self.region(next, &inner)?;
+        body.extend(rest);
+
+        let item = self.code.tries[t].clone();
+        let mut handlers: Vec<(String, usize)> = item.handlers.iter()
+            .map(|h| (self.d.type_name(h.type_idx), h.addr))
+            .collect();
+        if let Some(addr) = item.catch_all_addr {
+            handlers.push(("java.lang.Throwable".to_string(), addr));
+        }
+
+        let mut catches: Vec<Catch> = Vec::new();
+        for (exception_type, addr) in handlers {
+            let h = self.block_of(addr)?;
+            let mut handler_ctx = ctx.clone();
+            handler_ctx.stops.extend(follow);
+            let (body, _) = self.region(Some(h), &handler_ctx)?;
+            catches.push(Catch { exception_type, variable: "e".to_string(), body });
+        }
+
+        out.push(Statement::Try(body, catches));
+        Ok(follow)
+    }
+}
+
+struct BlockState {
+    block: usize,
+    pending: HashMap<Register, Pending>,
+    statements: Vec<Statement>,
+    // new-instance registers waiting for their <init> call
+    allocations: HashMap<Register, TypeIndex>,
+}
+
+// What a literal 0 or 1 should read as where it is used.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Hint {
+    Boolean,
+    Reference,
+    Other,
+}
+
+fn hint_of_descriptor(descriptor: &str) -> Hint {
+    match descriptor.chars().next() {
+        Some('Z') => Hint::Boolean,
+        Some('L') | Some('[') => Hint::Reference,
+        _ => Hint::Other,
+    }
+}
+
+fn hint_of_register_type(t: &RegisterType) -> Hint {
+    match t {
+        RegisterType::Boolean => Hint::Boolean,
+        RegisterType::Reference(_) => Hint::Reference,
+        _ => Hint::Other,
+    }
+}
+
+fn coerce(e: Expr, hint: Hint) -> Expr {
+    match (&e, hint) {
+        (Expr::Literal(x), Hint::Reference) if x == "0" => Expr::Literal("null".to_string()),
+        (Expr::Literal(x), Hint::Boolean) if x == "0" => Expr::Literal("false".to_string()),
+        (Expr::Literal(x), Hint::Boolean) if x == "1" => Expr::Literal("true".to_string()),
+        _ => e,
+    }
+}
+
+fn is_wide(descriptor: &str) -> bool {
+    descriptor == "J" || descriptor == "D"
+}
+
+fn java_type(t: &RegisterType) -> String {
+    match t {
+        RegisterType::Boolean => "boolean".to_string(),
+        RegisterType::Byte => "byte".to_string(),
+        RegisterType::Short => "short".to_string(),
+        RegisterType::Char => "char".to_string(),
+        RegisterType::Float => "float".to_string(),
+        RegisterType::LongLo | RegisterType::ConstantLo => "long".to_string(),
+        RegisterType::DoubleLo => "double".to_string(),
+          RegisterType::Reference(d)
+        | RegisterType::UninitializedThis(d)
+        | RegisterType::Uninitialized(d, _) => get_type_descriptor_string(&parse_type_descriptor(d.clone())),
+        RegisterType::Undefined | RegisterType::Conflict => "Object".to_string(),
+        _ => "int".to_string(),
+    }
+}
+
+fn array_elements(array_type: &str, width: usize, data: &[u8]) -> Vec<Expr> {
+    data.chunks(width.max(1))
+        .filter(|c| c.len() == width)
+        .map(|c| {
+            let mut bits: u64 = 0;
+            for (n, byte) in c.iter().enumerate() {
+                bits |= (*byte as u64) << (8 * n);
+            }
+            let literal = match (array_type, width) {
+                ("float[]", 4) => format!("{:?}f", f32::from_bits(bits as u32)),
+                ("double[]", 8) => format!("{:?}", f64::from_bits(bits)),
+                ("boolean[]", _) => (bits != 0).to_string(),
+                (_, 1) => (bits as u8 as i8).to_string(),
+                (_, 2) if array_type == "char[]" => (bits as u16).to_string(),
+                (_, 2) => (bits as u16 as i16).to_string(),
+                (_, 4) => (bits as u32 as i32).to_string(),
+                _ => format!("{}L", bits as i64),
+            };
+            Expr::Literal(literal)
+        })
+        .collect()
+}
+
+fn reachable<T>(graph: &Graph<T>) -> Vec<usize> {
+    let mut seen: HashSet<usize> = HashSet::new();
+    let mut work = vec![0];
+    while let Some(n) = work.pop() {
+        if seen.insert(n) {
+            work.extend(graph.successors(n));
+        }
+    }
+    let mut result = seen.into_iter().collect::<Vec<usize>>();
+    result.sort_unstable();
+    result
+}
+
+fn invoke_parts(kind: &InstructionKind) -> Option<(Vec<Register>, MethodIndex, &'static str)> {
+    let (regs, m, kind) = match kind {
+        InstructionKind::InvokeVirtual(regs, m) => (regs.clone(), *m, "virtual"),
+        InstructionKind::InvokeSuper(regs, m) => (regs.clone(), *m, "super"),
+        InstructionKind::InvokeDirect(regs, m) => (regs.clone(), *m, "direct"),
+        InstructionKind::InvokeStatic(regs, m) => (regs.clone(), *m, "static"),
+        InstructionKind::InvokeInterface(regs, m) => (regs.clone(), *m, "interface"),
+        InstructionKind::InvokeVirtualRange(a, b, m) => (register_range(*a, *b), *m, "virtual"),
+        InstructionKind::InvokeSuperRange(a, b, m) => (register_range(*a, *b), *m, "super"),
+        InstructionKind::InvokeDirectRange(a, b, m) => (register_range(*a, *b), *m, "direct"),
+        InstructionKind::InvokeStaticRange(a, b, m) => (register_range(*a, *b), *m, "static"),
+        InstructionKind::InvokeInterfaceRange(a, b, m) => (register_range(*a, *b), *m, "interface"),
+        _ => return None,
+    };
+    Some((regs, m, kind))
+}
+
+// Instructions that can be moved past others without changing what the
+// method does. Integer division is left out since it can throw.
+fn is_pure(kind: &InstructionKind) -> bool {
+    if let Some((op, ..)) = binary_operation(kind) {
+        let integral_division = (op == "/" || op == "%") && !kind.mnemonic().contains("float") && !kind.mnemonic().contains("double");
+        return !integral_division;
+    }
+    unary_operation(kind).is_some() || matches!(kind,
+          InstructionKind::Move(..)
+        | InstructionKind::MoveFrom16(..)
+        | InstructionKind::Move16(..)
+        | InstructionKind::MoveWide(..)
+        | InstructionKind::MoveWideFrom16(..)
+        | InstructionKind::MoveWide16(..)
+        | InstructionKind::MoveObject(..)
+        | InstructionKind::MoveObjectFrom16(..)
+        | InstructionKind::MoveObject16(..)
+        | InstructionKind::MoveException(..)
+        | InstructionKind::Const4(..)
+        | InstructionKind::Const16(..)
+        | InstructionKind::Const(..)
+        | InstructionKind::ConstHigh16(..)
+        | InstructionKind::ConstWide16(..)
+        | InstructionKind::ConstWide32(..)
+        | InstructionKind::ConstWide(..)
+        | InstructionKind::ConstWideHigh16(..)
+        | InstructionKind::ConstString(..)
+        | InstructionKind::ConstStringJumbo(..)
+        | InstructionKind::ConstClass(..)
+        | InstructionKind::CheckCast(..)
+        | InstructionKind::InstanceOf(..)
+        | InstructionKind::ArrayLength(..)
+        | InstructionKind::NewArray(..)
+        | InstructionKind::CmpLFloat(..)
+        | InstructionKind::CmpGFloat(..)
+        | InstructionKind::CmpLDouble(..)
+        | InstructionKind::CmpGDouble(..)
+        | InstructionKind::CmpLong(..))
+}
+
+// (operator, destination, left operand, right operand) for arithmetic.
+fn binary_operation(kind: &InstructionKind) -> Option<(&'static str, Register, Register, Operand)> {
+    use InstructionKind::*;
+    let r = Operand::Register;
+    let l = |x: &i32| Operand::Literal(*x as i64);
+    let result = match kind {
+        AddInt(a, b, c) | AddLong(a, b, c) | AddFloat(a, b, c) | AddDouble(a, b, c) => ("+", *a, *b, r(*c)),
+        SubInt(a, b, c) | SubLong(a, b, c) | SubFloat(a, b, c) | SubDouble(a, b, c) => ("-", *a, *b, r(*c)),
+        MulInt(a, b, c) | MulLong(a, b, c) | MulFloat(a, b, c) | MulDouble(a, b, c) => ("*", *a, *b, r(*c)),
+        DivInt(a, b, c) | DivLong(a, b, c) | DivFloat(a, b, c) | DivDouble(a, b, c) => ("/", *a, *b, r(*c)),
+        RemInt(a, b, c) | RemLong(a, b, c) | RemFloat(a, b, c) | RemDouble(a, b, c) => ("%", *a, *b, r(*c)),
+        AndInt(a, b, c) | AndLong(a, b, c) => ("&", *a, *b, r(*c)),
+        OrInt(a, b, c) | OrLong(a, b, c) => ("|", *a, *b, r(*c)),
+        XorInt(a, b, c) | XorLong(a, b, c) => ("^", *a, *b, r(*c)),
+        ShlInt(a, b, c) | ShlLong(a, b, c) => ("<<", *a, *b, r(*c)),
+        ShrInt(a, b, c) | ShrLong(a, b, c) => (">>", *a, *b, r(*c)),
+        UShrInt(a, b, c) | UShrLong(a, b, c) => (">>>", *a, *b, r(*c)),
+        AddInt2Addr(a, b) | AddLong2Addr(a, b) | AddFloat2Addr(a, b) | AddDouble2Addr(a, b) => ("+", *a, *a, r(*b)),
+        SubInt2Addr(a, b) | SubLong2Addr(a, b) | SubFloat2Addr(a, b) | SubDouble2Addr(a, b) => ("-", *a, *a, r(*b)),
+        MulInt2Addr(a, b) | MulLong2Addr(a, b) | MulFloat2Addr(a, b) | MulDouble2Addr(a, b) => ("*", *a, *a, r(*b)),
+        DivInt2Addr(a, b) | DivLong2Addr(a, b) | DivFloat2Addr(a, b) | DivDouble2Addr(a, b) => ("/", *a, *a, r(*b)),
+        RemInt2Addr(a, b) | RemLong2Addr(a, b) | RemFloat2Addr(a, b) | RemDouble2Addr(a, b) => ("%", *a, *a, r(*b)),
+        AndInt2Addr(a, b) | AndLong2Addr(a, b) => ("&", *a, *a, r(*b)),
+        OrInt2Addr(a, b) | OrLong2Addr(a, b) => ("|", *a, *a, r(*b)),
+        XorInt2Addr(a, b) | XorLong2Addr(a, b) => ("^", *a, *a, r(*b)),
+        ShlInt2Addr(a, b) | ShlLong2Addr(a, b) => ("<<", *a, *a, r(*b)),
+        ShrInt2Addr(a, b) | ShrLong2Addr(a, b) => (">>", *a, *a, r(*b)),
+        UShrInt2Addr(a, b) | UShrLong2Addr(a, b) => (">>>", *a, *a, r(*b)),
+        AddIntLit16(a, b, c) | AddIntLit8(a, b, c) => ("+", *a, *b, l(c)),
+        RSubIntLit16(a, b, c) | RSubIntLit8(a, b, c) => ("-", *a, *b, l(c)),
+        MulIntLit16(a, b, c) | MulIntLit8(a, b, c) => ("*", *a, *b, l(c)),
+        DivIntLit16(a, b, c) | DivIntLit8(a, b, c) => ("/", *a, *b, l(c)),
+        RemIntLit16(a, b, c) | RemIntLit8(a, b, c) => ("%", *a, *b, l(c)),
+        AndIntLit16(a, b, c) | AndIntLit8(a, b, c) => ("&", *a, *b, l(c)),
+        OrIntLit16(a, b, c) | OrIntLit8(a, b, c) => ("|", *a, *b, l(c)),
+        XorIntLit16(a, b, c) | XorIntLit8(a, b, c) => ("^", *a, *b, l(c)),
+        ShlIntLit8(a, b, c) => ("<<", *a, *b, l(c)),
+        ShrIntLit8(a, b, c) => (">>", *a, *b, l(c)),
+        UShrIntLit8(a, b, c) => (">>>", *a, *b, l(c)),
+        _ => return None,
+    };
+    Some(result)
+}
+
+// (operator, destination, source) for negation, complement and
+// conversions; conversions are written as the cast in parentheses.
+fn unary_operation(kind: &InstructionKind) -> Option<(&'static str, Register, Register)> {
+    use InstructionKind::*;
+    let result = match kind {
+        NegInt(a, b) | NegLong(a, b) | NegFloat(a, b) | NegDouble(a, b) => ("-", *a, *b),
+        NotInt(a, b) | NotLong(a, b) => ("~", *a, *b),
+        IntToLong(a, b) | FloatToLong(a, b) | DoubleToLong(a, b) => ("(long)", *a, *b),
+        IntToFloat(a, b) | LongToFloat(a, b) | DoubleToFloat(a, b) => ("(float)", *a, *b),
+        IntToDouble(a, b) | LongToDouble(a, b) | FloatToDouble(a, b) => ("(double)", *a, *b),
+        LongToInt(a, b) | FloatToInt(a, b) | DoubleToInt(a, b) => ("(int)", *a, *b),
+        IntToByte(a, b) => ("(byte)", *a, *b),
+        IntToChar(a, b) => ("(char)", *a, *b),
+        IntToShort(a, b) => ("(short)", *a, *b),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn comparison(kind: &InstructionKind) -> &'static str {
+    use InstructionKind::*;
+    match kind {
+        IfEq(..) | IfEqZ(..) => "==",
+        IfNe(..) | IfNeZ(..) => "!=",
+        IfLt(..) | IfLtZ(..) => "<",
+        IfGe(..) | IfGeZ(..) => ">=",
+        IfGt(..) | IfGtZ(..) => ">",
+        _ => "<=",
+    }
+}
+
+fn negate(e: Expr) -> Expr {
+    match e {
+        Expr::Unary("!", inner) => *inner,
+        Expr::Literal(x) if x == "true" => Expr::Literal("false".to_string()),
+        Expr::Literal(x) if x == "false" => Expr::Literal("true".to_string()),
+        Expr::Binary(a, op, c) => {
+            let flipped = match op {
+                "==" => "!=",
+                "!=" => "==",
+                "<" => ">=",
+                ">=" => "<",
+                ">" => "<=",
+                "<=" => ">",
+                _ => return Expr::Unary("!", Box::new(Expr::Binary(a, op, c))),
+            };
+            Expr::Binary(a, flipped, c)
+        }
+        e => Expr::Unary("!", Box::new(e)),
+    }
+}
+
+impl Expr {
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Unary(..) | Expr::Cast(..) => 13,
+            Expr::Binary(_, op, _) => match *op {
+                "*" | "/" | "%" => 12,
+                "+" | "-" => 11,
+                "<<" | ">>" | ">>>" => 10,
+                "<" | ">" | "<=" | ">=" => 9,
+                "==" | "!=" => 8,
+                "&" => 7,
+                "^" => 6,
+                "|" => 5,
+                _ => 4,
+            },
+            Expr::InstanceOf(..) => 9,
+            _ => 15,
+        }
+    }
+
+    fn mentions(&self, name: &str) -> bool {
+        match self {
+            Expr::Var(x) => x == name,
+            Expr::Literal(_) => false,
+              Expr::Unary(_, e)
+            | Expr::Cast(_, e)
+            | Expr::InstanceOf(e, _)
+            | Expr::Field(e, _)
+            | Expr::Length(e)
+            | Expr::NewArray(_, e) => e.mentions(name),
+              Expr::Binary(a, _, c)
+            | Expr::Index(a, c)
+            | Expr::Compare(_, a, c) => a.mentions(name) || c.mentions(name),
+            Expr::Call(receiver, _, args) => {
+                receiver.as_ref().map(|r| r.mentions(name)).unwrap_or(false) || args.iter().any(|a| a.mentions(name))
+            }
+            Expr::New(_, args) | Expr::ArrayInit(_, args) => args.iter().any(|a| a.mentions(name)),
+        }
+    }
+}
+
+// Writes `e`, in parentheses if it binds less tightly than `precedence`.
+fn operand(f: &mut fmt::Formatter, e: &Expr, precedence: u8) -> fmt::Result {
+    if e.precedence() < precedence {
+        write!(f, "({})", e)
+    } else {
+        write!(f, "{}", e)
+    }
+}
+
+fn join(items: &[Expr]) -> String {
+    items.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Var(x) | Expr::Literal(x) => write!(f, "{}", x),
+            Expr::Unary(op, e) => {
+                write!(f, "{}", op)?;
+                operand(f, e, 13)
+            }
+            Expr::Cast(t, e) => {
+                write!(f, "({}) ", t)?;
+                operand(f, e, 13)
+            }
+            Expr::Binary(a, op, c) => {
+                let p = self.precedence();
+                operand(f, a, p)?;
+                write!(f, " {} ", op)?;
+                operand(f, c, p + 1)
+            }
+            Expr::InstanceOf(e, t) => {
+                operand(f, e, 9)?;
+                write!(f, " instanceof {}", t)
+            }
+            Expr::Field(e, name) => {
+                operand(f, e, 15)?;
+                write!(f, ".{}", name)
+            }
+            Expr::Index(a, i) => {
+                operand(f, a, 15)?;
+                write!(f, "[{}]", i)
+            }
+            Expr::Length(a) => {
+                operand(f, a, 15)?;
+                write!(f, ".length")
+            }
+            Expr::Call(receiver, name, args) => {
+                if let Some(r) = receiver {
+                    operand(f, r, 15)?;
+                    write!(f, ".")?;
+                }
+                write!(f, "{}({})", name, join(args))
+            }
+            Expr::New(t, args) => write!(f, "new {}({})", t, join(args)),
+            Expr::NewArray(t, size) => match t.find("[]") {
+                Some(i) => write!(f, "new {}[{}]{}", &t[..i], size, &t[i + 2..]),
+                None => write!(f, "new {}[{}]", t, size),
+            },
+            Expr::ArrayInit(t, items) => write!(f, "new {} {{{}}}", t, join(items)),
+            Expr::Compare(class, a, c) => write!(f, "{}.compare({}, {})", class, a, c),
+        }
+    }
+}
+
+// Tidies the structured statements: loop conditions out of `while (true)`,
+// `do`/`while` and `for` loops, flatter ifs and no redundant continues.
+fn simplify(statements: Vec<Statement>) -> Vec<Statement> {
+    let mut result: Vec<Statement> = Vec::new();
+    for s in statements {
+        let s = match s {
+            Statement::If(c, then, otherwise) => {
+                let (then, otherwise) = (simplify(then), simplify(otherwise));
+                if then.is_empty() && !otherwise.is_empty() {
+                    Statement::If(negate(c), otherwise, Vec::new())
+                } else if !otherwise.is_empty() && then.last().map(is_jump).unwrap_or(false) {
+                    // the else branch can follow the if
+                    result.push(Statement::If(c, then, Vec::new()));
+                    result.extend(otherwise);
+                    continue;
+                } else {
+                    Statement::If(c, then, otherwise)
+                }
+            }
+            Statement::While(c, body) => simplify_loop(c, simplify(body)),
+            Statement::Switch(e, cases) => Statement::Switch(e, cases.into_iter()
+                .map(|c| Case { body: simplify(c.body), ..c })
+                .collect()),
+            Statement::Try(body, catches) => Statement::Try(simplify(body), catches.into_iter()
+                .map(|c| Catch { body: simplify(c.body), ..c })
+                .collect()),
+            s => s,
+        };
+
+        // `x = a; while (x < b) { ...; x = x + 1; }` is a for loop
+        if let Statement::While(c, body) = &s {
+            let init = match result.last() {
+                Some(Statement::Assign(Expr::Var(x), _)) if c.mentions(x) => x.clone(),
+                _ => String::new(),
+            };
+            let update = match body.last() {
+                Some(Statement::Assign(Expr::Var(x), _)) => x == &init,
+                _ => false,
+            };
+            if !init.is_empty() && update && !body.iter().any(contains_continue) {
+                let mut body = body.clone();
+                let update = body.pop().unwrap();
+                let init = result.pop().unwrap();
+                result.push(Statement::For(Box::new(init), c.clone(), Box::new(update), body));
+                continue;
+            }
+        }
+        result.push(s);
+    }
+    result
+}
+
+fn simplify_loop(c: Expr, mut body: Vec<Statement>) -> Statement {
+    if c == Expr::Literal("true".to_string()) {
+        match body.first() {
+            Some(Statement::If(cond, then, otherwise)) if then == &[Statement::Break] && otherwise.is_empty() => {
+                let cond = negate(cond.clone());
+                body.remove(0);
+                return simplify_loop(cond, body);
+            }
+            _ => {}
+        }
+        if body.len() == 2 && body[1] == Statement::Break {
+            if let Statement::If(cond, then, otherwise) = &body[0] {
+                if otherwise.is_empty() {
+                    return simplify_loop(cond.clone(), then.clone());
+                }
+            }
+        }
+        if let Some(Statement::If(cond, then, otherwise)) = body.last() {
+            if then == &[Statement::Break] && otherwise.is_empty() && !body[..body.len() - 1].iter().any(contains_continue) {
+                let cond = negate(cond.clone());
+                body.pop();
+                return Statement::DoWhile(body, cond);
+            }
+        }
+    }
+    if body.last() == Some(&Statement::Continue) {
+        body.pop();
+    }
+    Statement::While(c, body)
+}
+
+// Turns `x = e` into `T x = e` when it is the first top-level statement to
+// mention x, and likewise for the initializer of a for loop that is the
+// only place x is used.
+fn declare_at_first_assignment(statements: &mut [Statement], type_string: &str, name: &str) -> bool {
+    let first = match statements.iter().position(|s| statement_mentions(s, name)) {
+        Some(i) => i,
+        None => return false,
+    };
+    let used_later = statements[first + 1..].iter().any(|s| statement_mentions(s, name));
+    let target: &mut Statement = match statements[first] {
+        Statement::For(ref mut init, ..) if !used_later => init,
+        ref mut s => s,
+    };
+    match target {
+        Statement::Assign(Expr::Var(x), value) if x == name && !value.mentions(name) => {}
+        _ => return false,
+    }
+    let assignment = std::mem::replace(target, Statement::Break);
+    *target = Statement::Declare(type_string.to_string(), Box::new(assignment));
+    true
+}
+
+fn statement_mentions(s: &Statement, name: &str) -> bool {
+    let any = |list: &[Statement]| list.iter().any(|x| statement_mentions(x, name));
+    match s {
+        Statement::Expr(e) | Statement::Throw(e) | Statement::Return(Some(e)) => e.mentions(name),
+        Statement::Assign(a, b) => a.mentions(name) || b.mentions(name),
+        Statement::If(c, a, b) => c.mentions(name) || any(a) || any(b),
+        Statement::While(c, body) | Statement::DoWhile(body, c) => c.mentions(name) || any(body),
+        Statement::For(init, c, update, body) => {
+            statement_mentions(init, name) || c.mentions(name) || statement_mentions(update, name) || any(body)
+        }
+        Statement::Switch(e, cases) => e.mentions(name) || cases.iter().any(|c| any(&c.body)),
+        Statement::Try(body, catches) => any(body) || catches.iter().any(|c| c.variable == name || any(&c.body)),
+        Statement::Declare(_, inner) => statement_mentions(inner, name),
+        Statement::Return(None) | Statement::Break | Statement::Continue | Statement::Comment(_) => false,
+    }
+}
+
+fn is_jump(s: &Statement) -> bool {
+    matches!(s, Statement::Return(_) | Statement::Throw(_) | Statement::Break | Statement::Continue)
+}
+
+// A continue for the loop being looked at, not one nested inside it.
+fn contains_continue(s: &Statement) -> bool {
+    match s {
+        Statement::Continue => true,
+        Statement::If(_, a, b) => a.iter().chain(b.iter()).any(contains_continue),
+        Statement::Switch(_, cases) => cases.iter().any(|c| c.body.iter().any(contains_continue)),
+        Statement::Try(body, catches) => body.iter().chain(catches.iter().flat_map(|c| c.body.iter())).any(contains_continue),
+        _ => false,
+    }
+}
+
+// A break or continue that would bind to a switch instead of a loop.
+fn contains_loop_jump(statements: &[Statement]) -> bool {
+    statements.iter().any(|s| match s {
+        Statement::Break | Statement::Continue => true,
+        Statement::If(_, a, b) => contains_loop_jump(a) || contains_loop_jump(b),
+        Statement::Try(body, catches) => contains_loop_jump(body) || catches.iter().any(|c| contains_loop_jump(&c.body)),
+        _ => false,
+    })
+}
+
+// `x = x + y` as `x += y`, and `x++` for steps of one.
+fn simple_statement(s: &Statement) -> String {
+    match s {
+        Statement::Assign(target, Expr::Binary(a, op, c)) if **a == *target && !matches!(*op, "==" | "!=" | "<" | ">" | "<=" | ">=") => {
+            match (*op, &**c) {
+                ("+", Expr::Literal(x)) if x == "1" => format!("{}++", target),
+                ("-", Expr::Literal(x)) if x == "1" => format!("{}--", target),
+                _ => format!("{} {}= {}", target, op, c),
+            }
+        }
+        Statement::Assign(target, value) => format!("{} = {}", target, value),
+        Statement::Declare(t, inner) => format!("{} {}", t, simple_statement(inner)),
+        Statement::Expr(e) => e.to_string(),
+        _ => String::new(),
+    }
+}
+
+fn render(statements: &[Statement], depth: usize, out: &mut Vec<String>) {
+    let indent = "    ".repeat(depth);
+    for s in statements {
+        match s {
+            Statement::Expr(_) | Statement::Assign(..) | Statement::Declare(..) => out.push(format!("{}{};", indent, simple_statement(s))),
+            Statement::Return(None) => out.push(format!("{}return;", indent)),
+            Statement::Return(Some(e)) => out.push(format!("{}return {};", indent, e)),
+            Statement::Throw(e) => out.push(format!("{}throw {};", indent, e)),
+            Statement::If(c, then, otherwise) => {
+                out.push(format!("{}if ({}) {{", indent, c));
+                render(then, depth + 1, out);
+                let mut otherwise = otherwise;
+                loop {
+                    match otherwise.as_slice() {
+                        [] => break,
+                        [Statement::If(c, then, rest)] => {
+                            out.push(format!("{}}} else if ({}) {{", indent, c));
+                            render(then, depth + 1, out);
+                            otherwise = rest;
+                        }
+                        _ => {
+                            out.push(format!("{}}} else {{", indent));
+                            render(otherwise, depth + 1, out);
+                            break;
+                        }
+                    }
+                }
+                out.push(format!("{}}}", indent));
+            }
+            Statement::While(c, body) => {
+                out.push(format!("{}while ({}) {{", indent, c));
+                render(body, depth + 1, out);
+                out.push(format!("{}}}", indent));
+            }
+            Statement::DoWhile(body, c) => {
+                out.push(format!("{}do {{", indent));
+                render(body, depth + 1, out);
+                out.push(format!("{}}} while ({});", indent, c));
+            }
+            Statement::For(init, c, update, body) => {
+                out.push(format!("{}for ({}; {}; {}) {{", indent, simple_statement(init), c, simple_statement(update)));
+                render(body, depth + 1, out);
+                out.push(format!("{}}}", indent));
+            }
+            Statement::Switch(e, cases) => {
+                out.push(format!("{}switch ({}) {{", indent, e));
+                for c in cases {
+                    for key in c.keys.iter() {
+                        out.push(format!("{}    case {}:", indent, key));
+                    }
+                    if c.default {
+                        out.push(format!("{}    default:", indent));
+                    }
+                    render(&c.body, depth + 2, out);
+                }
+                out.push(format!("{}}}", indent));
+            }
+            Statement::Try(body, catches) => {
+                out.push(format!("{}try {{", indent));
+                render(body, depth + 1, out);
+                for c in catches {
+                    out.push(format!("{}}} catch ({} {}) {{", indent, c.exception_type, c.variable));
+                    render(&c.body, depth + 1, out);
+                }
+                out.push(format!("{}}}", indent));
+            }
+            Statement::Break => out.push(format!("{}break;", indent)),
+            Statement::Continue => out.push(format!("{}continue;", indent)),
+            Statement::Comment(x) => out.push(format!("{}// {}", indent, x)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+    use crate::anomaly::STRICT;
+
+    // Decompiles `static int f(int)` of class Foo from a hand-assembled
+    // code item: two registers, the argument in v1, and `tries` as the raw
+    // try items and handlers after the (padded) instructions.
+    fn decompile(insns: &[u16], tries_size: u16, tries: &[u8]) -> Vec<String> {
+        let mut buffer = vec![0u8; 4];
+        for x in [2, 1, 0, tries_size] {
+            buffer.extend_from_slice(&x.to_le_bytes());
+        }
+        buffer.extend_from_slice(&0u32.to_le_bytes());
+        buffer.extend_from_slice(&(insns.len() as u32).to_le_bytes());
+        for x in insns {
+            buffer.extend_from_slice(&x.to_le_bytes());
+        }
+        if tries_size > 0 && insns.len() % 2 == 1 {
+            buffer.extend_from_slice(&[0, 0]);
+        }
+        buffer.extend_from_slice(tries);
+
+        let strings = ["LFoo;", "I", "Ljava/lang/Exception;", "f"].iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        let types = strings[..3].iter()
+            .map(|s| DexType { raw: s.clone(), parsed: parse_type_descriptor(s.clone()) })
+            .collect::<Vec<DexType>>();
+        let protos = vec![DexProto { shorty_idx: 1, return_type_idx: 1, parameters_offset: 0, parameter_type_idx_list: vec![1] }];
+        let methods = vec![DexMethod { class_idx: 0, proto_idx: 0, name_idx: 3 }];
+        let method = EncodedMethod {
+            method_idx: 0,
+            access_flags: MethodAccessLevel::Static as u32,
+            code_offset: 4,
+            hiddenapi_flags: None,
+            source: DexSource { buffer: &buffer, data_base: 0, compact: None, anomalies: &STRICT, header_size: 0, field_ids_size: 0, method_ids_size: 1 },
+            code: OnceLock::new(),
+        };
+
+        let decompiler = Decompiler::new(&strings, &types, &protos, &[], &methods, &[], &[]);
+        decompiler.decompile(&method).unwrap()
+    }
+
+    #[test]
+    fn if_else() {
+        let insns = [
+            0x0138, 4,      // if-eqz v1, +4
+            0x1012,         // const/4 v0, 1
+            0x0228,         // goto +2
+            0x2012,         // const/4 v0, 2
+            0x000f,         // return v0
+        ];
+        assert_eq!(decompile(&insns, 0, &[]), vec![
+            "int v0;",
+            "if (arg0 != 0) {",
+            "    v0 = 1;",
+            "} else {",
+            "    v0 = 2;",
+            "}",
+            "return v0;",
+        ]);
+    }
+
+    #[test]
+    fn while_loop() {
+        let insns = [
+            0x0012,         // const/4 v0, 0
+            0x013d, 6,      // if-lez v1, +6
+            0x10b0,         // add-int/2addr v0, v1
+            0x01d8, 0xff01, // add-int/lit8 v1, v1, -1
+            0xfb28,         // goto -5
+            0x000f,         // return v0
+        ];
+        assert_eq!(decompile(&insns, 0, &[]), vec![
+            "int v0 = 0;",
+            "while (arg0 > 0) {",
+            "    v0 += arg0;",
+            "    arg0 += -1;",
+            "}",
+            "return v0;",
+        ]);
+    }
+
+    #[test]
+    fn switch() {
+        let insns = [
+            0x012b, 10, 0,  // packed-switch v1, +10
+            0x0012,         // const/4 v0, 0
+            0x000f,         // return v0
+            0x5012,         // const/4 v0, 5
+            0x000f,         // return v0
+            0x7012,         // const/4 v0, 7
+            0x000f,         // return v0
+            0x0000,         // nop, to align the payload
+            0x0100, 2,      // packed-switch-payload, 2 targets
+            0, 0,           // first key 0
+            5, 0, 7, 0,     // +5, +7
+        ];
+        assert_eq!(decompile(&insns, 0, &[]), vec![
+            "switch (arg0) {",
+            "    case 0:",
+            "        return 5;",
+            "    case 1:",
+            "        return 7;",
+            "    default:",
+            "        return 0;",
+            "}",
+        ]);
+    }
+
+    #[test]
+    fn try_catch() {
+        let insns = [
+            0x0013, 100,    // const/16 v0, 100
+            0x10b3,         // div-int/2addr v0, v1
+            0x000f,         // return v0
+            0x000d,         // move-exception v0
+            0x0012,         // const/4 v0, 0
+            0x000f,         // return v0
+        ];
+        let tries = [
+            2, 0, 0, 0, 1, 0, 1, 0, // the div-int, handlers at +1
+            1,                      // one handler list
+            1, 2, 4,                // Exception at 4
+        ];
+        assert_eq!(decompile(&insns, 1, &tries), vec![
+            "int v0 = 100;",
+            "try {",
+            "    v0 /= arg0;",
+            "} catch (java.lang.Exception e) {",
+            "    return 0;",
+            "}",
+            "return v0;",
+        ]);
+    }
+}
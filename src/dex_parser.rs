@@ -1,89 +1,231 @@
+use std::sync::OnceLock;
+
 use crate::binary_parser::BinaryParser;
 use crate::dex_types::*;
-use crate::util::{to_decimal, to_decimal_short, to_utf8, to_hex_string};
+use crate::util::{to_utf8, to_hex_string};
 use crate::instructions::parse_bytecode;
+use crate::version::{parse_version, supports, version_warnings, Feature};
+
+// The format allows no more than this many array dimensions in a type.
+const MAX_ARRAY_DIMENSIONS: usize = 255;
+// Nothing legitimate nests annotations and arrays anywhere near this deep;
+// it keeps a crafted value from overflowing the stack.
+const MAX_ENCODED_VALUE_DEPTH: usize = 256;
+
+pub fn parse_dex_file<'a>(parser: &mut BinaryParser<'a>) -> Result<DexFile<'a>, String> {
+    let header  = parse_header(parser)?;
+    let strings = parse_strings(parser, header.string_ids_offset as usize, header.string_ids_size as usize)?;
+    let types   = parse_types(parser, header.type_ids_offset as usize, header.type_ids_size as usize, &strings)?;
+    let protos  = parse_protos(parser, header.proto_ids_offset as usize, header.proto_ids_size as usize)?;
+    let fields  = parse_fields(parser, header.field_ids_offset as usize, header.field_ids_size as usize)?;
+    let methods = parse_methods(parser, header.method_ids_offset as usize, header.method_ids_size as usize)?;
+    let classes = parse_class_defs(parser, &header)?;
+    let map     = parse_map_list(parser, header.map_offset as usize)?;
+
+    // sections the version does not have are left out, the warnings below
+    // still report them
+    let version = parse_version(&header.dex_version, header.compact.is_some());
+    let method_handles = if supports(version, Feature::MethodHandles) { parse_method_handles(parser, &map)? } else { Vec::new() };
+    let call_sites     = if supports(version, Feature::CallSites) { parse_call_sites(parser, &map)? } else { Vec::new() };
+
+    let dex = DexFile {
+        header,
+        strings,
+        types,
+        protos,
+        fields,
+        methods,
+        classes,
+        map,
+        method_handles,
+        call_sites,
+    };
+
+    // warnings go to stderr so they never end up in JSON output
+    for w in version_warnings(&dex)? {
+        eprintln!("warning: {}", w);
+    }
+    Ok(dex)
+}
 
-pub fn parse_header(parser: &mut BinaryParser) -> DexHeader {
+pub fn parse_header(parser: &mut BinaryParser) -> Result<DexHeader, String> {
+    if parser.len() < 0x70 {
+        return Err(format!("Not a dex file, {} bytes is too short for a header", parser.len()));
+    }
     parser.seek_to(0);
-    let dex_magic = parser.take(8);
-    assert_eq!(dex_magic[0..3], [0x64, 0x65, 0x78]);
-    assert_eq!(dex_magic[3], 0x0a);
-    assert_eq!(dex_magic[7], 0x00);
-    let dex_version = dex_magic[4..7].to_vec();
-    let checksum = parser.take(4);
-    let sha1 = parser.take(20);
-    let file_size = parser.take(4);
-    let header_size = parser.take(4);
-    let endian_constant = parser.take(4);
-    let link_size = parser.take(4);
-    let link_offset = parser.take(4);
-    let map_offset = parser.take(4);
-    let string_ids_size = parser.take(4);
-    let string_ids_offset = parser.take(4);
-    let type_ids_size = parser.take(4);
-    let type_ids_offset = parser.take(4);
-    let proto_ids_size = parser.take(4);
-    let proto_ids_offset = parser.take(4);
-    let field_ids_size = parser.take(4);
-    let field_ids_offset = parser.take(4);
-    let method_ids_size = parser.take(4);
-    let method_ids_offset = parser.take(4);
-    let class_defs_size = parser.take(4);
-    let class_defs_offset = parser.take(4);
-    let data_size = parser.take(4);
-    let data_offset = parser.take(4);
-
-    let endianness = match endian_constant.as_slice() {
+    let dex_magic = parser.take(8)?;
+    let is_compact = match &dex_magic[0..4] {
+        b"dex\n" => false,
+        b"cdex" => true,
+        _ => return Err(format!("Not a dex file, the magic is {}", to_hex_string(dex_magic))),
+    };
+    if dex_magic[7] != 0x00 {
+        return Err(format!("Not a dex file, the magic is {}", to_hex_string(dex_magic)));
+    }
+    let dex_version = &dex_magic[4..7];
+    let checksum = parser.read_u32()?;
+    let sha1 = parser.take(20)?;
+    let file_size = parser.read_u32()?;
+    let header_size = parser.read_u32()?;
+    let endian_constant = parser.take(4)?;
+    let link_size = parser.read_u32()?;
+    let link_offset = parser.read_u32()?;
+    let map_offset = parser.read_u32()?;
+    let string_ids_size = parser.read_u32()?;
+    let string_ids_offset = parser.read_u32()?;
+    let type_ids_size = parser.read_u32()?;
+    let type_ids_offset = parser.read_u32()?;
+    let proto_ids_size = parser.read_u32()?;
+    let proto_ids_offset = parser.read_u32()?;
+    let field_ids_size = parser.read_u32()?;
+    let field_ids_offset = parser.read_u32()?;
+    let method_ids_size = parser.read_u32()?;
+    let method_ids_offset = parser.read_u32()?;
+    let class_defs_size = parser.read_u32()?;
+    let class_defs_offset = parser.read_u32()?;
+    let data_size = parser.read_u32()?;
+    let data_offset = parser.read_u32()?;
+
+    let version = parse_version(&to_utf8(dex_version), is_compact);
+    let (container_size, header_offset) = if supports(version, Feature::Container) && header_size >= 0x78 {
+        (Some(parser.read_u32()?), Some(parser.read_u32()?))
+    } else {
+        (None, None)
+    };
+
+    let compact = if is_compact {
+        Some(CompactDexHeader {
+            feature_flags: parser.read_u32()?,
+            debug_info_offsets_pos: parser.read_u32()?,
+            debug_info_offsets_table_offset: parser.read_u32()?,
+            debug_info_base: parser.read_u32()?,
+            owned_data_begin: parser.read_u32()?,
+            owned_data_end: parser.read_u32()?,
+        })
+    } else {
+        None
+    };
+    // every offset into the data section counts from data_off in compact dex
+    parser.set_data_base(if is_compact { data_offset as usize } else { 0 });
+
+    let endianness = match endian_constant {
         [0x78, 0x56, 0x34, 0x12] => Endianness::LittleEndian, 
         _ => Endianness::BigEndian,
     };
 
-    DexHeader {
-        dex_version: to_utf8(&dex_version),
-        checksum: to_decimal(&checksum),
-        sha1: to_hex_string(&sha1).replace(" ", ""),
-        file_size: to_decimal(&file_size),
-        header_size: to_decimal(&header_size),
+    Ok(DexHeader {
+        dex_version: to_utf8(dex_version),
+        checksum,
+        sha1: to_hex_string(sha1).replace(" ", ""),
+        file_size,
+        header_size,
         endianness,
-        link_size: to_decimal(&link_size),
-        link_offset: to_decimal(&link_offset),
-        map_offset: to_decimal(&map_offset),
-        string_ids_size: to_decimal(&string_ids_size),
-        string_ids_offset: to_decimal(&string_ids_offset),
-        type_ids_size: to_decimal(&type_ids_size),
-        type_ids_offset: to_decimal(&type_ids_offset),
-        proto_ids_size: to_decimal(&proto_ids_size),
-        proto_ids_offset: to_decimal(&proto_ids_offset),
-        field_ids_size: to_decimal(&field_ids_size),
-        field_ids_offset: to_decimal(&field_ids_offset),
-        method_ids_size: to_decimal(&method_ids_size),
-        method_ids_offset: to_decimal(&method_ids_offset),
-        class_defs_size: to_decimal(&class_defs_size),
-        class_defs_offset: to_decimal(&class_defs_offset),
-        data_size: to_decimal(&data_size),
-        data_offset: to_decimal(&data_offset),
-    }
-}
-
-pub fn parse_strings(parser: &mut BinaryParser, offset: usize, list_size: usize) -> Vec<String> {
-    let parse_item = Box::new(|p: &mut BinaryParser| {
-        let start_hex = &p.take(4);
-        let start_addr = to_decimal(start_hex) as usize;
-
-        p.seek_to(start_addr as usize);
-        let _length = p.parse_uleb128();
-
-        let s = p.take_until(0x00);
-        p.expect(0x00);
-
-        return to_utf8(&s);
-    });
-        
+        link_size,
+        link_offset,
+        map_offset,
+        string_ids_size,
+        string_ids_offset,
+        type_ids_size,
+        type_ids_offset,
+        proto_ids_size,
+        proto_ids_offset,
+        field_ids_size,
+        field_ids_offset,
+        method_ids_size,
+        method_ids_offset,
+        class_defs_size,
+        class_defs_offset,
+        data_size,
+        data_offset,
+        container_size,
+        header_offset,
+        compact,
+    })
+}
+
+// An offset of 0 means the file has no map, which only happens in files
+// that were not produced by dx or d8.
+pub fn parse_map_list(parser: &mut BinaryParser, offset: usize) -> Result<Vec<MapItem>, String> {
+    if offset == 0 {
+        return Ok(Vec::new());
+    }
+
+    // without a map the sections it alone locates are left out
+    let start = parser.data_base() + offset;
+    let map = parse_map_items(parser, offset);
+    Ok(parser.anomalies().recover(start, || "map list".to_string(), map)?.unwrap_or_default())
+}
+
+fn parse_map_items(parser: &mut BinaryParser, offset: usize) -> Result<Vec<MapItem>, String> {
+    parser.seek_to_data(offset);
+    let size = parser.read_u32()? as usize;
+    let start = parser.current_location();
+    let parse_item = |p: &mut BinaryParser| {
+        let type_code = p.read_u16()?;
+        let _unused = p.take(2)?;
+        let size = p.read_u32()?;
+        let offset = p.read_u32()?;
+        Ok(MapItem { type_code, size, offset })
+    };
+
+    parse_list_items(parser, start, size, 12, parse_item)
+}
+
+pub fn parse_method_handles(parser: &mut BinaryParser, map: &[MapItem]) -> Result<Vec<MethodHandleItem>, String> {
+    let section = match map.iter().find(|m| m.type_code == 0x0008) {
+        Some(m) => m,
+        None => return Ok(Vec::new()),
+    };
+    let parse_item = |p: &mut BinaryParser| {
+        let kind = MethodHandleKind::from_code(p.read_u16()?);
+        let _unused = p.take(2)?;
+        let field_or_method_idx = p.read_u16()? as usize;
+        Ok(MethodHandleItem { kind, field_or_method_idx })
+    };
+
+    parse_list_items(parser, section.offset as usize, section.size as usize, 8, parse_item)
+}
+
+pub fn parse_call_sites(parser: &mut BinaryParser, map: &[MapItem]) -> Result<Vec<Vec<EncodedValue>>, String> {
+    let section = match map.iter().find(|m| m.type_code == 0x0007) {
+        Some(m) => m,
+        None => return Ok(Vec::new()),
+    };
+    let parse_item = |p: &mut BinaryParser| {
+        let call_site_offset = p.read_u32()?;
+        parse_encoded_array_item(p, call_site_offset as usize)
+    };
+
+    parse_list_items(parser, section.offset as usize, section.size as usize, 4, parse_item)
+}
+
+pub fn parse_strings(parser: &mut BinaryParser, offset: usize, list_size: usize) -> Result<Vec<String>, String> {
+    let parse_item = |p: &mut BinaryParser| {
+        let start_addr = p.read_u32()? as usize;
+
+        // an unreadable string stays in the list as "" so the indexes of
+        // the ones after it do not shift
+        let string_data = p.data_base() + start_addr;
+        let s = parse_string_data(p, start_addr);
+        Ok(p.anomalies().recover(string_data, || "string".to_string(), s)?.unwrap_or_default())
+    };
+
     return parse_list_items(parser, offset, list_size, 4, parse_item);
 }
 
-pub fn parse_types(parser: &mut BinaryParser, offset: usize, list_size: usize, strings: &Vec<String>) -> Vec<DexType> {
+fn parse_string_data(p: &mut BinaryParser, offset: usize) -> Result<String, String> {
+    p.seek_to_data(offset);
+    let _length = p.parse_uleb128()?;
+
+    let s = p.take_until(0x00)?;
+    p.expect(0x00)?;
+
+    Ok(to_utf8(s))
+}
+
+pub fn parse_types(parser: &mut BinaryParser, offset: usize, list_size: usize, strings: &Vec<String>) -> Result<Vec<DexType>, String> {
     let mut result: Vec<DexType> = Vec::new();
+    let list_size = fit_list(parser, offset, list_size, 4, "type ids")?;
     let size_in_bytes = list_size * 4; // each type_id is 4 bytes
     parser.seek_to(offset);
     loop {
@@ -92,137 +234,265 @@ pub fn parse_types(parser: &mut BinaryParser, offset: usize, list_size: usize, s
             break;
         }
 
-        let idx = to_decimal(&parser.take(4));
-        let s = strings[idx as usize].clone();
+        let idx = parser.read_u32()?;
+        let s = match strings.get(idx as usize) {
+            Some(s) => s.clone(),
+            None if parser.anomalies().reject(addr, || format!("type name string {} is out of range", idx)) => String::new(),
+            None => return Err(format!("Type name string {} at {:#x} is out of range", idx, addr)),
+        };
+        let dimensions = s.chars().take_while(|c| *c == '[').count();
+        let parsed = if dimensions <= MAX_ARRAY_DIMENSIONS {
+            parse_type_descriptor(s.clone())
+        } else if parser.anomalies().reject(addr, || format!("type descriptor has more than {} array dimensions", MAX_ARRAY_DIMENSIONS)) {
+            TypeDescriptor::Void
+        } else {
+            return Err(format!("Type descriptor at {:#x} has more than {} array dimensions", addr, MAX_ARRAY_DIMENSIONS));
+        };
         let t = DexType {
-            raw: s.clone(),
-            parsed: parse_type_descriptor(s),
+            raw: s,
+            parsed,
         };
 
         result.push(t);
         parser.seek_to(addr + 4);
     }
 
-    return result;
+    return Ok(result);
 }
 
-pub fn parse_protos(parser: &mut BinaryParser, offset: usize, list_size: usize) -> Vec<DexProto> {
-    let parse_item = Box::new(|p: &mut BinaryParser| {
-        let shorty_idx = to_decimal(&p.take(4)) as StringIndex;
-        let return_type_idx = to_decimal(&p.take(4)) as TypeIndex;
-        let parameters_offset = to_decimal(&p.take(4));
-        
-        let mut parameter_type_idx_list: Vec<TypeIndex> = Vec::new();
-        if parameters_offset != 0 { // offset of 0 indicates no parameter_type_idx_list
-            p.seek_to(parameters_offset as usize);
-            let parameter_count = to_decimal(&p.take(4));
+pub fn parse_protos(parser: &mut BinaryParser, offset: usize, list_size: usize) -> Result<Vec<DexProto>, String> {
+    let parse_item = |p: &mut BinaryParser| {
+        let shorty_idx = p.read_u32()? as StringIndex;
+        let return_type_idx = p.read_u32()? as TypeIndex;
+        let parameters_offset = p.read_u32()?;
 
-            for _ in 0..parameter_count {
-                let param_idx = to_decimal_short(&p.take(2));
-                parameter_type_idx_list.push(param_idx as TypeIndex);
-            }
-        }
+        let parameter_type_idx_list = parse_type_list(p, parameters_offset as usize)?;
 
-        DexProto {
+        Ok(DexProto {
             shorty_idx,
             return_type_idx,
             parameters_offset,
             parameter_type_idx_list,
-        }
-    });
+        })
+    };
 
     return parse_list_items(parser, offset, list_size, 12, parse_item);
 }
 
-pub fn parse_fields(parser: &mut BinaryParser, offset: usize, list_size: usize) -> Vec<DexField> {
-    let parse_item = Box::new(|p: &mut BinaryParser| {
+pub fn parse_fields(parser: &mut BinaryParser, offset: usize, list_size: usize) -> Result<Vec<DexField>, String> {
+    let parse_item = |p: &mut BinaryParser| {
 
-        let class_idx = to_decimal_short(&p.take(2));
-        let type_idx = to_decimal_short(&p.take(2));
-        let name_idx = to_decimal(&p.take(4));
+        let class_idx = p.read_u16()?;
+        let type_idx = p.read_u16()?;
+        let name_idx = p.read_u32()?;
 
-        DexField {
+        Ok(DexField {
             class_idx: class_idx as ClassIndex,
             type_idx: type_idx as TypeIndex,
             name_idx: name_idx as StringIndex,
-        }
-    });
+        })
+    };
 
     return parse_list_items(parser, offset, list_size, 8, parse_item);
 }
 
-pub fn parse_methods(parser: &mut BinaryParser, offset: usize, list_size: usize) -> Vec<DexMethod> {
-    let parse_item = Box::new(|p: &mut BinaryParser| {
-        let class_idx = to_decimal_short(&p.take(2));
-        let proto_idx = to_decimal_short(&p.take(2));
-        let name_idx = to_decimal(&p.take(4));
+pub fn parse_methods(parser: &mut BinaryParser, offset: usize, list_size: usize) -> Result<Vec<DexMethod>, String> {
+    let parse_item = |p: &mut BinaryParser| {
+        let class_idx = p.read_u16()?;
+        let proto_idx = p.read_u16()?;
+        let name_idx = p.read_u32()?;
 
-        DexMethod {
+        Ok(DexMethod {
             class_idx: class_idx as ClassIndex,
             proto_idx: proto_idx as ProtoIndex,
             name_idx: name_idx as StringIndex,
-        }
-    });
+        })
+    };
 
     return parse_list_items(parser, offset, list_size, 8, parse_item);
 }
 
-pub fn parse_class_defs(parser: &mut BinaryParser, offset: usize, list_size: usize) -> Vec<DexClassDef> {
-    let parse_item = Box::new(|p: &mut BinaryParser| {
-        let class_idx = to_decimal(&p.take(4)) as TypeIndex;
-        let access_flags = to_decimal(&p.take(4));
-        let superclass_idx = to_decimal(&p.take(4)) as TypeIndex;
-        let interfaces_offset = to_decimal(&p.take(4));
-        let source_file_idx = to_decimal(&p.take(4)) as StringIndex;
-        let annotations_offset = to_decimal(&p.take(4));
-        let class_data_offset = to_decimal(&p.take(4));
-        let static_values_offset = to_decimal(&p.take(4));
-
-        if class_data_offset == 0 {
-            return DexClassDef {
-                class_idx,
-                access_flags,
-                superclass_idx,
-                interfaces_offset,
-                source_file_idx,
-                annotations_offset,
-                class_data_offset,
-                static_values_offset,
-                static_fields: Vec::new(),
-                instance_fields: Vec::new(),
-                direct_methods: Vec::new(),
-                virtual_methods: Vec::new(),
-            };
-        }
-
-        p.seek_to(class_data_offset as usize);
-        let static_fields_list_size = p.parse_uleb128();
-        let instance_fields_list_size = p.parse_uleb128();
-        let direct_methods_list_size = p.parse_uleb128();
-        let virtual_methods_list_size = p.parse_uleb128();
-
-        let static_fields = parse_encoded_fields(p, static_fields_list_size as usize);
-        let instance_fields = parse_encoded_fields(p, instance_fields_list_size as usize);
-        let direct_methods = parse_encoded_methods(p, direct_methods_list_size as usize);
-        let virtual_methods = parse_encoded_methods(p, virtual_methods_list_size as usize);
-
-        DexClassDef {
+// Class data and code items are only decoded when first used.
+pub fn parse_class_defs<'a>(parser: &mut BinaryParser<'a>, header: &DexHeader) -> Result<Vec<DexClassDef<'a>>, String> {
+    let source = DexSource {
+        buffer: parser.buffer(),
+        data_base: parser.data_base(),
+        compact: header.compact,
+        anomalies: parser.anomalies(),
+        header_size: header.header_size as usize,
+        field_ids_size: header.field_ids_size as usize,
+        method_ids_size: header.method_ids_size as usize,
+    };
+    let parse_item = move |p: &mut BinaryParser| {
+        let offset = p.current_location();
+        let class_idx = p.read_u32()? as TypeIndex;
+        let access_flags = p.read_u32()?;
+        let superclass_idx = p.read_u32()? as TypeIndex;
+        let interfaces_offset = p.read_u32()?;
+        let source_file_idx = p.read_u32()? as StringIndex;
+        let annotations_offset = p.read_u32()?;
+        let class_data_offset = p.read_u32()?;
+        let static_values_offset = p.read_u32()?;
+        let interfaces = parse_type_list(p, interfaces_offset as usize)?;
+        let static_values = parse_encoded_array_item(p, static_values_offset as usize)?;
+
+        Ok(DexClassDef {
+            offset,
             class_idx,
             access_flags,
             superclass_idx,
             interfaces_offset,
+            interfaces,
             source_file_idx,
             annotations_offset,
             class_data_offset,
             static_values_offset,
-            static_fields,
-            instance_fields,
-            direct_methods,
-            virtual_methods,
-        }
+            static_values,
+            hiddenapi_offset: None,
+            source,
+            class_data: OnceLock::new(),
+        })
+    };
+
+    let mut classes = parse_list_items(parser, header.class_defs_offset as usize, header.class_defs_size as usize, 32, parse_item)?;
+    let map = parse_map_list(parser, header.map_offset as usize)?;
+    let hiddenapi = map.iter().find(|m| m.type_code == 0xf000).map(|m| parser.data_base() + m.offset as usize);
+    let offsets = parse_hiddenapi_offsets(parser, &map, &mut classes);
+    parser.anomalies().recover(hiddenapi.unwrap_or(0), || "hiddenapi class data".to_string(), offsets)?;
+
+    // everything that lists classes names them by class_idx; dropped only
+    // after the hiddenapi offsets, which are per class def in file order
+    let type_ids = (header.type_ids_size as usize).min(parser.len().saturating_sub(header.type_ids_offset as usize) / 4);
+    let anomalies = parser.anomalies();
+    classes.retain(|c| {
+        c.class_idx < type_ids
+            || !anomalies.reject(c.offset, || format!("class def type {} is out of range", c.class_idx))
     });
+    Ok(classes)
+}
+
+// Class data and code items are decoded when first used, long after the
+// file was opened, so a strict parser's error reaches whoever asked for
+// them rather than the caller of parse_dex_file. Only a strict parser
+// gives one; a hardened one leaves out what it cannot decode.
+fn undecodable(e: String) -> String {
+    format!("{}; --hardened skips what cannot be decoded", e)
+}
+
+pub fn parse_class_data<'a>(c: &DexClassDef<'a>) -> Result<ClassData<'a>, String> {
+    if c.class_data_offset == 0 {
+        return Ok(ClassData::default());
+    }
+
+    let anomalies = c.source.anomalies;
+    let offset = c.source.data_base + c.class_data_offset as usize;
+    if offset < c.source.header_size && anomalies.reject(offset, || "class data points into the header".to_string()) {
+        return Ok(ClassData::default());
+    }
+    let mut data = anomalies.recover(offset, || "class data".to_string(), decode_class_data(c))
+        .map_err(undecodable)?
+        .unwrap_or_default();
+
+    if anomalies.is_hardened() {
+        let in_range = |idx: usize, size: usize, what: &str| {
+            idx < size || !anomalies.reject(offset, || format!("class data {} index {} is out of range", what, idx))
+        };
+        let fields = c.source.field_ids_size;
+        let methods = c.source.method_ids_size;
+        data.static_fields.retain(|f| in_range(f.field_idx, fields, "field"));
+        data.instance_fields.retain(|f| in_range(f.field_idx, fields, "field"));
+        data.direct_methods.retain(|m| in_range(m.method_idx, methods, "method"));
+        data.virtual_methods.retain(|m| in_range(m.method_idx, methods, "method"));
+    }
+    Ok(data)
+}
+
+fn decode_class_data<'a>(c: &DexClassDef<'a>) -> Result<ClassData<'a>, String> {
+    let p = &mut BinaryParser::with_anomalies(c.source.buffer, c.source.anomalies);
+    p.set_data_base(c.source.data_base);
+    p.seek_to_data(c.class_data_offset as usize);
+    let static_fields_list_size = p.parse_uleb128()?;
+    let instance_fields_list_size = p.parse_uleb128()?;
+    let direct_methods_list_size = p.parse_uleb128()?;
+    let virtual_methods_list_size = p.parse_uleb128()?;
+    // a field is at least two uleb128s and a method three
+    check_count(p, static_fields_list_size as usize + instance_fields_list_size as usize, 2, "fields")?;
+    check_count(p, direct_methods_list_size as usize + virtual_methods_list_size as usize, 3, "methods")?;
+
+    let mut data = ClassData {
+        static_fields: parse_encoded_fields(p, static_fields_list_size as usize)?,
+        instance_fields: parse_encoded_fields(p, instance_fields_list_size as usize)?,
+        direct_methods: parse_encoded_methods(p, direct_methods_list_size as usize, c.source)?,
+        virtual_methods: parse_encoded_methods(p, virtual_methods_list_size as usize, c.source)?,
+    };
+
+    // a uleb128 per field and method, in class_data order
+    if let Some(offset) = c.hiddenapi_offset {
+        p.seek_to_data(offset);
+        for f in data.static_fields.iter_mut().chain(data.instance_fields.iter_mut()) {
+            f.hiddenapi_flags = Some(HiddenApiFlags::from_value(p.parse_uleb128()?));
+        }
+        for m in data.direct_methods.iter_mut().chain(data.virtual_methods.iter_mut()) {
+            m.hiddenapi_flags = Some(HiddenApiFlags::from_value(p.parse_uleb128()?));
+        }
+    }
+    Ok(data)
+}
 
-    return parse_list_items(parser, offset, list_size, 32, parse_item);
+pub fn parse_code<'a>(m: &EncodedMethod<'a>) -> Result<Option<CodeItem<'a>>, String> {
+    if m.code_offset == 0 {
+        return Ok(None);
+    }
+
+    let anomalies = m.source.anomalies;
+    let offset = m.source.data_base + m.code_offset as usize;
+    if offset < m.source.header_size && anomalies.reject(offset, || format!("code of method {} points into the header", m.method_idx)) {
+        return Ok(None);
+    }
+    let p = &mut BinaryParser::with_anomalies(m.source.buffer, anomalies);
+    p.set_data_base(m.source.data_base);
+    let code = match &m.source.compact {
+        None => parse_code_item(p, m.code_offset as usize),
+        Some(c) => parse_compact_code_item(p, m.code_offset as usize, m.method_idx, c),
+    };
+    anomalies.recover(offset, || format!("code of method {}", m.method_idx), code).map_err(undecodable)
+}
+
+// The hiddenapi_class_data_item starts with its size and an offset per
+// class def, from the start of the item, to the flags of its members.
+// Classes with an offset of 0 have no flags.
+fn parse_hiddenapi_offsets(parser: &mut BinaryParser, map: &[MapItem], classes: &mut [DexClassDef]) -> Result<(), String> {
+    let section = match map.iter().find(|m| m.type_code == 0xf000) {
+        Some(m) => m.offset as usize,
+        None => return Ok(()),
+    };
+    for (i, c) in classes.iter_mut().enumerate() {
+        parser.seek_to_data(section + 4 + i * 4);
+        let offset = parser.read_u32()? as usize;
+        if offset != 0 {
+            c.hiddenapi_offset = Some(section + offset);
+        }
+    }
+    Ok(())
+}
+
+// An offset of 0 indicates an empty list.
+fn parse_type_list(p: &mut BinaryParser, offset: usize) -> Result<Vec<TypeIndex>, String> {
+    if offset == 0 {
+        return Ok(Vec::new());
+    }
+
+    // an unreadable list is left empty rather than losing the item it is in
+    let start = p.data_base() + offset;
+    let list = parse_type_list_items(p, offset);
+    Ok(p.anomalies().recover(start, || "type list".to_string(), list)?.unwrap_or_default())
+}
+
+fn parse_type_list_items(p: &mut BinaryParser, offset: usize) -> Result<Vec<TypeIndex>, String> {
+    p.seek_to_data(offset);
+    let size = p.read_u32()?;
+    check_count(p, size as usize, 2, "types")?;
+    (0..size).map(|_| Ok(p.read_u16()? as TypeIndex)).collect()
 }
 
 fn parse_list_items<T>(
@@ -230,9 +500,10 @@ fn parse_list_items<T>(
     offset: usize,
     list_size: usize,
     list_item_size: usize,
-    parse_item: Box<(Fn(&mut BinaryParser) -> T)>
-) -> Vec<T> {
+    parse_item: impl Fn(&mut BinaryParser) -> Result<T, String>
+) -> Result<Vec<T>, String> {
     let mut result: Vec<T> = Vec::new();
+    let list_size = fit_list(parser, offset, list_size, list_item_size, "items")?;
     let size_in_bytes = list_size * list_item_size;
     parser.seek_to(offset);
     loop {
@@ -241,96 +512,369 @@ fn parse_list_items<T>(
             break;
         }
 
-        let item = parse_item(parser);
+        let item = parse_item(parser)?;
         result.push(item);
         parser.seek_to(addr + list_item_size);
     }
 
-    return result;
+    return Ok(result);
+}
+
+// The number of items of a list that are read: all of them if they fit in
+// the file. In hardened mode a list that does not is cut down to the items
+// that do, so that a crafted size in the header does not send the parser
+// past the end; otherwise it is an error.
+fn fit_list(parser: &BinaryParser, offset: usize, list_size: usize, list_item_size: usize, what: &str) -> Result<usize, String> {
+    let fit = parser.len().saturating_sub(offset) / list_item_size;
+    if list_size <= fit {
+        return Ok(list_size);
+    }
+    let message = || format!("{} {} run past the end of the file, only {} fit", list_size, what, fit);
+    if parser.anomalies().reject(offset, message) {
+        return Ok(fit);
+    }
+    Err(format!("{} at {:#x}", message(), offset))
+}
+
+// Counts read from the file size the lists they are collected into, so one
+// that needs more than the bytes left is rejected before anything is
+// allocated for it.
+fn check_count(p: &BinaryParser, count: usize, min_item_size: usize, what: &str) -> Result<(), String> {
+    let remaining = p.len().saturating_sub(p.current_location());
+    if count.saturating_mul(min_item_size) > remaining {
+        return Err(format!("{} {} at {:#x} run past the end of the file", count, what, p.current_location()));
+    }
+    Ok(())
+}
+
+// Indexes are stored as the difference from the previous one.
+fn add_index_diff(last: Option<u32>, diff: u32, p: &BinaryParser) -> Result<u32, String> {
+    match last {
+        Some(idx) => idx.checked_add(diff).ok_or_else(|| format!("Index at {:#x} overflows", p.current_location())),
+        None => Ok(diff),
+    }
 }
 
-fn parse_encoded_fields(p: &mut BinaryParser, list_size: usize) -> Vec<EncodedField> {
-    let mut last_field_idx: Option<FieldIndex> = None;
+fn parse_encoded_fields(p: &mut BinaryParser, list_size: usize) -> Result<Vec<EncodedField>, String> {
+    let mut last_field_idx: Option<u32> = None;
     let mut fields: Vec<EncodedField> = Vec::new();
     for _ in 0..list_size {
-        let field_idx_diff = p.parse_uleb128() as FieldIndex;
-        let field_idx = match last_field_idx {
-            Some(idx) => idx + field_idx_diff,
-            None => field_idx_diff as FieldIndex,
-        };
-        let access_flags = p.parse_uleb128();
-        fields.push(EncodedField{ field_idx: (field_idx as FieldIndex), access_flags });
-        last_field_idx = Some(field_idx as FieldIndex);
+        let field_idx_diff = p.parse_uleb128()?;
+        let field_idx = add_index_diff(last_field_idx, field_idx_diff, p)?;
+        let access_flags = p.parse_uleb128()?;
+        fields.push(EncodedField{ field_idx: (field_idx as FieldIndex), access_flags, hiddenapi_flags: None });
+        last_field_idx = Some(field_idx);
     }
-    return fields;
+    return Ok(fields);
 }
 
-fn parse_encoded_methods(p: &mut BinaryParser, list_size: usize) -> Vec<EncodedMethod> {
+fn parse_encoded_methods<'a>(p: &mut BinaryParser, list_size: usize, source: DexSource<'a>) -> Result<Vec<EncodedMethod<'a>>, String> {
     let mut last_method_idx: Option<u32> = None;
     let mut methods: Vec<EncodedMethod> = Vec::new();
     for _ in 0..list_size {
-        let method_idx_diff = p.parse_uleb128();
-        let method_idx = match last_method_idx {
-            Some(idx) => idx + method_idx_diff,
-            None => method_idx_diff,
-        };
-        let access_flags = p.parse_uleb128();
-        let code_offset = p.parse_uleb128();
-        let mut code_item = None;
-        if code_offset != 0 {
-            let addr = p.current_location();
-            p.seek_to(code_offset as usize);
-            let registers_size = to_decimal_short(&p.take(2));
-            let ins_size = to_decimal_short(&p.take(2));
-            let outs_size = to_decimal_short(&p.take(2));
-            let tries_size = to_decimal_short(&p.take(2));
-            let debug_info_offset = to_decimal(&p.take(4));
-            let instructions_size = to_decimal(&p.take(4));
-
-            let instructions = parse_bytecode(p, (code_offset + 16) as usize, instructions_size as usize);
-
-            code_item = Some(CodeItem {
-                addr: code_offset,
-                registers_size,
-                ins_size,
-                outs_size,
-                tries_size,
-                debug_info_offset,
-                instructions_size,
-                instructions,
-            });
-
-            p.seek_to(addr);
+        let method_idx_diff = p.parse_uleb128()?;
+        let method_idx = add_index_diff(last_method_idx, method_idx_diff, p)?;
+        let access_flags = p.parse_uleb128()?;
+        let code_offset = p.parse_uleb128()?;
+        methods.push(EncodedMethod {
+            method_idx: (method_idx as MethodIndex),
+            access_flags,
+            code_offset,
+            hiddenapi_flags: None,
+            source,
+            code: OnceLock::new(),
+        });
+        last_method_idx = Some(method_idx);
+    }
+
+    return Ok(methods);
+}
+
+fn parse_code_item<'a>(p: &mut BinaryParser<'a>, code_offset: usize) -> Result<CodeItem<'a>, String> {
+    p.seek_to_data(code_offset);
+    let addr = p.current_location();
+    let registers_size = p.read_u16()?;
+    let ins_size = p.read_u16()?;
+    let outs_size = p.read_u16()?;
+    let tries_size = p.read_u16()?;
+    let debug_info_offset = p.read_u32()?;
+    let instructions_size = p.read_u32()?;
+    check_count(p, instructions_size as usize, 2, "code units")?;
+
+    let instructions_addr = addr + 16;
+    let instructions = parse_bytecode(p, instructions_addr, instructions_size as usize)?;
+    let tries = parse_tries(p, instructions_addr, instructions_size as usize, tries_size as usize)?;
+    let parameter_names = parse_parameter_names(p, debug_info_offset as usize)?;
+
+    Ok(CodeItem {
+        addr: addr as u32,
+        instructions_addr: instructions_addr as u32,
+        registers_size,
+        ins_size,
+        outs_size,
+        tries_size,
+        debug_info_offset,
+        instructions_size,
+        instructions,
+        tries,
+        parameter_names,
+    })
+}
+
+// Compact dex packs the four sizes into nibbles of one u16 and the
+// instruction count into the top 11 bits of another, whose low 5 bits flag
+// the values too large for that. Those are added from a "preheader" of u16s
+// stored just before the code item, read backwards. The debug info offset
+// is not in the code item at all but in a table indexed by method.
+fn parse_compact_code_item<'a>(p: &mut BinaryParser<'a>, code_offset: usize, method_idx: MethodIndex, compact: &CompactDexHeader) -> Result<CodeItem<'a>, String> {
+    p.seek_to_data(code_offset);
+    let addr = p.current_location();
+    let fields = p.read_u16()?;
+    let insns_count_and_flags = p.read_u16()?;
+
+    let mut registers_size = fields >> 12 & 0xf;
+    let mut ins_size = fields >> 8 & 0xf;
+    let mut outs_size = fields >> 4 & 0xf;
+    let mut tries_size = fields & 0xf;
+    let mut instructions_size = (insns_count_and_flags >> 5) as u32;
+
+    let mut preheader = addr;
+    let mut previous = |p: &mut BinaryParser| {
+        preheader = preheader.checked_sub(2).ok_or_else(|| format!("Code item preheader at {:#x} starts before the file", addr))?;
+        p.seek_to(preheader);
+        p.read_u16()
+    };
+    let too_large = || format!("Code item sizes at {:#x} overflow", addr);
+    if insns_count_and_flags & 0x10 != 0 {
+        let low = previous(p)? as u32;
+        let high = previous(p)? as u32;
+        instructions_size = instructions_size.checked_add(low).and_then(|x| x.checked_add(high << 16)).ok_or_else(too_large)?;
+    }
+    if insns_count_and_flags & 0x1 != 0 {
+        registers_size = registers_size.checked_add(previous(p)?).ok_or_else(too_large)?;
+    }
+    if insns_count_and_flags & 0x2 != 0 {
+        ins_size = ins_size.checked_add(previous(p)?).ok_or_else(too_large)?;
+    }
+    if insns_count_and_flags & 0x4 != 0 {
+        outs_size = outs_size.checked_add(previous(p)?).ok_or_else(too_large)?;
+    }
+    if insns_count_and_flags & 0x8 != 0 {
+        tries_size = tries_size.checked_add(previous(p)?).ok_or_else(too_large)?;
+    }
+    // stored without the ins
+    registers_size = registers_size.checked_add(ins_size).ok_or_else(too_large)?;
+
+    let debug_info_offset = compact_debug_info_offset(p, compact, method_idx)?;
+    p.seek_to(addr + 4);
+    check_count(p, instructions_size as usize, 2, "code units")?;
+    let instructions_addr = addr + 4;
+    let instructions = parse_bytecode(p, instructions_addr, instructions_size as usize)?;
+    let tries = parse_tries(p, instructions_addr, instructions_size as usize, tries_size as usize)?;
+    let parameter_names = parse_parameter_names(p, debug_info_offset as usize)?;
+
+    Ok(CodeItem {
+        addr: addr as u32,
+        instructions_addr: instructions_addr as u32,
+        registers_size,
+        ins_size,
+        outs_size,
+        tries_size,
+        debug_info_offset,
+        instructions_size,
+        instructions,
+        tries,
+        parameter_names,
+    })
+}
+
+// The table has a u32 per block of 16 methods pointing at a big endian
+// 16-bit mask of the methods in the block that have debug info, followed
+// by one uleb128 per set bit. Each is the distance from the previous
+// method's offset, starting from debug_info_base. 0 means no debug info.
+fn compact_debug_info_offset(p: &mut BinaryParser, compact: &CompactDexHeader, method_idx: MethodIndex) -> Result<u32, String> {
+    let table = compact.debug_info_offsets_pos as usize;
+    p.seek_to_data(table + compact.debug_info_offsets_table_offset as usize + method_idx / 16 * 4);
+    let block = p.read_u32()? as usize;
+
+    p.seek_to_data(table + block);
+    let mask = (p.next()? as u16) << 8 | p.next()? as u16;
+    let bit = method_idx % 16;
+    if mask & (1 << bit) == 0 {
+        return Ok(0);
+    }
+
+    let mut offset = compact.debug_info_base;
+    for _ in 0..=(mask & ((1 << bit) - 1)).count_ones() {
+        offset = offset.wrapping_add(p.parse_uleb128()?);
+    }
+    Ok(offset)
+}
+
+// `instructions_start` is absolute, like the addresses in the result.
+fn parse_tries(p: &mut BinaryParser, instructions_start: usize, instructions_size: usize, tries_size: usize) -> Result<Vec<TryItem>, String> {
+    if tries_size == 0 {
+        return Ok(Vec::new());
+    }
+
+    // padded to keep the tries 4-byte aligned
+    let tries_start = (instructions_start + instructions_size * 2 + 3) & !3;
+    let handlers_start = tries_start + tries_size * 8;
+
+    p.seek_to(tries_start);
+    let mut raw_tries: Vec<(u32, u16, u16)> = Vec::new();
+    for _ in 0..tries_size {
+        let start_addr = p.read_u32()?;
+        let instructions_count = p.read_u16()?;
+        let handler_offset = p.read_u16()?;
+        raw_tries.push((start_addr, instructions_count, handler_offset));
+    }
+
+    let mut tries: Vec<TryItem> = Vec::new();
+    for (start_addr, instructions_count, handler_offset) in raw_tries {
+        p.seek_to(handlers_start + handler_offset as usize);
+        let size = p.parse_sleb128()?;
+        check_count(p, size.unsigned_abs() as usize, 2, "catch handlers")?;
+
+        let mut handlers: Vec<CatchHandler> = Vec::new();
+        for _ in 0..size.unsigned_abs() {
+            let type_idx = p.parse_uleb128()? as TypeIndex;
+            let addr = p.parse_uleb128()? as usize;
+            handlers.push(CatchHandler { type_idx, addr: instructions_start + addr * 2 });
         }
 
-        methods.push(EncodedMethod{ method_idx: (method_idx as MethodIndex), access_flags, code_offset, code_item });
-        last_method_idx = Some(method_idx);
+        // a non-positive size means the list ends with a catch-all handler
+        let catch_all_addr = if size <= 0 {
+            Some(instructions_start + p.parse_uleb128()? as usize * 2)
+        } else {
+            None
+        };
+
+        tries.push(TryItem {
+            start_addr: instructions_start + start_addr as usize * 2,
+            end_addr: instructions_start + (start_addr as usize + instructions_count as usize) * 2,
+            handlers,
+            catch_all_addr,
+        });
     }
 
-    return methods;
-}
-
-fn parse_type_descriptor(s: String) -> TypeDescriptor {
-    match s.chars().map(|x| x.clone()).collect::<Vec<char>>().as_slice() {
-        ['V'] => TypeDescriptor::Void,
-        ['Z'] => TypeDescriptor::Boolean,
-        ['B'] => TypeDescriptor::Byte,
-        ['S'] => TypeDescriptor::Short,
-        ['C'] => TypeDescriptor::Char,
-        ['I'] => TypeDescriptor::Int,
-        ['J'] => TypeDescriptor::Long,
-        ['F'] => TypeDescriptor::Float,
-        ['D'] => TypeDescriptor::Double,
-        c if *c.first().unwrap() == '[' => {
-            let rest = c.iter().skip(1).collect::<String>();
-            let nested_descriptor = parse_type_descriptor(rest);
-            TypeDescriptor::Array(Box::new(nested_descriptor))
-        },
-        c if *c.first().unwrap() == 'L' => {
-            let mut class_name = c.iter().skip(1).collect::<String>();
+    Ok(tries)
+}
+
+// Only the header of a debug_info_item; the state machine that follows it
+// maps addresses to lines and locals and is not decoded.
+fn parse_parameter_names(p: &mut BinaryParser, offset: usize) -> Result<Vec<Option<StringIndex>>, String> {
+    if offset == 0 {
+        return Ok(Vec::new());
+    }
+
+    p.seek_to_data(offset);
+    let _line_start = p.parse_uleb128()?;
+    let parameters_size = p.parse_uleb128()?;
+    check_count(p, parameters_size as usize, 1, "parameter names")?;
+    (0..parameters_size)
+        // uleb128p1, so 0 is NO_INDEX
+        .map(|_| Ok((p.parse_uleb128()? as StringIndex).checked_sub(1)))
+        .collect()
+}
+
+// An offset of 0 indicates an empty array.
+fn parse_encoded_array_item(p: &mut BinaryParser, offset: usize) -> Result<Vec<EncodedValue>, String> {
+    if offset == 0 {
+        return Ok(Vec::new());
+    }
+
+    let start = p.data_base() + offset;
+    p.seek_to_data(offset);
+    let array = parse_encoded_array(p, 0);
+    Ok(p.anomalies().recover(start, || "encoded array".to_string(), array)?.unwrap_or_default())
+}
+
+fn parse_encoded_array(p: &mut BinaryParser, depth: usize) -> Result<Vec<EncodedValue>, String> {
+    let size = p.parse_uleb128()?;
+    check_count(p, size as usize, 1, "encoded values")?;
+    (0..size).map(|_| parse_encoded_value(p, depth + 1)).collect()
+}
+
+fn parse_encoded_value(p: &mut BinaryParser, depth: usize) -> Result<EncodedValue, String> {
+    if depth > MAX_ENCODED_VALUE_DEPTH {
+        return Err(format!("Encoded value at {:#x} is nested more than {} deep", p.current_location(), MAX_ENCODED_VALUE_DEPTH));
+    }
+    let header = p.next()?;
+    let value_arg = (header >> 5) as usize;
+    let value_type = header & 0x1f;
+
+    // all the numeric forms store value_arg + 1 little endian bytes
+    let read = |p: &mut BinaryParser| -> Result<(u64, usize), String> {
+        let bytes = p.take(value_arg + 1)?;
+        let value = bytes.iter().rev().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+        Ok((value, bytes.len() * 8))
+    };
+    let signed = |(value, bits): (u64, usize)| ((value << (64 - bits)) as i64) >> (64 - bits);
+
+    let value = match value_type {
+        0x00 => EncodedValue::Byte(signed(read(p)?) as i8),
+        0x02 => EncodedValue::Short(signed(read(p)?) as i16),
+        0x03 => EncodedValue::Char(read(p)?.0 as u16),
+        0x04 => EncodedValue::Int(signed(read(p)?) as i32),
+        0x06 => EncodedValue::Long(signed(read(p)?)),
+        // floating point values are zero extended to the right
+        0x10 => {
+            let (value, bits) = read(p)?;
+            if bits > 32 {
+                return Err(format!("Float at {:#x} has {} bytes", p.current_location(), bits / 8));
+            }
+            EncodedValue::Float(f32::from_bits((value << (32 - bits)) as u32))
+        }
+        0x11 => {
+            let (value, bits) = read(p)?;
+            EncodedValue::Double(f64::from_bits(value << (64 - bits)))
+        }
+        0x15 => EncodedValue::MethodType(read(p)?.0 as ProtoIndex),
+        0x16 => EncodedValue::MethodHandle(read(p)?.0 as usize),
+        0x17 => EncodedValue::String(read(p)?.0 as StringIndex),
+        0x18 => EncodedValue::Type(read(p)?.0 as TypeIndex),
+        0x19 => EncodedValue::Field(read(p)?.0 as FieldIndex),
+        0x1a => EncodedValue::Method(read(p)?.0 as MethodIndex),
+        0x1b => EncodedValue::Enum(read(p)?.0 as FieldIndex),
+        0x1c => EncodedValue::Array(parse_encoded_array(p, depth)?),
+        0x1d => {
+            let type_idx = p.parse_uleb128()? as TypeIndex;
+            let size = p.parse_uleb128()?;
+            check_count(p, size as usize, 2, "annotation elements")?;
+            let elements = (0..size)
+                .map(|_| Ok((p.parse_uleb128()? as StringIndex, parse_encoded_value(p, depth + 1)?)))
+                .collect::<Result<_, String>>()?;
+            EncodedValue::Annotation(type_idx, elements)
+        }
+        0x1e => EncodedValue::Null,
+        0x1f => EncodedValue::Boolean(value_arg != 0),
+        _ => return Err(format!("Unknown encoded value type {:#04x}", value_type)),
+    };
+    Ok(value)
+}
+
+// Array dimensions are counted rather than recursed into, so a descriptor
+// with a great many of them cannot overflow the stack; files are held to
+// MAX_ARRAY_DIMENSIONS when their types are parsed.
+pub fn parse_type_descriptor(s: String) -> TypeDescriptor {
+    let dimensions = s.chars().take_while(|c| *c == '[').count();
+    let element = match &s[dimensions..] {
+        "V" => TypeDescriptor::Void,
+        "Z" => TypeDescriptor::Boolean,
+        "B" => TypeDescriptor::Byte,
+        "S" => TypeDescriptor::Short,
+        "C" => TypeDescriptor::Char,
+        "I" => TypeDescriptor::Int,
+        "J" => TypeDescriptor::Long,
+        "F" => TypeDescriptor::Float,
+        "D" => TypeDescriptor::Double,
+        c if c.starts_with('L') => {
+            let mut class_name = c[1..].to_string();
             class_name.pop(); // last char is always ';' so drop it
             TypeDescriptor::Class(class_name)
         }
-        _ => TypeDescriptor:: Void,
-    }
-}
\ No newline at end of file
+        _ => TypeDescriptor::Void,
+    };
+    (0..dimensions).fold(element, |t, _| TypeDescriptor::Array(Box::new(t)))
+}
@@ -1,8 +1,14 @@
 #![allow(dead_code, unused_variables)]
 
+use std::borrow::Cow;
 use std::fmt;
 use std::fmt::Display;
+use std::sync::OnceLock;
+use crate::dex_parser::{parse_class_data, parse_code};
+use crate::anomaly::Anomalies;
 use crate::instructions::*;
+use serde::Serialize;
+use serde::ser::{Error as _, SerializeStruct, Serializer};
 
 pub type StringIndex = usize;
 pub type TypeIndex   = usize;
@@ -10,8 +16,27 @@ pub type ProtoIndex  = usize;
 pub type FieldIndex  = usize;
 pub type MethodIndex = usize;
 pub type ClassIndex  = usize;
+pub type CallSiteIndex     = usize;
+pub type MethodHandleIndex = usize;
 
+// Every table of a single dex file, parsed up front.
 #[derive(Debug)]
+pub struct DexFile<'a> {
+    pub header: DexHeader,
+    pub strings: Vec<String>,
+    pub types: Vec<DexType>,
+    pub protos: Vec<DexProto>,
+    pub fields: Vec<DexField>,
+    pub methods: Vec<DexMethod>,
+    pub classes: Vec<DexClassDef<'a>>,
+    pub map: Vec<MapItem>,
+    pub method_handles: Vec<MethodHandleItem>,
+    // each call site is an encoded array of a bootstrap method handle, a
+    // method name, a method type and any extra bootstrap arguments
+    pub call_sites: Vec<Vec<EncodedValue>>,
+}
+
+#[derive(Debug, Serialize)]
 pub struct DexHeader {
     pub dex_version: String,
     pub checksum: u32,
@@ -36,21 +61,68 @@ pub struct DexHeader {
     pub class_defs_offset: u32,
     pub data_size: u32,
     pub data_offset: u32,
+    // only in headers of 0x78 bytes or more, when the file is one of
+    // several dex files sharing a container
+    pub container_size: Option<u32>,
+    pub header_offset: Option<u32>,
+    // only in compact dex files
+    pub compact: Option<CompactDexHeader>,
 }
 
-#[derive(Debug)]
+// The fields ART appends to the header of a compact dex (`cdex`) file.
+// Offsets are relative to the data section, which may be shared with
+// other dex files in the same vdex.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CompactDexHeader {
+    pub feature_flags: u32,
+    pub debug_info_offsets_pos: u32,
+    pub debug_info_offsets_table_offset: u32,
+    pub debug_info_base: u32,
+    pub owned_data_begin: u32,
+    pub owned_data_end: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MapItem {
+    pub type_code: u16,
+    pub size: u32,
+    pub offset: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MethodHandleItem {
+    pub kind: MethodHandleKind,
+    // a field for the static/instance put and get kinds, a method otherwise
+    pub field_or_method_idx: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum MethodHandleKind {
+    StaticPut,
+    StaticGet,
+    InstancePut,
+    InstanceGet,
+    InvokeStatic,
+    InvokeInstance,
+    InvokeConstructor,
+    InvokeDirect,
+    InvokeInterface,
+    Unknown(u16),
+}
+
+#[derive(Debug, Serialize)]
 pub enum Endianness {
     LittleEndian,
     BigEndian,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DexType {
     pub raw: String,
     pub parsed: TypeDescriptor,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum TypeDescriptor {
     Void,
     Boolean,
@@ -65,7 +137,7 @@ pub enum TypeDescriptor {
     Array(Box<TypeDescriptor>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct DexProto {
     pub shorty_idx: StringIndex,
     pub return_type_idx: TypeIndex,
@@ -73,60 +145,247 @@ pub struct DexProto {
     pub parameter_type_idx_list: Vec<TypeIndex>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DexField {
     pub class_idx: ClassIndex,
     pub type_idx: TypeIndex,
     pub name_idx: StringIndex,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct DexMethod {
     pub class_idx: TypeIndex,
     pub proto_idx: ProtoIndex,
     pub name_idx: StringIndex,
 }
 
+// What class data and code items are decoded from when first used.
+#[derive(Debug, Clone, Copy)]
+pub struct DexSource<'a> {
+    pub buffer: &'a [u8],
+    pub data_base: usize,
+    pub compact: Option<CompactDexHeader>,
+    // whether what is malformed in them is reported or an error
+    pub anomalies: &'a Anomalies,
+    // from the header, what class data and code items are checked against
+    pub header_size: usize,
+    pub field_ids_size: usize,
+    pub method_ids_size: usize,
+}
+
 #[derive(Debug)]
-pub struct DexClassDef {
+pub struct DexClassDef<'a> {
+    // where the class_def_item is, to report what is wrong with it
+    pub offset: usize,
     pub class_idx: TypeIndex,
     pub access_flags: u32,
     pub superclass_idx: TypeIndex,
     pub interfaces_offset: u32,
+    pub interfaces: Vec<TypeIndex>,
     pub source_file_idx: StringIndex,
     pub annotations_offset: u32,
     pub class_data_offset: u32,
     pub static_values_offset: u32,
+    // initial values of the first static fields, in the same order; fields
+    // past the end of the list start out as 0 or null
+    pub static_values: Vec<EncodedValue>,
+    // data offset of this class's hiddenapi flags, if the file has any
+    pub hiddenapi_offset: Option<usize>,
+    pub source: DexSource<'a>,
+    pub class_data: OnceLock<Result<ClassData<'a>, String>>,
+}
+
+#[derive(Debug, Default)]
+pub struct ClassData<'a> {
     pub static_fields: Vec<EncodedField>,
     pub instance_fields: Vec<EncodedField>,
-    pub direct_methods: Vec<EncodedMethod>,
-    pub virtual_methods: Vec<EncodedMethod>,
+    pub direct_methods: Vec<EncodedMethod<'a>>,
+    pub virtual_methods: Vec<EncodedMethod<'a>>,
 }
 
-#[derive(Debug)]
+impl<'a> DexClassDef<'a> {
+    // Decoded on first use, so an error in the class data only turns up
+    // here; it is kept and returned again on every later call.
+    pub fn class_data(&self) -> Result<&ClassData<'a>, String> {
+        self.class_data.get_or_init(|| parse_class_data(self)).as_ref().map_err(Clone::clone)
+    }
+
+    pub fn static_fields(&self) -> Result<&[EncodedField], String> {
+        Ok(&self.class_data()?.static_fields)
+    }
+
+    pub fn instance_fields(&self) -> Result<&[EncodedField], String> {
+        Ok(&self.class_data()?.instance_fields)
+    }
+
+    pub fn direct_methods(&self) -> Result<&[EncodedMethod<'a>], String> {
+        Ok(&self.class_data()?.direct_methods)
+    }
+
+    pub fn virtual_methods(&self) -> Result<&[EncodedMethod<'a>], String> {
+        Ok(&self.class_data()?.virtual_methods)
+    }
+
+    // static fields before instance ones
+    pub fn fields(&self) -> Result<impl Iterator<Item = &EncodedField>, String> {
+        let data = self.class_data()?;
+        Ok(data.static_fields.iter().chain(data.instance_fields.iter()))
+    }
+
+    // direct methods before virtual ones
+    pub fn methods(&self) -> Result<impl Iterator<Item = &EncodedMethod<'a>>, String> {
+        let data = self.class_data()?;
+        Ok(data.direct_methods.iter().chain(data.virtual_methods.iter()))
+    }
+}
+
+impl<'a> Serialize for DexClassDef<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let data = self.class_data().map_err(S::Error::custom)?;
+        let mut s = serializer.serialize_struct("DexClassDef", 14)?;
+        s.serialize_field("class_idx", &self.class_idx)?;
+        s.serialize_field("access_flags", &self.access_flags)?;
+        s.serialize_field("superclass_idx", &self.superclass_idx)?;
+        s.serialize_field("interfaces_offset", &self.interfaces_offset)?;
+        s.serialize_field("interfaces", &self.interfaces)?;
+        s.serialize_field("source_file_idx", &self.source_file_idx)?;
+        s.serialize_field("annotations_offset", &self.annotations_offset)?;
+        s.serialize_field("class_data_offset", &self.class_data_offset)?;
+        s.serialize_field("static_values_offset", &self.static_values_offset)?;
+        s.serialize_field("static_values", &self.static_values)?;
+        s.serialize_field("static_fields", &data.static_fields)?;
+        s.serialize_field("instance_fields", &data.instance_fields)?;
+        s.serialize_field("direct_methods", &data.direct_methods)?;
+        s.serialize_field("virtual_methods", &data.virtual_methods)?;
+        s.end()
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct EncodedField {
     pub field_idx: FieldIndex,
     pub access_flags: u32,
+    // only in platform dex files, from the hiddenapi_class_data_item
+    pub hiddenapi_flags: Option<HiddenApiFlags>,
 }
 
 #[derive(Debug)]
-pub struct EncodedMethod {
+pub struct EncodedMethod<'a> {
     pub method_idx: MethodIndex,
     pub access_flags: u32,
     pub code_offset: u32,
-    pub code_item: Option<CodeItem>,
+    pub hiddenapi_flags: Option<HiddenApiFlags>,
+    pub source: DexSource<'a>,
+    pub code: OnceLock<Result<Option<CodeItem<'a>>, String>>,
 }
 
-#[derive(Debug, Clone)]
-pub struct CodeItem {
+impl<'a> EncodedMethod<'a> {
+    // None for abstract and native methods.
+    pub fn code_item(&self) -> Result<Option<&CodeItem<'a>>, String> {
+        match self.code.get_or_init(|| parse_code(self)) {
+            Ok(code) => Ok(code.as_ref()),
+            Err(e) => Err(e.clone()),
+        }
+    }
+
+    // The code item without caching it, for a single pass over more code
+    // than should stay in memory at once.
+    pub fn decode_code_item(&self) -> Result<Option<Cow<'_, CodeItem<'a>>>, String> {
+        match self.code.get() {
+            Some(Ok(code)) => Ok(code.as_ref().map(Cow::Borrowed)),
+            Some(Err(e)) => Err(e.clone()),
+            None => Ok(parse_code(self)?.map(Cow::Owned)),
+        }
+    }
+}
+
+impl<'a> Serialize for EncodedMethod<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("EncodedMethod", 5)?;
+        s.serialize_field("method_idx", &self.method_idx)?;
+        s.serialize_field("access_flags", &self.access_flags)?;
+        s.serialize_field("code_offset", &self.code_offset)?;
+        s.serialize_field("code_item", &self.decode_code_item().map_err(S::Error::custom)?)?;
+        s.serialize_field("hiddenapi_flags", &self.hiddenapi_flags)?;
+        s.end()
+    }
+}
+
+// Which apps may use a platform member: anything in the SDK, or else
+// only apps targeting at most the given version, or none at all.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum ApiList {
+    Sdk,
+    Unsupported,
+    Blocked,
+    MaxTargetO,
+    MaxTargetP,
+    MaxTargetQ,
+    MaxTargetR,
+    MaxTargetS,
+    Unknown(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct HiddenApiFlags {
+    pub list: ApiList,
+    // usable by the platform's own apps and by tests, whatever the list
+    pub core_platform_api: bool,
+    pub test_api: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CodeItem<'a> {
     pub addr: u32,
+    // where the bytecode starts, after a header that is shorter in compact dex
+    pub instructions_addr: u32,
     pub registers_size: u16,
     pub ins_size: u16,
     pub outs_size: u16,
     pub tries_size: u16,
     pub debug_info_offset: u32,
     pub instructions_size: u32,
-    pub instructions: Vec<Instruction>,
+    pub instructions: Vec<Instruction<'a>>,
+    pub tries: Vec<TryItem>,
+    // from the debug info, not counting `this`; None where the name was stripped
+    pub parameter_names: Vec<Option<StringIndex>>,
+}
+
+// Addresses are absolute file offsets, the same as `Instruction::addr`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TryItem {
+    pub start_addr: usize,
+    pub end_addr: usize,
+    pub handlers: Vec<CatchHandler>,
+    pub catch_all_addr: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CatchHandler {
+    pub type_idx: TypeIndex,
+    pub addr: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum EncodedValue {
+    Byte(i8),
+    Short(i16),
+    Char(u16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    MethodType(ProtoIndex),
+    MethodHandle(usize),
+    String(StringIndex),
+    Type(TypeIndex),
+    Field(FieldIndex),
+    Method(MethodIndex),
+    Enum(FieldIndex),
+    Array(Vec<EncodedValue>),
+    Annotation(TypeIndex, Vec<(StringIndex, EncodedValue)>),
+    Null,
+    Boolean(bool),
 }
 
 #[derive(Debug, Clone, PartialEq, Hash)]
@@ -174,6 +433,207 @@ pub enum MethodAccessLevel {
     DeclaredSynchronized = 0x20000,
 }
 
+impl DexHeader {
+    // `035` is 35; None if the magic does not hold a number
+    pub fn version(&self) -> Option<u32> {
+        self.dex_version.parse().ok()
+    }
+}
+
+impl MapItem {
+    pub fn type_name(&self) -> &'static str {
+        match self.type_code {
+            0x0000 => "header_item",
+            0x0001 => "string_id_item",
+            0x0002 => "type_id_item",
+            0x0003 => "proto_id_item",
+            0x0004 => "field_id_item",
+            0x0005 => "method_id_item",
+            0x0006 => "class_def_item",
+            0x0007 => "call_site_id_item",
+            0x0008 => "method_handle_item",
+            0x1000 => "map_list",
+            0x1001 => "type_list",
+            0x1002 => "annotation_set_ref_list",
+            0x1003 => "annotation_set_item",
+            0x2000 => "class_data_item",
+            0x2001 => "code_item",
+            0x2002 => "string_data_item",
+            0x2003 => "debug_info_item",
+            0x2004 => "annotation_item",
+            0x2005 => "encoded_array_item",
+            0x2006 => "annotations_directory_item",
+            0xf000 => "hiddenapi_class_data_item",
+            _ => "unknown",
+        }
+    }
+}
+
+impl MethodHandleKind {
+    pub fn from_code(code: u16) -> MethodHandleKind {
+        match code {
+            0x00 => MethodHandleKind::StaticPut,
+            0x01 => MethodHandleKind::StaticGet,
+            0x02 => MethodHandleKind::InstancePut,
+            0x03 => MethodHandleKind::InstanceGet,
+            0x04 => MethodHandleKind::InvokeStatic,
+            0x05 => MethodHandleKind::InvokeInstance,
+            0x06 => MethodHandleKind::InvokeConstructor,
+            0x07 => MethodHandleKind::InvokeDirect,
+            0x08 => MethodHandleKind::InvokeInterface,
+            x => MethodHandleKind::Unknown(x),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MethodHandleKind::StaticPut         => "static-put",
+            MethodHandleKind::StaticGet         => "static-get",
+            MethodHandleKind::InstancePut       => "instance-put",
+            MethodHandleKind::InstanceGet       => "instance-get",
+            MethodHandleKind::InvokeStatic      => "invoke-static",
+            MethodHandleKind::InvokeInstance    => "invoke-instance",
+            MethodHandleKind::InvokeConstructor => "invoke-constructor",
+            MethodHandleKind::InvokeDirect      => "invoke-direct",
+            MethodHandleKind::InvokeInterface   => "invoke-interface",
+            MethodHandleKind::Unknown(_)        => "unknown",
+        }
+    }
+
+    pub fn is_field_accessor(self) -> bool {
+        matches!(self,
+              MethodHandleKind::StaticPut
+            | MethodHandleKind::StaticGet
+            | MethodHandleKind::InstancePut
+            | MethodHandleKind::InstanceGet)
+    }
+}
+
+impl HiddenApiFlags {
+    pub fn from_value(value: u32) -> HiddenApiFlags {
+        let list = match value & 0x7 {
+            0 => ApiList::Sdk,
+            1 => ApiList::Unsupported,
+            2 => ApiList::Blocked,
+            3 => ApiList::MaxTargetO,
+            4 => ApiList::MaxTargetP,
+            5 => ApiList::MaxTargetQ,
+            6 => ApiList::MaxTargetR,
+            _ => ApiList::MaxTargetS,
+        };
+        // values past the known domain flags are kept rather than guessed at
+        let list = if value >> 5 != 0 { ApiList::Unknown(value) } else { list };
+        HiddenApiFlags { list, core_platform_api: value & 0x8 != 0, test_api: value & 0x10 != 0 }
+    }
+
+    // Whether apps calling the member are refused or warned about,
+    // depending on their target SDK.
+    pub fn is_restricted(&self) -> bool {
+        self.list != ApiList::Sdk
+    }
+
+    // `blocked`, `max-target-o,core-platform-api`, as in the platform's
+    // hiddenapi-flags.csv
+    pub fn name(&self) -> String {
+        let list = match self.list {
+            ApiList::Sdk => "sdk".to_string(),
+            ApiList::Unsupported => "unsupported".to_string(),
+            ApiList::Blocked => "blocked".to_string(),
+            ApiList::MaxTargetO => "max-target-o".to_string(),
+            ApiList::MaxTargetP => "max-target-p".to_string(),
+            ApiList::MaxTargetQ => "max-target-q".to_string(),
+            ApiList::MaxTargetR => "max-target-r".to_string(),
+            ApiList::MaxTargetS => "max-target-s".to_string(),
+            ApiList::Unknown(x) => format!("unknown({:#x})", x),
+        };
+        let mut names = vec![list];
+        if self.core_platform_api {
+            names.push("core-platform-api".to_string());
+        }
+        if self.test_api {
+            names.push("test-api".to_string());
+        }
+        names.join(",")
+    }
+}
+
+impl<'a> DexFile<'a> {
+    // The ids the tables refer to each other by are not checked when they
+    // are parsed, so a crafted file can point them anywhere. What is missing
+    // is shown by its index.
+    pub fn string(&self, idx: StringIndex) -> Cow<'_, str> {
+        match self.strings.get(idx) {
+            Some(s) => Cow::Borrowed(s),
+            None => Cow::Owned(format!("string@{:#x}", idx)),
+        }
+    }
+
+    pub fn type_descriptor(&self, idx: TypeIndex) -> Cow<'_, str> {
+        match self.types.get(idx) {
+            Some(t) => Cow::Borrowed(&t.raw),
+            None => Cow::Owned(format!("type@{:#x}", idx)),
+        }
+    }
+
+    // `(ILjava/lang/String;)V`
+    pub fn proto_signature(&self, idx: ProtoIndex) -> String {
+        match self.protos.get(idx) {
+            Some(p) => {
+                let parameters = p.parameter_type_idx_list.iter()
+                    .map(|t| self.type_descriptor(*t))
+                    .collect::<String>();
+                format!("({}){}", parameters, self.type_descriptor(p.return_type_idx))
+            }
+            None => format!("proto@{:#x}", idx),
+        }
+    }
+
+    // `name(ILjava/lang/String;)V`, the part of a method reference that
+    // overriding methods share
+    pub fn method_name_and_proto(&self, idx: MethodIndex) -> String {
+        match self.methods.get(idx) {
+            Some(m) => format!("{}{}", self.string(m.name_idx), self.proto_signature(m.proto_idx)),
+            None => format!("method@{:#x}", idx),
+        }
+    }
+
+    // `Lcom/example/Foo;->name(ILjava/lang/String;)V`
+    pub fn method_signature(&self, idx: MethodIndex) -> String {
+        match self.methods.get(idx) {
+            Some(m) => format!("{}->{}", self.type_descriptor(m.class_idx), self.method_name_and_proto(idx)),
+            None => format!("method@{:#x}", idx),
+        }
+    }
+
+    // `invoke-static@Lcom/example/Foo;->bootstrap(...)Ljava/lang/invoke/CallSite;`
+    pub fn method_handle_signature(&self, idx: MethodHandleIndex) -> String {
+        match self.method_handles.get(idx) {
+            Some(h) if h.kind.is_field_accessor() => format!("{}@{}", h.kind.name(), self.field_signature(h.field_or_method_idx)),
+            Some(h) => format!("{}@{}", h.kind.name(), self.method_signature(h.field_or_method_idx)),
+            None => format!("method_handle@{:#x}", idx),
+        }
+    }
+
+    // `call_site_0("apply", ()Ljava/util/function/Function;)`, the name and
+    // type the bootstrap method is asked to link
+    pub fn call_site_signature(&self, idx: CallSiteIndex) -> String {
+        match self.call_sites.get(idx).map(|c| c.as_slice()) {
+            Some([_, EncodedValue::String(name), EncodedValue::MethodType(proto), ..]) => {
+                format!("call_site_{}({:?}, {})", idx, self.string(*name), self.proto_signature(*proto))
+            }
+            _ => format!("call_site_{}", idx),
+        }
+    }
+
+    // `Lcom/example/Foo;->mName:Ljava/lang/String;`
+    pub fn field_signature(&self, idx: FieldIndex) -> String {
+        match self.fields.get(idx) {
+            Some(f) => format!("{}->{}:{}", self.type_descriptor(f.class_idx), self.string(f.name_idx), self.type_descriptor(f.type_idx)),
+            None => format!("field@{:#x}", idx),
+        }
+    }
+}
+
 impl Display for Endianness {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -209,7 +669,7 @@ class defs size    {} bytes
 class defs offset  {:#x}
 data size          {} bytes
 data offset        {:#x}"#, 
-        self.dex_version, 
+        if self.compact.is_some() { format!("{} (compact dex)", self.dex_version) } else { self.dex_version.clone() },
         self.checksum, 
         self.sha1, 
         self.file_size,
@@ -232,7 +692,15 @@ data offset        {:#x}"#,
         self.class_defs_offset,
         self.data_size,
         self.data_offset
-        )
+        )?;
+        if let Some(c) = &self.compact {
+            write!(f, "\nfeature flags      {:#x}\ndebug info table   {:#x}\nowned data         {:#x}..{:#x}",
+                c.feature_flags, c.debug_info_offsets_pos, c.owned_data_begin, c.owned_data_end)?;
+        }
+        if let (Some(size), Some(offset)) = (self.container_size, self.header_offset) {
+            write!(f, "\ncontainer size     {} bytes\nheader offset      {:#x}", size, offset)?;
+        }
+        Ok(())
     }
 }
 
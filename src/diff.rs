@@ -0,0 +1,364 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::dex_types::*;
+use crate::disassembler::symbolic_instruction_to_string;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl Change {
+    pub fn symbol(self) -> char {
+        match self {
+            Change::Added => '+',
+            Change::Removed => '-',
+            Change::Changed => '~',
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClassDiff {
+    pub class: String,
+    pub change: Change,
+    // superclass, interface and access flag changes
+    pub details: Vec<String>,
+    pub fields: Vec<MemberDiff>,
+    pub methods: Vec<MemberDiff>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemberDiff {
+    // `name:Type` for fields, `name(args)ret` for methods
+    pub member: String,
+    pub change: Change,
+    pub details: Vec<String>,
+    // runs of changed instructions with some unchanged ones around them
+    pub hunks: Vec<Vec<DiffLine>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffLine {
+    // ' ' for context, '-' for the old file and '+' for the new one
+    pub op: char,
+    pub text: String,
+}
+
+// What a class looks like with every index resolved, so two dex files can
+// be compared no matter how their tables are numbered.
+struct ClassSummary {
+    access_flags: u32,
+    superclass: String,
+    interfaces: Vec<String>,
+    fields: BTreeMap<String, u32>,
+    methods: BTreeMap<String, MethodSummary>,
+}
+
+struct MethodSummary {
+    access_flags: u32,
+    code: Option<Vec<String>>,
+}
+
+// Lines of unchanged code kept around each change in a method body.
+const CONTEXT: usize = 2;
+
+pub fn diff_dex_files(old: &DexFile, new: &DexFile) -> Result<Vec<ClassDiff>, String> {
+    let old_classes = summarize(old)?;
+    let new_classes = summarize(new)?;
+
+    let mut result: Vec<ClassDiff> = Vec::new();
+    for (name, o) in old_classes.iter() {
+        match new_classes.get(name) {
+            None => result.push(ClassDiff { class: name.clone(), change: Change::Removed, details: Vec::new(), fields: Vec::new(), methods: Vec::new() }),
+            Some(n) => {
+                let d = diff_class(name, o, n);
+                if !d.details.is_empty() || !d.fields.is_empty() || !d.methods.is_empty() {
+                    result.push(d);
+                }
+            }
+        }
+    }
+    for name in new_classes.keys().filter(|n| !old_classes.contains_key(*n)) {
+        result.push(ClassDiff { class: name.clone(), change: Change::Added, details: Vec::new(), fields: Vec::new(), methods: Vec::new() });
+    }
+
+    result.sort_by(|a, b| a.class.cmp(&b.class));
+    Ok(result)
+}
+
+fn summarize(dex: &DexFile) -> Result<BTreeMap<String, ClassSummary>, String> {
+    let mut classes: BTreeMap<String, ClassSummary> = BTreeMap::new();
+    for c in dex.classes.iter() {
+        let mut fields: BTreeMap<String, u32> = BTreeMap::new();
+        for f in c.fields()? {
+            let signature = dex.field_signature(f.field_idx);
+            let member = signature.split_once("->").map(|x| x.1).unwrap_or("?").to_string();
+            fields.insert(member, f.access_flags);
+        }
+
+        let mut methods: BTreeMap<String, MethodSummary> = BTreeMap::new();
+        for m in c.methods()? {
+            let code = m.code_item()?.map(|code| {
+                code.instructions.iter()
+                    .map(|i| symbolic_instruction_to_string(dex, i, None))
+                    .collect::<Vec<String>>()
+            });
+            methods.insert(dex.method_name_and_proto(m.method_idx), MethodSummary { access_flags: m.access_flags, code });
+        }
+
+        // the first definition wins, as it does at runtime
+        classes.entry(dex.type_descriptor(c.class_idx).to_string()).or_insert(ClassSummary {
+            access_flags: c.access_flags,
+            superclass: dex.type_descriptor(c.superclass_idx).to_string(),
+            interfaces: c.interfaces.iter().map(|i| dex.type_descriptor(*i).to_string()).collect(),
+            fields,
+            methods,
+        });
+    }
+    Ok(classes)
+}
+
+fn diff_class(name: &str, old: &ClassSummary, new: &ClassSummary) -> ClassDiff {
+    let mut details: Vec<String> = Vec::new();
+    if old.access_flags != new.access_flags {
+        details.push(format!("access flags {:#x} -> {:#x}", old.access_flags, new.access_flags));
+    }
+    if old.superclass != new.superclass {
+        details.push(format!("superclass {} -> {}", old.superclass, new.superclass));
+    }
+    for i in old.interfaces.iter().filter(|i| !new.interfaces.contains(i)) {
+        details.push(format!("no longer implements {}", i));
+    }
+    for i in new.interfaces.iter().filter(|i| !old.interfaces.contains(i)) {
+        details.push(format!("implements {}", i));
+    }
+
+    let fields = diff_members(&old.fields, &new.fields, |o, n| {
+        let mut details: Vec<String> = Vec::new();
+        if o != n {
+            details.push(format!("access flags {:#x} -> {:#x}", o, n));
+        }
+        (details, Vec::new())
+    });
+
+    let methods = diff_members(&old.methods, &new.methods, |o, n| {
+        let mut details: Vec<String> = Vec::new();
+        if o.access_flags != n.access_flags {
+            details.push(format!("access flags {:#x} -> {:#x}", o.access_flags, n.access_flags));
+        }
+        let hunks = match (&o.code, &n.code) {
+            (Some(a), Some(b)) => diff_lines(a, b),
+            (Some(_), None) => {
+                details.push("code removed".to_string());
+                Vec::new()
+            }
+            (None, Some(_)) => {
+                details.push("code added".to_string());
+                Vec::new()
+            }
+            (None, None) => Vec::new(),
+        };
+        (details, hunks)
+    });
+
+    ClassDiff { class: name.to_string(), change: Change::Changed, details, fields, methods }
+}
+
+fn diff_members<T>(
+    old: &BTreeMap<String, T>,
+    new: &BTreeMap<String, T>,
+    compare: impl Fn(&T, &T) -> (Vec<String>, Vec<Vec<DiffLine>>),
+) -> Vec<MemberDiff> {
+    let mut result: Vec<MemberDiff> = Vec::new();
+    for (member, o) in old.iter() {
+        match new.get(member) {
+            None => result.push(MemberDiff { member: member.clone(), change: Change::Removed, details: Vec::new(), hunks: Vec::new() }),
+            Some(n) => {
+                let (details, hunks) = compare(o, n);
+                if !details.is_empty() || !hunks.is_empty() {
+                    result.push(MemberDiff { member: member.clone(), change: Change::Changed, details, hunks });
+                }
+            }
+        }
+    }
+    for member in new.keys().filter(|m| !old.contains_key(*m)) {
+        result.push(MemberDiff { member: member.clone(), change: Change::Added, details: Vec::new(), hunks: Vec::new() });
+    }
+
+    result.sort_by(|a, b| a.member.cmp(&b.member));
+    result
+}
+
+// A line diff based on the longest common subsequence, cut into hunks of
+// changes with CONTEXT lines around them. Empty if the bodies are equal.
+fn diff_lines(old: &[String], new: &[String]) -> Vec<Vec<DiffLine>> {
+    if old == new {
+        return Vec::new();
+    }
+
+    // the common prefix and suffix are left out of the subsequence search
+    let prefix = old.iter().zip(new.iter()).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut lines: Vec<DiffLine> = old[..prefix].iter().map(|x| DiffLine { op: ' ', text: x.clone() }).collect();
+    align(a, b, &mut lines);
+    lines.extend(old[old.len() - suffix..].iter().map(|x| DiffLine { op: ' ', text: x.clone() }));
+
+    // every line within CONTEXT of a change is shown
+    let mut shown = vec![false; lines.len()];
+    for (i, _) in lines.iter().enumerate().filter(|(_, l)| l.op != ' ') {
+        let end = (i + CONTEXT + 1).min(lines.len());
+        shown[i.saturating_sub(CONTEXT)..end].iter_mut().for_each(|x| *x = true);
+    }
+    let mut hunks: Vec<Vec<DiffLine>> = Vec::new();
+    let mut last: Option<usize> = None;
+    for (i, l) in lines.into_iter().enumerate() {
+        if !shown[i] {
+            continue;
+        }
+        if last.map(|x| x + 1 != i).unwrap_or(true) {
+            hunks.push(Vec::new());
+        }
+        hunks.last_mut().unwrap().push(l);
+        last = Some(i);
+    }
+    hunks
+}
+
+// Hirschberg's algorithm: the longest common subsequence found by splitting
+// `a` in half and `b` where the halves' subsequences meet, so a method of
+// tens of thousands of instructions needs two rows rather than a table.
+fn align(a: &[String], b: &[String], lines: &mut Vec<DiffLine>) {
+    let removed = |x: &String| DiffLine { op: '-', text: x.clone() };
+    let added = |x: &String| DiffLine { op: '+', text: x.clone() };
+    match a {
+        [] => lines.extend(b.iter().map(added)),
+        _ if b.is_empty() => lines.extend(a.iter().map(removed)),
+        [x] => match b.iter().position(|y| y == x) {
+            Some(j) => {
+                lines.extend(b[..j].iter().map(added));
+                lines.push(DiffLine { op: ' ', text: x.clone() });
+                lines.extend(b[j + 1..].iter().map(added));
+            }
+            None => {
+                lines.push(removed(x));
+                lines.extend(b.iter().map(added));
+            }
+        },
+        _ => {
+            let mid = a.len() / 2;
+            let front = lcs_lengths(a[..mid].iter(), b.iter());
+            let back = lcs_lengths(a[mid..].iter().rev(), b.iter().rev());
+            let split = (0..=b.len()).max_by_key(|j| (front[*j] + back[b.len() - j], std::cmp::Reverse(*j))).unwrap_or(0);
+            align(&a[..mid], &b[..split], lines);
+            align(&a[mid..], &b[split..], lines);
+        }
+    }
+}
+
+// The length of the longest common subsequence of `a` and each prefix of `b`.
+fn lcs_lengths<'a>(a: impl Iterator<Item = &'a String>, b: impl Iterator<Item = &'a String> + Clone) -> Vec<u32> {
+    let mut row = vec![0u32; b.clone().count() + 1];
+    for x in a {
+        let mut diagonal = 0;
+        for (j, y) in b.clone().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if x == y { diagonal + 1 } else { above.max(row[j]) };
+            diagonal = above;
+        }
+    }
+    row
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|x| x.to_string()).collect()
+    }
+
+    // `-a`, ` b`, `+c`, one per line
+    fn render(lines: &[DiffLine]) -> Vec<String> {
+        lines.iter().map(|l| format!("{}{}", l.op, l.text)).collect()
+    }
+
+    fn hunks(old: &str, new: &str) -> Vec<Vec<String>> {
+        diff_lines(&strings(old), &strings(new)).iter().map(|h| render(h)).collect()
+    }
+
+    fn aligned(a: &str, b: &str) -> Vec<String> {
+        let mut lines = Vec::new();
+        align(&strings(a), &strings(b), &mut lines);
+        render(&lines)
+    }
+
+    #[test]
+    fn lcs() {
+        let (a, b) = (strings("a b c d"), strings("b x d"));
+        assert_eq!(lcs_lengths(a.iter(), b.iter()), vec![0, 1, 1, 2]);
+        assert_eq!(lcs_lengths(a.iter(), [].iter()), vec![0]);
+        assert_eq!(lcs_lengths([].iter(), b.iter()), vec![0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn alignment() {
+        assert!(aligned("", "").is_empty());
+        assert_eq!(aligned("", "a b"), vec!["+a", "+b"]);
+        assert_eq!(aligned("a b", ""), vec!["-a", "-b"]);
+        assert_eq!(aligned("a b", "c d"), vec!["-a", "-b", "+c", "+d"]);
+        assert_eq!(aligned("a b c d e", "a x c e y"), vec![" a", "-b", "+x", " c", "-d", " e", "+y"]);
+
+        // every line of both sides is kept, in order, around a longest
+        // common subsequence
+        let (a, b) = ("p q r s t u v w", "q x s t y v w z p");
+        let lines = aligned(a, b);
+        let side = |op: char| lines.iter().filter(|l| l.starts_with(op) || l.starts_with(' ')).map(|l| &l[1..]).collect::<Vec<&str>>().join(" ");
+        assert_eq!(side('-'), a);
+        assert_eq!(side('+'), b);
+        let common = lines.iter().filter(|l| l.starts_with(' ')).count() as u32;
+        assert_eq!(common, *lcs_lengths(strings(a).iter(), strings(b).iter()).last().unwrap());
+    }
+
+    #[test]
+    fn no_hunks() {
+        assert!(hunks("", "").is_empty());
+        assert!(hunks("a b c", "a b c").is_empty());
+    }
+
+    #[test]
+    fn disjoint() {
+        assert_eq!(hunks("a b", "c d"), vec![vec!["-a", "-b", "+c", "+d"]]);
+        assert_eq!(hunks("", "a b"), vec![vec!["+a", "+b"]]);
+        assert_eq!(hunks("a b", ""), vec![vec!["-a", "-b"]]);
+    }
+
+    #[test]
+    fn changes_at_either_end() {
+        assert_eq!(hunks("a b c d e", "x a b c d e"), vec![vec!["+x", " a", " b"]]);
+        assert_eq!(hunks("a b c d e", "a b c d e x"), vec![vec![" d", " e", "+x"]]);
+        assert_eq!(hunks("a b c d e", "b c d e"), vec![vec!["-a", " b", " c"]]);
+        assert_eq!(hunks("a b c d e", "a b c d"), vec![vec![" c", " d", "-e"]]);
+    }
+
+    #[test]
+    fn hunks_merge_within_twice_the_context() {
+        // four unchanged lines between the changes: both contexts touch
+        assert_eq!(hunks("a 1 2 3 4 b", "x 1 2 3 4 y"), vec![
+            vec!["-a", "+x", " 1", " 2", " 3", " 4", "-b", "+y"],
+        ]);
+        // five: the middle one is shown by neither
+        assert_eq!(hunks("a 1 2 3 4 5 b", "x 1 2 3 4 5 y"), vec![
+            vec!["-a", "+x", " 1", " 2"],
+            vec![" 4", " 5", "-b", "+y"],
+        ]);
+    }
+}
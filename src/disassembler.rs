@@ -1,28 +1,120 @@
+use std::collections::HashMap;
 use std::fmt;
+use std::io;
 use std::fmt::{Display, Write};
 
+use crate::color::{paint, paint_instruction, Style};
 use crate::dex_types::*;
 use crate::instructions::*;
+use crate::type_inference::RegisterType;
 
-pub struct Disassembler {
-    pub strings: Vec<String>,
-    pub types: Vec<DexType>,
-    pub protos: Vec<DexProto>,
-    pub fields: Vec<DexField>,
-    pub methods: Vec<DexMethod>,
-    pub classes: Vec<DexClassDef>,
-    pub instructions: Vec<Instruction>,
+pub struct Disassembler<'a> {
+    pub dex: DexFile<'a>,
+    // when set, operands are resolved inline and branch targets get labels
+    pub labels: Option<Labels>,
+    // ANSI colors for terminals
+    pub color: bool,
 }
 
-impl Disassembler {
+// Names for every branch target and payload among a set of instructions.
+#[derive(Debug, Default)]
+pub struct Labels {
+    names: HashMap<usize, String>,
+    // payload address to the switch using it, since switch targets are
+    // relative to the switch rather than to the payload
+    switches: HashMap<usize, usize>,
+    targets: Vec<usize>,
+}
+
+impl Labels {
+    // Collects the targets of a method's instructions, or of several whole
+    // methods, since a switch and its payload are always in the same one.
+    pub fn add<'a, 'b: 'a>(&mut self, instructions: impl IntoIterator<Item = &'a Instruction<'b>>) {
+        let instructions = instructions.into_iter().collect::<Vec<&Instruction>>();
+        let payloads = instructions.iter()
+            .map(|i| (i.addr, &i.kind))
+            .collect::<HashMap<usize, &InstructionKind>>();
 
-    pub fn print(&self) {
-        for i in &self.instructions {
-            
-            match self.instruction_comment(i) {
-                Some(s) => println!("{:#x} {: <12} {: <64} ; {}", i.addr, to_hex(&i.bytecode), self.instruction_to_string(i), s),
-                None => println!("{:#x} {: <12} {}", i.addr, to_hex(&i.bytecode), self.instruction_to_string(i)),
+        for i in instructions.iter() {
+            let target = match offset_target(i) {
+                Some(t) => t,
+                None => continue,
             };
+            self.targets.push(target);
+            match payloads.get(&target) {
+                  Some(InstructionKind::PackedSwitchPayload(_, offsets))
+                | Some(InstructionKind::SparseSwitchPayload(_, offsets)) => {
+                    self.switches.insert(target, i.addr);
+                    self.targets.extend(offsets.iter().map(|o| (i.addr as i64 + *o as i64 * 2) as usize));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Names the targets added so far in address order: `:L0`, `:L1`, ...
+    pub fn number(&mut self) {
+        self.targets.sort_unstable();
+        self.targets.dedup();
+        self.names = self.targets.drain(..)
+            .enumerate()
+            .map(|(n, addr)| (addr, format!(":L{}", n)))
+            .collect();
+    }
+
+    fn name(&self, addr: usize) -> String {
+        match self.names.get(&addr) {
+            Some(name) => name.clone(),
+            None => format!("{:#x}", addr),
+        }
+    }
+}
+
+impl<'a> Disassembler<'a> {
+
+    // One method's listing, or any other run of instructions, with the
+    // register types inferred for them by address.
+    pub fn write_instructions<W: io::Write>(&self, out: &mut W, instructions: &[Instruction], types: &HashMap<usize, Vec<(Register, RegisterType)>>) -> io::Result<()> {
+        for i in instructions {
+            if let Some(label) = self.label(i) {
+                writeln!(out, "{}", label)?;
+            }
+            writeln!(out, "{}", self.format_instruction(i, types.get(&i.addr).map(|t| t.as_slice())))?;
+        }
+        Ok(())
+    }
+
+    // The label line to print before an instruction that is jumped to.
+    pub fn label(&self, i: &Instruction) -> Option<String> {
+        let label = self.labels.as_ref()?.names.get(&i.addr)?;
+        Some(if self.color { paint(Style::Label, label) } else { label.clone() })
+    }
+
+    // One line of the listing: address, raw bytes, the instruction and a comment.
+    pub fn format_instruction(&self, i: &Instruction, types: Option<&[(Register, RegisterType)]>) -> String {
+        let (text, comment) = match &self.labels {
+            Some(labels) => (symbolic_instruction_to_string(&self.dex, i, Some(labels)), None),
+            None => (self.instruction_to_string(i), self.instruction_comment(i)),
+        };
+        let comment = match (comment, types.and_then(register_types_comment)) {
+            (Some(a), Some(b)) => Some(format!("{}; {}", a, b)),
+            (a, b) => a.or(b),
+        };
+
+        // pad before coloring, escape codes would count towards the width
+        let text = match comment {
+            Some(_) => format!("{: <64}", text),
+            None => text,
+        };
+        let (text, comment) = if self.color {
+            (paint_instruction(&text), comment.map(|c| paint(Style::Comment, &format!("; {}", c))))
+        } else {
+            (text, comment.map(|c| format!("; {}", c)))
+        };
+
+        match comment {
+            Some(s) => format!("{:#x} {: <12} {} {}", i.addr, to_hex(i.bytecode), text, s),
+            None => format!("{:#x} {: <12} {}", i.addr, to_hex(i.bytecode), text),
         }
     }
 
@@ -40,7 +132,7 @@ impl Disassembler {
             InstructionKind::MoveObject16(a, b)     => format!("move-object/16 v{} v{}", a, b),
             InstructionKind::MoveResult(a)          => format!("move-result v{}", a),
             InstructionKind::MoveResultWide(a)      => format!("move-result-wide v{}", a),
-            InstructionKind::MoveResultObject(a)    => format!("move-object-wide v{}", a),
+            InstructionKind::MoveResultObject(a)    => format!("move-result-object v{}", a),
             InstructionKind::MoveException(a)       => format!("move-exception v{}", a),
             InstructionKind::ReturnVoid             => format!("return-void"),
             InstructionKind::Return(a)              => format!("return v{}", a),
@@ -65,7 +157,7 @@ impl Disassembler {
             InstructionKind::NewInstance(a, b)    => format!("new-instance v{} {:#x}", a, b),
             InstructionKind::NewArray(a, b, c)    => format!("new-array v{} v{} {:#x}", a, b, c),
             InstructionKind::FilledNewArray(a, b)         => format!("filled-new-array {{{}}} {:#x}", register_list_to_string(&a), b),
-            InstructionKind::FilledNewArrayRange(a, b, c) => format!("filled-new-array/range {{v{}..v{}}} {:#x}", a, b, c),
+            InstructionKind::FilledNewArrayRange(a, b, c) => format!("filled-new-array/range {{{}}} {:#x}", register_range_to_string(*a, *b), c),
             InstructionKind::FillArrayData(a, b)          => format!("fill-array-data v{} {:#x}", a, b),
             InstructionKind::Throw(a)  => format!("throw v{}", a),
             InstructionKind::GoTo(a)   => format!("goto {:#x}", i.addr as i32 + *a),
@@ -137,11 +229,11 @@ impl Disassembler {
             InstructionKind::InvokeDirect(a, b)            => format!("invoke-direct {{{}}} {:#x}", register_list_to_string(&a), b),
             InstructionKind::InvokeStatic(a, b)            => format!("invoke-static {{{}}} {:#x}", register_list_to_string(&a), b),
             InstructionKind::InvokeInterface(a, b)         => format!("invoke-interface {{{}}} {:#x}", register_list_to_string(&a), b),
-            InstructionKind::InvokeVirtualRange(a, b, c)   => format!("invoke-virtual/range {{v{}..v{}}} {:#x}", a, b, c), 
-            InstructionKind::InvokeSuperRange(a, b, c)     => format!("invoke-super/range {{v{}..v{}}} {:#x}", a, b, c),
-            InstructionKind::InvokeDirectRange(a, b, c)    => format!("invoke-direct/range {{v{}..v{}}} {:#x}", a, b, c),
-            InstructionKind::InvokeStaticRange(a, b, c)    => format!("invoke-static/range {{v{}..v{}}} {:#x}", a, b, c),
-            InstructionKind::InvokeInterfaceRange(a, b, c) => format!("invoke-interface/range {{v{}..v{}}} {:#x}", a, b, c),
+            InstructionKind::InvokeVirtualRange(a, b, c)   => format!("invoke-virtual/range {{{}}} {:#x}", register_range_to_string(*a, *b), c), 
+            InstructionKind::InvokeSuperRange(a, b, c)     => format!("invoke-super/range {{{}}} {:#x}", register_range_to_string(*a, *b), c),
+            InstructionKind::InvokeDirectRange(a, b, c)    => format!("invoke-direct/range {{{}}} {:#x}", register_range_to_string(*a, *b), c),
+            InstructionKind::InvokeStaticRange(a, b, c)    => format!("invoke-static/range {{{}}} {:#x}", register_range_to_string(*a, *b), c),
+            InstructionKind::InvokeInterfaceRange(a, b, c) => format!("invoke-interface/range {{{}}} {:#x}", register_range_to_string(*a, *b), c),
             InstructionKind::NegInt(a, b)        => format!("neg-int v{} v{}", a, b),
             InstructionKind::NotInt(a, b)        => format!("not-int v{} v{}", a, b),
             InstructionKind::NegLong(a, b)       => format!("neg-long v{} v{}", a, b),
@@ -246,14 +338,39 @@ impl Disassembler {
             InstructionKind::ShlIntLit8(a, b, c)   => format!("shl-int/lit8 v{} v{} {:#x}", a, b, c),
             InstructionKind::ShrIntLit8(a, b, c)   => format!("shr-int/lit8 v{} v{} {:#x}", a, b, c),
             InstructionKind::UShrIntLit8(a, b, c)  => format!("ushr-int/lit8 v{} v{} {:#x}", a, b, c),
-            InstructionKind::InvokePolymorphic      => format!(""), // TODO 
-            InstructionKind::InvokePolymorphicRange => format!(""), // TODO 
-            InstructionKind::InvokeCustom           => format!(""), // TODO 
-            InstructionKind::InvokeCustomRange      => format!(""), // TODO 
-            InstructionKind::ConstMethodHandle      => format!(""), // TODO 
-            InstructionKind::ConstMethodType        => format!(""), // TODO 
+            InstructionKind::InvokePolymorphic(a, b, c)         => format!("invoke-polymorphic {{{}}} {:#x} {:#x}", register_list_to_string(&a), b, c),
+            InstructionKind::InvokePolymorphicRange(a, b, c, d) => format!("invoke-polymorphic/range {{{}}} {:#x} {:#x}", register_range_to_string(*a, *b), c, d),
+            InstructionKind::InvokeCustom(a, b)                 => format!("invoke-custom {{{}}} {:#x}", register_list_to_string(&a), b),
+            InstructionKind::InvokeCustomRange(a, b, c)         => format!("invoke-custom/range {{{}}} {:#x}", register_range_to_string(*a, *b), c),
+            InstructionKind::ConstMethodHandle(a, b)            => format!("const-method-handle v{} {:#x}", a, b),
+            InstructionKind::ConstMethodType(a, b)              => format!("const-method-type v{} {:#x}", a, b),
+            InstructionKind::ReturnVoidNoBarrier    => "return-void-no-barrier".to_string(),
+            InstructionKind::IGetQuick(a, b, c)        => format!("iget-quick v{} v{} [obj+{:#x}]", a, b, c),
+            InstructionKind::IGetWideQuick(a, b, c)    => format!("iget-wide-quick v{} v{} [obj+{:#x}]", a, b, c),
+            InstructionKind::IGetObjectQuick(a, b, c)  => format!("iget-object-quick v{} v{} [obj+{:#x}]", a, b, c),
+            InstructionKind::IPutQuick(a, b, c)        => format!("iput-quick v{} v{} [obj+{:#x}]", a, b, c),
+            InstructionKind::IPutWideQuick(a, b, c)    => format!("iput-wide-quick v{} v{} [obj+{:#x}]", a, b, c),
+            InstructionKind::IPutObjectQuick(a, b, c)  => format!("iput-object-quick v{} v{} [obj+{:#x}]", a, b, c),
+            InstructionKind::InvokeVirtualQuick(a, b)           => format!("invoke-virtual-quick {{{}}} vtable@{:#x}", register_list_to_string(a), b),
+            InstructionKind::InvokeVirtualQuickRange(a, b, c)   => format!("invoke-virtual-quick/range {{{}}} vtable@{:#x}", register_range_to_string(*a, *b), c),
+            InstructionKind::IPutBooleanQuick(a, b, c) => format!("iput-boolean-quick v{} v{} [obj+{:#x}]", a, b, c),
+            InstructionKind::IPutByteQuick(a, b, c)    => format!("iput-byte-quick v{} v{} [obj+{:#x}]", a, b, c),
+            InstructionKind::IPutCharQuick(a, b, c)    => format!("iput-char-quick v{} v{} [obj+{:#x}]", a, b, c),
+            InstructionKind::IPutShortQuick(a, b, c)   => format!("iput-short-quick v{} v{} [obj+{:#x}]", a, b, c),
+            InstructionKind::IGetBooleanQuick(a, b, c) => format!("iget-boolean-quick v{} v{} [obj+{:#x}]", a, b, c),
+            InstructionKind::IGetByteQuick(a, b, c)    => format!("iget-byte-quick v{} v{} [obj+{:#x}]", a, b, c),
+            InstructionKind::IGetCharQuick(a, b, c)    => format!("iget-char-quick v{} v{} [obj+{:#x}]", a, b, c),
+            InstructionKind::IGetShortQuick(a, b, c)   => format!("iget-short-quick v{} v{} [obj+{:#x}]", a, b, c),
             InstructionKind::Unused                 => format!(""),
-            _ => "".to_string(),
+            InstructionKind::Stop                   => "".to_string(),
+            InstructionKind::PackedSwitchPayload(a, b)  => format!("packed-switch-payload {:#x} {{{}}}", a, payload_targets(i, b, None).join(", ")),
+            InstructionKind::SparseSwitchPayload(a, b)  => {
+                let cases = a.iter().zip(payload_targets(i, b, None))
+                    .map(|(k, t)| format!("{:#x} -> {}", k, t))
+                    .collect::<Vec<String>>();
+                format!("sparse-switch-payload {{{}}}", cases.join(", "))
+            }
+            InstructionKind::FillArrayDataPayload(a, b) => format!("fill-array-data-payload {} {{{}}}", a, fill_array_elements(*a, b).join(", ")),
         }
     }
 
@@ -302,6 +419,9 @@ impl Disassembler {
             InstructionKind::InvokeStatic(_, i)            => Some(self.method_at_index(i)),
             InstructionKind::InvokeInterface(_, i)         => Some(self.method_at_index(i)),
             InstructionKind::InvokeVirtualRange(_, _, i)   => Some(self.method_at_index(i)),
+            InstructionKind::InvokePolymorphic(_, i, _)    => Some(self.method_at_index(i)),
+            InstructionKind::InvokePolymorphicRange(_, _, i, _) => Some(self.method_at_index(i)),
+            InstructionKind::ConstMethodType(_, i)         => Some(self.dex.proto_signature(i)),
             InstructionKind::InvokeSuperRange(_, _, i)     => Some(self.method_at_index(i)),
             InstructionKind::InvokeDirectRange(_, _, i)    => Some(self.method_at_index(i)),
             InstructionKind::InvokeStaticRange(_, _, i)    => Some(self.method_at_index(i)),
@@ -311,52 +431,155 @@ impl Disassembler {
     }
 
     fn string_at_index(&self, i: StringIndex) -> String {
-        self.strings[i].clone()
+        self.dex.string(i).into_owned()
     }
 
     fn type_at_index(&self, i: TypeIndex) -> String {
-        if i >= self.types.len() {
+        if i >= self.dex.types.len() {
             return format!("TypeIndex out of bounds: {}", i);
         }
-        format!("{}", self.types[i].parsed)
+        format!("{}", self.dex.types[i].parsed)
     }
 
     fn field_at_index(&self, i: FieldIndex) -> String {
-        if i >= self.fields.len() {
+        if i >= self.dex.fields.len() {
             return format!("FieldIndex ouf of bounds: {}", i);
         }
-        let field = self.fields[i].clone();
-        let name = self.strings[field.name_idx].clone();
-        let field_type = self.types[field.type_idx].clone();
-        format!("{} ({})", name, field_type.parsed)
+        let field = &self.dex.fields[i];
+        format!("{} ({})", self.dex.string(field.name_idx), self.type_at_index(field.type_idx))
     }
 
     fn method_at_index(&self, i: MethodIndex) -> String {
-        if i >= self.methods.len() {
+        if i >= self.dex.methods.len() {
             return format!("MethodIndex out of bounds: {}", i);
         }
-        let method = &self.methods[i];
-        let method_proto = &self.protos[method.proto_idx as usize];
-        let method_name = &self.strings[method.name_idx as usize];
+        let method = &self.dex.methods[i];
+        let method_name = self.dex.string(method.name_idx);
+        let method_proto = match self.dex.protos.get(method.proto_idx) {
+            Some(p) => p,
+            None => return format!("{}(ProtoIndex out of bounds: {})", method_name, method.proto_idx),
+        };
 
-        let return_type = &self.types[method_proto.return_type_idx as usize];
         let param_types = method_proto.parameter_type_idx_list.iter()
-            .map(|idx: &TypeIndex| {
-                let t = &self.types[*idx].parsed;
-                return format!("{}", t);
-            })
+            .map(|idx: &TypeIndex| self.type_at_index(*idx))
             .collect::<Vec<String>>()
             .join(", ");
 
-        format!("{}({}) -> {}", method_name, param_types, return_type.parsed)
+        format!("{}({}) -> {}", method_name, param_types, self.type_at_index(method_proto.return_type_idx))
     }
 }
 
+// `invoke-direct {v0}, Ljava/lang/Object;-><init>()V`, with every index
+// resolved, as the symbolic listing, grep and diff print instructions.
+// Branches go to labels when there are any and are relative otherwise.
+pub fn symbolic_instruction_to_string(dex: &DexFile, i: &Instruction, labels: Option<&Labels>) -> String {
+    let operands = match &i.kind {
+        InstructionKind::PackedSwitchPayload(first, offsets) => {
+            let targets = payload_targets(i, offsets, labels);
+            return format!("{} {:#x} {{{}}}", i.kind.mnemonic(), first, targets.join(", "));
+        }
+        InstructionKind::SparseSwitchPayload(keys, offsets) => {
+            let targets = payload_targets(i, offsets, labels);
+            let cases = keys.iter().zip(targets.iter())
+                .map(|(k, t)| format!("{:#x} -> {}", k, t))
+                .collect::<Vec<String>>();
+            return format!("{} {{{}}}", i.kind.mnemonic(), cases.join(", "));
+        }
+        InstructionKind::FillArrayDataPayload(width, data) => {
+            let elements = fill_array_elements(*width, data);
+            return format!("{} {} {{{}}}", i.kind.mnemonic(), width, elements.join(", "));
+        }
+        kind => kind.operands(),
+    };
+
+    let operands = operands.iter()
+        .map(|o| match o {
+            Operand::Register(r) => format!("v{}", r),
+            Operand::RegisterList(list) => format!("{{{}}}", register_list_to_string(list)),
+            Operand::RegisterRange(_, 0) => "{}".to_string(),
+            Operand::RegisterRange(first, count) => format!("{{v{} .. v{}}}", first, first + count - 1),
+            Operand::Literal(x) if *x < 0 => format!("-{:#x}", x.unsigned_abs()),
+            Operand::Literal(x) => format!("{:#x}", x),
+            Operand::Offset(x) => match labels {
+                Some(labels) => labels.name(offset_target(i).unwrap()),
+                None => format!("{:+}", x),
+            },
+            Operand::String(idx) => match dex.strings.get(*idx) {
+                Some(s) => format!("{:?}", s),
+                None => format!("string@{:#x}", idx),
+            },
+            Operand::Type(idx) => dex.type_descriptor(*idx).into_owned(),
+            Operand::Field(idx) => dex.field_signature(*idx),
+            Operand::Method(idx) => dex.method_signature(*idx),
+            Operand::Proto(idx) => dex.proto_signature(*idx),
+            Operand::CallSite(idx) => dex.call_site_signature(*idx),
+            Operand::MethodHandle(idx) => dex.method_handle_signature(*idx),
+        })
+        .collect::<Vec<String>>();
+
+    if operands.is_empty() {
+        return i.kind.mnemonic().to_string();
+    }
+    format!("{} {}", i.kind.mnemonic(), operands.join(", "))
+}
+
+fn payload_targets(payload: &Instruction, offsets: &[i32], labels: Option<&Labels>) -> Vec<String> {
+    match labels.and_then(|l| Some((l, l.switches.get(&payload.addr)?))) {
+        Some((labels, switch)) => offsets.iter().map(|o| labels.name((*switch as i64 + *o as i64 * 2) as usize)).collect(),
+        None => offsets.iter().map(|o| format!("{:+}", o)).collect(),
+    }
+}
+
+// The elements of a fill-array-data payload, little-endian and `width`
+// bytes each.
+fn fill_array_elements(width: u16, data: &[u8]) -> Vec<String> {
+    data.chunks((width as usize).max(1))
+        .map(|c| {
+            let value = c.iter().rev().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+            format!("{:#x}", value)
+        })
+        .collect()
+}
+
+fn register_types_comment(types: &[(Register, RegisterType)]) -> Option<String> {
+    if types.is_empty() {
+        return None;
+    }
+
+    let s = types.iter()
+        .map(|(r, t)| format!("v{}: {}", r, t))
+        .collect::<Vec<String>>()
+        .join(", ");
+    Some(s)
+}
+
+// The absolute address a branch, switch or fill-array-data refers to.
+// Branch offsets are stored in bytes, payload offsets in code units.
+fn offset_target(i: &Instruction) -> Option<usize> {
+    let offset = match i.kind {
+          InstructionKind::PackedSwitch(_, o)
+        | InstructionKind::SparseSwitch(_, o)
+        | InstructionKind::FillArrayData(_, o) => o as i64 * 2,
+        _ => match i.kind.operands().iter().find_map(|o| match o { Operand::Offset(x) => Some(*x), _ => None }) {
+            Some(o) => o as i64,
+            None => return None,
+        },
+    };
+    Some((i.addr as i64 + offset) as usize)
+}
+
 fn register_list_to_string(v: &Vec<Register>) -> String {
     v.iter().map(|x| format!("v{}", x)).collect::<Vec<String>>().join(", ")
 }
 
-fn to_hex(ls: &Vec<u8>) -> String {
+fn register_range_to_string(first: Register, count: Register) -> String {
+    match count {
+        0 => String::new(),
+        _ => format!("v{}..v{}", first, first + count - 1),
+    }
+}
+
+fn to_hex(ls: &[u8]) -> String {
     let mut output = String::new();
     for i in ls {
         write!(&mut output, "{:01$x}", i, 2).expect("Couldn't write to string.");
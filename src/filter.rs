@@ -0,0 +1,70 @@
+use regex::Regex;
+
+// A name pattern over dotted names such as `com.example.Foo` or
+// `com.example.Foo.onCreate`. Globs support `*` and `?` and match the
+// whole name or any suffix that starts after a dot, so `Foo.onCreate`
+// finds `com.example.Foo.onCreate`. A pattern wrapped in slashes, like
+// `/net\.(Http|Socket)/`, is a regex searched anywhere in the name.
+pub struct NamePattern {
+    regex: Regex,
+}
+
+impl NamePattern {
+    pub fn new(pattern: &str) -> Result<NamePattern, String> {
+        let source = if pattern.len() > 1 && pattern.starts_with('/') && pattern.ends_with('/') {
+            pattern[1..pattern.len() - 1].to_string()
+        } else {
+            glob_to_regex(pattern, r"(?:^|\.)")
+        };
+
+        match Regex::new(&source) {
+            Ok(regex) => Ok(NamePattern { regex }),
+            Err(e) => Err(format!("invalid pattern {}: {}", pattern, e)),
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+}
+
+// A regex for a glob with `*` and `?` that matches to the end of the
+// name; `start` says where the match may begin, such as `^`.
+pub fn glob_to_regex(glob: &str, start: &str) -> String {
+    let mut result = String::from(start);
+    for c in glob.chars() {
+        match c {
+            '*' => result.push_str(".*"),
+            '?' => result.push('.'),
+            c => result.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    result.push('$');
+    result
+}
+
+// The `--class` and `--method` selectors; either may be absent.
+pub struct NameFilter {
+    pub class: Option<NamePattern>,
+    pub method: Option<NamePattern>,
+}
+
+impl NameFilter {
+    pub fn matches_class(&self, class_name: &str) -> bool {
+        match &self.class {
+            Some(p) => p.matches(class_name),
+            None => true,
+        }
+    }
+
+    pub fn matches_method(&self, class_name: &str, method_name: &str) -> bool {
+        match &self.method {
+            Some(p) => p.matches(&format!("{}.{}", class_name, method_name)),
+            None => true,
+        }
+    }
+
+    pub fn has_method_pattern(&self) -> bool {
+        self.method.is_some()
+    }
+}
@@ -1,19 +1,52 @@
 
 use crate::binary_parser::BinaryParser;
 use crate::dex_types::*;
-use crate::util::{to_decimal, to_decimal_short, to_hex_string, to_i8, to_i16};
+use serde::Serialize;
 
 pub type Register = u32;
 
-#[derive(Debug, Clone)]
-pub struct Instruction {
+// The registers of a `/range` instruction, which names `count` of them
+// starting at `first`; the count may be 0.
+pub fn register_range(first: Register, count: Register) -> Vec<Register> {
+    (first..first + count).collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Instruction<'a> {
     pub addr: usize,
-    pub kind: InstructionKind,
-    pub bytecode: Vec<u8>,
+    pub kind: InstructionKind<'a>,
+    // borrowed from the file, like everything else the parser returns
+    pub bytecode: &'a [u8],
 }
 
 #[derive(Debug, PartialEq, Clone)]
-pub enum InstructionKind {
+pub enum Operand {
+    Register(Register),
+    RegisterList(Vec<Register>),
+    // the first register and how many follow it
+    RegisterRange(Register, Register),
+    Literal(i64),
+    // branch or payload offset relative to the instruction, as decoded
+    Offset(i32),
+    String(StringIndex),
+    Type(TypeIndex),
+    Field(FieldIndex),
+    Method(MethodIndex),
+    Proto(ProtoIndex),
+    CallSite(CallSiteIndex),
+    MethodHandle(MethodHandleIndex),
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum IndexReference {
+    String(StringIndex),
+    Type(TypeIndex),
+    Field(FieldIndex),
+    Method(MethodIndex),
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub enum InstructionKind<'a> {
     Nop,
     Move(Register, Register),
     MoveFrom16(Register, Register),
@@ -123,6 +156,7 @@ pub enum InstructionKind {
     InvokeDirect(Vec<Register>, MethodIndex),
     InvokeStatic(Vec<Register>, MethodIndex),
     InvokeInterface(Vec<Register>, MethodIndex),
+    // the `/range` forms hold their first register and a count
     InvokeVirtualRange(Register, Register, MethodIndex), 
     InvokeSuperRange(Register, Register, MethodIndex),
     InvokeDirectRange(Register, Register, MethodIndex),
@@ -232,17 +266,984 @@ pub enum InstructionKind {
     ShlIntLit8(Register, Register, i32),
     ShrIntLit8(Register, Register, i32),
     UShrIntLit8(Register, Register, i32),
-    InvokePolymorphic,      // TODO 
-    InvokePolymorphicRange, // TODO 
-    InvokeCustom,           // TODO 
-    InvokeCustomRange,      // TODO 
-    ConstMethodHandle,      // TODO 
-    ConstMethodType,        // TODO 
+    InvokePolymorphic(Vec<Register>, MethodIndex, ProtoIndex),
+    InvokePolymorphicRange(Register, Register, MethodIndex, ProtoIndex),
+    InvokeCustom(Vec<Register>, CallSiteIndex),
+    InvokeCustomRange(Register, Register, CallSiteIndex),
+    ConstMethodHandle(Register, MethodHandleIndex),
+    ConstMethodType(Register, ProtoIndex),
+    // ART's quickened forms, written over the standard ones when it
+    // optimizes a dex file: field accesses carry the field's byte offset in
+    // the object and virtual calls the method's vtable index
+    ReturnVoidNoBarrier,
+    IGetQuick(Register, Register, u32),
+    IGetWideQuick(Register, Register, u32),
+    IGetObjectQuick(Register, Register, u32),
+    IPutQuick(Register, Register, u32),
+    IPutWideQuick(Register, Register, u32),
+    IPutObjectQuick(Register, Register, u32),
+    InvokeVirtualQuick(Vec<Register>, u32),
+    InvokeVirtualQuickRange(Register, Register, u32),
+    IPutBooleanQuick(Register, Register, u32),
+    IPutByteQuick(Register, Register, u32),
+    IPutCharQuick(Register, Register, u32),
+    IPutShortQuick(Register, Register, u32),
+    IGetBooleanQuick(Register, Register, u32),
+    IGetByteQuick(Register, Register, u32),
+    IGetCharQuick(Register, Register, u32),
+    IGetShortQuick(Register, Register, u32),
+    PackedSwitchPayload(i32, Vec<i32>),
+    SparseSwitchPayload(Vec<i32>, Vec<i32>),
+    FillArrayDataPayload(u16, &'a [u8]),
     Unused,
     Stop,
 }
 
-pub fn parse_bytecode(mut bytes: &mut BinaryParser, start: usize, instructions_count: usize) -> Vec<Instruction> {
+impl InstructionKind<'_> {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            InstructionKind::Nop                      => "nop",
+            InstructionKind::Move(..)                 => "move",
+            InstructionKind::MoveFrom16(..)           => "move/from16",
+            InstructionKind::Move16(..)               => "move/16",
+            InstructionKind::MoveWide(..)             => "move-wide",
+            InstructionKind::MoveWideFrom16(..)       => "move-wide/from16",
+            InstructionKind::MoveWide16(..)           => "move-wide/16",
+            InstructionKind::MoveObject(..)           => "move-object",
+            InstructionKind::MoveObjectFrom16(..)     => "move-object/from16",
+            InstructionKind::MoveObject16(..)         => "move-object/16",
+            InstructionKind::MoveResult(..)           => "move-result",
+            InstructionKind::MoveResultWide(..)       => "move-result-wide",
+            InstructionKind::MoveResultObject(..)     => "move-result-object",
+            InstructionKind::MoveException(..)        => "move-exception",
+            InstructionKind::ReturnVoid               => "return-void",
+            InstructionKind::Return(..)               => "return",
+            InstructionKind::ReturnWide(..)           => "return-wide",
+            InstructionKind::ReturnObject(..)         => "return-object",
+            InstructionKind::Const4(..)               => "const/4",
+            InstructionKind::Const16(..)              => "const/16",
+            InstructionKind::Const(..)                => "const",
+            InstructionKind::ConstHigh16(..)          => "const/high16",
+            InstructionKind::ConstWide16(..)          => "const-wide/16",
+            InstructionKind::ConstWide32(..)          => "const-wide/32",
+            InstructionKind::ConstWide(..)            => "const-wide",
+            InstructionKind::ConstWideHigh16(..)      => "const-wide/high16",
+            InstructionKind::ConstString(..)          => "const-string",
+            InstructionKind::ConstStringJumbo(..)     => "const-string/jumbo",
+            InstructionKind::ConstClass(..)           => "const-class",
+            InstructionKind::MonitorEnter(..)         => "monitor-enter",
+            InstructionKind::MonitorExit(..)          => "monitor-exit",
+            InstructionKind::CheckCast(..)            => "check-cast",
+            InstructionKind::InstanceOf(..)           => "instance-of",
+            InstructionKind::ArrayLength(..)          => "array-length",
+            InstructionKind::NewInstance(..)          => "new-instance",
+            InstructionKind::NewArray(..)             => "new-array",
+            InstructionKind::FilledNewArray(..)       => "filled-new-array",
+            InstructionKind::FilledNewArrayRange(..)  => "filled-new-array/range",
+            InstructionKind::FillArrayData(..)        => "fill-array-data",
+            InstructionKind::Throw(..)                => "throw",
+            InstructionKind::GoTo(..)                 => "goto",
+            InstructionKind::GoTo16(..)               => "goto/16",
+            InstructionKind::GoTo32(..)               => "goto/32",
+            InstructionKind::PackedSwitch(..)         => "packed-switch",
+            InstructionKind::SparseSwitch(..)         => "sparse-switch",
+            InstructionKind::CmpLFloat(..)            => "cmpl-float",
+            InstructionKind::CmpGFloat(..)            => "cmpg-float",
+            InstructionKind::CmpLDouble(..)           => "cmpl-double",
+            InstructionKind::CmpGDouble(..)           => "cmpg-double",
+            InstructionKind::CmpLong(..)              => "cmp-long",
+            InstructionKind::IfEq(..)                 => "if-eq",
+            InstructionKind::IfNe(..)                 => "if-ne",
+            InstructionKind::IfLt(..)                 => "if-lt",
+            InstructionKind::IfGe(..)                 => "if-ge",
+            InstructionKind::IfGt(..)                 => "if-gt",
+            InstructionKind::IfLe(..)                 => "if-le",
+            InstructionKind::IfEqZ(..)                => "if-eqz",
+            InstructionKind::IfNeZ(..)                => "if-nez",
+            InstructionKind::IfLtZ(..)                => "if-ltz",
+            InstructionKind::IfGeZ(..)                => "if-gez",
+            InstructionKind::IfGtZ(..)                => "if-gtz",
+            InstructionKind::IfLeZ(..)                => "if-lez",
+            InstructionKind::AGet(..)                 => "aget",
+            InstructionKind::AGetWide(..)             => "aget-wide",
+            InstructionKind::AGetObject(..)           => "aget-object",
+            InstructionKind::AGetBoolean(..)          => "aget-boolean",
+            InstructionKind::AGetByte(..)             => "aget-byte",
+            InstructionKind::AGetChar(..)             => "aget-char",
+            InstructionKind::AGetShort(..)            => "aget-short",
+            InstructionKind::APut(..)                 => "aput",
+            InstructionKind::APutWide(..)             => "aput-wide",
+            InstructionKind::APutObject(..)           => "aput-object",
+            InstructionKind::APutBoolean(..)          => "aput-boolean",
+            InstructionKind::APutByte(..)             => "aput-byte",
+            InstructionKind::APutChar(..)             => "aput-char",
+            InstructionKind::APutShort(..)            => "aput-short",
+            InstructionKind::IGet(..)                 => "iget",
+            InstructionKind::IGetWide(..)             => "iget-wide",
+            InstructionKind::IGetObject(..)           => "iget-object",
+            InstructionKind::IGetBoolean(..)          => "iget-boolean",
+            InstructionKind::IGetByte(..)             => "iget-byte",
+            InstructionKind::IGetChar(..)             => "iget-char",
+            InstructionKind::IGetShort(..)            => "iget-short",
+            InstructionKind::IPut(..)                 => "iput",
+            InstructionKind::IPutWide(..)             => "iput-wide",
+            InstructionKind::IPutObject(..)           => "iput-object",
+            InstructionKind::IPutBoolean(..)          => "iput-boolean",
+            InstructionKind::IPutByte(..)             => "iput-byte",
+            InstructionKind::IPutChar(..)             => "iput-char",
+            InstructionKind::IPutShort(..)            => "iput-short",
+            InstructionKind::SGet(..)                 => "sget",
+            InstructionKind::SGetWide(..)             => "sget-wide",
+            InstructionKind::SGetObject(..)           => "sget-object",
+            InstructionKind::SGetBoolean(..)          => "sget-boolean",
+            InstructionKind::SGetByte(..)             => "sget-byte",
+            InstructionKind::SGetChar(..)             => "sget-char",
+            InstructionKind::SGetShort(..)            => "sget-short",
+            InstructionKind::SPut(..)                 => "sput",
+            InstructionKind::SPutWide(..)             => "sput-wide",
+            InstructionKind::SPutObject(..)           => "sput-object",
+            InstructionKind::SPutBoolean(..)          => "sput-boolean",
+            InstructionKind::SPutByte(..)             => "sput-byte",
+            InstructionKind::SPutChar(..)             => "sput-char",
+            InstructionKind::SPutShort(..)            => "sput-short",
+            InstructionKind::InvokeVirtual(..)        => "invoke-virtual",
+            InstructionKind::InvokeSuper(..)          => "invoke-super",
+            InstructionKind::InvokeDirect(..)         => "invoke-direct",
+            InstructionKind::InvokeStatic(..)         => "invoke-static",
+            InstructionKind::InvokeInterface(..)      => "invoke-interface",
+            InstructionKind::InvokeVirtualRange(..)   => "invoke-virtual/range",
+            InstructionKind::InvokeSuperRange(..)     => "invoke-super/range",
+            InstructionKind::InvokeDirectRange(..)    => "invoke-direct/range",
+            InstructionKind::InvokeStaticRange(..)    => "invoke-static/range",
+            InstructionKind::InvokeInterfaceRange(..) => "invoke-interface/range",
+            InstructionKind::NegInt(..)               => "neg-int",
+            InstructionKind::NotInt(..)               => "not-int",
+            InstructionKind::NegLong(..)              => "neg-long",
+            InstructionKind::NotLong(..)              => "not-long",
+            InstructionKind::NegFloat(..)             => "neg-float",
+            InstructionKind::NegDouble(..)            => "neg-double",
+            InstructionKind::IntToLong(..)            => "int-to-long",
+            InstructionKind::IntToFloat(..)           => "int-to-float",
+            InstructionKind::IntToDouble(..)          => "int-to-double",
+            InstructionKind::LongToInt(..)            => "long-to-int",
+            InstructionKind::LongToFloat(..)          => "long-to-float",
+            InstructionKind::LongToDouble(..)         => "long-to-double",
+            InstructionKind::FloatToInt(..)           => "float-to-int",
+            InstructionKind::FloatToLong(..)          => "float-to-long",
+            InstructionKind::FloatToDouble(..)        => "float-to-double",
+            InstructionKind::DoubleToInt(..)          => "double-to-int",
+            InstructionKind::DoubleToLong(..)         => "double-to-long",
+            InstructionKind::DoubleToFloat(..)        => "double-to-float",
+            InstructionKind::IntToByte(..)            => "int-to-byte",
+            InstructionKind::IntToChar(..)            => "int-to-char",
+            InstructionKind::IntToShort(..)           => "int-to-short",
+            InstructionKind::AddInt(..)               => "add-int",
+            InstructionKind::SubInt(..)               => "sub-int",
+            InstructionKind::MulInt(..)               => "mul-int",
+            InstructionKind::DivInt(..)               => "div-int",
+            InstructionKind::RemInt(..)               => "rem-int",
+            InstructionKind::AndInt(..)               => "and-int",
+            InstructionKind::OrInt(..)                => "or-int",
+            InstructionKind::XorInt(..)               => "xor-int",
+            InstructionKind::ShlInt(..)               => "shl-int",
+            InstructionKind::ShrInt(..)               => "shr-int",
+            InstructionKind::UShrInt(..)              => "ushr-int",
+            InstructionKind::AddLong(..)              => "add-long",
+            InstructionKind::SubLong(..)              => "sub-long",
+            InstructionKind::MulLong(..)              => "mul-long",
+            InstructionKind::DivLong(..)              => "div-long",
+            InstructionKind::RemLong(..)              => "rem-long",
+            InstructionKind::AndLong(..)              => "and-long",
+            InstructionKind::OrLong(..)               => "or-long",
+            InstructionKind::XorLong(..)              => "xor-long",
+            InstructionKind::ShlLong(..)              => "shl-long",
+            InstructionKind::ShrLong(..)              => "shr-long",
+            InstructionKind::UShrLong(..)             => "ushr-long",
+            InstructionKind::AddFloat(..)             => "add-float",
+            InstructionKind::SubFloat(..)             => "sub-float",
+            InstructionKind::MulFloat(..)             => "mul-float",
+            InstructionKind::DivFloat(..)             => "div-float",
+            InstructionKind::RemFloat(..)             => "rem-float",
+            InstructionKind::AddDouble(..)            => "add-double",
+            InstructionKind::SubDouble(..)            => "sub-double",
+            InstructionKind::MulDouble(..)            => "mul-double",
+            InstructionKind::DivDouble(..)            => "div-double",
+            InstructionKind::RemDouble(..)            => "rem-double",
+            InstructionKind::AddInt2Addr(..)          => "add-int/2addr",
+            InstructionKind::SubInt2Addr(..)          => "sub-int/2addr",
+            InstructionKind::MulInt2Addr(..)          => "mul-int/2addr",
+            InstructionKind::DivInt2Addr(..)          => "div-int/2addr",
+            InstructionKind::RemInt2Addr(..)          => "rem-int/2addr",
+            InstructionKind::AndInt2Addr(..)          => "and-int/2addr",
+            InstructionKind::OrInt2Addr(..)           => "or-int/2addr",
+            InstructionKind::XorInt2Addr(..)          => "xor-int/2addr",
+            InstructionKind::ShlInt2Addr(..)          => "shl-int/2addr",
+            InstructionKind::ShrInt2Addr(..)          => "shr-int/2addr",
+            InstructionKind::UShrInt2Addr(..)         => "ushr-int/2addr",
+            InstructionKind::AddLong2Addr(..)         => "add-long/2addr",
+            InstructionKind::SubLong2Addr(..)         => "sub-long/2addr",
+            InstructionKind::MulLong2Addr(..)         => "mul-long/2addr",
+            InstructionKind::DivLong2Addr(..)         => "div-long/2addr",
+            InstructionKind::RemLong2Addr(..)         => "rem-long/2addr",
+            InstructionKind::AndLong2Addr(..)         => "and-long/2addr",
+            InstructionKind::OrLong2Addr(..)          => "or-long/2addr",
+            InstructionKind::XorLong2Addr(..)         => "xor-long/2addr",
+            InstructionKind::ShlLong2Addr(..)         => "shl-long/2addr",
+            InstructionKind::ShrLong2Addr(..)         => "shr-long/2addr",
+            InstructionKind::UShrLong2Addr(..)        => "ushr-long/2addr",
+            InstructionKind::AddFloat2Addr(..)        => "add-float/2addr",
+            InstructionKind::SubFloat2Addr(..)        => "sub-float/2addr",
+            InstructionKind::MulFloat2Addr(..)        => "mul-float/2addr",
+            InstructionKind::DivFloat2Addr(..)        => "div-float/2addr",
+            InstructionKind::RemFloat2Addr(..)        => "rem-float/2addr",
+            InstructionKind::AddDouble2Addr(..)       => "add-double/2addr",
+            InstructionKind::SubDouble2Addr(..)       => "sub-double/2addr",
+            InstructionKind::MulDouble2Addr(..)       => "mul-double/2addr",
+            InstructionKind::DivDouble2Addr(..)       => "div-double/2addr",
+            InstructionKind::RemDouble2Addr(..)       => "rem-double/2addr",
+            InstructionKind::AddIntLit16(..)          => "add-int/lit16",
+            InstructionKind::RSubIntLit16(..)         => "rsub-int/lit16",
+            InstructionKind::MulIntLit16(..)          => "mul-int/lit16",
+            InstructionKind::DivIntLit16(..)          => "div-int/lit16",
+            InstructionKind::RemIntLit16(..)          => "rem-int/lit16",
+            InstructionKind::AndIntLit16(..)          => "and-int/lit16",
+            InstructionKind::OrIntLit16(..)           => "or-int/lit16",
+            InstructionKind::XorIntLit16(..)          => "xor-int/lit16",
+            InstructionKind::AddIntLit8(..)           => "add-int/lit8",
+            InstructionKind::RSubIntLit8(..)          => "rsub-int/lit8",
+            InstructionKind::MulIntLit8(..)           => "mul-int/lit8",
+            InstructionKind::DivIntLit8(..)           => "div-int/lit8",
+            InstructionKind::RemIntLit8(..)           => "rem-int/lit8",
+            InstructionKind::AndIntLit8(..)           => "and-int/lit8",
+            InstructionKind::OrIntLit8(..)            => "or-int/lit8",
+            InstructionKind::XorIntLit8(..)           => "xor-int/lit8",
+            InstructionKind::ShlIntLit8(..)           => "shl-int/lit8",
+            InstructionKind::ShrIntLit8(..)           => "shr-int/lit8",
+            InstructionKind::UShrIntLit8(..)          => "ushr-int/lit8",
+            InstructionKind::InvokePolymorphic(..)    => "invoke-polymorphic",
+            InstructionKind::InvokePolymorphicRange(..) => "invoke-polymorphic/range",
+            InstructionKind::InvokeCustom(..)         => "invoke-custom",
+            InstructionKind::InvokeCustomRange(..)    => "invoke-custom/range",
+            InstructionKind::ConstMethodHandle(..)    => "const-method-handle",
+            InstructionKind::ConstMethodType(..)      => "const-method-type",
+            InstructionKind::ReturnVoidNoBarrier      => "return-void-no-barrier",
+            InstructionKind::IGetQuick(..)            => "iget-quick",
+            InstructionKind::IGetWideQuick(..)        => "iget-wide-quick",
+            InstructionKind::IGetObjectQuick(..)      => "iget-object-quick",
+            InstructionKind::IPutQuick(..)            => "iput-quick",
+            InstructionKind::IPutWideQuick(..)        => "iput-wide-quick",
+            InstructionKind::IPutObjectQuick(..)      => "iput-object-quick",
+            InstructionKind::InvokeVirtualQuick(..)   => "invoke-virtual-quick",
+            InstructionKind::InvokeVirtualQuickRange(..) => "invoke-virtual-quick/range",
+            InstructionKind::IPutBooleanQuick(..)     => "iput-boolean-quick",
+            InstructionKind::IPutByteQuick(..)        => "iput-byte-quick",
+            InstructionKind::IPutCharQuick(..)        => "iput-char-quick",
+            InstructionKind::IPutShortQuick(..)       => "iput-short-quick",
+            InstructionKind::IGetBooleanQuick(..)     => "iget-boolean-quick",
+            InstructionKind::IGetByteQuick(..)        => "iget-byte-quick",
+            InstructionKind::IGetCharQuick(..)        => "iget-char-quick",
+            InstructionKind::IGetShortQuick(..)       => "iget-short-quick",
+            InstructionKind::PackedSwitchPayload(..)  => "packed-switch-payload",
+            InstructionKind::SparseSwitchPayload(..)  => "sparse-switch-payload",
+            InstructionKind::FillArrayDataPayload(..) => "fill-array-data-payload",
+            InstructionKind::Unused                   => "unused",
+            InstructionKind::Stop                     => "stop",
+        }
+    }
+
+    // Wide values occupy a register pair, so both halves are reported.
+    pub fn defined_registers(&self) -> Vec<Register> {
+        match self {
+              InstructionKind::Move(a, _)
+            | InstructionKind::MoveFrom16(a, _)
+            | InstructionKind::Move16(a, _)
+            | InstructionKind::MoveObject(a, _)
+            | InstructionKind::MoveObjectFrom16(a, _)
+            | InstructionKind::MoveObject16(a, _)
+            | InstructionKind::MoveResult(a)
+            | InstructionKind::MoveResultObject(a)
+            | InstructionKind::MoveException(a)
+            | InstructionKind::Const4(a, _)
+            | InstructionKind::Const16(a, _)
+            | InstructionKind::Const(a, _)
+            | InstructionKind::ConstHigh16(a, _)
+            | InstructionKind::ConstString(a, _)
+            | InstructionKind::ConstStringJumbo(a, _)
+            | InstructionKind::ConstClass(a, _)
+            | InstructionKind::ConstMethodHandle(a, _)
+            | InstructionKind::ConstMethodType(a, _)
+            | InstructionKind::InstanceOf(a, _, _)
+            | InstructionKind::ArrayLength(a, _)
+            | InstructionKind::NewInstance(a, _)
+            | InstructionKind::NewArray(a, _, _)
+            | InstructionKind::CmpLFloat(a, _, _)
+            | InstructionKind::CmpGFloat(a, _, _)
+            | InstructionKind::CmpLDouble(a, _, _)
+            | InstructionKind::CmpGDouble(a, _, _)
+            | InstructionKind::CmpLong(a, _, _)
+            | InstructionKind::AGet(a, _, _)
+            | InstructionKind::AGetObject(a, _, _)
+            | InstructionKind::AGetBoolean(a, _, _)
+            | InstructionKind::AGetByte(a, _, _)
+            | InstructionKind::AGetChar(a, _, _)
+            | InstructionKind::AGetShort(a, _, _)
+            | InstructionKind::IGet(a, _, _)
+            | InstructionKind::IGetObject(a, _, _)
+            | InstructionKind::IGetBoolean(a, _, _)
+            | InstructionKind::IGetByte(a, _, _)
+            | InstructionKind::IGetChar(a, _, _)
+            | InstructionKind::IGetShort(a, _, _)
+            | InstructionKind::IGetQuick(a, _, _)
+            | InstructionKind::IGetObjectQuick(a, _, _)
+            | InstructionKind::IGetBooleanQuick(a, _, _)
+            | InstructionKind::IGetByteQuick(a, _, _)
+            | InstructionKind::IGetCharQuick(a, _, _)
+            | InstructionKind::IGetShortQuick(a, _, _)
+            | InstructionKind::SGet(a, _)
+            | InstructionKind::SGetObject(a, _)
+            | InstructionKind::SGetBoolean(a, _)
+            | InstructionKind::SGetByte(a, _)
+            | InstructionKind::SGetChar(a, _)
+            | InstructionKind::SGetShort(a, _)
+            | InstructionKind::NegInt(a, _)
+            | InstructionKind::NotInt(a, _)
+            | InstructionKind::NegFloat(a, _)
+            | InstructionKind::IntToFloat(a, _)
+            | InstructionKind::LongToInt(a, _)
+            | InstructionKind::LongToFloat(a, _)
+            | InstructionKind::FloatToInt(a, _)
+            | InstructionKind::DoubleToInt(a, _)
+            | InstructionKind::DoubleToFloat(a, _)
+            | InstructionKind::IntToByte(a, _)
+            | InstructionKind::IntToChar(a, _)
+            | InstructionKind::IntToShort(a, _)
+            | InstructionKind::AddInt(a, _, _)
+            | InstructionKind::SubInt(a, _, _)
+            | InstructionKind::MulInt(a, _, _)
+            | InstructionKind::DivInt(a, _, _)
+            | InstructionKind::RemInt(a, _, _)
+            | InstructionKind::AndInt(a, _, _)
+            | InstructionKind::OrInt(a, _, _)
+            | InstructionKind::XorInt(a, _, _)
+            | InstructionKind::ShlInt(a, _, _)
+            | InstructionKind::ShrInt(a, _, _)
+            | InstructionKind::UShrInt(a, _, _)
+            | InstructionKind::AddFloat(a, _, _)
+            | InstructionKind::SubFloat(a, _, _)
+            | InstructionKind::MulFloat(a, _, _)
+            | InstructionKind::DivFloat(a, _, _)
+            | InstructionKind::RemFloat(a, _, _)
+            | InstructionKind::AddInt2Addr(a, _)
+            | InstructionKind::SubInt2Addr(a, _)
+            | InstructionKind::MulInt2Addr(a, _)
+            | InstructionKind::DivInt2Addr(a, _)
+            | InstructionKind::RemInt2Addr(a, _)
+            | InstructionKind::AndInt2Addr(a, _)
+            | InstructionKind::OrInt2Addr(a, _)
+            | InstructionKind::XorInt2Addr(a, _)
+            | InstructionKind::ShlInt2Addr(a, _)
+            | InstructionKind::ShrInt2Addr(a, _)
+            | InstructionKind::UShrInt2Addr(a, _)
+            | InstructionKind::AddFloat2Addr(a, _)
+            | InstructionKind::SubFloat2Addr(a, _)
+            | InstructionKind::MulFloat2Addr(a, _)
+            | InstructionKind::DivFloat2Addr(a, _)
+            | InstructionKind::RemFloat2Addr(a, _)
+            | InstructionKind::AddIntLit16(a, _, _)
+            | InstructionKind::RSubIntLit16(a, _, _)
+            | InstructionKind::MulIntLit16(a, _, _)
+            | InstructionKind::DivIntLit16(a, _, _)
+            | InstructionKind::RemIntLit16(a, _, _)
+            | InstructionKind::AndIntLit16(a, _, _)
+            | InstructionKind::OrIntLit16(a, _, _)
+            | InstructionKind::XorIntLit16(a, _, _)
+            | InstructionKind::AddIntLit8(a, _, _)
+            | InstructionKind::RSubIntLit8(a, _, _)
+            | InstructionKind::MulIntLit8(a, _, _)
+            | InstructionKind::DivIntLit8(a, _, _)
+            | InstructionKind::RemIntLit8(a, _, _)
+            | InstructionKind::AndIntLit8(a, _, _)
+            | InstructionKind::OrIntLit8(a, _, _)
+            | InstructionKind::XorIntLit8(a, _, _)
+            | InstructionKind::ShlIntLit8(a, _, _)
+            | InstructionKind::ShrIntLit8(a, _, _)
+            | InstructionKind::UShrIntLit8(a, _, _) => vec![*a],
+              InstructionKind::MoveWide(a, _)
+            | InstructionKind::MoveWideFrom16(a, _)
+            | InstructionKind::MoveWide16(a, _)
+            | InstructionKind::MoveResultWide(a)
+            | InstructionKind::ConstWide16(a, _)
+            | InstructionKind::ConstWide32(a, _)
+            | InstructionKind::ConstWide(a, _)
+            | InstructionKind::ConstWideHigh16(a, _)
+            | InstructionKind::AGetWide(a, _, _)
+            | InstructionKind::IGetWide(a, _, _)
+            | InstructionKind::IGetWideQuick(a, _, _)
+            | InstructionKind::SGetWide(a, _)
+            | InstructionKind::NegLong(a, _)
+            | InstructionKind::NotLong(a, _)
+            | InstructionKind::NegDouble(a, _)
+            | InstructionKind::IntToLong(a, _)
+            | InstructionKind::IntToDouble(a, _)
+            | InstructionKind::LongToDouble(a, _)
+            | InstructionKind::FloatToLong(a, _)
+            | InstructionKind::FloatToDouble(a, _)
+            | InstructionKind::DoubleToLong(a, _)
+            | InstructionKind::AddLong(a, _, _)
+            | InstructionKind::SubLong(a, _, _)
+            | InstructionKind::MulLong(a, _, _)
+            | InstructionKind::DivLong(a, _, _)
+            | InstructionKind::RemLong(a, _, _)
+            | InstructionKind::AndLong(a, _, _)
+            | InstructionKind::OrLong(a, _, _)
+            | InstructionKind::XorLong(a, _, _)
+            | InstructionKind::ShlLong(a, _, _)
+            | InstructionKind::ShrLong(a, _, _)
+            | InstructionKind::UShrLong(a, _, _)
+            | InstructionKind::AddDouble(a, _, _)
+            | InstructionKind::SubDouble(a, _, _)
+            | InstructionKind::MulDouble(a, _, _)
+            | InstructionKind::DivDouble(a, _, _)
+            | InstructionKind::RemDouble(a, _, _)
+            | InstructionKind::AddLong2Addr(a, _)
+            | InstructionKind::SubLong2Addr(a, _)
+            | InstructionKind::MulLong2Addr(a, _)
+            | InstructionKind::DivLong2Addr(a, _)
+            | InstructionKind::RemLong2Addr(a, _)
+            | InstructionKind::AndLong2Addr(a, _)
+            | InstructionKind::OrLong2Addr(a, _)
+            | InstructionKind::XorLong2Addr(a, _)
+            | InstructionKind::ShlLong2Addr(a, _)
+            | InstructionKind::ShrLong2Addr(a, _)
+            | InstructionKind::UShrLong2Addr(a, _)
+            | InstructionKind::AddDouble2Addr(a, _)
+            | InstructionKind::SubDouble2Addr(a, _)
+            | InstructionKind::MulDouble2Addr(a, _)
+            | InstructionKind::DivDouble2Addr(a, _)
+            | InstructionKind::RemDouble2Addr(a, _) => vec![*a, *a + 1],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn used_registers(&self) -> Vec<Register> {
+        match self {
+              InstructionKind::Move(_, b)
+            | InstructionKind::MoveFrom16(_, b)
+            | InstructionKind::Move16(_, b)
+            | InstructionKind::MoveObject(_, b)
+            | InstructionKind::MoveObjectFrom16(_, b)
+            | InstructionKind::MoveObject16(_, b)
+            | InstructionKind::InstanceOf(_, b, _)
+            | InstructionKind::ArrayLength(_, b)
+            | InstructionKind::NewArray(_, b, _)
+            | InstructionKind::NegInt(_, b)
+            | InstructionKind::NotInt(_, b)
+            | InstructionKind::NegFloat(_, b)
+            | InstructionKind::IntToLong(_, b)
+            | InstructionKind::IntToFloat(_, b)
+            | InstructionKind::IntToDouble(_, b)
+            | InstructionKind::FloatToInt(_, b)
+            | InstructionKind::FloatToLong(_, b)
+            | InstructionKind::FloatToDouble(_, b)
+            | InstructionKind::IntToByte(_, b)
+            | InstructionKind::IntToChar(_, b)
+            | InstructionKind::IntToShort(_, b)
+            | InstructionKind::IGet(_, b, _)
+            | InstructionKind::IGetWide(_, b, _)
+            | InstructionKind::IGetObject(_, b, _)
+            | InstructionKind::IGetBoolean(_, b, _)
+            | InstructionKind::IGetByte(_, b, _)
+            | InstructionKind::IGetChar(_, b, _)
+            | InstructionKind::IGetShort(_, b, _)
+            | InstructionKind::IGetQuick(_, b, _)
+            | InstructionKind::IGetWideQuick(_, b, _)
+            | InstructionKind::IGetObjectQuick(_, b, _)
+            | InstructionKind::IGetBooleanQuick(_, b, _)
+            | InstructionKind::IGetByteQuick(_, b, _)
+            | InstructionKind::IGetCharQuick(_, b, _)
+            | InstructionKind::IGetShortQuick(_, b, _)
+            | InstructionKind::AddIntLit16(_, b, _)
+            | InstructionKind::RSubIntLit16(_, b, _)
+            | InstructionKind::MulIntLit16(_, b, _)
+            | InstructionKind::DivIntLit16(_, b, _)
+            | InstructionKind::RemIntLit16(_, b, _)
+            | InstructionKind::AndIntLit16(_, b, _)
+            | InstructionKind::OrIntLit16(_, b, _)
+            | InstructionKind::XorIntLit16(_, b, _)
+            | InstructionKind::AddIntLit8(_, b, _)
+            | InstructionKind::RSubIntLit8(_, b, _)
+            | InstructionKind::MulIntLit8(_, b, _)
+            | InstructionKind::DivIntLit8(_, b, _)
+            | InstructionKind::RemIntLit8(_, b, _)
+            | InstructionKind::AndIntLit8(_, b, _)
+            | InstructionKind::OrIntLit8(_, b, _)
+            | InstructionKind::XorIntLit8(_, b, _)
+            | InstructionKind::ShlIntLit8(_, b, _)
+            | InstructionKind::ShrIntLit8(_, b, _)
+            | InstructionKind::UShrIntLit8(_, b, _) => vec![*b],
+              InstructionKind::MoveWide(_, b)
+            | InstructionKind::MoveWideFrom16(_, b)
+            | InstructionKind::MoveWide16(_, b)
+            | InstructionKind::NegLong(_, b)
+            | InstructionKind::NotLong(_, b)
+            | InstructionKind::NegDouble(_, b)
+            | InstructionKind::LongToInt(_, b)
+            | InstructionKind::LongToFloat(_, b)
+            | InstructionKind::LongToDouble(_, b)
+            | InstructionKind::DoubleToInt(_, b)
+            | InstructionKind::DoubleToLong(_, b)
+            | InstructionKind::DoubleToFloat(_, b) => vec![*b, *b + 1],
+              InstructionKind::Return(a)
+            | InstructionKind::ReturnObject(a)
+            | InstructionKind::MonitorEnter(a)
+            | InstructionKind::MonitorExit(a)
+            | InstructionKind::CheckCast(a, _)
+            | InstructionKind::FillArrayData(a, _)
+            | InstructionKind::Throw(a)
+            | InstructionKind::PackedSwitch(a, _)
+            | InstructionKind::SparseSwitch(a, _)
+            | InstructionKind::IfEqZ(a, _)
+            | InstructionKind::IfNeZ(a, _)
+            | InstructionKind::IfLtZ(a, _)
+            | InstructionKind::IfGeZ(a, _)
+            | InstructionKind::IfGtZ(a, _)
+            | InstructionKind::IfLeZ(a, _)
+            | InstructionKind::SPut(a, _)
+            | InstructionKind::SPutObject(a, _)
+            | InstructionKind::SPutBoolean(a, _)
+            | InstructionKind::SPutByte(a, _)
+            | InstructionKind::SPutChar(a, _)
+            | InstructionKind::SPutShort(a, _) => vec![*a],
+              InstructionKind::ReturnWide(a)
+            | InstructionKind::SPutWide(a, _) => vec![*a, *a + 1],
+              InstructionKind::IfEq(a, b, _)
+            | InstructionKind::IfNe(a, b, _)
+            | InstructionKind::IfLt(a, b, _)
+            | InstructionKind::IfGe(a, b, _)
+            | InstructionKind::IfGt(a, b, _)
+            | InstructionKind::IfLe(a, b, _)
+            | InstructionKind::IPut(a, b, _)
+            | InstructionKind::IPutObject(a, b, _)
+            | InstructionKind::IPutBoolean(a, b, _)
+            | InstructionKind::IPutByte(a, b, _)
+            | InstructionKind::IPutChar(a, b, _)
+            | InstructionKind::IPutShort(a, b, _)
+            | InstructionKind::IPutQuick(a, b, _)
+            | InstructionKind::IPutObjectQuick(a, b, _)
+            | InstructionKind::IPutBooleanQuick(a, b, _)
+            | InstructionKind::IPutByteQuick(a, b, _)
+            | InstructionKind::IPutCharQuick(a, b, _)
+            | InstructionKind::IPutShortQuick(a, b, _)
+            | InstructionKind::AddInt2Addr(a, b)
+            | InstructionKind::SubInt2Addr(a, b)
+            | InstructionKind::MulInt2Addr(a, b)
+            | InstructionKind::DivInt2Addr(a, b)
+            | InstructionKind::RemInt2Addr(a, b)
+            | InstructionKind::AndInt2Addr(a, b)
+            | InstructionKind::OrInt2Addr(a, b)
+            | InstructionKind::XorInt2Addr(a, b)
+            | InstructionKind::ShlInt2Addr(a, b)
+            | InstructionKind::ShrInt2Addr(a, b)
+            | InstructionKind::UShrInt2Addr(a, b)
+            | InstructionKind::AddFloat2Addr(a, b)
+            | InstructionKind::SubFloat2Addr(a, b)
+            | InstructionKind::MulFloat2Addr(a, b)
+            | InstructionKind::DivFloat2Addr(a, b)
+            | InstructionKind::RemFloat2Addr(a, b) => vec![*a, *b],
+              InstructionKind::IPutWide(a, b, _)
+            | InstructionKind::IPutWideQuick(a, b, _) => vec![*a, *a + 1, *b],
+              InstructionKind::ShlLong2Addr(a, b)
+            | InstructionKind::ShrLong2Addr(a, b)
+            | InstructionKind::UShrLong2Addr(a, b) => vec![*a, *a + 1, *b],
+              InstructionKind::AddLong2Addr(a, b)
+            | InstructionKind::SubLong2Addr(a, b)
+            | InstructionKind::MulLong2Addr(a, b)
+            | InstructionKind::DivLong2Addr(a, b)
+            | InstructionKind::RemLong2Addr(a, b)
+            | InstructionKind::AndLong2Addr(a, b)
+            | InstructionKind::OrLong2Addr(a, b)
+            | InstructionKind::XorLong2Addr(a, b)
+            | InstructionKind::AddDouble2Addr(a, b)
+            | InstructionKind::SubDouble2Addr(a, b)
+            | InstructionKind::MulDouble2Addr(a, b)
+            | InstructionKind::DivDouble2Addr(a, b)
+            | InstructionKind::RemDouble2Addr(a, b) => vec![*a, *a + 1, *b, *b + 1],
+              InstructionKind::CmpLFloat(_, b, c)
+            | InstructionKind::CmpGFloat(_, b, c)
+            | InstructionKind::AGet(_, b, c)
+            | InstructionKind::AGetWide(_, b, c)
+            | InstructionKind::AGetObject(_, b, c)
+            | InstructionKind::AGetBoolean(_, b, c)
+            | InstructionKind::AGetByte(_, b, c)
+            | InstructionKind::AGetChar(_, b, c)
+            | InstructionKind::AGetShort(_, b, c)
+            | InstructionKind::AddInt(_, b, c)
+            | InstructionKind::SubInt(_, b, c)
+            | InstructionKind::MulInt(_, b, c)
+            | InstructionKind::DivInt(_, b, c)
+            | InstructionKind::RemInt(_, b, c)
+            | InstructionKind::AndInt(_, b, c)
+            | InstructionKind::OrInt(_, b, c)
+            | InstructionKind::XorInt(_, b, c)
+            | InstructionKind::ShlInt(_, b, c)
+            | InstructionKind::ShrInt(_, b, c)
+            | InstructionKind::UShrInt(_, b, c)
+            | InstructionKind::AddFloat(_, b, c)
+            | InstructionKind::SubFloat(_, b, c)
+            | InstructionKind::MulFloat(_, b, c)
+            | InstructionKind::DivFloat(_, b, c)
+            | InstructionKind::RemFloat(_, b, c) => vec![*b, *c],
+              InstructionKind::ShlLong(_, b, c)
+            | InstructionKind::ShrLong(_, b, c)
+            | InstructionKind::UShrLong(_, b, c) => vec![*b, *b + 1, *c],
+              InstructionKind::CmpLDouble(_, b, c)
+            | InstructionKind::CmpGDouble(_, b, c)
+            | InstructionKind::CmpLong(_, b, c)
+            | InstructionKind::AddLong(_, b, c)
+            | InstructionKind::SubLong(_, b, c)
+            | InstructionKind::MulLong(_, b, c)
+            | InstructionKind::DivLong(_, b, c)
+            | InstructionKind::RemLong(_, b, c)
+            | InstructionKind::AndLong(_, b, c)
+            | InstructionKind::OrLong(_, b, c)
+            | InstructionKind::XorLong(_, b, c)
+            | InstructionKind::AddDouble(_, b, c)
+            | InstructionKind::SubDouble(_, b, c)
+            | InstructionKind::MulDouble(_, b, c)
+            | InstructionKind::DivDouble(_, b, c)
+            | InstructionKind::RemDouble(_, b, c) => vec![*b, *b + 1, *c, *c + 1],
+              InstructionKind::APut(a, b, c)
+            | InstructionKind::APutObject(a, b, c)
+            | InstructionKind::APutBoolean(a, b, c)
+            | InstructionKind::APutByte(a, b, c)
+            | InstructionKind::APutChar(a, b, c)
+            | InstructionKind::APutShort(a, b, c) => vec![*a, *b, *c],
+              InstructionKind::APutWide(a, b, c) => vec![*a, *a + 1, *b, *c],
+              InstructionKind::FilledNewArray(args, _)
+            | InstructionKind::InvokeVirtual(args, _)
+            | InstructionKind::InvokeSuper(args, _)
+            | InstructionKind::InvokeDirect(args, _)
+            | InstructionKind::InvokeStatic(args, _)
+            | InstructionKind::InvokeInterface(args, _)
+            | InstructionKind::InvokePolymorphic(args, _, _)
+            | InstructionKind::InvokeCustom(args, _)
+            | InstructionKind::InvokeVirtualQuick(args, _) => args.clone(),
+              InstructionKind::FilledNewArrayRange(first, count, _)
+            | InstructionKind::InvokeVirtualRange(first, count, _)
+            | InstructionKind::InvokeSuperRange(first, count, _)
+            | InstructionKind::InvokeDirectRange(first, count, _)
+            | InstructionKind::InvokeStaticRange(first, count, _)
+            | InstructionKind::InvokeInterfaceRange(first, count, _)
+            | InstructionKind::InvokePolymorphicRange(first, count, _, _)
+            | InstructionKind::InvokeCustomRange(first, count, _)
+            | InstructionKind::InvokeVirtualQuickRange(first, count, _) => register_range(*first, *count),
+            _ => Vec::new(),
+        }
+    }
+
+    // Operands in the order they are written in smali.
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+              InstructionKind::Move(a, b)
+            | InstructionKind::MoveFrom16(a, b)
+            | InstructionKind::Move16(a, b)
+            | InstructionKind::MoveWide(a, b)
+            | InstructionKind::MoveWideFrom16(a, b)
+            | InstructionKind::MoveWide16(a, b)
+            | InstructionKind::MoveObject(a, b)
+            | InstructionKind::MoveObjectFrom16(a, b)
+            | InstructionKind::MoveObject16(a, b)
+            | InstructionKind::ArrayLength(a, b)
+            | InstructionKind::NegInt(a, b)
+            | InstructionKind::NotInt(a, b)
+            | InstructionKind::NegLong(a, b)
+            | InstructionKind::NotLong(a, b)
+            | InstructionKind::NegFloat(a, b)
+            | InstructionKind::NegDouble(a, b)
+            | InstructionKind::IntToLong(a, b)
+            | InstructionKind::IntToFloat(a, b)
+            | InstructionKind::IntToDouble(a, b)
+            | InstructionKind::LongToInt(a, b)
+            | InstructionKind::LongToFloat(a, b)
+            | InstructionKind::LongToDouble(a, b)
+            | InstructionKind::FloatToInt(a, b)
+            | InstructionKind::FloatToLong(a, b)
+            | InstructionKind::FloatToDouble(a, b)
+            | InstructionKind::DoubleToInt(a, b)
+            | InstructionKind::DoubleToLong(a, b)
+            | InstructionKind::DoubleToFloat(a, b)
+            | InstructionKind::IntToByte(a, b)
+            | InstructionKind::IntToChar(a, b)
+            | InstructionKind::IntToShort(a, b)
+            | InstructionKind::AddInt2Addr(a, b)
+            | InstructionKind::SubInt2Addr(a, b)
+            | InstructionKind::MulInt2Addr(a, b)
+            | InstructionKind::DivInt2Addr(a, b)
+            | InstructionKind::RemInt2Addr(a, b)
+            | InstructionKind::AndInt2Addr(a, b)
+            | InstructionKind::OrInt2Addr(a, b)
+            | InstructionKind::XorInt2Addr(a, b)
+            | InstructionKind::ShlInt2Addr(a, b)
+            | InstructionKind::ShrInt2Addr(a, b)
+            | InstructionKind::UShrInt2Addr(a, b)
+            | InstructionKind::AddLong2Addr(a, b)
+            | InstructionKind::SubLong2Addr(a, b)
+            | InstructionKind::MulLong2Addr(a, b)
+            | InstructionKind::DivLong2Addr(a, b)
+            | InstructionKind::RemLong2Addr(a, b)
+            | InstructionKind::AndLong2Addr(a, b)
+            | InstructionKind::OrLong2Addr(a, b)
+            | InstructionKind::XorLong2Addr(a, b)
+            | InstructionKind::ShlLong2Addr(a, b)
+            | InstructionKind::ShrLong2Addr(a, b)
+            | InstructionKind::UShrLong2Addr(a, b)
+            | InstructionKind::AddFloat2Addr(a, b)
+            | InstructionKind::SubFloat2Addr(a, b)
+            | InstructionKind::MulFloat2Addr(a, b)
+            | InstructionKind::DivFloat2Addr(a, b)
+            | InstructionKind::RemFloat2Addr(a, b)
+            | InstructionKind::AddDouble2Addr(a, b)
+            | InstructionKind::SubDouble2Addr(a, b)
+            | InstructionKind::MulDouble2Addr(a, b)
+            | InstructionKind::DivDouble2Addr(a, b)
+            | InstructionKind::RemDouble2Addr(a, b) => vec![Operand::Register(*a), Operand::Register(*b)],
+              InstructionKind::MoveResult(a)
+            | InstructionKind::MoveResultWide(a)
+            | InstructionKind::MoveResultObject(a)
+            | InstructionKind::MoveException(a)
+            | InstructionKind::Return(a)
+            | InstructionKind::ReturnWide(a)
+            | InstructionKind::ReturnObject(a)
+            | InstructionKind::MonitorEnter(a)
+            | InstructionKind::MonitorExit(a)
+            | InstructionKind::Throw(a) => vec![Operand::Register(*a)],
+              InstructionKind::Const4(a, b)
+            | InstructionKind::Const16(a, b)
+            | InstructionKind::Const(a, b)
+            | InstructionKind::ConstHigh16(a, b) => vec![Operand::Register(*a), Operand::Literal(*b as i64)],
+              InstructionKind::ConstWide16(a, b)
+            | InstructionKind::ConstWide32(a, b)
+            | InstructionKind::ConstWide(a, b)
+            | InstructionKind::ConstWideHigh16(a, b) => vec![Operand::Register(*a), Operand::Literal(*b)],
+              InstructionKind::ConstString(a, b)
+            | InstructionKind::ConstStringJumbo(a, b) => vec![Operand::Register(*a), Operand::String(*b)],
+              InstructionKind::ConstClass(a, b)
+            | InstructionKind::CheckCast(a, b)
+            | InstructionKind::NewInstance(a, b) => vec![Operand::Register(*a), Operand::Type(*b)],
+              InstructionKind::InstanceOf(a, b, c)
+            | InstructionKind::NewArray(a, b, c) => vec![Operand::Register(*a), Operand::Register(*b), Operand::Type(*c)],
+            InstructionKind::FilledNewArray(a, b) => vec![Operand::RegisterList(a.clone()), Operand::Type(*b)],
+            InstructionKind::FilledNewArrayRange(a, b, c) => vec![Operand::RegisterRange(*a, *b), Operand::Type(*c)],
+              InstructionKind::FillArrayData(a, b)
+            | InstructionKind::PackedSwitch(a, b)
+            | InstructionKind::SparseSwitch(a, b)
+            | InstructionKind::IfEqZ(a, b)
+            | InstructionKind::IfNeZ(a, b)
+            | InstructionKind::IfLtZ(a, b)
+            | InstructionKind::IfGeZ(a, b)
+            | InstructionKind::IfGtZ(a, b)
+            | InstructionKind::IfLeZ(a, b) => vec![Operand::Register(*a), Operand::Offset(*b)],
+              InstructionKind::GoTo(a)
+            | InstructionKind::GoTo16(a)
+            | InstructionKind::GoTo32(a) => vec![Operand::Offset(*a)],
+              InstructionKind::CmpLFloat(a, b, c)
+            | InstructionKind::CmpGFloat(a, b, c)
+            | InstructionKind::CmpLDouble(a, b, c)
+            | InstructionKind::CmpGDouble(a, b, c)
+            | InstructionKind::CmpLong(a, b, c)
+            | InstructionKind::AGet(a, b, c)
+            | InstructionKind::AGetWide(a, b, c)
+            | InstructionKind::AGetObject(a, b, c)
+            | InstructionKind::AGetBoolean(a, b, c)
+            | InstructionKind::AGetByte(a, b, c)
+            | InstructionKind::AGetChar(a, b, c)
+            | InstructionKind::AGetShort(a, b, c)
+            | InstructionKind::APut(a, b, c)
+            | InstructionKind::APutWide(a, b, c)
+            | InstructionKind::APutObject(a, b, c)
+            | InstructionKind::APutBoolean(a, b, c)
+            | InstructionKind::APutByte(a, b, c)
+            | InstructionKind::APutChar(a, b, c)
+            | InstructionKind::APutShort(a, b, c)
+            | InstructionKind::AddInt(a, b, c)
+            | InstructionKind::SubInt(a, b, c)
+            | InstructionKind::MulInt(a, b, c)
+            | InstructionKind::DivInt(a, b, c)
+            | InstructionKind::RemInt(a, b, c)
+            | InstructionKind::AndInt(a, b, c)
+            | InstructionKind::OrInt(a, b, c)
+            | InstructionKind::XorInt(a, b, c)
+            | InstructionKind::ShlInt(a, b, c)
+            | InstructionKind::ShrInt(a, b, c)
+            | InstructionKind::UShrInt(a, b, c)
+            | InstructionKind::AddLong(a, b, c)
+            | InstructionKind::SubLong(a, b, c)
+            | InstructionKind::MulLong(a, b, c)
+            | InstructionKind::DivLong(a, b, c)
+            | InstructionKind::RemLong(a, b, c)
+            | InstructionKind::AndLong(a, b, c)
+            | InstructionKind::OrLong(a, b, c)
+            | InstructionKind::XorLong(a, b, c)
+            | InstructionKind::ShlLong(a, b, c)
+            | InstructionKind::ShrLong(a, b, c)
+            | InstructionKind::UShrLong(a, b, c)
+            | InstructionKind::AddFloat(a, b, c)
+            | InstructionKind::SubFloat(a, b, c)
+            | InstructionKind::MulFloat(a, b, c)
+            | InstructionKind::DivFloat(a, b, c)
+            | InstructionKind::RemFloat(a, b, c)
+            | InstructionKind::AddDouble(a, b, c)
+            | InstructionKind::SubDouble(a, b, c)
+            | InstructionKind::MulDouble(a, b, c)
+            | InstructionKind::DivDouble(a, b, c)
+            | InstructionKind::RemDouble(a, b, c) => vec![Operand::Register(*a), Operand::Register(*b), Operand::Register(*c)],
+              InstructionKind::IfEq(a, b, c)
+            | InstructionKind::IfNe(a, b, c)
+            | InstructionKind::IfLt(a, b, c)
+            | InstructionKind::IfGe(a, b, c)
+            | InstructionKind::IfGt(a, b, c)
+            | InstructionKind::IfLe(a, b, c) => vec![Operand::Register(*a), Operand::Register(*b), Operand::Offset(*c)],
+              InstructionKind::IGet(a, b, c)
+            | InstructionKind::IGetWide(a, b, c)
+            | InstructionKind::IGetObject(a, b, c)
+            | InstructionKind::IGetBoolean(a, b, c)
+            | InstructionKind::IGetByte(a, b, c)
+            | InstructionKind::IGetChar(a, b, c)
+            | InstructionKind::IGetShort(a, b, c)
+            | InstructionKind::IPut(a, b, c)
+            | InstructionKind::IPutWide(a, b, c)
+            | InstructionKind::IPutObject(a, b, c)
+            | InstructionKind::IPutBoolean(a, b, c)
+            | InstructionKind::IPutByte(a, b, c)
+            | InstructionKind::IPutChar(a, b, c)
+            | InstructionKind::IPutShort(a, b, c) => vec![Operand::Register(*a), Operand::Register(*b), Operand::Field(*c)],
+              InstructionKind::SGet(a, b)
+            | InstructionKind::SGetWide(a, b)
+            | InstructionKind::SGetObject(a, b)
+            | InstructionKind::SGetBoolean(a, b)
+            | InstructionKind::SGetByte(a, b)
+            | InstructionKind::SGetChar(a, b)
+            | InstructionKind::SGetShort(a, b)
+            | InstructionKind::SPut(a, b)
+            | InstructionKind::SPutWide(a, b)
+            | InstructionKind::SPutObject(a, b)
+            | InstructionKind::SPutBoolean(a, b)
+            | InstructionKind::SPutByte(a, b)
+            | InstructionKind::SPutChar(a, b)
+            | InstructionKind::SPutShort(a, b) => vec![Operand::Register(*a), Operand::Field(*b)],
+              InstructionKind::InvokeVirtual(a, b)
+            | InstructionKind::InvokeSuper(a, b)
+            | InstructionKind::InvokeDirect(a, b)
+            | InstructionKind::InvokeStatic(a, b)
+            | InstructionKind::InvokeInterface(a, b) => vec![Operand::RegisterList(a.clone()), Operand::Method(*b)],
+              InstructionKind::InvokeVirtualRange(a, b, c)
+            | InstructionKind::InvokeSuperRange(a, b, c)
+            | InstructionKind::InvokeDirectRange(a, b, c)
+            | InstructionKind::InvokeStaticRange(a, b, c)
+            | InstructionKind::InvokeInterfaceRange(a, b, c) => vec![Operand::RegisterRange(*a, *b), Operand::Method(*c)],
+              InstructionKind::InvokePolymorphic(a, b, c) => vec![Operand::RegisterList(a.clone()), Operand::Method(*b), Operand::Proto(*c)],
+              InstructionKind::InvokePolymorphicRange(a, b, c, d) => vec![Operand::RegisterRange(*a, *b), Operand::Method(*c), Operand::Proto(*d)],
+              InstructionKind::InvokeCustom(a, b) => vec![Operand::RegisterList(a.clone()), Operand::CallSite(*b)],
+              InstructionKind::InvokeCustomRange(a, b, c) => vec![Operand::RegisterRange(*a, *b), Operand::CallSite(*c)],
+              InstructionKind::ConstMethodHandle(a, b) => vec![Operand::Register(*a), Operand::MethodHandle(*b)],
+              InstructionKind::ConstMethodType(a, b) => vec![Operand::Register(*a), Operand::Proto(*b)],
+              InstructionKind::IGetQuick(a, b, c)
+            | InstructionKind::IGetWideQuick(a, b, c)
+            | InstructionKind::IGetObjectQuick(a, b, c)
+            | InstructionKind::IGetBooleanQuick(a, b, c)
+            | InstructionKind::IGetByteQuick(a, b, c)
+            | InstructionKind::IGetCharQuick(a, b, c)
+            | InstructionKind::IGetShortQuick(a, b, c)
+            | InstructionKind::IPutQuick(a, b, c)
+            | InstructionKind::IPutWideQuick(a, b, c)
+            | InstructionKind::IPutObjectQuick(a, b, c)
+            | InstructionKind::IPutBooleanQuick(a, b, c)
+            | InstructionKind::IPutByteQuick(a, b, c)
+            | InstructionKind::IPutCharQuick(a, b, c)
+            | InstructionKind::IPutShortQuick(a, b, c) => vec![Operand::Register(*a), Operand::Register(*b), Operand::Literal(*c as i64)],
+              InstructionKind::InvokeVirtualQuick(a, b) => vec![Operand::RegisterList(a.clone()), Operand::Literal(*b as i64)],
+              InstructionKind::InvokeVirtualQuickRange(a, b, c) => vec![Operand::RegisterRange(*a, *b), Operand::Literal(*c as i64)],
+              InstructionKind::AddIntLit16(a, b, c)
+            | InstructionKind::RSubIntLit16(a, b, c)
+            | InstructionKind::MulIntLit16(a, b, c)
+            | InstructionKind::DivIntLit16(a, b, c)
+            | InstructionKind::RemIntLit16(a, b, c)
+            | InstructionKind::AndIntLit16(a, b, c)
+            | InstructionKind::OrIntLit16(a, b, c)
+            | InstructionKind::XorIntLit16(a, b, c)
+            | InstructionKind::AddIntLit8(a, b, c)
+            | InstructionKind::RSubIntLit8(a, b, c)
+            | InstructionKind::MulIntLit8(a, b, c)
+            | InstructionKind::DivIntLit8(a, b, c)
+            | InstructionKind::RemIntLit8(a, b, c)
+            | InstructionKind::AndIntLit8(a, b, c)
+            | InstructionKind::OrIntLit8(a, b, c)
+            | InstructionKind::XorIntLit8(a, b, c)
+            | InstructionKind::ShlIntLit8(a, b, c)
+            | InstructionKind::ShrIntLit8(a, b, c)
+            | InstructionKind::UShrIntLit8(a, b, c) => vec![Operand::Register(*a), Operand::Register(*b), Operand::Literal(*c as i64)],
+            _ => Vec::new(),
+        }
+    }
+
+    // The constant pool entry an instruction refers to, if any.
+    pub fn index_reference(&self) -> Option<IndexReference> {
+        match *self {
+              InstructionKind::ConstString(_, i)
+            | InstructionKind::ConstStringJumbo(_, i) => Some(IndexReference::String(i)),
+              InstructionKind::ConstClass(_, i)
+            | InstructionKind::CheckCast(_, i)
+            | InstructionKind::InstanceOf(_, _, i)
+            | InstructionKind::NewInstance(_, i)
+            | InstructionKind::NewArray(_, _, i)
+            | InstructionKind::FilledNewArray(_, i)
+            | InstructionKind::FilledNewArrayRange(_, _, i) => Some(IndexReference::Type(i)),
+              InstructionKind::IGet(_, _, i)
+            | InstructionKind::IGetWide(_, _, i)
+            | InstructionKind::IGetObject(_, _, i)
+            | InstructionKind::IGetBoolean(_, _, i)
+            | InstructionKind::IGetByte(_, _, i)
+            | InstructionKind::IGetChar(_, _, i)
+            | InstructionKind::IGetShort(_, _, i)
+            | InstructionKind::IPut(_, _, i)
+            | InstructionKind::IPutWide(_, _, i)
+            | InstructionKind::IPutObject(_, _, i)
+            | InstructionKind::IPutBoolean(_, _, i)
+            | InstructionKind::IPutByte(_, _, i)
+            | InstructionKind::IPutChar(_, _, i)
+            | InstructionKind::IPutShort(_, _, i)
+            | InstructionKind::SGet(_, i)
+            | InstructionKind::SGetWide(_, i)
+            | InstructionKind::SGetObject(_, i)
+            | InstructionKind::SGetBoolean(_, i)
+            | InstructionKind::SGetByte(_, i)
+            | InstructionKind::SGetChar(_, i)
+            | InstructionKind::SGetShort(_, i)
+            | InstructionKind::SPut(_, i)
+            | InstructionKind::SPutWide(_, i)
+            | InstructionKind::SPutObject(_, i)
+            | InstructionKind::SPutBoolean(_, i)
+            | InstructionKind::SPutByte(_, i)
+            | InstructionKind::SPutChar(_, i)
+            | InstructionKind::SPutShort(_, i) => Some(IndexReference::Field(i)),
+              InstructionKind::InvokeVirtual(_, i)
+            | InstructionKind::InvokeSuper(_, i)
+            | InstructionKind::InvokeDirect(_, i)
+            | InstructionKind::InvokeStatic(_, i)
+            | InstructionKind::InvokeInterface(_, i)
+            | InstructionKind::InvokeVirtualRange(_, _, i)
+            | InstructionKind::InvokeSuperRange(_, _, i)
+            | InstructionKind::InvokeDirectRange(_, _, i)
+            | InstructionKind::InvokeStaticRange(_, _, i)
+            | InstructionKind::InvokeInterfaceRange(_, _, i)
+            | InstructionKind::InvokePolymorphic(_, i, _)
+            | InstructionKind::InvokePolymorphicRange(_, _, i, _) => Some(IndexReference::Method(i)),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse_bytecode<'a>(bytes: &mut BinaryParser<'a>, start: usize, instructions_count: usize) -> Result<Vec<Instruction<'a>>, String> {
     let mut result: Vec<Instruction> = Vec::new();
     bytes.seek_to(start);
 
@@ -252,408 +1253,437 @@ pub fn parse_bytecode(mut bytes: &mut BinaryParser, start: usize, instructions_c
         }
 
         let addr = bytes.current_location();
-        match bytecode_to_instruction_kind(&mut bytes) {
-            Some(kind) => {
+        let kind = bytecode_to_instruction_kind(bytes)?;
 
-                let end_addr = bytes.current_location();
-                let diff = end_addr - addr;
+        let end_addr = bytes.current_location();
+        let diff = end_addr - addr;
 
-                bytes.seek_to(addr);
-                let bytecode = bytes.take(diff);
+        bytes.seek_to(addr);
+        let bytecode = bytes.take(diff)?;
 
-                result.push(Instruction{
-                    addr,
-                    kind,
-                    bytecode,
-                });
-            }
-            None => break
-        }
+        result.push(Instruction{
+            addr,
+            kind,
+            bytecode,
+        });
     }
 
-    return result;
+    return Ok(result);
 }
 
-fn bytecode_to_instruction_kind(x: &mut BinaryParser) -> Option<InstructionKind> {
-    let ins = x.next();
+fn bytecode_to_instruction_kind<'a>(x: &mut BinaryParser<'a>) -> Result<InstructionKind<'a>, String> {
+    let ins = x.next()?;
     let res: InstructionKind = match ins {
         0x00 => { 
-            match x.next() {
+            match x.next()? {
                 0x00 => InstructionKind::Nop,
                 0x01 => {
-                    let size = slAA(x);
-                    let payload = size * 2 + 4;
-                    //x.take(payload as usize);
-                    return None;
+                    // packed-switch-payload
+                    let size = x.read_u16()?;
+                    let first_key = slAAAAAAAA(x)?;
+                    let targets = (0..size).map(|_| slAAAAAAAA(x)).collect::<Result<_, String>>()?;
+                    InstructionKind::PackedSwitchPayload(first_key, targets)
                 }
                 0x02 => {
-                    let size = slAA(x);
-                    let payload = size * 4 + 2;
-                    //x.take(payload as usize);
-                    return None;
+                    // sparse-switch-payload
+                    let size = x.read_u16()?;
+                    let keys = (0..size).map(|_| slAAAAAAAA(x)).collect::<Result<_, String>>()?;
+                    let targets = (0..size).map(|_| slAAAAAAAA(x)).collect::<Result<_, String>>()?;
+                    InstructionKind::SparseSwitchPayload(keys, targets)
                 }
                 0x03 => {
                     // fill-array-data-payload
-                    let u = x.next() as u32;
-                    let v = x.next() as u32;
-                    let elem_width = u | (v << 8);
-
-                    let (a, b, c, d) = (x.next() as u32, x.next() as u32, x.next() as u32, x.next() as u32);
-                    let array_size = a | (b << 8) | (c << 16) | (d << 24);
-                    //println!("{} {}", elem_width, array_size);
-                    let payload = (array_size as i64 * elem_width as i64) / 4;
-                    //println!("fill array payload of {} bytes", payload);
-                    //x.take(payload as usize);
-
-                    if x.current_location() - 1 == 644995 {
-                        panic!("");
+                    let elem_width = x.read_u16()?;
+                    let array_size = x.read_u32()?;
+                    let payload = array_size as usize * elem_width as usize;
+                    let data = x.take(payload)?;
+                    if payload % 2 == 1 {
+                        x.take(1)?;
                     }
-                    return None;
+                    InstructionKind::FillArrayDataPayload(elem_width, data)
                 }
                 _ => InstructionKind::Nop
             }
         },
-        0x01 => InstructionKind::Move(vA1(x), vA2(x)),
-        0x02 => InstructionKind::MoveFrom16(vAA(x), vAAAA(x)),
-        0x03 => { x.take(1); InstructionKind::Move16(vAAAA(x), vAAAA(x))},
-        0x04 => InstructionKind::MoveWide(vA1(x), vA2(x)),
-        0x05 => InstructionKind::MoveWideFrom16(vAA(x), vAAAA(x)),
-        0x06 => { x.take(1); InstructionKind::MoveWide16(vAAAA(x), vAAAA(x))},
-        0x07 => InstructionKind::MoveObject(vA1(x), vA2(x)),
-        0x08 => InstructionKind::MoveObjectFrom16(vAA(x), vAAAA(x)),
-        0x09 => {x.take(1); InstructionKind::MoveObject16(vAAAA(x), vAAAA(x))},
-        0x0a => InstructionKind::MoveResult(vAA(x)),
-        0x0b => InstructionKind::MoveResultWide(vAA(x)),
-        0x0c => InstructionKind::MoveResultObject(vAA(x)),
-        0x0d => InstructionKind::MoveException(vAA(x)),
-        0x0e => { x.take(1); InstructionKind::ReturnVoid},
-        0x0f => InstructionKind::Return(vAA(x)),
-        0x10 => InstructionKind::ReturnWide(vAA(x)),
-        0x11 => InstructionKind::ReturnObject(vAA(x)),
-        0x12 => InstructionKind::Const4(vA1(x), slA(x)),
-        0x13 => InstructionKind::Const16(vAA(x), slAAAA(x)),
-        0x14 => InstructionKind::Const(vAA(x), slAAAAAAAA(x)),
-        0x15 => InstructionKind::ConstHigh16(vAA(x), slAAAA0000(x)),
-        0x16 => InstructionKind::ConstWide16(vAA(x), slAAAA(x) as i64), 
-        0x17 => InstructionKind::ConstWide32(vAA(x), slAAAAAAAA(x) as i64),
-        0x18 => InstructionKind::ConstWide(vAA(x), slAAAAAAAAAAAAAAAA(x)),
-        0x19 => InstructionKind::ConstWideHigh16(vAA(x), slAAAA000000000000(x)),
-        0x1a => InstructionKind::ConstString(vAA(x), stringAAAA(x)),
-        0x1b => InstructionKind::ConstStringJumbo(vAA(x), stringAAAAAAAA(x)),
-        0x1c => InstructionKind::ConstClass(vAA(x), typeAAAA(x)),
-        0x1d => InstructionKind::MonitorEnter(vAA(x)),
-        0x1e => InstructionKind::MonitorExit(vAA(x)),
-        0x1f => InstructionKind::CheckCast(vAA(x), typeAAAA(x)),
-        0x20 => InstructionKind::InstanceOf(vA1(x), vA2(x), typeAAAA(x)),
-        0x21 => InstructionKind::ArrayLength(vA1(x), vA2(x)),
-        0x22 => InstructionKind::NewInstance(vAA(x), typeAAAA(x)),
-        0x23 => InstructionKind::NewArray(vA1(x), vA2(x), typeAAAA(x)),
-        0x24 => { let (args, t) = invoke_kind(x); InstructionKind::FilledNewArray(args, t as TypeIndex) },
-        0x25 => { let (r1, r2, t) = invoke_kind_range(x); InstructionKind::FilledNewArrayRange(r1, r2, t as TypeIndex) }
-        0x26 => InstructionKind::FillArrayData(vAA(x), slAAAAAAAA(x)),
-        0x27 => InstructionKind::Throw(vAA(x)),
+        0x01 => InstructionKind::Move(vA1(x)?, vA2(x)?),
+        0x02 => InstructionKind::MoveFrom16(vAA(x)?, vAAAA(x)?),
+        0x03 => { x.take(1)?; InstructionKind::Move16(vAAAA(x)?, vAAAA(x)?)},
+        0x04 => InstructionKind::MoveWide(vA1(x)?, vA2(x)?),
+        0x05 => InstructionKind::MoveWideFrom16(vAA(x)?, vAAAA(x)?),
+        0x06 => { x.take(1)?; InstructionKind::MoveWide16(vAAAA(x)?, vAAAA(x)?)},
+        0x07 => InstructionKind::MoveObject(vA1(x)?, vA2(x)?),
+        0x08 => InstructionKind::MoveObjectFrom16(vAA(x)?, vAAAA(x)?),
+        0x09 => {x.take(1)?; InstructionKind::MoveObject16(vAAAA(x)?, vAAAA(x)?)},
+        0x0a => InstructionKind::MoveResult(vAA(x)?),
+        0x0b => InstructionKind::MoveResultWide(vAA(x)?),
+        0x0c => InstructionKind::MoveResultObject(vAA(x)?),
+        0x0d => InstructionKind::MoveException(vAA(x)?),
+        0x0e => { x.take(1)?; InstructionKind::ReturnVoid},
+        0x0f => InstructionKind::Return(vAA(x)?),
+        0x10 => InstructionKind::ReturnWide(vAA(x)?),
+        0x11 => InstructionKind::ReturnObject(vAA(x)?),
+        0x12 => InstructionKind::Const4(vA1(x)?, slA(x)?),
+        0x13 => InstructionKind::Const16(vAA(x)?, slAAAA(x)?),
+        0x14 => InstructionKind::Const(vAA(x)?, slAAAAAAAA(x)?),
+        0x15 => InstructionKind::ConstHigh16(vAA(x)?, slAAAA0000(x)?),
+        0x16 => InstructionKind::ConstWide16(vAA(x)?, slAAAA(x)? as i64), 
+        0x17 => InstructionKind::ConstWide32(vAA(x)?, slAAAAAAAA(x)? as i64),
+        0x18 => InstructionKind::ConstWide(vAA(x)?, slAAAAAAAAAAAAAAAA(x)?),
+        0x19 => InstructionKind::ConstWideHigh16(vAA(x)?, slAAAA000000000000(x)?),
+        0x1a => InstructionKind::ConstString(vAA(x)?, stringAAAA(x)?),
+        0x1b => InstructionKind::ConstStringJumbo(vAA(x)?, stringAAAAAAAA(x)?),
+        0x1c => InstructionKind::ConstClass(vAA(x)?, typeAAAA(x)?),
+        0x1d => InstructionKind::MonitorEnter(vAA(x)?),
+        0x1e => InstructionKind::MonitorExit(vAA(x)?),
+        0x1f => InstructionKind::CheckCast(vAA(x)?, typeAAAA(x)?),
+        0x20 => InstructionKind::InstanceOf(vA1(x)?, vA2(x)?, typeAAAA(x)?),
+        0x21 => InstructionKind::ArrayLength(vA1(x)?, vA2(x)?),
+        0x22 => InstructionKind::NewInstance(vAA(x)?, typeAAAA(x)?),
+        0x23 => InstructionKind::NewArray(vA1(x)?, vA2(x)?, typeAAAA(x)?),
+        0x24 => { let (args, t) = invoke_kind(x)?; InstructionKind::FilledNewArray(args, t as TypeIndex) },
+        0x25 => { let (r1, r2, t) = invoke_kind_range(x)?; InstructionKind::FilledNewArrayRange(r1, r2, t as TypeIndex) }
+        0x26 => InstructionKind::FillArrayData(vAA(x)?, slAAAAAAAA(x)?),
+        0x27 => InstructionKind::Throw(vAA(x)?),
         0x28 => {
-            let mut offset = slAA(x);
+            let mut offset = slAA(x)?;
             if offset == 0 { offset = 1; }
             InstructionKind::GoTo(offset * 2)
         },
-        0x29 => {x.take(1); InstructionKind::GoTo16((slAAAA(x) as i64 * 2) as i32)},
-        0x2a => {x.take(1); InstructionKind::GoTo32((slAAAAAAAA(x) as i64 * 2) as i32)},
-        0x2b => InstructionKind::PackedSwitch(vAA(x), slAAAAAAAA(x)),
-        0x2c => InstructionKind::SparseSwitch(vAA(x), slAAAAAAAA(x)),
-        0x2d => InstructionKind::CmpLFloat(vAA(x), vAA(x), vAA(x)),
-        0x2e => InstructionKind::CmpGFloat(vAA(x), vAA(x), vAA(x)),
-        0x2f => InstructionKind::CmpLDouble(vAA(x), vAA(x), vAA(x)),
-        0x30 => InstructionKind::CmpGDouble(vAA(x), vAA(x), vAA(x)),
-        0x31 => InstructionKind::CmpLong(vAA(x), vAA(x), vAA(x)),
-        0x32 => InstructionKind::IfEq(vA1(x), vA2(x), slAAAA(x) * 2),
-        0x33 => InstructionKind::IfNe(vA1(x), vA2(x), slAAAA(x) * 2),
-        0x34 => InstructionKind::IfLt(vA1(x), vA2(x), slAAAA(x) * 2),
-        0x35 => InstructionKind::IfGe(vA1(x), vA2(x), slAAAA(x) * 2),
-        0x36 => InstructionKind::IfGt(vA1(x), vA2(x), slAAAA(x) * 2),
-        0x37 => InstructionKind::IfLe(vA1(x), vA2(x), slAAAA(x) * 2),
-        0x38 => InstructionKind::IfEqZ(vAA(x), slAAAA(x) * 2),
-        0x39 => InstructionKind::IfNeZ(vAA(x), slAAAA(x) * 2),
-        0x3a => InstructionKind::IfLtZ(vAA(x), slAAAA(x) * 2),
-        0x3b => InstructionKind::IfGeZ(vAA(x), slAAAA(x) * 2),
-        0x3c => InstructionKind::IfGtZ(vAA(x), slAAAA(x) * 2),
-        0x3d => InstructionKind::IfLeZ(vAA(x), slAAAA(x) * 2),
-        0x44 => InstructionKind::AGet(vAA(x), vAA(x), vAA(x)),
-        0x45 => InstructionKind::AGetWide(vAA(x), vAA(x), vAA(x)),
-        0x46 => InstructionKind::AGetObject(vAA(x), vAA(x), vAA(x)),
-        0x47 => InstructionKind::AGetBoolean(vAA(x), vAA(x), vAA(x)),
-        0x48 => InstructionKind::AGetByte(vAA(x), vAA(x), vAA(x)),
-        0x49 => InstructionKind::AGetChar(vAA(x), vAA(x), vAA(x)),
-        0x4a => InstructionKind::AGetShort(vAA(x), vAA(x), vAA(x)),
-        0x4b => InstructionKind::APut(vAA(x), vAA(x), vAA(x)),
-        0x4c => InstructionKind::APutWide(vAA(x), vAA(x), vAA(x)),
-        0x4d => InstructionKind::APutObject(vAA(x), vAA(x), vAA(x)),
-        0x4e => InstructionKind::APutBoolean(vAA(x), vAA(x), vAA(x)),
-        0x4f => InstructionKind::APutByte(vAA(x), vAA(x), vAA(x)),
-        0x50 => InstructionKind::APutChar(vAA(x), vAA(x), vAA(x)),
-        0x51 => InstructionKind::APutShort(vAA(x), vAA(x), vAA(x)),
-        0x52 => InstructionKind::IGet(vA1(x), vA2(x), fieldAAAA(x)),
-        0x53 => InstructionKind::IGetWide(vA1(x), vA2(x), fieldAAAA(x)),
-        0x54 => InstructionKind::IGetObject(vA1(x), vA2(x), fieldAAAA(x)),
-        0x55 => InstructionKind::IGetBoolean(vA1(x), vA2(x), fieldAAAA(x)),
-        0x56 => InstructionKind::IGetByte(vA1(x), vA2(x), fieldAAAA(x)),
-        0x57 => InstructionKind::IGetChar(vA1(x), vA2(x), fieldAAAA(x)),
-        0x58 => InstructionKind::IGetShort(vA1(x), vA2(x), fieldAAAA(x)),
-        0x59 => InstructionKind::IPut(vA1(x), vA2(x), fieldAAAA(x)),
-        0x5a => InstructionKind::IPutWide(vA1(x), vA2(x), fieldAAAA(x)),
-        0x5b => InstructionKind::IPutObject(vA1(x), vA2(x), fieldAAAA(x)),
-        0x5c => InstructionKind::IPutBoolean(vA1(x), vA2(x), fieldAAAA(x)),
-        0x5d => InstructionKind::IPutByte(vA1(x), vA2(x), fieldAAAA(x)),
-        0x5e => InstructionKind::IPutChar(vA1(x), vA2(x), fieldAAAA(x)),
-        0x5f => InstructionKind::IPutShort(vA1(x), vA2(x), fieldAAAA(x)),
-        0x60 => InstructionKind::SGet(vAA(x), fieldAAAA(x)),
-        0x61 => InstructionKind::SGetWide(vAA(x), fieldAAAA(x)),
-        0x62 => InstructionKind::SGetObject(vAA(x), fieldAAAA(x)),
-        0x63 => InstructionKind::SGetBoolean(vAA(x), fieldAAAA(x)),
-        0x64 => InstructionKind::SGetByte(vAA(x), fieldAAAA(x)),
-        0x65 => InstructionKind::SGetChar(vAA(x), fieldAAAA(x)),
-        0x66 => InstructionKind::SGetShort(vAA(x), fieldAAAA(x)),
-        0x67 => InstructionKind::SPut(vAA(x), fieldAAAA(x)),
-        0x68 => InstructionKind::SPutWide(vAA(x), fieldAAAA(x)),
-        0x69 => InstructionKind::SPutObject(vAA(x), fieldAAAA(x)),
-        0x6a => InstructionKind::SPutBoolean(vAA(x), fieldAAAA(x)),
-        0x6b => InstructionKind::SPutByte(vAA(x), fieldAAAA(x)),
-        0x6c => InstructionKind::SPutChar(vAA(x), fieldAAAA(x)),
-        0x6d => InstructionKind::SPutShort(vAA(x), fieldAAAA(x)),
-        0x6e => { let (args, method) = invoke_kind(x); InstructionKind::InvokeVirtual(args, method) }
-        0x6f => { let (args, method) = invoke_kind(x); InstructionKind::InvokeSuper(args, method) }
-        0x70 => { let (args, method) = invoke_kind(x); InstructionKind::InvokeDirect(args, method) }
-        0x71 => { let (args, method) = invoke_kind(x); InstructionKind::InvokeStatic(args, method) }
-        0x72 => { let (args, method) = invoke_kind(x); InstructionKind::InvokeInterface(args, method) }
-        0x74 => { let (r1, r2, method) = invoke_kind_range(x); InstructionKind::InvokeVirtualRange(r1, r2, method) }
-        0x75 => { let (r1, r2, method) = invoke_kind_range(x); InstructionKind::InvokeSuperRange(r1, r2, method) }
-        0x76 => { let (r1, r2, method) = invoke_kind_range(x); InstructionKind::InvokeDirectRange(r1, r2, method) }
-        0x77 => { let (r1, r2, method) = invoke_kind_range(x); InstructionKind::InvokeStaticRange(r1, r2, method) }
-        0x78 => { let (r1, r2, method) = invoke_kind_range(x); InstructionKind::InvokeInterfaceRange(r1, r2, method) }
-        0x7b => InstructionKind::NegInt(vA1(x), vA2(x)),
-        0x7c => InstructionKind::NotInt(vA1(x), vA2(x)),
-        0x7d => InstructionKind::NegLong(vA1(x), vA2(x)),
-        0x7e => InstructionKind::NotLong(vA1(x), vA2(x)),
-        0x7f => InstructionKind::NegFloat(vA1(x), vA2(x)),
-        0x80 => InstructionKind::NegDouble(vA1(x), vA2(x)),
-        0x81 => InstructionKind::IntToLong(vA1(x), vA2(x)),
-        0x82 => InstructionKind::IntToFloat(vA1(x), vA2(x)),
-        0x83 => InstructionKind::IntToDouble(vA1(x), vA2(x)),
-        0x84 => InstructionKind::LongToInt(vA1(x), vA2(x)),
-        0x85 => InstructionKind::LongToFloat(vA1(x), vA2(x)),
-        0x86 => InstructionKind::LongToDouble(vA1(x), vA2(x)),
-        0x87 => InstructionKind::FloatToInt(vA1(x), vA2(x)),
-        0x88 => InstructionKind::FloatToLong(vA1(x), vA2(x)),
-        0x89 => InstructionKind::FloatToDouble(vA1(x), vA2(x)),
-        0x8a => InstructionKind::DoubleToInt(vA1(x), vA2(x)),
-        0x8b => InstructionKind::DoubleToLong(vA1(x), vA2(x)),
-        0x8c => InstructionKind::DoubleToFloat(vA1(x), vA2(x)),
-        0x8d => InstructionKind::IntToByte(vA1(x), vA2(x)),
-        0x8e => InstructionKind::IntToChar(vA1(x), vA2(x)),
-        0x8f => InstructionKind::IntToShort(vA1(x), vA2(x)),
-        0x90 => InstructionKind::AddInt(vAA(x), vAA(x), vAA(x)),
-        0x91 => InstructionKind::SubInt(vAA(x), vAA(x), vAA(x)),
-        0x92 => InstructionKind::MulInt(vAA(x), vAA(x), vAA(x)),
-        0x93 => InstructionKind::DivInt(vAA(x), vAA(x), vAA(x)),
-        0x94 => InstructionKind::RemInt(vAA(x), vAA(x), vAA(x)),
-        0x95 => InstructionKind::AndInt(vAA(x), vAA(x), vAA(x)),
-        0x96 => InstructionKind::OrInt(vAA(x), vAA(x), vAA(x)),
-        0x97 => InstructionKind::XorInt(vAA(x), vAA(x), vAA(x)),
-        0x98 => InstructionKind::ShlInt(vAA(x), vAA(x), vAA(x)),
-        0x99 => InstructionKind::ShrInt(vAA(x), vAA(x), vAA(x)),
-        0x9a => InstructionKind::UShrInt(vAA(x), vAA(x), vAA(x)),
-        0x9b => InstructionKind::AddLong(vAA(x), vAA(x), vAA(x)),
-        0x9c => InstructionKind::SubLong(vAA(x), vAA(x), vAA(x)),
-        0x9d => InstructionKind::MulLong(vAA(x), vAA(x), vAA(x)),
-        0x9e => InstructionKind::DivLong(vAA(x), vAA(x), vAA(x)),
-        0x9f => InstructionKind::RemLong(vAA(x), vAA(x), vAA(x)),
-        0xa0 => InstructionKind::AndLong(vAA(x), vAA(x), vAA(x)),
-        0xa1 => InstructionKind::OrLong(vAA(x), vAA(x), vAA(x)),
-        0xa2 => InstructionKind::XorLong(vAA(x), vAA(x), vAA(x)),
-        0xa3 => InstructionKind::ShlLong(vAA(x), vAA(x), vAA(x)),
-        0xa4 => InstructionKind::ShrLong(vAA(x), vAA(x), vAA(x)),
-        0xa5 => InstructionKind::UShrLong(vAA(x), vAA(x), vAA(x)),
-        0xa6 => InstructionKind::AddFloat(vAA(x), vAA(x), vAA(x)),
-        0xa7 => InstructionKind::SubFloat(vAA(x), vAA(x), vAA(x)),
-        0xa8 => InstructionKind::MulFloat(vAA(x), vAA(x), vAA(x)),
-        0xa9 => InstructionKind::DivFloat(vAA(x), vAA(x), vAA(x)),
-        0xaa => InstructionKind::RemFloat(vAA(x), vAA(x), vAA(x)),
-        0xab => InstructionKind::AddDouble(vAA(x), vAA(x), vAA(x)),
-        0xac => InstructionKind::SubDouble(vAA(x), vAA(x), vAA(x)),
-        0xad => InstructionKind::MulDouble(vAA(x), vAA(x), vAA(x)),
-        0xae => InstructionKind::DivDouble(vAA(x), vAA(x), vAA(x)),
-        0xaf => InstructionKind::RemDouble(vAA(x), vAA(x), vAA(x)),
-        0xb0 => InstructionKind::AddInt2Addr(vA1(x), vA2(x)),
-        0xb1 => InstructionKind::SubInt2Addr(vA1(x), vA2(x)),
-        0xb2 => InstructionKind::MulInt2Addr(vA1(x), vA2(x)),
-        0xb3 => InstructionKind::DivInt2Addr(vA1(x), vA2(x)),
-        0xb4 => InstructionKind::RemInt2Addr(vA1(x), vA2(x)),
-        0xb5 => InstructionKind::AndInt2Addr(vA1(x), vA2(x)),
-        0xb6 => InstructionKind::OrInt2Addr(vA1(x), vA2(x)),
-        0xb7 => InstructionKind::XorInt2Addr(vA1(x), vA2(x)),
-        0xb8 => InstructionKind::ShlInt2Addr(vA1(x), vA2(x)),
-        0xb9 => InstructionKind::ShrInt2Addr(vA1(x), vA2(x)),
-        0xba => InstructionKind::UShrInt2Addr(vA1(x), vA2(x)),
-        0xbb => InstructionKind::AddLong2Addr(vA1(x), vA2(x)),
-        0xbc => InstructionKind::SubLong2Addr(vA1(x), vA2(x)),
-        0xbd => InstructionKind::MulLong2Addr(vA1(x), vA2(x)),
-        0xbe => InstructionKind::DivLong2Addr(vA1(x), vA2(x)),
-        0xbf => InstructionKind::RemLong2Addr(vA1(x), vA2(x)),
-        0xc0 => InstructionKind::AndLong2Addr(vA1(x), vA2(x)),
-        0xc1 => InstructionKind::OrLong2Addr(vA1(x), vA2(x)),
-        0xc2 => InstructionKind::XorLong2Addr(vA1(x), vA2(x)),
-        0xc3 => InstructionKind::ShlLong2Addr(vA1(x), vA2(x)),
-        0xc4 => InstructionKind::ShrLong2Addr(vA1(x), vA2(x)),
-        0xc5 => InstructionKind::UShrLong2Addr(vA1(x), vA2(x)),
-        0xc6 => InstructionKind::AddFloat2Addr(vA1(x), vA2(x)),
-        0xc7 => InstructionKind::SubFloat2Addr(vA1(x), vA2(x)),
-        0xc8 => InstructionKind::MulFloat2Addr(vA1(x), vA2(x)),
-        0xc9 => InstructionKind::DivFloat2Addr(vA1(x), vA2(x)),
-        0xca => InstructionKind::RemFloat2Addr(vA1(x), vA2(x)),
-        0xcb => InstructionKind::AddDouble2Addr(vA1(x), vA2(x)),
-        0xcc => InstructionKind::SubDouble2Addr(vA1(x), vA2(x)),
-        0xcd => InstructionKind::MulDouble2Addr(vA1(x), vA2(x)),
-        0xce => InstructionKind::DivDouble2Addr(vA1(x), vA2(x)),
-        0xcf => InstructionKind::RemDouble2Addr(vA1(x), vA2(x)),
-        0xd0 => InstructionKind::AddIntLit16(vA1(x), vA2(x), slAAAA(x)),
-        0xd1 => InstructionKind::RSubIntLit16(vA1(x), vA2(x), slAAAA(x)),
-        0xd2 => InstructionKind::MulIntLit16(vA1(x), vA2(x), slAAAA(x)),
-        0xd3 => InstructionKind::DivIntLit16(vA1(x), vA2(x), slAAAA(x)),
-        0xd4 => InstructionKind::RemIntLit16(vA1(x), vA2(x), slAAAA(x)),
-        0xd5 => InstructionKind::AndIntLit16(vA1(x), vA2(x), slAAAA(x)),
-        0xd6 => InstructionKind::OrIntLit16(vA1(x), vA2(x), slAAAA(x)),
-        0xd7 => InstructionKind::XorIntLit16(vA1(x), vA2(x), slAAAA(x)),
-        0xd8 => InstructionKind::AddIntLit8(vAA(x), vAA(x), slAA(x)),
-        0xd9 => InstructionKind::RSubIntLit8(vAA(x), vAA(x), slAA(x)),
-        0xda => InstructionKind::MulIntLit8(vAA(x), vAA(x), slAA(x)),
-        0xdb => InstructionKind::DivIntLit8(vAA(x), vAA(x), slAA(x)),
-        0xdc => InstructionKind::RemIntLit8(vAA(x), vAA(x), slAA(x)),
-        0xdd => InstructionKind::AndIntLit8(vAA(x), vAA(x), slAA(x)),
-        0xde => InstructionKind::OrIntLit8(vAA(x), vAA(x), slAA(x)),
-        0xdf => InstructionKind::XorIntLit8(vAA(x), vAA(x), slAA(x)),
-        0xe0 => InstructionKind::ShlIntLit8(vAA(x), vAA(x), slAA(x)),
-        0xe1 => InstructionKind::ShrIntLit8(vAA(x), vAA(x), slAA(x)),
-        0xe2 => InstructionKind::UShrIntLit8(vAA(x), vAA(x), slAA(x)),
-        0xfa => InstructionKind::InvokePolymorphic,
-        0xfb => InstructionKind::InvokePolymorphicRange,
-        0xfc => InstructionKind::InvokeCustom,
-        0xfd => InstructionKind::InvokeCustomRange,
-        0xfe => InstructionKind::ConstMethodHandle,
-        0xff => InstructionKind::ConstMethodType,
-        _ => {x.take(1); InstructionKind::Unused},
+        0x29 => {x.take(1)?; InstructionKind::GoTo16((slAAAA(x)? as i64 * 2) as i32)},
+        0x2a => {x.take(1)?; InstructionKind::GoTo32((slAAAAAAAA(x)? as i64 * 2) as i32)},
+        0x2b => InstructionKind::PackedSwitch(vAA(x)?, slAAAAAAAA(x)?),
+        0x2c => InstructionKind::SparseSwitch(vAA(x)?, slAAAAAAAA(x)?),
+        0x2d => InstructionKind::CmpLFloat(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x2e => InstructionKind::CmpGFloat(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x2f => InstructionKind::CmpLDouble(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x30 => InstructionKind::CmpGDouble(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x31 => InstructionKind::CmpLong(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x32 => InstructionKind::IfEq(vA1(x)?, vA2(x)?, slAAAA(x)? * 2),
+        0x33 => InstructionKind::IfNe(vA1(x)?, vA2(x)?, slAAAA(x)? * 2),
+        0x34 => InstructionKind::IfLt(vA1(x)?, vA2(x)?, slAAAA(x)? * 2),
+        0x35 => InstructionKind::IfGe(vA1(x)?, vA2(x)?, slAAAA(x)? * 2),
+        0x36 => InstructionKind::IfGt(vA1(x)?, vA2(x)?, slAAAA(x)? * 2),
+        0x37 => InstructionKind::IfLe(vA1(x)?, vA2(x)?, slAAAA(x)? * 2),
+        0x38 => InstructionKind::IfEqZ(vAA(x)?, slAAAA(x)? * 2),
+        0x39 => InstructionKind::IfNeZ(vAA(x)?, slAAAA(x)? * 2),
+        0x3a => InstructionKind::IfLtZ(vAA(x)?, slAAAA(x)? * 2),
+        0x3b => InstructionKind::IfGeZ(vAA(x)?, slAAAA(x)? * 2),
+        0x3c => InstructionKind::IfGtZ(vAA(x)?, slAAAA(x)? * 2),
+        0x3d => InstructionKind::IfLeZ(vAA(x)?, slAAAA(x)? * 2),
+        0x44 => InstructionKind::AGet(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x45 => InstructionKind::AGetWide(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x46 => InstructionKind::AGetObject(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x47 => InstructionKind::AGetBoolean(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x48 => InstructionKind::AGetByte(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x49 => InstructionKind::AGetChar(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x4a => InstructionKind::AGetShort(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x4b => InstructionKind::APut(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x4c => InstructionKind::APutWide(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x4d => InstructionKind::APutObject(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x4e => InstructionKind::APutBoolean(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x4f => InstructionKind::APutByte(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x50 => InstructionKind::APutChar(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x51 => InstructionKind::APutShort(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x52 => InstructionKind::IGet(vA1(x)?, vA2(x)?, fieldAAAA(x)?),
+        0x53 => InstructionKind::IGetWide(vA1(x)?, vA2(x)?, fieldAAAA(x)?),
+        0x54 => InstructionKind::IGetObject(vA1(x)?, vA2(x)?, fieldAAAA(x)?),
+        0x55 => InstructionKind::IGetBoolean(vA1(x)?, vA2(x)?, fieldAAAA(x)?),
+        0x56 => InstructionKind::IGetByte(vA1(x)?, vA2(x)?, fieldAAAA(x)?),
+        0x57 => InstructionKind::IGetChar(vA1(x)?, vA2(x)?, fieldAAAA(x)?),
+        0x58 => InstructionKind::IGetShort(vA1(x)?, vA2(x)?, fieldAAAA(x)?),
+        0x59 => InstructionKind::IPut(vA1(x)?, vA2(x)?, fieldAAAA(x)?),
+        0x5a => InstructionKind::IPutWide(vA1(x)?, vA2(x)?, fieldAAAA(x)?),
+        0x5b => InstructionKind::IPutObject(vA1(x)?, vA2(x)?, fieldAAAA(x)?),
+        0x5c => InstructionKind::IPutBoolean(vA1(x)?, vA2(x)?, fieldAAAA(x)?),
+        0x5d => InstructionKind::IPutByte(vA1(x)?, vA2(x)?, fieldAAAA(x)?),
+        0x5e => InstructionKind::IPutChar(vA1(x)?, vA2(x)?, fieldAAAA(x)?),
+        0x5f => InstructionKind::IPutShort(vA1(x)?, vA2(x)?, fieldAAAA(x)?),
+        0x60 => InstructionKind::SGet(vAA(x)?, fieldAAAA(x)?),
+        0x61 => InstructionKind::SGetWide(vAA(x)?, fieldAAAA(x)?),
+        0x62 => InstructionKind::SGetObject(vAA(x)?, fieldAAAA(x)?),
+        0x63 => InstructionKind::SGetBoolean(vAA(x)?, fieldAAAA(x)?),
+        0x64 => InstructionKind::SGetByte(vAA(x)?, fieldAAAA(x)?),
+        0x65 => InstructionKind::SGetChar(vAA(x)?, fieldAAAA(x)?),
+        0x66 => InstructionKind::SGetShort(vAA(x)?, fieldAAAA(x)?),
+        0x67 => InstructionKind::SPut(vAA(x)?, fieldAAAA(x)?),
+        0x68 => InstructionKind::SPutWide(vAA(x)?, fieldAAAA(x)?),
+        0x69 => InstructionKind::SPutObject(vAA(x)?, fieldAAAA(x)?),
+        0x6a => InstructionKind::SPutBoolean(vAA(x)?, fieldAAAA(x)?),
+        0x6b => InstructionKind::SPutByte(vAA(x)?, fieldAAAA(x)?),
+        0x6c => InstructionKind::SPutChar(vAA(x)?, fieldAAAA(x)?),
+        0x6d => InstructionKind::SPutShort(vAA(x)?, fieldAAAA(x)?),
+        0x6e => { let (args, method) = invoke_kind(x)?; InstructionKind::InvokeVirtual(args, method) }
+        0x6f => { let (args, method) = invoke_kind(x)?; InstructionKind::InvokeSuper(args, method) }
+        0x70 => { let (args, method) = invoke_kind(x)?; InstructionKind::InvokeDirect(args, method) }
+        0x71 => { let (args, method) = invoke_kind(x)?; InstructionKind::InvokeStatic(args, method) }
+        0x72 => { let (args, method) = invoke_kind(x)?; InstructionKind::InvokeInterface(args, method) }
+        0x73 => { x.take(1)?; InstructionKind::ReturnVoidNoBarrier },
+        0x74 => { let (r1, r2, method) = invoke_kind_range(x)?; InstructionKind::InvokeVirtualRange(r1, r2, method) }
+        0x75 => { let (r1, r2, method) = invoke_kind_range(x)?; InstructionKind::InvokeSuperRange(r1, r2, method) }
+        0x76 => { let (r1, r2, method) = invoke_kind_range(x)?; InstructionKind::InvokeDirectRange(r1, r2, method) }
+        0x77 => { let (r1, r2, method) = invoke_kind_range(x)?; InstructionKind::InvokeStaticRange(r1, r2, method) }
+        0x78 => { let (r1, r2, method) = invoke_kind_range(x)?; InstructionKind::InvokeInterfaceRange(r1, r2, method) }
+        0x7b => InstructionKind::NegInt(vA1(x)?, vA2(x)?),
+        0x7c => InstructionKind::NotInt(vA1(x)?, vA2(x)?),
+        0x7d => InstructionKind::NegLong(vA1(x)?, vA2(x)?),
+        0x7e => InstructionKind::NotLong(vA1(x)?, vA2(x)?),
+        0x7f => InstructionKind::NegFloat(vA1(x)?, vA2(x)?),
+        0x80 => InstructionKind::NegDouble(vA1(x)?, vA2(x)?),
+        0x81 => InstructionKind::IntToLong(vA1(x)?, vA2(x)?),
+        0x82 => InstructionKind::IntToFloat(vA1(x)?, vA2(x)?),
+        0x83 => InstructionKind::IntToDouble(vA1(x)?, vA2(x)?),
+        0x84 => InstructionKind::LongToInt(vA1(x)?, vA2(x)?),
+        0x85 => InstructionKind::LongToFloat(vA1(x)?, vA2(x)?),
+        0x86 => InstructionKind::LongToDouble(vA1(x)?, vA2(x)?),
+        0x87 => InstructionKind::FloatToInt(vA1(x)?, vA2(x)?),
+        0x88 => InstructionKind::FloatToLong(vA1(x)?, vA2(x)?),
+        0x89 => InstructionKind::FloatToDouble(vA1(x)?, vA2(x)?),
+        0x8a => InstructionKind::DoubleToInt(vA1(x)?, vA2(x)?),
+        0x8b => InstructionKind::DoubleToLong(vA1(x)?, vA2(x)?),
+        0x8c => InstructionKind::DoubleToFloat(vA1(x)?, vA2(x)?),
+        0x8d => InstructionKind::IntToByte(vA1(x)?, vA2(x)?),
+        0x8e => InstructionKind::IntToChar(vA1(x)?, vA2(x)?),
+        0x8f => InstructionKind::IntToShort(vA1(x)?, vA2(x)?),
+        0x90 => InstructionKind::AddInt(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x91 => InstructionKind::SubInt(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x92 => InstructionKind::MulInt(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x93 => InstructionKind::DivInt(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x94 => InstructionKind::RemInt(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x95 => InstructionKind::AndInt(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x96 => InstructionKind::OrInt(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x97 => InstructionKind::XorInt(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x98 => InstructionKind::ShlInt(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x99 => InstructionKind::ShrInt(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x9a => InstructionKind::UShrInt(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x9b => InstructionKind::AddLong(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x9c => InstructionKind::SubLong(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x9d => InstructionKind::MulLong(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x9e => InstructionKind::DivLong(vAA(x)?, vAA(x)?, vAA(x)?),
+        0x9f => InstructionKind::RemLong(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xa0 => InstructionKind::AndLong(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xa1 => InstructionKind::OrLong(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xa2 => InstructionKind::XorLong(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xa3 => InstructionKind::ShlLong(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xa4 => InstructionKind::ShrLong(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xa5 => InstructionKind::UShrLong(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xa6 => InstructionKind::AddFloat(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xa7 => InstructionKind::SubFloat(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xa8 => InstructionKind::MulFloat(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xa9 => InstructionKind::DivFloat(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xaa => InstructionKind::RemFloat(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xab => InstructionKind::AddDouble(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xac => InstructionKind::SubDouble(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xad => InstructionKind::MulDouble(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xae => InstructionKind::DivDouble(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xaf => InstructionKind::RemDouble(vAA(x)?, vAA(x)?, vAA(x)?),
+        0xb0 => InstructionKind::AddInt2Addr(vA1(x)?, vA2(x)?),
+        0xb1 => InstructionKind::SubInt2Addr(vA1(x)?, vA2(x)?),
+        0xb2 => InstructionKind::MulInt2Addr(vA1(x)?, vA2(x)?),
+        0xb3 => InstructionKind::DivInt2Addr(vA1(x)?, vA2(x)?),
+        0xb4 => InstructionKind::RemInt2Addr(vA1(x)?, vA2(x)?),
+        0xb5 => InstructionKind::AndInt2Addr(vA1(x)?, vA2(x)?),
+        0xb6 => InstructionKind::OrInt2Addr(vA1(x)?, vA2(x)?),
+        0xb7 => InstructionKind::XorInt2Addr(vA1(x)?, vA2(x)?),
+        0xb8 => InstructionKind::ShlInt2Addr(vA1(x)?, vA2(x)?),
+        0xb9 => InstructionKind::ShrInt2Addr(vA1(x)?, vA2(x)?),
+        0xba => InstructionKind::UShrInt2Addr(vA1(x)?, vA2(x)?),
+        0xbb => InstructionKind::AddLong2Addr(vA1(x)?, vA2(x)?),
+        0xbc => InstructionKind::SubLong2Addr(vA1(x)?, vA2(x)?),
+        0xbd => InstructionKind::MulLong2Addr(vA1(x)?, vA2(x)?),
+        0xbe => InstructionKind::DivLong2Addr(vA1(x)?, vA2(x)?),
+        0xbf => InstructionKind::RemLong2Addr(vA1(x)?, vA2(x)?),
+        0xc0 => InstructionKind::AndLong2Addr(vA1(x)?, vA2(x)?),
+        0xc1 => InstructionKind::OrLong2Addr(vA1(x)?, vA2(x)?),
+        0xc2 => InstructionKind::XorLong2Addr(vA1(x)?, vA2(x)?),
+        0xc3 => InstructionKind::ShlLong2Addr(vA1(x)?, vA2(x)?),
+        0xc4 => InstructionKind::ShrLong2Addr(vA1(x)?, vA2(x)?),
+        0xc5 => InstructionKind::UShrLong2Addr(vA1(x)?, vA2(x)?),
+        0xc6 => InstructionKind::AddFloat2Addr(vA1(x)?, vA2(x)?),
+        0xc7 => InstructionKind::SubFloat2Addr(vA1(x)?, vA2(x)?),
+        0xc8 => InstructionKind::MulFloat2Addr(vA1(x)?, vA2(x)?),
+        0xc9 => InstructionKind::DivFloat2Addr(vA1(x)?, vA2(x)?),
+        0xca => InstructionKind::RemFloat2Addr(vA1(x)?, vA2(x)?),
+        0xcb => InstructionKind::AddDouble2Addr(vA1(x)?, vA2(x)?),
+        0xcc => InstructionKind::SubDouble2Addr(vA1(x)?, vA2(x)?),
+        0xcd => InstructionKind::MulDouble2Addr(vA1(x)?, vA2(x)?),
+        0xce => InstructionKind::DivDouble2Addr(vA1(x)?, vA2(x)?),
+        0xcf => InstructionKind::RemDouble2Addr(vA1(x)?, vA2(x)?),
+        0xd0 => InstructionKind::AddIntLit16(vA1(x)?, vA2(x)?, slAAAA(x)?),
+        0xd1 => InstructionKind::RSubIntLit16(vA1(x)?, vA2(x)?, slAAAA(x)?),
+        0xd2 => InstructionKind::MulIntLit16(vA1(x)?, vA2(x)?, slAAAA(x)?),
+        0xd3 => InstructionKind::DivIntLit16(vA1(x)?, vA2(x)?, slAAAA(x)?),
+        0xd4 => InstructionKind::RemIntLit16(vA1(x)?, vA2(x)?, slAAAA(x)?),
+        0xd5 => InstructionKind::AndIntLit16(vA1(x)?, vA2(x)?, slAAAA(x)?),
+        0xd6 => InstructionKind::OrIntLit16(vA1(x)?, vA2(x)?, slAAAA(x)?),
+        0xd7 => InstructionKind::XorIntLit16(vA1(x)?, vA2(x)?, slAAAA(x)?),
+        0xd8 => InstructionKind::AddIntLit8(vAA(x)?, vAA(x)?, slAA(x)?),
+        0xd9 => InstructionKind::RSubIntLit8(vAA(x)?, vAA(x)?, slAA(x)?),
+        0xda => InstructionKind::MulIntLit8(vAA(x)?, vAA(x)?, slAA(x)?),
+        0xdb => InstructionKind::DivIntLit8(vAA(x)?, vAA(x)?, slAA(x)?),
+        0xdc => InstructionKind::RemIntLit8(vAA(x)?, vAA(x)?, slAA(x)?),
+        0xdd => InstructionKind::AndIntLit8(vAA(x)?, vAA(x)?, slAA(x)?),
+        0xde => InstructionKind::OrIntLit8(vAA(x)?, vAA(x)?, slAA(x)?),
+        0xdf => InstructionKind::XorIntLit8(vAA(x)?, vAA(x)?, slAA(x)?),
+        0xe0 => InstructionKind::ShlIntLit8(vAA(x)?, vAA(x)?, slAA(x)?),
+        0xe1 => InstructionKind::ShrIntLit8(vAA(x)?, vAA(x)?, slAA(x)?),
+        0xe2 => InstructionKind::UShrIntLit8(vAA(x)?, vAA(x)?, slAA(x)?),
+        0xe3 => InstructionKind::IGetQuick(vA1(x)?, vA2(x)?, field_offset(x)?),
+        0xe4 => InstructionKind::IGetWideQuick(vA1(x)?, vA2(x)?, field_offset(x)?),
+        0xe5 => InstructionKind::IGetObjectQuick(vA1(x)?, vA2(x)?, field_offset(x)?),
+        0xe6 => InstructionKind::IPutQuick(vA1(x)?, vA2(x)?, field_offset(x)?),
+        0xe7 => InstructionKind::IPutWideQuick(vA1(x)?, vA2(x)?, field_offset(x)?),
+        0xe8 => InstructionKind::IPutObjectQuick(vA1(x)?, vA2(x)?, field_offset(x)?),
+        0xe9 => { let (args, index) = invoke_kind(x)?; InstructionKind::InvokeVirtualQuick(args, index as u32) }
+        0xea => { let (r1, r2, index) = invoke_kind_range(x)?; InstructionKind::InvokeVirtualQuickRange(r1, r2, index as u32) }
+        0xeb => InstructionKind::IPutBooleanQuick(vA1(x)?, vA2(x)?, field_offset(x)?),
+        0xec => InstructionKind::IPutByteQuick(vA1(x)?, vA2(x)?, field_offset(x)?),
+        0xed => InstructionKind::IPutCharQuick(vA1(x)?, vA2(x)?, field_offset(x)?),
+        0xee => InstructionKind::IPutShortQuick(vA1(x)?, vA2(x)?, field_offset(x)?),
+        0xef => InstructionKind::IGetBooleanQuick(vA1(x)?, vA2(x)?, field_offset(x)?),
+        0xf0 => InstructionKind::IGetByteQuick(vA1(x)?, vA2(x)?, field_offset(x)?),
+        0xf1 => InstructionKind::IGetCharQuick(vA1(x)?, vA2(x)?, field_offset(x)?),
+        0xf2 => InstructionKind::IGetShortQuick(vA1(x)?, vA2(x)?, field_offset(x)?),
+        // 0xf3-0xf9 were experimental lambda instructions that never shipped
+        0xfa => { let (args, method) = invoke_kind(x)?; InstructionKind::InvokePolymorphic(args, method, proto_aaaa(x)?) }
+        0xfb => { let (r1, r2, method) = invoke_kind_range(x)?; InstructionKind::InvokePolymorphicRange(r1, r2, method, proto_aaaa(x)?) }
+        0xfc => { let (args, call_site) = invoke_kind(x)?; InstructionKind::InvokeCustom(args, call_site as CallSiteIndex) }
+        0xfd => { let (r1, r2, call_site) = invoke_kind_range(x)?; InstructionKind::InvokeCustomRange(r1, r2, call_site as CallSiteIndex) }
+        0xfe => InstructionKind::ConstMethodHandle(vAA(x)?, method_handle_aaaa(x)?),
+        0xff => InstructionKind::ConstMethodType(vAA(x)?, proto_aaaa(x)?),
+        _ => {x.take(1)?; InstructionKind::Unused},
     };
-    return Some(res);
+    return Ok(res);
 }
 
-fn vA1(v: &mut BinaryParser) -> Register {
-    (v.peek(1)[0] & 0b00001111) as Register
+fn vA1(v: &mut BinaryParser) -> Result<Register, String> {
+    Ok((v.peek(1)?[0] & 0b00001111) as Register)
 }
 
-fn vA2(v: &mut BinaryParser) -> Register {
-    (v.next() >> 4 & 0b00001111) as Register
+fn vA2(v: &mut BinaryParser) -> Result<Register, String> {
+    Ok((v.next()? >> 4 & 0b00001111) as Register)
 }
 
-fn vAA(v: &mut BinaryParser) -> Register {
-    v.next() as Register
+fn vAA(v: &mut BinaryParser) -> Result<Register, String> {
+    Ok(v.next()? as Register)
 }
 
-fn vAAAA(v: &mut BinaryParser) -> Register {
-    let x = v.take(2);
-    to_decimal_short(&x) as Register
+fn vAAAA(v: &mut BinaryParser) -> Result<Register, String> {
+    Ok(v.read_u16()? as Register)
 }
 
-fn slA(v: &mut BinaryParser) -> i32 {
-    (v.next() & 0b11110000) as i32
+fn slA(v: &mut BinaryParser) -> Result<i32, String> {
+    Ok(((v.next()? as i8) >> 4) as i32)
 }
 
-fn slAA(v: &mut BinaryParser) -> i32 {
-    let x = v.take(1);
-    to_i8(&x) as i32
+fn slAA(v: &mut BinaryParser) -> Result<i32, String> {
+    Ok(v.read_i8()? as i32)
 }
 
-fn slAAAA(v: &mut BinaryParser) -> i32 {
-    let x = v.take(2);
-    to_i16(&x) as i32
+fn slAAAA(v: &mut BinaryParser) -> Result<i32, String> {
+    Ok(v.read_i16()? as i32)
 }
 
-fn slAAAAAAAA(v: &mut BinaryParser) -> i32 {
-    let x = v.take(4);
-    to_decimal(&x) as i32
+fn slAAAAAAAA(v: &mut BinaryParser) -> Result<i32, String> {
+    v.read_i32()
 }
 
-fn slAAAA0000(v: &mut BinaryParser) -> i32 {
-    0 // TODO
+fn slAAAA0000(v: &mut BinaryParser) -> Result<i32, String> {
+    Ok((v.read_u16()? as i32) << 16)
 }
 
-fn slAAAAAAAAAAAAAAAA(v: &mut BinaryParser) -> i64 {
-    0 // TODO
+fn slAAAAAAAAAAAAAAAA(v: &mut BinaryParser) -> Result<i64, String> {
+    v.read_i64()
 }
 
-fn slAAAA000000000000(v: &mut BinaryParser) -> i64 {
-    0 // TODO
+fn slAAAA000000000000(v: &mut BinaryParser) -> Result<i64, String> {
+    Ok((v.read_u16()? as i64) << 48)
 }
 
-fn stringAAAA(v: &mut BinaryParser) -> StringIndex {
-    let x = v.take(2);
-    to_decimal_short(&x) as StringIndex
+fn stringAAAA(v: &mut BinaryParser) -> Result<StringIndex, String> {
+    Ok(v.read_u16()? as StringIndex)
 }
 
-fn stringAAAAAAAA(v: &mut BinaryParser) -> StringIndex {
-    0 as StringIndex // TODO
+fn stringAAAAAAAA(v: &mut BinaryParser) -> Result<StringIndex, String> {
+    Ok(v.read_u32()? as StringIndex)
 }
 
-fn typeAAAA(v: &mut BinaryParser) -> TypeIndex {
-    let x = v.take(2);
-    to_decimal_short(&x) as TypeIndex
+fn typeAAAA(v: &mut BinaryParser) -> Result<TypeIndex, String> {
+    Ok(v.read_u16()? as TypeIndex)
 }
 
-fn fieldAAAA(v: &mut BinaryParser) -> FieldIndex {
-    let x = v.take(2);
-    to_decimal_short(&x) as FieldIndex
+fn fieldAAAA(v: &mut BinaryParser) -> Result<FieldIndex, String> {
+    Ok(v.read_u16()? as FieldIndex)
 }
 
-fn methodAAAA(v: &mut BinaryParser) -> MethodIndex {
-    let x = v.take(2);
-    to_decimal_short(&x) as MethodIndex
+fn proto_aaaa(v: &mut BinaryParser) -> Result<ProtoIndex, String> {
+    Ok(v.read_u16()? as ProtoIndex)
 }
 
-fn invoke_kind(v: &mut BinaryParser) -> (Vec<Register>, MethodIndex) {
-    let first_byte = v.next();
-    let addr = v.take(2);
-
-    let mut args: Vec<Register> = Vec::new();
-    let arg_count = first_byte >> 4 & 0b00001111;
-
-    let mut arg_bytes = v.take(2);
-    if arg_count > 0 && arg_count <= 5 {
-        for i in 0..(arg_count - 1) {
-            let b = arg_bytes[0];
-            if i % 2 == 0 {
-                args.push((b & 0b00001111) as Register);
-            } else {
-                args.push((b >> 4 & 0b00001111) as Register);
-                arg_bytes.drain(0..1);
-            }
-        }
-    }
+fn method_handle_aaaa(v: &mut BinaryParser) -> Result<MethodHandleIndex, String> {
+    Ok(v.read_u16()? as MethodHandleIndex)
+}
 
-    if arg_count == 1 {
-        let b = arg_bytes[0];
-        args.push((b & 0b00001111) as Register);
-    }
+// a field's byte offset in quickened instructions
+fn field_offset(v: &mut BinaryParser) -> Result<u32, String> {
+    Ok(v.read_u16()? as u32)
+}
 
-    if arg_count == 5 {
-        let last_arg = first_byte & 0b00001111;
-        args.push(last_arg as Register);
-    }
+fn invoke_kind(v: &mut BinaryParser) -> Result<(Vec<Register>, MethodIndex), String> {
+    let first_byte = v.next()?;
+    let method = v.read_u16()?;
+    let arg_bytes = v.take(2)?;
 
-    return (args, to_decimal_short(&addr) as MethodIndex);
+    // arguments are packed as nibbles in the order C, D, E, F, G
+    let arg_count = (first_byte >> 4 & 0b00001111) as usize;
+    let nibbles = [
+        arg_bytes[0] & 0b00001111,
+        arg_bytes[0] >> 4 & 0b00001111,
+        arg_bytes[1] & 0b00001111,
+        arg_bytes[1] >> 4 & 0b00001111,
+        first_byte & 0b00001111,
+    ];
+    let args = nibbles.iter()
+        .take(arg_count)
+        .map(|x| *x as Register)
+        .collect::<Vec<Register>>();
+
+    return Ok((args, method as MethodIndex));
+}
+
+fn invoke_kind_range(v: &mut BinaryParser) -> Result<(Register, Register, MethodIndex), String> {
+    let count = v.next()? as Register;
+    let method_addr = v.read_u16()?;
+    let start_register = v.read_u16()? as Register;
+    return Ok((start_register, count, method_addr as MethodIndex));
 }
 
-fn invoke_kind_range(v: &mut BinaryParser) -> (Register, Register, MethodIndex) {
-    let first_byte = v.next() as u32;
-    let method_addr = to_decimal_short(&v.take(2));
-    let start_register = to_decimal_short(&v.take(2)) as Register;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(units: &[u16]) -> Vec<u8> {
+        units.iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+
+    fn decode(bytes: &[u8]) -> Vec<InstructionKind<'_>> {
+        parse_bytecode(&mut BinaryParser::new(bytes), 0, bytes.len() / 2).unwrap()
+            .into_iter()
+            .map(|i| i.kind)
+            .collect()
+    }
+
+    #[test]
+    fn register_ranges() {
+        let bytes = code(&[
+            0x0077, 0x0001, 0x0000, // invoke-static/range {}, method@1
+            0x0274, 0x0002, 0x0003, // invoke-virtual/range {v3 .. v4}, method@2
+        ]);
+        let kinds = decode(&bytes);
+        assert_eq!(kinds[0], InstructionKind::InvokeStaticRange(0, 0, 1));
+        assert!(kinds[0].used_registers().is_empty());
+        assert_eq!(kinds[0].operands()[0], Operand::RegisterRange(0, 0));
 
-    return (start_register, (start_register + first_byte - 1), method_addr as MethodIndex);
-}
\ No newline at end of file
+        assert_eq!(kinds[1], InstructionKind::InvokeVirtualRange(3, 2, 2));
+        assert_eq!(kinds[1].used_registers(), vec![3, 4]);
+    }
+}
@@ -1,22 +1,62 @@
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Write};
+use std::borrow::Cow;
+use std::ops::{Deref, Range};
 use std::env;
+use std::path::Path;
 
-use clap::{Arg, App, SubCommand, AppSettings};
+use memmap2::Mmap;
+use clap::{Arg, App, ArgGroup, ArgMatches, SubCommand, AppSettings};
+use serde_json::json;
 
 mod analysis;
+mod anomaly;
 mod binary_parser;
+mod call_graph;
+mod check;
+mod class_hierarchy;
+mod color;
+mod container;
+mod decompiler;
 mod dex_parser;
 mod dex_types;
+mod diff;
 mod disassembler;
+mod filter;
 mod instructions;
+mod output;
+mod parallel;
 mod printer;
+mod search;
+mod ssa;
+mod stats;
+mod type_inference;
 mod util;
+mod verifier;
+mod version;
+mod xrefs;
+use crate::anomaly::Anomalies;
 use crate::binary_parser::BinaryParser;
-use crate::dex_parser::{parse_header, parse_strings, parse_types, parse_protos, parse_fields, parse_methods, parse_class_defs};
-use crate::disassembler::Disassembler;
-use crate::instructions::Instruction;
-use crate::printer::get_type_descriptor_string;
+use crate::call_graph::build_call_graph;
+use crate::check::check_dex_file;
+use crate::class_hierarchy::ClassHierarchy;
+use crate::dex_parser::{parse_dex_file, parse_header, parse_strings, parse_types, parse_methods, parse_class_defs};
+use crate::dex_types::{DexClassDef, DexFile, DexMethod, DexType, EncodedMethod};
+use crate::diff::diff_dex_files;
+use crate::color::use_color;
+use crate::container::{container_kind, extract_dex_files};
+use crate::disassembler::{symbolic_instruction_to_string, Disassembler, Labels};
+use crate::filter::{NameFilter, NamePattern};
+use crate::instructions::IndexReference;
+use crate::output::{print_json, print_json_items, print_text, write_json_items, Format};
+use crate::printer::{get_type_descriptor_string, Printer};
+use crate::search::Query;
+use crate::ssa::build_ssa;
+use crate::stats::compute_stats;
+use crate::type_inference::TypeInference;
+use crate::verifier::Verifier;
+use crate::xrefs::{XrefIndex, XrefQuery};
 
 fn main() {
 
@@ -28,17 +68,131 @@ fn main() {
             .short("i")
             .long("input")
             .takes_value(true)
-            .required(true))
+            .multiple(true)
+            .number_of_values(1)
+            .required(true)
+            .help("The dex, vdex or oat file to read; repeat for each file of a multidex app. Select one dex file of a vdex or oat file with 'base.vdex!classes2.dex'"))
+        .arg(Arg::with_name("format")
+            .long("format")
+            .takes_value(true)
+            .possible_values(&["text", "json", "ndjson"])
+            .default_value("text")
+            .help("Output format; ndjson prints one JSON document per line"))
+        .arg(Arg::with_name("jobs")
+            .long("jobs")
+            .short("j")
+            .takes_value(true)
+            .value_name("N")
+            .default_value("1")
+            .help("Decodes and analyzes classes on N threads, or one per CPU with 0; output is the same either way"))
+        .arg(Arg::with_name("hardened")
+            .long("hardened")
+            .help("Reads malformed or deliberately corrupted files, skipping what cannot be decoded and reporting each problem as a warning"))
+        .arg(Arg::with_name("unquicken")
+            .long("unquicken")
+            .help("Restores the instructions ART quickened in a vdex file, using the indexes it kept"))
+        .arg(Arg::with_name("no-color")
+            .long("no-color")
+            .global(true)
+            .help("Disables colored output, which is otherwise used when printing to a terminal"))
+        .subcommand(SubCommand::with_name("check")
+            .about("Reports what is malformed in each input: sections that do not fit or overlap, indexes out of range, looping class hierarchies and data that cannot be decoded"))
+        .subcommand(SubCommand::with_name("diff")
+            .about("Compares two dex files, given as the first and second input, by signature"))
+        .subcommand(SubCommand::with_name("extract")
+            .about("Lists the dex files embedded in vdex and oat inputs")
+            .arg(Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .value_name("DIR")
+                .help("Also writes each dex file to DIR")))
+        .subcommand(SubCommand::with_name("grep")
+            .about("Searches every method for a sequence of instructions")
+            .arg(Arg::with_name("QUERY")
+                .required(true)
+                .help("Instruction patterns separated by ';', e.g. 'const-string $r \"AES\"; invoke-static * Ljavax/crypto/Cipher;->getInstance'")))
         .subcommand(SubCommand::with_name("header")
             .about("Prints header information from the dex file"))
+        .subcommand(SubCommand::with_name("stats")
+            .about("Summarizes each dex file: counts, opcode usage, the largest and most complex methods and the 64K reference limits")
+            .arg(Arg::with_name("top")
+                .long("top")
+                .takes_value(true)
+                .default_value("10")
+                .help("How many methods to list as the largest and most complex")))
         .subcommand(SubCommand::with_name("strings")
             .about("Prints the strings contained within the dex file"))
         .subcommand(SubCommand::with_name("types")
             .about("Prints the names of the types contained within the dex file"))
         .subcommand(SubCommand::with_name("classes")
-            .about("Prints the names of the classes contained within the dex file"))
+            .about("Prints the names of the classes contained within the dex file")
+            .args(&name_filter_args()))
+        .subcommand(SubCommand::with_name("class")
+            .about("Prints classes as Java-like skeletons with disassembled method bodies")
+            .args(&name_filter_args())
+            .arg(symbolic_arg()))
+        .subcommand(SubCommand::with_name("decompile")
+            .about("Decompiles classes to Java source, keeping the disassembly of methods it cannot structure")
+            .args(&name_filter_args())
+            .arg(symbolic_arg()))
         .subcommand(SubCommand::with_name("disassemble")
-            .about("Disassembles the app and prints the results"));
+            .about("Disassembles the app and prints the results")
+            .args(&name_filter_args())
+            .arg(symbolic_arg())
+            .arg(Arg::with_name("types")
+                .long("types")
+                .help("Annotates each instruction with the inferred types of its registers")))
+        .subcommand(SubCommand::with_name("hierarchy")
+            .about("Prints the superclasses, interfaces, subclasses and overrides of a class")
+            .arg(Arg::with_name("CLASS")
+                .required(true)
+                .help("The class descriptor, e.g. 'Lcom/example/Foo;'")))
+        .subcommand(SubCommand::with_name("ssa")
+            .about("Prints each method body in SSA form"))
+        .subcommand(SubCommand::with_name("call-graph")
+            .about("Prints the calls between methods across all input files")
+            .arg(Arg::with_name("export")
+                .long("export")
+                .takes_value(true)
+                .possible_values(&["dot", "json"])
+                .help("Exports the whole graph instead of listing calls"))
+            .arg(Arg::with_name("callers-of")
+                .long("callers-of")
+                .takes_value(true)
+                .value_name("METHOD")
+                .conflicts_with("callees-of")
+                .help("Lists the methods that call METHOD, e.g. 'Lcom/example/Foo;->run()V' or 'Lcom/example/Foo;->run'"))
+            .arg(Arg::with_name("callees-of")
+                .long("callees-of")
+                .takes_value(true)
+                .value_name("METHOD")
+                .help("Lists the methods that METHOD calls")))
+        .subcommand(SubCommand::with_name("verify-code")
+            .about("Checks each method body for structural errors"))
+        .subcommand(SubCommand::with_name("xrefs")
+            .about("Lists the instructions that reference a string, type, field or method")
+            .arg(Arg::with_name("string")
+                .long("string")
+                .takes_value(true)
+                .value_name("TEXT"))
+            .arg(Arg::with_name("type")
+                .long("type")
+                .takes_value(true)
+                .value_name("TYPE")
+                .help("A descriptor or dotted name, e.g. 'Ljava/lang/String;' or 'java.lang.String'"))
+            .arg(Arg::with_name("field")
+                .long("field")
+                .takes_value(true)
+                .value_name("FIELD")
+                .help("e.g. 'Lcom/example/Foo;->mName:Ljava/lang/String;' or 'Lcom/example/Foo;->mName'"))
+            .arg(Arg::with_name("method")
+                .long("method")
+                .takes_value(true)
+                .value_name("METHOD")
+                .help("e.g. 'Lcom/example/Foo;->run()V' or 'Lcom/example/Foo;->run'"))
+            .group(ArgGroup::with_name("target")
+                .args(&["string", "type", "field", "method"])
+                .required(true)));
 
     let args: Vec<String> = env::args().collect();
     let matches = match app.get_matches_from_safe_borrow(args) {
@@ -49,79 +203,808 @@ fn main() {
         }
     };
 
-    let file_path = matches.value_of("FILE").unwrap();
-    let mut file = File::open(file_path).unwrap();
-    let mut buf = Vec::new();
-    file.read_to_end(&mut buf).unwrap();
-    let mut parser = BinaryParser::new(buf);
+    let format = Format::from_name(matches.value_of("format").unwrap());
+    let unquicken = matches.is_present("unquicken");
+    let jobs = matches.value_of("jobs").unwrap();
+    match jobs.parse::<usize>().map_err(|e| e.to_string()).and_then(parallel::set_jobs) {
+        Ok(()) => {}
+        Err(e) => {
+            println!("invalid --jobs: {}: {}", jobs, e);
+            return;
+        }
+    }
+    // check collects the anomalies of each input itself
+    let anomalies = if matches.is_present("hardened") { Anomalies::hardened(true) } else { Anomalies::strict() };
+    // the other commands read the first input before looking at it
+    if let Some(sub_matches) = matches.subcommand_matches("extract") {
+        extract(matches.values_of("FILE").unwrap(), sub_matches.value_of("output"), unquicken, format);
+        return;
+    }
+    // commands that read every input open them themselves
+    let input = match matches.subcommand_name() {
+        Some("check") | Some("diff") | Some("grep") | Some("stats") | Some("call-graph") | Some("hierarchy") | Some("xrefs") => DexBytes::Owned(Vec::new()),
+        _ => open_dex(matches.value_of("FILE").unwrap(), unquicken),
+    };
+    let mut parser = BinaryParser::with_anomalies(&input, &anomalies);
+    let color = format == Format::Text && use_color(matches.is_present("no-color"));
 
     match matches.subcommand_name() {
+        Some("check") => {
+            let paths = input_paths(&matches);
+            let mut items = Vec::new();
+            let mut total = 0;
+            for path in paths.iter() {
+                let found = Anomalies::hardened(false);
+                let input = open_dex(path, unquicken);
+                // what is left of a file parsing gave up on is not worth checking
+                let dex = found.recover(0, || "dex file".to_string(), parse_dex_file(&mut BinaryParser::with_anomalies(&input, &found)));
+                if let Ok(Some(dex)) = dex.as_ref() {
+                    check_dex_file(dex, input.len(), &found);
+                }
+
+                let (anomalies, count) = found.take();
+                total += count;
+                match format {
+                    Format::Text => {
+                        if count > 0 && paths.len() > 1 {
+                            println!("{}", path);
+                        }
+                        for a in anomalies.iter() {
+                            println!("{}", a);
+                        }
+                        if count > anomalies.len() {
+                            println!("... and {} more", count - anomalies.len());
+                        }
+                    }
+                    _ => {
+                        for a in anomalies {
+                            let item = json!({ "file": path, "offset": a.offset, "message": a.message });
+                            if format == Format::Ndjson {
+                                print_json(&item);
+                            } else {
+                                items.push(item);
+                            }
+                        }
+                    }
+                }
+            }
+            match format {
+                Format::Text => println!("{} files checked, {} anomalies", paths.len(), total),
+                Format::Json => print_json(&items),
+                Format::Ndjson => {}
+            }
+        }
+        Some("diff") => {
+            let paths = matches.values_of("FILE").unwrap().collect::<Vec<&str>>();
+            if paths.len() != 2 {
+                println!("diff needs exactly two inputs, e.g. -i old.dex -i new.dex");
+                return;
+            }
+            let (old_input, new_input) = (open_dex(paths[0], unquicken), open_dex(paths[1], unquicken));
+            let old = or_exit(parse_dex_file(&mut BinaryParser::with_anomalies(&old_input, &anomalies)));
+            let new = or_exit(parse_dex_file(&mut BinaryParser::with_anomalies(&new_input, &anomalies)));
+            let diffs = or_exit(diff_dex_files(&old, &new));
+
+            if format != Format::Text {
+                print_json_items(format, diffs);
+                return;
+            }
+
+            print_text(|out| {
+                for c in diffs {
+                    writeln!(out, "{} {}", c.change.symbol(), c.class)?;
+                    for d in c.details {
+                        writeln!(out, "    {}", d)?;
+                    }
+                    let members = c.fields.into_iter().map(|f| ("field", f))
+                        .chain(c.methods.into_iter().map(|m| ("method", m)));
+                    for (kind, m) in members {
+                        writeln!(out, "    {} {} {}", m.change.symbol(), kind, m.member)?;
+                        for d in m.details {
+                            writeln!(out, "        {}", d)?;
+                        }
+                        for (i, hunk) in m.hunks.iter().enumerate() {
+                            if i > 0 {
+                                writeln!(out, "        ...")?;
+                            }
+                            for l in hunk {
+                                writeln!(out, "        {} {}", l.op, l.text)?;
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            });
+        }
+        Some("grep") => {
+            let query = matches.subcommand_matches("grep").unwrap().value_of("QUERY").unwrap();
+            let query = match Query::parse(query) {
+                Ok(q) => q,
+                Err(e) => {
+                    println!("invalid query: {}", e);
+                    return;
+                }
+            };
+
+            let mut items = Vec::new();
+            for path in input_paths(&matches).iter() {
+                let input = open_dex(path, unquicken);
+                let dex = or_exit(parse_dex_file(&mut BinaryParser::with_anomalies(&input, &anomalies)));
+                for m in or_exit(query.search(&dex)) {
+                    let method = dex.method_signature(m.method);
+                    let lines = m.instructions.iter()
+                        .map(|i| format!("{:#x} {}", i.addr, symbolic_instruction_to_string(&dex, i, None)))
+                        .collect::<Vec<String>>();
+                    match format {
+                        Format::Text => print_text(|out| {
+                            writeln!(out, "{}", method)?;
+                            for l in lines {
+                                writeln!(out, "    {}", l)?;
+                            }
+                            Ok(())
+                        }),
+                        _ => {
+                            let item = json!({ "file": path, "method": method, "addr": m.instructions[0].addr, "instructions": lines });
+                            if format == Format::Ndjson {
+                                print_json(&item);
+                            } else {
+                                items.push(item);
+                            }
+                        }
+                    }
+                }
+            }
+            if format == Format::Json {
+                print_json(&items);
+            }
+        }
         Some("header") => {
-            let header  = parse_header(&mut parser);
-            println!("{}", header);
+            let header  = or_exit(parse_header(&mut parser));
+            match format {
+                Format::Text => println!("{}", header),
+                _ => print_json(&header),
+            }
+        }
+        Some("stats") => {
+            let top = matches.subcommand_matches("stats").unwrap().value_of("top").unwrap();
+            let top = match top.parse::<usize>() {
+                Ok(x) => x,
+                Err(_) => {
+                    println!("invalid --top: {}", top);
+                    return;
+                }
+            };
+
+            let mut items = Vec::new();
+            for path in input_paths(&matches).iter() {
+                let input = open_dex(path, unquicken);
+                let dex = or_exit(parse_dex_file(&mut BinaryParser::with_anomalies(&input, &anomalies)));
+                let stats = or_exit(compute_stats(&dex, top));
+                match format {
+                    Format::Text => print_text(|out| writeln!(out, "{}\n{}", path, stats)),
+                    _ => {
+                        let item = json!({ "file": path, "stats": stats });
+                        if format == Format::Ndjson {
+                            print_json(&item);
+                        } else {
+                            items.push(item);
+                        }
+                    }
+                }
+            }
+            if format == Format::Json {
+                print_json(&items);
+            }
         }
         Some("strings") => {
-            let header  = parse_header(&mut parser);
-            let strings = parse_strings(&mut parser, header.string_ids_offset as usize, header.string_ids_size as usize);
-            for s in strings {
-                println!("{}", s);
+            let header  = or_exit(parse_header(&mut parser));
+            let strings = or_exit(parse_strings(&mut parser, header.string_ids_offset as usize, header.string_ids_size as usize));
+            match format {
+                Format::Text => {
+                    for s in strings {
+                        println!("{}", s);
+                    }
+                }
+                _ => print_json_items(format, strings),
             }
         }
         Some("types") => {
-            let header  = parse_header(&mut parser);
-            let strings = parse_strings(&mut parser, header.string_ids_offset as usize, header.string_ids_size as usize);
-            let types   = parse_types(&mut parser, header.type_ids_offset as usize, header.type_ids_size as usize, &strings);
-            for t in types {
-                println!("{}", get_type_descriptor_string(&t.parsed));
+            let header  = or_exit(parse_header(&mut parser));
+            let strings = or_exit(parse_strings(&mut parser, header.string_ids_offset as usize, header.string_ids_size as usize));
+            let types   = or_exit(parse_types(&mut parser, header.type_ids_offset as usize, header.type_ids_size as usize, &strings));
+            match format {
+                Format::Text => {
+                    for t in types {
+                        println!("{}", get_type_descriptor_string(&t.parsed));
+                    }
+                }
+                _ => print_json_items(format, types),
             }
         }
         Some("classes") => {
-            let header  = parse_header(&mut parser);
-            let strings = parse_strings(&mut parser, header.string_ids_offset as usize, header.string_ids_size as usize);
-            let types   = parse_types(&mut parser, header.type_ids_offset as usize, header.type_ids_size as usize, &strings);
-            let classes = parse_class_defs(&mut parser, header.class_defs_offset as usize, header.class_defs_size as usize);
-            for c in classes {
-                let class_name = &types[c.class_idx as usize];
-                println!("{}", get_type_descriptor_string(&class_name.parsed));
+            let header  = or_exit(parse_header(&mut parser));
+            let strings = or_exit(parse_strings(&mut parser, header.string_ids_offset as usize, header.string_ids_size as usize));
+            let types   = or_exit(parse_types(&mut parser, header.type_ids_offset as usize, header.type_ids_size as usize, &strings));
+            let classes = or_exit(parse_class_defs(&mut parser, &header));
+            let methods = or_exit(parse_methods(&mut parser, header.method_ids_offset as usize, header.method_ids_size as usize));
+
+            // with a --method pattern, only classes declaring a matching method are listed
+            let filter = name_filter(matches.subcommand_matches("classes").unwrap());
+            let classes = classes.into_iter().filter(|c| {
+                let class_name = type_name(&types, c.class_idx);
+                filter.matches_class(&class_name) && (!filter.has_method_pattern() ||
+                    or_exit(c.methods()).any(|m| filter.matches_method(&class_name, method_name(&strings, &methods, m.method_idx))))
+            }).collect::<Vec<DexClassDef>>();
+
+            match format {
+                Format::Text => {
+                    for c in classes {
+                        println!("{}", type_name(&types, c.class_idx));
+                    }
+                }
+                _ => {
+                    let items = classes.iter().map(|c| json!({
+                        "name": type_name(&types, c.class_idx),
+                        "definition": c,
+                    }));
+                    print_json_items(format, items);
+                }
+            }
+        }
+        Some(command @ "class") | Some(command @ "decompile") => {
+            let dex = or_exit(parse_dex_file(&mut parser));
+            let sub_matches = matches.subcommand_matches(command).unwrap();
+            let filter = name_filter(sub_matches);
+            let labels = if sub_matches.is_present("symbolic") {
+                let mut labels = Labels::default();
+                for m in dex.classes.iter().flat_map(|c| or_exit(c.methods())) {
+                    if let Some(code) = or_exit(m.decode_code_item()) {
+                        labels.add(code.instructions.iter());
+                    }
+                }
+                labels.number();
+                Some(labels)
+            } else {
+                None
+            };
+            let printer = Printer {
+                disassembler: Disassembler {
+                    dex,
+                    labels,
+                    color,
+                },
+                decompile: command == "decompile",
+            };
+
+            match format {
+                Format::Text => or_exit(printer.print_classes(&filter)),
+                _ => {
+                    let decompiler = printer.decompiler();
+                    let views = (0..printer.disassembler.dex.classes.len()).filter_map(|i| or_exit(printer.class_view(i, &filter, decompiler.as_ref())));
+                    print_json_items(format, views);
+                }
             }
         }
         Some("disassemble") => {
-            let header  = parse_header(&mut parser);
-            let strings = parse_strings(&mut parser, header.string_ids_offset as usize, header.string_ids_size as usize);
-            let types   = parse_types(&mut parser, header.type_ids_offset as usize, header.type_ids_size as usize, &strings);
-            let classes = parse_class_defs(&mut parser, header.class_defs_offset as usize, header.class_defs_size as usize);
-            let protos  = parse_protos(&mut parser, header.proto_ids_offset as usize, header.proto_ids_size as usize);
-            let fields  = parse_fields(&mut parser, header.field_ids_offset as usize, header.field_ids_size as usize);
-            let methods = parse_methods(&mut parser, header.method_ids_offset as usize, header.method_ids_size as usize);
-
-            let mut instructions: Vec<Instruction> = Vec::new();
-            for c in classes.iter() {
-                for d in c.direct_methods.iter() {
-                    match d.code_item.clone() {
-                        Some(mut code) => {
-                            instructions.append(&mut code.instructions);
-                        }
-                        None => {}
+            let dex = or_exit(parse_dex_file(&mut parser));
+
+            let sub_matches = matches.subcommand_matches("disassemble").unwrap();
+            let disassembler = Disassembler {
+                dex,
+                labels: None,
+                color,
+            };
+            let stdout = io::stdout();
+            let mut out = io::BufWriter::new(stdout.lock());
+            let result = write_disassembly(&mut out, disassembler, &name_filter(sub_matches),
+                sub_matches.is_present("symbolic"), sub_matches.is_present("types"), format);
+            match result.and_then(|_| out.flush()) {
+                Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    // the methods listed before it still go out
+                    let _ = out.flush();
+                    or_exit(Err::<(), String>(e.to_string()));
+                }
+                r => r.unwrap(),
+            }
+        }
+        Some("ssa") => {
+            let header  = or_exit(parse_header(&mut parser));
+            let strings = or_exit(parse_strings(&mut parser, header.string_ids_offset as usize, header.string_ids_size as usize));
+            let types   = or_exit(parse_types(&mut parser, header.type_ids_offset as usize, header.type_ids_size as usize, &strings));
+            let classes = or_exit(parse_class_defs(&mut parser, &header));
+            let methods = or_exit(parse_methods(&mut parser, header.method_ids_offset as usize, header.method_ids_size as usize));
+
+            let mut items = Vec::new();
+            // decoded without caching, a method's code is not needed again
+            let decoded = parallel::for_each_method_batch(&classes, |c, m| {
+                Ok(m.decode_code_item()?.map(|code| (c, m, build_ssa(&code))))
+            }, |result| {
+                let (c, m, ssa) = match result {
+                    Some(x) => x,
+                    None => return,
+                };
+                let class_name = type_name(&types, c.class_idx);
+                let method_name = method_name(&strings, &methods, m.method_idx);
+                match format {
+                    Format::Text => {
+                        println!("{}.{}", class_name, method_name);
+                        print!("{}", ssa);
                     }
+                    Format::Json => items.push(json!({ "class": class_name, "method": method_name, "ssa": ssa })),
+                    Format::Ndjson => print_json(&json!({ "class": class_name, "method": method_name, "ssa": ssa })),
                 }
+            });
+            or_exit(decoded);
+            if format == Format::Json {
+                print_json(&items);
             }
+        }
+        Some("call-graph") => {
+            // parsed files borrow their inputs, so those are all opened first
+            let inputs = input_paths(&matches).iter()
+                .map(|path| open_dex(path, unquicken))
+                .collect::<Vec<DexBytes>>();
+            let dex_files = inputs.iter()
+                .map(|input| or_exit(parse_dex_file(&mut BinaryParser::with_anomalies(input, &anomalies))))
+                .collect::<Vec<DexFile>>();
 
-            instructions.sort_by_key(|x| x.addr);
+            let graph = or_exit(build_call_graph(&dex_files));
+            let sub_matches = matches.subcommand_matches("call-graph").unwrap();
+            let query = sub_matches.value_of("callers-of").map(|q| (q, true))
+                .or_else(|| sub_matches.value_of("callees-of").map(|q| (q, false)));
 
-            let disassembler = Disassembler {
-                strings,
-                types,
-                protos,
-                fields,
-                methods,
-                classes,
-                instructions,
+            match (sub_matches.value_of("export"), query) {
+                (_, Some((q, callers))) => {
+                    let found = graph.find(q);
+                    if found.is_empty() && format == Format::Text {
+                        println!("no method matches {}", q);
+                    }
+                    let related = found.into_iter()
+                        .flat_map(|m| if callers { graph.callers_of(m) } else { graph.callees_of(m) })
+                        .map(|r| &graph.methods[r]);
+                    match format {
+                        Format::Text => related.for_each(|r| println!("{}", r)),
+                        _ => print_json_items(format, related),
+                    }
+                }
+                (Some("dot"), None) => print!("{}", graph.to_dot()),
+                (Some(_), None) => println!("{}", graph.to_json()),
+                (None, None) => {
+                    let calls = graph.edges.iter().map(|(a, b)| (&graph.methods[*a], &graph.methods[*b]));
+                    match format {
+                        Format::Text => calls.for_each(|(a, b)| println!("{} -> {}", a, b)),
+                        _ => print_json_items(format, calls.map(|(a, b)| json!({ "caller": a, "callee": b }))),
+                    }
+                }
+            }
+        }
+        Some("hierarchy") => {
+            // parsed files borrow their inputs, so those are all opened first
+            let inputs = input_paths(&matches).iter()
+                .map(|path| open_dex(path, unquicken))
+                .collect::<Vec<DexBytes>>();
+            let dex_files = inputs.iter()
+                .map(|input| or_exit(parse_dex_file(&mut BinaryParser::with_anomalies(input, &anomalies))))
+                .collect::<Vec<DexFile>>();
+
+            let hierarchy = or_exit(ClassHierarchy::new(&dex_files));
+            let name = matches.subcommand_matches("hierarchy").unwrap().value_of("CLASS").unwrap();
+            let class = match hierarchy.get(name) {
+                Some(c) => c,
+                None => {
+                    println!("{} is not defined or referenced as a supertype", name);
+                    return;
+                }
             };
 
-            disassembler.print();
+            if format != Format::Text {
+                let names = |classes: Vec<&class_hierarchy::ClassNode>| classes.iter().map(|c| c.name.clone()).collect::<Vec<String>>();
+                let methods = class.methods.keys().map(|m| json!({
+                    "method": m,
+                    "overrides": hierarchy.overridden_method(name, m).map(|o| &o.name),
+                    "overridden_by": names(hierarchy.overriding_methods(name, m)),
+                })).collect::<Vec<_>>();
+                print_json(&json!({
+                    "class": class,
+                    "superclasses": names(hierarchy.superclass_chain(name)),
+                    "interfaces": names(hierarchy.interfaces_of(name)),
+                    "subclasses": names(hierarchy.subclasses_of(name)),
+                    "methods": methods,
+                }));
+                return;
+            }
+
+            let describe = |c: &class_hierarchy::ClassNode| {
+                if c.phantom { format!("{} (phantom)", c.name) } else { c.name.clone() }
+            };
+            println!("{}{}", describe(class), if class.is_interface() { " (interface)" } else { "" });
+            println!("superclasses:");
+            for s in hierarchy.superclass_chain(name) {
+                println!("    {}", describe(s));
+            }
+            println!("interfaces:");
+            for i in hierarchy.interfaces_of(name) {
+                println!("    {}", describe(i));
+            }
+            println!("subclasses:");
+            for s in hierarchy.subclasses_of(name) {
+                println!("    {}", describe(s));
+            }
+            println!("methods:");
+            for m in class.methods.keys() {
+                println!("    {}", m);
+                if let Some(o) = hierarchy.overridden_method(name, m) {
+                    println!("        overrides {}", describe(o));
+                }
+                for o in hierarchy.overriding_methods(name, m) {
+                    println!("        overridden by {}", describe(o));
+                }
+            }
+        }
+        Some("verify-code") => {
+            let dex = or_exit(parse_dex_file(&mut parser));
+            let (strings, types, methods) = (&dex.strings, &dex.types, &dex.methods);
+
+            let verifier = Verifier::new(strings, types, &dex.protos, &dex.fields, methods, &dex.classes, &dex.call_sites);
+            let mut method_count = 0;
+            let mut failed_count = 0;
+            let mut items = Vec::new();
+            let decoded = parallel::for_each_method_batch(&dex.classes, |c, m| Ok((c, m, verifier.verify(m)?)), |(c, m, diagnostics)| {
+                method_count += 1;
+                if diagnostics.is_empty() {
+                    return;
+                }
+
+                failed_count += 1;
+                let class_name = type_name(types, c.class_idx);
+                let method_name = method_name(strings, methods, m.method_idx);
+                match format {
+                    Format::Text => {
+                        println!("{}.{}", class_name, method_name);
+                        for d in diagnostics {
+                            println!("    {}", d);
+                        }
+                    }
+                    Format::Json => items.push(json!({ "class": class_name, "method": method_name, "diagnostics": diagnostics })),
+                    Format::Ndjson => print_json(&json!({ "class": class_name, "method": method_name, "diagnostics": diagnostics })),
+                }
+            });
+            or_exit(decoded);
+            match format {
+                Format::Text => println!("{} methods verified, {} with errors", method_count, failed_count),
+                Format::Json => print_json(&items),
+                Format::Ndjson => {}
+            }
+        }
+        Some("xrefs") => {
+            let sub_matches = matches.subcommand_matches("xrefs").unwrap();
+            let query = if let Some(s) = sub_matches.value_of("string") {
+                XrefQuery::String(s)
+            } else if let Some(t) = sub_matches.value_of("type") {
+                XrefQuery::Type(t)
+            } else if let Some(f) = sub_matches.value_of("field") {
+                XrefQuery::Field(f)
+            } else {
+                XrefQuery::Method(sub_matches.value_of("method").unwrap())
+            };
+
+            let paths = input_paths(&matches);
+            let mut items = Vec::new();
+            for path in paths.iter() {
+                let input = open_dex(path, unquicken);
+                let dex = or_exit(parse_dex_file(&mut BinaryParser::with_anomalies(&input, &anomalies)));
+                let index = or_exit(XrefIndex::new(&dex.classes));
+                for target in query.resolve(&dex) {
+                    let target_name = match target {
+                        IndexReference::String(i) => format!("{:?}", dex.strings[i]),
+                        IndexReference::Type(i) => dex.type_descriptor(i).to_string(),
+                        IndexReference::Field(i) => dex.field_signature(i),
+                        IndexReference::Method(i) => dex.method_signature(i),
+                    };
+                    let xrefs = index.references_to(target);
+                    if format == Format::Text {
+                        print_text(|out| {
+                            if !xrefs.is_empty() && paths.len() > 1 {
+                                writeln!(out, "{} in {}", target_name, path)?;
+                            } else if !xrefs.is_empty() {
+                                writeln!(out, "{}", target_name)?;
+                            }
+                            for x in xrefs {
+                                writeln!(out, "    {} {:#x} {}", dex.method_signature(x.method), x.addr, x.mnemonic)?;
+                            }
+                            Ok(())
+                        });
+                        continue;
+                    }
+
+                    for x in xrefs {
+                        let item = json!({
+                            "file": path,
+                            "target": target_name,
+                            "method": dex.method_signature(x.method),
+                            "addr": x.addr,
+                            "instruction": x.mnemonic,
+                        });
+                        match format {
+                            Format::Ndjson => print_json(&item),
+                            _ => items.push(item),
+                        }
+                    }
+                }
+            }
+            if format == Format::Json {
+                print_json(&items);
+            }
         }
         Some(_) | None => app.print_help().expect(""),
     }
 
 
-}
\ No newline at end of file
+}
+
+// The bytes of an input: the mapped file for a dex file, the range of the
+// mapped file for one embedded in a vdex or oat file, or a copy of it once
+// quickened instructions have been restored.
+enum DexBytes {
+    Mapped(Mmap),
+    Embedded(Mmap, Range<usize>),
+    Owned(Vec<u8>),
+}
+
+impl Deref for DexBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            DexBytes::Mapped(m) => m,
+            DexBytes::Embedded(m, range) => &m[range.clone()],
+            DexBytes::Owned(v) => v,
+        }
+    }
+}
+
+fn map_file(path: &str) -> Result<Mmap, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    // the file must not change while it is mapped, which nothing here
+    // guards against, as with any other reader of it
+    unsafe { Mmap::map(&file) }.map_err(|e| format!("{}: {}", path, e))
+}
+
+// Opens a dex file, or one of the dex files in a vdex or oat file: the one
+// named after a '!' in the path, or else the first.
+fn open_dex(path: &str, unquicken: bool) -> DexBytes {
+    let (file_path, name) = match path.rfind('!') {
+        Some(i) if !Path::new(path).exists() => (&path[..i], Some(&path[i + 1..])),
+        _ => (path, None),
+    };
+    let buf = or_exit(map_file(file_path));
+    if container_kind(&buf).is_none() {
+        return DexBytes::Mapped(buf);
+    }
+
+    let mut embedded = extract_dex_files(&buf, unquicken);
+    let names = embedded.iter().map(|e| e.name.clone()).collect::<Vec<String>>();
+    let i = match name {
+        Some(name) => names.iter().position(|n| n == name),
+        None if names.len() > 1 => {
+            eprintln!("note: {} contains {} dex files, reading {}; select another with e.g. '{}!{}'",
+                file_path, names.len(), names[0], file_path, names[1]);
+            Some(0)
+        }
+        None if names.is_empty() => None,
+        None => Some(0),
+    };
+    match i {
+        Some(i) => {
+            let e = embedded.swap_remove(i);
+            let range = e.offset..e.offset + e.bytes.len();
+            match e.bytes {
+                Cow::Borrowed(_) => DexBytes::Embedded(buf, range),
+                Cow::Owned(bytes) => DexBytes::Owned(bytes),
+            }
+        }
+        None if names.is_empty() => {
+            println!("{} contains no dex files; they are probably still in the apk", file_path);
+            std::process::exit(1);
+        }
+        None => {
+            println!("{} has no dex file {}; it contains: {}", file_path, name.unwrap(), names.join(", "));
+            std::process::exit(1);
+        }
+    }
+}
+
+// The inputs with every vdex and oat file expanded to the dex files in it.
+fn input_paths(matches: &ArgMatches) -> Vec<String> {
+    let mut paths = Vec::new();
+    for path in matches.values_of("FILE").unwrap() {
+        if path.contains('!') || !Path::new(path).exists() {
+            paths.push(path.to_string());
+            continue;
+        }
+        let buf = or_exit(map_file(path));
+        if container_kind(&buf).is_none() {
+            paths.push(path.to_string());
+            continue;
+        }
+        paths.extend(extract_dex_files(&buf, false).into_iter().map(|e| format!("{}!{}", path, e.name)));
+    }
+    paths
+}
+
+fn extract<'a>(paths: impl Iterator<Item = &'a str>, output: Option<&str>, unquicken: bool, format: Format) {
+    let mut items = Vec::new();
+    for path in paths {
+        let buf = or_exit(map_file(path));
+        if container_kind(&buf).is_none() {
+            println!("{} is not a vdex or oat file", path);
+            continue;
+        }
+        for e in extract_dex_files(&buf, unquicken) {
+            let magic = String::from_utf8_lossy(&e.bytes[0..7]).replace('\n', " ");
+            let written = output.map(|dir| {
+                let stem = or_exit(Path::new(path).file_stem().ok_or(format!("{} is not a file name", path)));
+                let out = Path::new(dir).join(format!("{}-{}", stem.to_string_lossy(), e.name));
+                or_exit_with("cannot write the output", std::fs::create_dir_all(dir).map_err(|x| format!("{}: {}", dir, x)));
+                or_exit_with("cannot write the output", std::fs::write(&out, &e.bytes).map_err(|x| format!("{}: {}", out.display(), x)));
+                out.to_string_lossy().to_string()
+            });
+            match format {
+                Format::Text => {
+                    print!("{}!{} {:#x} {} bytes {}", path, e.name, e.offset, e.bytes.len(), magic);
+                    match written {
+                        Some(out) => println!(" -> {}", out),
+                        None => println!(),
+                    }
+                }
+                _ => items.push(json!({
+                    "file": path,
+                    "name": e.name,
+                    "offset": e.offset,
+                    "size": e.bytes.len(),
+                    "magic": magic,
+                    "output": written,
+                })),
+            }
+        }
+    }
+    if format != Format::Text {
+        print_json_items(format, items);
+    }
+}
+
+// Writes the listing a method at a time, in file order. Only a batch of
+// methods' code items and inferred types is ever decoded at once, since
+// the code items of a whole framework-sized file would not fit. The
+// methods of a batch are decoded and formatted on `--jobs` threads and
+// written out in order.
+fn write_disassembly<W: Write>(out: &mut W, mut d: Disassembler, filter: &NameFilter, symbolic: bool, show_types: bool, format: Format) -> io::Result<()> {
+    const BATCH: usize = 256;
+
+    let mut selected: Vec<&EncodedMethod> = Vec::new();
+    for c in d.dex.classes.iter() {
+        let class_name = type_name(&d.dex.types, c.class_idx);
+        if !filter.matches_class(&class_name) {
+            continue;
+        }
+        for m in c.methods().map_err(decode_error)? {
+            if m.code_offset != 0 && filter.matches_method(&class_name, method_name(&d.dex.strings, &d.dex.methods, m.method_idx)) {
+                selected.push(m);
+            }
+        }
+    }
+    selected.sort_by_key(|m| m.code_offset);
+
+    if format != Format::Text {
+        // the listing stops at the first method that cannot be decoded
+        let mut error = None;
+        let instructions = selected.iter()
+            .map_while(|m| m.decode_code_item().map_err(|e| error = Some(e)).ok())
+            .flatten()
+            .flat_map(|code| code.into_owned().instructions);
+        write_json_items(out, format, instructions)?;
+        return error.map_or(Ok(()), |e| Err(decode_error(e)));
+    }
+
+    // labels are numbered across the whole listing, so they take a pass of their own
+    if symbolic {
+        let mut labels = Labels::default();
+        for m in selected.iter() {
+            if let Some(code) = m.decode_code_item().map_err(decode_error)? {
+                labels.add(code.instructions.iter());
+            }
+        }
+        labels.number();
+        d.labels = Some(labels);
+    }
+
+    let inference = if show_types {
+        Some(TypeInference::new(&d.dex.strings, &d.dex.types, &d.dex.protos, &d.dex.fields, &d.dex.methods, &d.dex.classes, &d.dex.call_sites))
+    } else {
+        None
+    };
+    for batch in selected.chunks(BATCH) {
+        let listings = parallel::map(batch, |m| {
+            let code = match m.decode_code_item()? {
+                Some(code) => code,
+                None => return Ok(Vec::new()),
+            };
+            let types = match &inference {
+                Some(inference) => inference.infer_code(m, &code).annotations,
+                None => HashMap::new(),
+            };
+            let mut listing = Vec::new();
+            d.write_instructions(&mut listing, &code.instructions, &types).map_err(|e| e.to_string())?;
+            Ok(listing)
+        });
+        for listing in listings {
+            out.write_all(&listing.map_err(decode_error)?)?;
+        }
+    }
+    Ok(())
+}
+
+// Class data or code that cannot be decoded, for the listing to stop at.
+fn decode_error(e: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+// What was parsed, or the end of the program when the input could not be.
+fn or_exit<T>(result: Result<T, String>) -> T {
+    or_exit_with("cannot read the input", result)
+}
+
+fn or_exit_with<T>(message: &str, result: Result<T, String>) -> T {
+    result.unwrap_or_else(|e| {
+        println!("{}: {}", message, e);
+        std::process::exit(1);
+    })
+}
+
+fn type_name(types: &[DexType], i: usize) -> String {
+    match types.get(i) {
+        Some(t) => get_type_descriptor_string(&t.parsed),
+        None => format!("type@{:#x}", i),
+    }
+}
+
+// "?" when the file's indexes do not lead to a name.
+fn method_name<'a>(strings: &'a [String], methods: &[DexMethod], i: usize) -> &'a str {
+    methods.get(i).and_then(|m| strings.get(m.name_idx)).map(|x| x.as_str()).unwrap_or("?")
+}
+
+fn name_filter_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("class")
+            .long("class")
+            .takes_value(true)
+            .value_name("PATTERN")
+            .help("Only classes whose dotted name matches PATTERN, a glob like 'com.example.net.*' or a /regex/"),
+        Arg::with_name("method")
+            .long("method")
+            .takes_value(true)
+            .value_name("PATTERN")
+            .help("Only methods whose Class.method name matches PATTERN, e.g. 'Foo.onCreate'"),
+    ]
+}
+
+fn symbolic_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("symbolic")
+        .long("symbolic")
+        .help("Resolves strings, types, fields and methods inline and labels branch targets")
+}
+
+fn name_filter(matches: &ArgMatches) -> NameFilter {
+    let pattern = |name: &str| matches.value_of(name).map(|p| {
+        NamePattern::new(p).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(1);
+        })
+    });
+
+    NameFilter {
+        class: pattern("class"),
+        method: pattern("method"),
+    }
+}
@@ -0,0 +1,71 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    // one JSON document per line, so large listings can be streamed
+    Ndjson,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Format {
+        match name {
+            "json" => Format::Json,
+            "ndjson" => Format::Ndjson,
+            _ => Format::Text,
+        }
+    }
+}
+
+// A single document. Both JSON formats print it on one line.
+pub fn print_json<T: Serialize + ?Sized>(value: &T) {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let result = serde_json::to_writer(&mut out, value).map_err(io::Error::from);
+    ignore_broken_pipe(result.and_then(|_| writeln!(out)));
+}
+
+// A listing: a JSON array, or one line per item for ndjson.
+pub fn print_json_items<T: Serialize>(format: Format, items: impl IntoIterator<Item = T>) {
+    let stdout = io::stdout();
+    ignore_broken_pipe(write_json_items(&mut stdout.lock(), format, items));
+}
+
+// Text written with `writeln!`, which unlike `println!` can stop early
+// when the pipe is closed.
+pub fn print_text(write: impl FnOnce(&mut dyn Write) -> io::Result<()>) {
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    ignore_broken_pipe(write(&mut out).and_then(|_| out.flush()));
+}
+
+// Output piped into e.g. `head` just stops when the reader is done with it.
+fn ignore_broken_pipe(result: io::Result<()>) {
+    match result {
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        r => r.unwrap(),
+    }
+}
+
+// Items are written as they come, the array is never all in memory.
+pub fn write_json_items<T: Serialize, W: Write>(out: &mut W, format: Format, items: impl IntoIterator<Item = T>) -> io::Result<()> {
+    if format == Format::Ndjson {
+        for item in items {
+            serde_json::to_writer(&mut *out, &item)?;
+            writeln!(out)?;
+        }
+        return Ok(());
+    }
+
+    write!(out, "[")?;
+    for (n, item) in items.into_iter().enumerate() {
+        if n > 0 {
+            write!(out, ",")?;
+        }
+        serde_json::to_writer(&mut *out, &item)?;
+    }
+    writeln!(out, "]")
+}
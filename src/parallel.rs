@@ -0,0 +1,75 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rayon::prelude::*;
+
+use crate::dex_types::{DexClassDef, EncodedMethod};
+
+// Work that is independent per class or method runs on rayon's global pool
+// once `--jobs` asks for more than one thread. Results are collected in
+// input order either way, so output does not depend on scheduling.
+static PARALLEL: AtomicBool = AtomicBool::new(false);
+
+// 0 uses a thread per CPU. Fails if the pool cannot be started, or was
+// already started with some other number of threads.
+pub fn set_jobs(jobs: usize) -> Result<(), String> {
+    if jobs == 1 {
+        return Ok(());
+    }
+    rayon::ThreadPoolBuilder::new().num_threads(jobs).build_global().map_err(|e| e.to_string())?;
+    PARALLEL.store(rayon::current_num_threads() > 1, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn is_parallel() -> bool {
+    PARALLEL.load(Ordering::Relaxed)
+}
+
+pub fn map<'b, T, U, F>(items: &'b [T], f: F) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&'b T) -> U + Sync + Send,
+{
+    if is_parallel() {
+        items.par_iter().map(f).collect()
+    } else {
+        items.iter().map(f).collect()
+    }
+}
+
+// `f` for every method of every class, in class order and with direct
+// methods before virtual ones. Stops at the first class data or code that
+// cannot be decoded; which one that is, with more than one thread, is
+// still the first in class order.
+pub fn map_methods<'a, 'b, U, F>(classes: &'b [DexClassDef<'a>], f: F) -> Result<Vec<U>, String>
+where
+    U: Send,
+    F: Fn(&'b DexClassDef<'a>, &'b EncodedMethod<'a>) -> Result<U, String> + Sync + Send,
+{
+    let per_class = map(classes, |c| {
+        c.methods()?.map(|m| f(c, m)).collect::<Result<Vec<U>, String>>()
+    });
+    let mut results = Vec::new();
+    for r in per_class {
+        results.extend(r?);
+    }
+    Ok(results)
+}
+
+// Like `map_methods`, but `f` only runs on a batch of classes at a time and
+// each batch's results are handed to `sink` before the next is started, so
+// a pass over a whole framework-sized file never holds all of them.
+// What the batches before an error produced has already been handed on.
+pub fn for_each_method_batch<'a, 'b, U, F, S>(classes: &'b [DexClassDef<'a>], f: F, mut sink: S) -> Result<(), String>
+where
+    U: Send,
+    F: Fn(&'b DexClassDef<'a>, &'b EncodedMethod<'a>) -> Result<U, String> + Sync + Send,
+    S: FnMut(U),
+{
+    const BATCH: usize = 64;
+
+    for batch in classes.chunks(BATCH) {
+        map_methods(batch, &f)?.into_iter().for_each(&mut sink);
+    }
+    Ok(())
+}
@@ -1,148 +1,347 @@
-use std::fmt::Write;
+use std::fmt;
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::decompiler::Decompiler;
 use crate::dex_types::*;
-use crate::util::to_hex_string;
-use crate::instructions::*;
-use crate::binary_parser::BinaryParser;
-
-pub struct Printer {
-    pub strings: Vec<String>,
-    pub types: Vec<DexType>,
-    pub protos: Vec<DexProto>,
-    pub fields: Vec<DexField>,
-    pub methods: Vec<DexMethod>,
-    pub classes: Vec<DexClassDef>,
-    pub parser: BinaryParser,
+use crate::disassembler::Disassembler;
+use crate::filter::NameFilter;
+
+// Renders classes as Java-like skeletons. The disassembler holds the dex
+// tables and formats the method bodies, unless `decompile` asks for Java.
+pub struct Printer<'a> {
+    pub disassembler: Disassembler<'a>,
+    pub decompile: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClassView {
+    pub name: String,
+    pub source_file: Option<String>,
+    pub package: String,
+    // `public final class Foo extends com.example.Base implements java.lang.Runnable`
+    pub declaration: String,
+    pub fields: Vec<MemberView>,
+    pub methods: Vec<MemberView>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemberView {
+    // `private static int sCount = 3`, `public Foo(java.lang.String name)`
+    pub declaration: String,
+    // disassembly or Java lines; None for fields and for methods without code
+    pub body: Option<Vec<String>>,
+    // `blocked`, `max-target-o`, ... for platform members
+    pub hiddenapi: Option<String>,
 }
 
-impl Printer {
-    pub fn print_classes(&mut self) {
-        for i in 0..self.classes.len() {
-            self.print_class_idx(i);
+impl<'a> Printer<'a> {
+    pub fn print_classes(&self, filter: &NameFilter) -> Result<(), String> {
+        let decompiler = self.decompiler();
+        for i in 0..self.disassembler.dex.classes.len() {
+            if let Some(view) = self.class_view(i, filter, decompiler.as_ref())? {
+                print!("{}", view);
+            }
         }
+        Ok(())
     }
 
-    pub fn print_class_idx(&mut self, i: usize) {
-        let class = &self.classes[i];
-        let class_type = &self.types[class.class_idx as usize];
-        let class_name = match &class_type.parsed {
-            TypeDescriptor::Class(s) => &s,
-            _ => "",
+    pub fn decompiler(&self) -> Option<Decompiler<'_>> {
+        if !self.decompile {
+            return None;
+        }
+        let d = &self.disassembler;
+        Some(Decompiler::new(&d.dex.strings, &d.dex.types, &d.dex.protos, &d.dex.fields, &d.dex.methods, &d.dex.classes, &d.dex.call_sites))
+    }
+
+    // None if the class, or every one of its methods when there is a
+    // method pattern, is filtered out. Method bodies are decompiled when a
+    // decompiler is given, falling back to commented-out disassembly.
+    pub fn class_view(&self, i: usize, filter: &NameFilter, decompiler: Option<&Decompiler>) -> Result<Option<ClassView>, String> {
+        let d = &self.disassembler;
+        let class = &d.dex.classes[i];
+        let name = self.type_name(class.class_idx);
+        if !filter.matches_class(&name) {
+            return Ok(None);
+        }
+
+        let (package, simple_name) = match name.rfind('.') {
+            Some(i) => (name[..i].to_string(), name[i + 1..].to_string()),
+            None => (String::new(), name.clone()),
         };
 
-        let access_level = get_class_access_level_string(class.access_flags);
-
-        let mut result = String::new();
-        write!(&mut result, "{} class {} {{\n", access_level, class_name.replace("/", ".")).expect("");
-
-        for encoded_field in &class.instance_fields {
-            let field = &self.fields[encoded_field.field_idx as usize];
-            let field_type = &self.types[field.type_idx as usize].parsed;
-            let field_name = &self.strings[field.name_idx as usize];
-            let access_level = get_field_access_level_string(encoded_field.access_flags);
-            let type_string = get_type_descriptor_string(field_type);
-            write!(&mut result, "\t{} {} {};\n", access_level, type_string, field_name).expect("");
-        }
-
-        for encoded_method in &class.direct_methods {
-            let method = &self.methods[encoded_method.method_idx as usize];
-            let method_proto = &self.protos[method.proto_idx as usize];
-            let method_name = &self.strings[method.name_idx as usize];
-            let access_level = get_method_access_level_string(encoded_method.access_flags);
-
-            let return_type = &self.types[method_proto.return_type_idx as usize];
-            let return_type_string = get_type_descriptor_string(&return_type.parsed);
-            let param_types = method_proto.parameter_type_idx_list.iter()
-                .map(|idx: &TypeIndex| {
-                    let t = &self.types[*idx].parsed;
-                    return get_type_descriptor_string(t);
-                })
-                .collect::<Vec<String>>()
-                .join(", ");
-
-            write!(&mut result, "\t{} {} {}({}) {{\n", access_level, return_type_string, method_name, param_types).expect("");
-            match &encoded_method.code_item {
-                Some(c) => {
-                    write!(&mut result, "\t\t// Offset: {:x}\n", c.addr).expect("");
-                    write!(&mut result, "\t\t// Ins: {:?}\n", c.ins_size).expect("");
-
-                    for i in c.instructions.iter() {
-                        write!(&mut result, "\t\t{:?}\n", i).expect("");
-                    }
-                },
-                None => {},
-            };
-            write!(&mut result, "\t}}\n").expect("");
+        let mut declaration = get_class_modifiers(class.access_flags);
+        declaration.push(simple_name.clone());
+        let superclass = d.dex.types.get(class.superclass_idx).map(|t| get_type_descriptor_string(&t.parsed));
+        let interfaces = class.interfaces.iter()
+            .map(|t| self.type_name(*t))
+            .collect::<Vec<String>>();
+        let is_interface = class.access_flags & ClassAccessLevel::Interface as u32 != 0;
+        match superclass {
+            Some(s) if !is_interface && s != "java.lang.Object" => declaration.push(format!("extends {}", s)),
+            _ => {}
+        }
+        if !interfaces.is_empty() {
+            let keyword = if is_interface { "extends" } else { "implements" };
+            declaration.push(format!("{} {}", keyword, interfaces.join(", ")));
+        }
+
+        let mut fields: Vec<MemberView> = Vec::new();
+        let static_fields = class.static_fields()?.len();
+        for (n, f) in class.fields()?.enumerate() {
+            let mut words = get_field_modifiers(f.access_flags);
+            match d.dex.fields.get(f.field_idx) {
+                Some(field) => {
+                    words.push(self.type_name(field.type_idx));
+                    words.push(self.string(field.name_idx));
+                }
+                None => words.push(format!("field@{:#x}", f.field_idx)),
+            }
+            let mut declaration = words.join(" ");
+            if let Some(value) = class.static_values.get(n).filter(|_| n < static_fields) {
+                declaration = format!("{} = {}", declaration, self.encoded_value_to_string(value));
+            }
+            fields.push(MemberView { declaration, body: None, hiddenapi: f.hiddenapi_flags.map(|x| x.name()) });
+        }
+
+        let mut methods: Vec<MemberView> = Vec::new();
+        for m in class.methods()? {
+            let method_name = d.dex.methods.get(m.method_idx).map(|x| self.string(x.name_idx)).unwrap_or_default();
+            if !filter.matches_method(&name, &method_name) {
+                continue;
+            }
+
+            // not cached, a class listing reads every method once
+            let code = m.decode_code_item()?;
+            let code = code.as_deref();
+            let body = code.map(|c| {
+                let disassembly = c.instructions.iter().flat_map(|i| d.label(i).into_iter().chain(std::iter::once(d.format_instruction(i, None))));
+                match decompiler.map(|x| x.decompile(m)) {
+                    None => disassembly.collect::<Vec<String>>(),
+                    Some(Ok(lines)) => lines,
+                    Some(Err(e)) => std::iter::once(format!("// could not decompile: {}", e))
+                        .chain(disassembly.map(|l| format!("// {}", l)))
+                        .collect(),
+                }
+            });
+            methods.push(MemberView {
+                declaration: self.method_declaration(m, code, &simple_name),
+                body,
+                hiddenapi: m.hiddenapi_flags.map(|x| x.name()),
+            });
+        }
+        if filter.has_method_pattern() && methods.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(ClassView {
+            name: name.clone(),
+            source_file: d.dex.strings.get(class.source_file_idx).cloned(),
+            package,
+            declaration: declaration.join(" "),
+            fields,
+            methods,
+        }))
+    }
+
+    fn method_declaration(&self, m: &EncodedMethod, code: Option<&CodeItem>, class_name: &str) -> String {
+        let d = &self.disassembler;
+        let (method, proto) = match d.dex.methods.get(m.method_idx).and_then(|x| Some((x, d.dex.protos.get(x.proto_idx)?))) {
+            Some(x) => x,
+            None => return format!("method@{:#x}", m.method_idx),
+        };
+        let method_name = &self.string(method.name_idx);
+        if method_name == "<clinit>" {
+            return "static".to_string();
+        }
+
+        let names = code.map(|c| c.parameter_names.as_slice()).unwrap_or(&[]);
+        let count = proto.parameter_type_idx_list.len();
+        let parameters = proto.parameter_type_idx_list.iter().enumerate()
+            .map(|(n, t)| {
+                let mut type_string = self.type_name(*t);
+                if n + 1 == count && m.access_flags & MethodAccessLevel::VarArgs as u32 != 0 && type_string.ends_with("[]") {
+                    type_string = format!("{}...", &type_string[..type_string.len() - 2]);
+                }
+                let name = match names.get(n) {
+                    Some(Some(idx)) if *idx < d.dex.strings.len() => d.dex.strings[*idx].clone(),
+                    _ => format!("arg{}", n),
+                };
+                format!("{} {}", type_string, name)
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        let mut words = get_method_modifiers(m.access_flags);
+        if method_name == "<init>" {
+            words.push(format!("{}({})", class_name, parameters));
+        } else {
+            words.push(self.type_name(proto.return_type_idx));
+            words.push(format!("{}({})", method_name, parameters));
+        }
+        words.join(" ")
+    }
+
+    // Names for the indexes the file gives, which may be out of range.
+    fn type_name(&self, i: TypeIndex) -> String {
+        match self.disassembler.dex.types.get(i) {
+            Some(t) => get_type_descriptor_string(&t.parsed),
+            None => format!("type@{:#x}", i),
         }
+    }
+
+    fn string(&self, i: StringIndex) -> String {
+        self.disassembler.dex.string(i).into_owned()
+    }
 
-        write!(&mut result, "}}\n").expect("");
-        print!("{}", result);
+    fn encoded_value_to_string(&self, v: &EncodedValue) -> String {
+        let d = &self.disassembler;
+        let field_name = |i: FieldIndex| match d.dex.fields.get(i) {
+            Some(f) => format!("{}.{}", self.type_name(f.class_idx), self.string(f.name_idx)),
+            None => format!("field@{:#x}", i),
+        };
+        let type_name = |i: TypeIndex| self.type_name(i);
+
+        match v {
+            EncodedValue::Byte(x) => x.to_string(),
+            EncodedValue::Short(x) => x.to_string(),
+            EncodedValue::Char(x) => match std::char::from_u32(*x as u32) {
+                Some(c) => format!("{:?}", c),
+                None => format!("'\\u{:04x}'", x),
+            },
+            EncodedValue::Int(x) => x.to_string(),
+            EncodedValue::Long(x) => format!("{}L", x),
+            EncodedValue::Float(x) => format!("{:?}f", x),
+            EncodedValue::Double(x) => format!("{:?}", x),
+            EncodedValue::MethodType(x) => format!("method type@{:#x}", x),
+            EncodedValue::MethodHandle(x) => format!("method handle@{:#x}", x),
+            EncodedValue::String(x) => match d.dex.strings.get(*x) {
+                Some(s) => format!("{:?}", s),
+                None => format!("string@{:#x}", x),
+            },
+            EncodedValue::Type(x) => format!("{}.class", type_name(*x)),
+            EncodedValue::Field(x) | EncodedValue::Enum(x) => field_name(*x),
+            EncodedValue::Method(x) => match d.dex.methods.get(*x) {
+                Some(m) => format!("{}.{}", type_name(m.class_idx), self.string(m.name_idx)),
+                None => format!("method@{:#x}", x),
+            },
+            EncodedValue::Array(values) => {
+                let values = values.iter().map(|x| self.encoded_value_to_string(x)).collect::<Vec<String>>();
+                format!("{{{}}}", values.join(", "))
+            }
+            EncodedValue::Annotation(t, elements) => {
+                let elements = elements.iter()
+                    .map(|(name, x)| format!("{} = {}", self.string(*name), self.encoded_value_to_string(x)))
+                    .collect::<Vec<String>>();
+                format!("@{}({})", type_name(*t), elements.join(", "))
+            }
+            EncodedValue::Null => "null".to_string(),
+            EncodedValue::Boolean(x) => x.to_string(),
+        }
+    }
+}
+
+impl Display for ClassView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(source) = &self.source_file {
+            writeln!(f, "// {}", source)?;
+        }
+        if !self.package.is_empty() {
+            writeln!(f, "package {};", self.package)?;
+            writeln!(f)?;
+        }
+        writeln!(f, "{} {{", self.declaration)?;
+
+        for field in self.fields.iter() {
+            if let Some(flags) = &field.hiddenapi {
+                writeln!(f, "    // hiddenapi: {}", flags)?;
+            }
+            writeln!(f, "    {};", field.declaration)?;
+        }
+        for method in self.methods.iter() {
+            writeln!(f)?;
+            if let Some(flags) = &method.hiddenapi {
+                writeln!(f, "    // hiddenapi: {}", flags)?;
+            }
+            match &method.body {
+                Some(lines) => {
+                    writeln!(f, "    {} {{", method.declaration)?;
+                    for l in lines {
+                        writeln!(f, "        {}", l)?;
+                    }
+                    writeln!(f, "    }}")?;
+                }
+                None => writeln!(f, "    {};", method.declaration)?,
+            }
+        }
+        writeln!(f, "}}")
     }
 }
 
-fn get_class_access_level_string(x: u32) -> String {
+// Modifiers in the order the Java Language Specification recommends.
+fn get_class_modifiers(x: u32) -> Vec<String> {
     let access_levels: Vec<(u32, &str)> = vec![
         (ClassAccessLevel::Public     as u32, "public"),
-        (ClassAccessLevel::Private    as u32, "private"),
         (ClassAccessLevel::Protected  as u32, "protected"),
+        (ClassAccessLevel::Private    as u32, "private"),
+        (ClassAccessLevel::Abstract   as u32, "abstract"),
         (ClassAccessLevel::Static     as u32, "static"),
         (ClassAccessLevel::Final      as u32, "final"),
-        (ClassAccessLevel::Interface  as u32, "interface"),
-        (ClassAccessLevel::Abstract   as u32, "abstract"),
-        (ClassAccessLevel::Synthetic  as u32, ""),
-        (ClassAccessLevel::Annotation as u32, ""),
-        (ClassAccessLevel::Enum       as u32, "enum"),
     ];
 
-    return access_levels.iter()
-        .filter(|(access_level, _)| access_level & x >= 1)
-        .map(|(_, s)| *s)
-        .collect::<Vec<&str>>()
-        .join(" ");
+    // interfaces are implicitly abstract and enums implicitly final
+    let (keyword, implicit) = if x & ClassAccessLevel::Annotation as u32 != 0 {
+        ("@interface", ClassAccessLevel::Abstract as u32)
+    } else if x & ClassAccessLevel::Interface as u32 != 0 {
+        ("interface", ClassAccessLevel::Abstract as u32)
+    } else if x & ClassAccessLevel::Enum as u32 != 0 {
+        ("enum", ClassAccessLevel::Final as u32 | ClassAccessLevel::Abstract as u32)
+    } else {
+        ("class", 0)
+    };
+
+    let mut result = modifiers(&access_levels, x & !implicit);
+    result.push(keyword.to_string());
+    result
 }
 
-fn get_field_access_level_string(x: u32) -> String {
+fn get_field_modifiers(x: u32) -> Vec<String> {
     let access_levels: Vec<(u32, &str)> = vec![
         (FieldAccessLevel::Public    as u32, "public"),
-        (FieldAccessLevel::Private   as u32, "private"),
         (FieldAccessLevel::Protected as u32, "protected"),
+        (FieldAccessLevel::Private   as u32, "private"),
         (FieldAccessLevel::Static    as u32, "static"),
         (FieldAccessLevel::Final     as u32, "final"),
+        (FieldAccessLevel::Transient as u32, "transient"),
         (FieldAccessLevel::Volatile  as u32, "volatile"),
-        (FieldAccessLevel::Transient as u32, ""),
-        (FieldAccessLevel::Synthetic as u32, ""),
-        (FieldAccessLevel::Enum      as u32, "enum"),
     ];
 
-    return access_levels.iter()
-        .filter(|(access_level, _)| access_level & x >= 1)
-        .map(|(_, s)| *s)
-        .collect::<Vec<&str>>()
-        .join(" ");
+    modifiers(&access_levels, x)
 }
 
-fn get_method_access_level_string(x: u32) -> String {
+fn get_method_modifiers(x: u32) -> Vec<String> {
     let access_levels: Vec<(u32, &str)> = vec![
         (MethodAccessLevel::Public       as u32, "public"),
-        (MethodAccessLevel::Private      as u32, "private"),
         (MethodAccessLevel::Protected    as u32, "protected"),
+        (MethodAccessLevel::Private      as u32, "private"),
+        (MethodAccessLevel::Abstract     as u32, "abstract"),
         (MethodAccessLevel::Static       as u32, "static"),
         (MethodAccessLevel::Final        as u32, "final"),
-        (MethodAccessLevel::Synchronized as u32, ""),
-        (MethodAccessLevel::Bridge       as u32, ""),
-        (MethodAccessLevel::VarArgs      as u32, ""),
-        (MethodAccessLevel::Native       as u32, ""),
-        (MethodAccessLevel::Abstract     as u32, "abstract"),
-        (MethodAccessLevel::Strict       as u32, ""),
-        (MethodAccessLevel::Synthetic    as u32, ""),
-        (MethodAccessLevel::Constructor  as u32, ""),
-        (MethodAccessLevel::DeclaredSynchronized as u32, ""),
+        // only native methods use the plain flag, the rest set DeclaredSynchronized
+        (MethodAccessLevel::Synchronized as u32 | MethodAccessLevel::DeclaredSynchronized as u32, "synchronized"),
+        (MethodAccessLevel::Native       as u32, "native"),
+        (MethodAccessLevel::Strict       as u32, "strictfp"),
     ];
 
-    return access_levels.iter()
-        .filter(|(access_level, _)| access_level & x >= 1)
-        .map(|(_, s)| *s)
-        .collect::<Vec<&str>>()
-        .join(" ");
+    modifiers(&access_levels, x)
+}
+
+fn modifiers(access_levels: &[(u32, &str)], x: u32) -> Vec<String> {
+    access_levels.iter()
+        .filter(|(access_level, _)| access_level & x != 0)
+        .map(|(_, s)| s.to_string())
+        .collect()
 }
 
 pub fn get_type_descriptor_string(t: &TypeDescriptor) -> String {
@@ -161,4 +360,4 @@ pub fn get_type_descriptor_string(t: &TypeDescriptor) -> String {
             format!("{}[]", get_type_descriptor_string(b))
         },
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::analysis::is_payload;
+use crate::dex_types::*;
+use crate::filter::glob_to_regex;
+use crate::instructions::*;
+use crate::parallel;
+use crate::printer::get_type_descriptor_string;
+use crate::xrefs::signature_matches;
+
+// A query is a sequence of instruction patterns separated by `;` that must
+// match consecutive instructions:
+//
+//     const-string $r "AES"; invoke-static * Ljavax/crypto/Cipher;->getInstance
+//
+// Each pattern is a mnemonic glob followed by operand patterns in smali
+// order. Instructions may have more operands than the pattern lists.
+pub struct Query {
+    patterns: Vec<InstructionPattern>,
+}
+
+struct InstructionPattern {
+    mnemonic: Regex,
+    operands: Vec<OperandPattern>,
+}
+
+#[derive(Debug, PartialEq)]
+enum OperandPattern {
+    Any,
+    Register(Register),
+    // `$name` binds to a register the first time it is seen and must be
+    // the same register everywhere else in the query
+    Variable(String),
+    Literal(i64),
+    String(String),
+    Type(String),
+    // a field or method signature, possibly cut short before `:` or `(`
+    Member(String),
+}
+
+#[derive(Debug)]
+pub struct Match<'a> {
+    pub method: MethodIndex,
+    pub instructions: Vec<Instruction<'a>>,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Query, String> {
+        let mut patterns: Vec<InstructionPattern> = Vec::new();
+        for tokens in tokenize(query)? {
+            let mut tokens = tokens.into_iter();
+            let mnemonic = match tokens.next() {
+                Some(m) => m,
+                None => continue,
+            };
+            let mnemonic = Regex::new(&glob_to_regex(&mnemonic, "^")).map_err(|e| e.to_string())?;
+            let operands = tokens.map(|t| parse_operand(&t)).collect::<Vec<OperandPattern>>();
+            patterns.push(InstructionPattern { mnemonic, operands });
+        }
+
+        if patterns.is_empty() {
+            return Err("empty query".to_string());
+        }
+        Ok(Query { patterns })
+    }
+
+    pub fn search<'a>(&self, dex: &DexFile<'a>) -> Result<Vec<Match<'a>>, String> {
+        let found = parallel::map_methods(&dex.classes, |_, m| {
+            let mut result: Vec<Match> = Vec::new();
+            let code = match m.code_item()? {
+                Some(code) => code,
+                None => return Ok(result),
+            };
+
+            let instructions = code.instructions.iter()
+                .filter(|i| !is_payload(&i.kind))
+                .collect::<Vec<&Instruction>>();
+            for window in instructions.windows(self.patterns.len()) {
+                if self.matches(dex, window) {
+                    result.push(Match {
+                        method: m.method_idx,
+                        instructions: window.iter().map(|i| (*i).clone()).collect(),
+                    });
+                }
+            }
+            Ok(result)
+        })?;
+        Ok(found.into_iter().flatten().collect())
+    }
+
+    fn matches(&self, dex: &DexFile, instructions: &[&Instruction]) -> bool {
+        let mut bindings: HashMap<&str, Register> = HashMap::new();
+        for (pattern, i) in self.patterns.iter().zip(instructions.iter()) {
+            if !pattern.mnemonic.is_match(i.kind.mnemonic()) {
+                return false;
+            }
+
+            let operands = i.kind.operands();
+            if pattern.operands.len() > operands.len() {
+                return false;
+            }
+            for (p, o) in pattern.operands.iter().zip(operands.iter()) {
+                if !operand_matches(dex, p, o, &mut bindings) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+fn operand_matches<'a>(dex: &DexFile, pattern: &'a OperandPattern, operand: &Operand, bindings: &mut HashMap<&'a str, Register>) -> bool {
+    let registers = match operand {
+        Operand::Register(r) => vec![*r],
+        Operand::RegisterList(list) => list.clone(),
+        Operand::RegisterRange(first, count) => register_range(*first, *count),
+        _ => Vec::new(),
+    };
+
+    match (pattern, operand) {
+        (OperandPattern::Any, _) => true,
+        (OperandPattern::Register(r), _) => registers.contains(r),
+        (OperandPattern::Variable(name), _) if !registers.is_empty() => {
+            match bindings.get(name.as_str()) {
+                Some(r) => registers.contains(r),
+                None => {
+                    bindings.insert(name, registers[0]);
+                    true
+                }
+            }
+        }
+        (OperandPattern::Literal(x), Operand::Literal(y)) => x == y,
+        (OperandPattern::Literal(x), Operand::Offset(y)) => *x == *y as i64,
+        (OperandPattern::String(s), Operand::String(idx)) => dex.strings.get(*idx) == Some(s),
+        (OperandPattern::Type(t), Operand::Type(idx)) => match dex.types.get(*idx) {
+            Some(x) => x.raw == *t || get_type_descriptor_string(&x.parsed) == *t,
+            None => false,
+        },
+        (OperandPattern::Member(m), Operand::Field(idx)) => signature_matches(&dex.field_signature(*idx), m, ':'),
+        (OperandPattern::Member(m), Operand::Method(idx)) => signature_matches(&dex.method_signature(*idx), m, '('),
+        _ => false,
+    }
+}
+
+fn parse_operand(token: &str) -> OperandPattern {
+    if token == "*" {
+        return OperandPattern::Any;
+    }
+    if let Some(s) = token.strip_prefix('"') {
+        return OperandPattern::String(s.to_string());
+    }
+    if let Some(name) = token.strip_prefix('$') {
+        return OperandPattern::Variable(name.to_string());
+    }
+    if let Some(r) = token.strip_prefix('v').and_then(|r| r.parse::<Register>().ok()) {
+        return OperandPattern::Register(r);
+    }
+    if token.contains("->") {
+        return OperandPattern::Member(token.to_string());
+    }
+    match parse_literal(token) {
+        Some(x) => OperandPattern::Literal(x),
+        None => OperandPattern::Type(token.to_string()),
+    }
+}
+
+fn parse_literal(token: &str) -> Option<i64> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, token),
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => digits.parse::<i64>().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+// Splits a query into patterns and each pattern into tokens. Commas count
+// as whitespace so operands can be written the way smali prints them.
+// String tokens keep their opening quote to tell them apart.
+fn tokenize(query: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut patterns: Vec<Vec<String>> = vec![Vec::new()];
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => patterns.push(Vec::new()),
+            ',' => {}
+            c if c.is_whitespace() => {}
+            '"' => {
+                let mut s = String::from("\"");
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => s.push('\n'),
+                            Some('t') => s.push('\t'),
+                            Some(x) => s.push(x),
+                            None => return Err("unterminated string".to_string()),
+                        },
+                        Some(x) => s.push(x),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                patterns.last_mut().unwrap().push(s);
+            }
+            c => {
+                let mut token = c.to_string();
+                while let Some(x) = chars.peek() {
+                    if x.is_whitespace() || *x == ',' || (*x == ';' && !in_class_descriptor(&token)) {
+                        break;
+                    }
+                    token.push(chars.next().unwrap());
+                }
+                patterns.last_mut().unwrap().push(token);
+            }
+        }
+    }
+    Ok(patterns)
+}
+
+// Whether a `;` after `token` closes a class descriptor such as
+// `Ljava/lang/String;` rather than ending the pattern.
+fn in_class_descriptor(token: &str) -> bool {
+    let start = token.rfind(&[';', '(', ')', ':', '>'][..]);
+    if let Some(i) = start {
+        // a member name follows `->`
+        if token[i..].starts_with('>') {
+            return false;
+        }
+    }
+    let segment = match start {
+        Some(i) => &token[i + 1..],
+        None => token,
+    };
+    segment.trim_start_matches('[').starts_with('L')
+}
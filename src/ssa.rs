@@ -0,0 +1,595 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fmt::Display;
+
+use crate::analysis::{control_flow_graph, dominators, dominance_frontiers, is_invoke, Graph};
+use crate::dex_types::CodeItem;
+use crate::instructions::*;
+use serde::Serialize;
+
+// Pseudo register holding the value produced by an invoke-* or
+// filled-new-array, which the following move-result* reads.
+pub const RESULT_REGISTER: Register = Register::MAX;
+
+pub type ValueId = usize;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Definition {
+    Parameter,
+    Uninitialized,
+    Phi(usize),
+    Instruction(usize),
+    CaughtException(usize),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Value {
+    pub register: Register,
+    pub version: usize,
+    pub definition: Definition,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Phi {
+    pub register: Register,
+    pub dest: ValueId,
+    pub operands: Vec<(usize, ValueId)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Statement<'a> {
+    pub instruction: Instruction<'a>,
+    pub defs: Vec<ValueId>,
+    pub uses: Vec<ValueId>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SsaBlock<'a> {
+    pub starting_addr: usize,
+    pub phis: Vec<Phi>,
+    pub statements: Vec<Statement<'a>>,
+}
+
+// Block 0 is a synthetic entry that defines the parameters; block `i + 1`
+// corresponds to block `i` of `analysis::control_flow_graph`.
+#[derive(Debug, Serialize)]
+pub struct SsaMethod<'a> {
+    pub graph: Graph<SsaBlock<'a>>,
+    pub values: Vec<Value>,
+    pub parameters: Vec<ValueId>,
+}
+
+impl SsaMethod<'_> {
+    pub fn value_name(&self, id: ValueId) -> String {
+        let value = &self.values[id];
+        match value.register {
+            RESULT_REGISTER => format!("result_{}", value.version),
+            r => format!("v{}_{}", r, value.version),
+        }
+    }
+}
+
+pub fn build_ssa<'a>(code: &CodeItem<'a>) -> SsaMethod<'a> {
+    let cfg = control_flow_graph(code);
+
+    let entry_addr = cfg.nodes.first().map(|b| b.starting_addr).unwrap_or(code.addr as usize + 16);
+    let mut nodes = vec![SsaBlock { starting_addr: entry_addr, phis: Vec::new(), statements: Vec::new() }];
+    for block in cfg.nodes.iter() {
+        let statements = block.instructions.iter()
+            .map(|i| Statement { instruction: i.clone(), defs: Vec::new(), uses: Vec::new() })
+            .collect();
+        nodes.push(SsaBlock { starting_addr: block.starting_addr, phis: Vec::new(), statements });
+    }
+
+    let mut edges: Vec<(usize, usize)> = cfg.edges.iter().map(|(a, b)| (a + 1, b + 1)).collect();
+    if !cfg.nodes.is_empty() {
+        edges.insert(0, (0, 1));
+    }
+    let exception_edges = cfg.exception_edges.iter().map(|(a, b)| (a + 1, b + 1)).collect();
+    let mut graph = Graph::new(nodes, edges, exception_edges);
+
+    let idom = dominators(&graph, 0);
+    let frontiers = dominance_frontiers(&graph, &idom);
+
+    let first_parameter = code.registers_size.saturating_sub(code.ins_size) as Register;
+    let parameter_registers = (first_parameter..code.registers_size as Register).collect::<Vec<Register>>();
+
+    insert_phis(&mut graph, &frontiers, &parameter_registers);
+
+    let mut renamer = Renamer {
+        values: Vec::new(),
+        counters: HashMap::new(),
+        stacks: HashMap::new(),
+        uninitialized: HashMap::new(),
+    };
+
+    let mut parameters: Vec<ValueId> = Vec::new();
+    for r in parameter_registers.iter() {
+        let id = renamer.define(*r, Definition::Parameter);
+        parameters.push(id);
+    }
+
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); graph.nodes.len()];
+    for (node, dominator) in idom.iter().enumerate() {
+        if let Some(d) = dominator {
+            if *d != node {
+                children[*d].push(node);
+            }
+        }
+    }
+
+    // blocks unreachable from the entry are renamed on their own
+    let mut roots = vec![0];
+    roots.extend((1..graph.nodes.len()).filter(|b| idom[*b].is_none()));
+    for root in roots {
+        renamer.rename(&mut graph, &children, root);
+    }
+
+    SsaMethod {
+        graph,
+        values: renamer.values,
+        parameters,
+    }
+}
+
+// Semi-pruned placement: only registers that are live across a block
+// boundary get phis.
+fn insert_phis(graph: &mut Graph<SsaBlock>, frontiers: &[Vec<usize>], parameter_registers: &[Register]) {
+    let mut def_blocks: HashMap<Register, Vec<usize>> = HashMap::new();
+    let mut globals: BTreeSet<Register> = BTreeSet::new();
+
+    for r in parameter_registers.iter() {
+        def_blocks.entry(*r).or_default().push(0);
+    }
+
+    for (b, block) in graph.nodes.iter().enumerate() {
+        let mut killed: HashSet<Register> = HashSet::new();
+        for s in block.statements.iter() {
+            for u in statement_uses(&s.instruction.kind) {
+                if !killed.contains(&u) {
+                    globals.insert(u);
+                }
+            }
+            for d in statement_defs(&s.instruction.kind) {
+                killed.insert(d);
+                let blocks = def_blocks.entry(d).or_default();
+                if !blocks.contains(&b) {
+                    blocks.push(b);
+                }
+            }
+        }
+    }
+
+    for r in globals {
+        let defined_in = def_blocks.get(&r).cloned().unwrap_or_default();
+        let mut worklist = defined_in.clone();
+        let mut has_phi: HashSet<usize> = HashSet::new();
+        while let Some(b) = worklist.pop() {
+            for f in frontiers[b].iter() {
+                if has_phi.insert(*f) {
+                    graph.nodes[*f].phis.push(Phi { register: r, dest: 0, operands: Vec::new() });
+                    if !defined_in.contains(f) {
+                        worklist.push(*f);
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Renamer {
+    values: Vec<Value>,
+    counters: HashMap<Register, usize>,
+    stacks: HashMap<Register, Vec<ValueId>>,
+    uninitialized: HashMap<Register, ValueId>,
+}
+
+enum Visit {
+    Enter(usize),
+    Exit(Vec<Register>),
+    // take back, and later restore, the definitions of a block's throwing
+    // instruction around the handlers it dominates
+    Hide(Vec<Register>),
+    Restore(Vec<ValueId>),
+}
+
+impl Renamer {
+    fn new_value(&mut self, register: Register, definition: Definition) -> ValueId {
+        let counter = self.counters.entry(register).or_insert(0);
+        let version = *counter;
+        *counter += 1;
+        self.values.push(Value { register, version, definition });
+        self.values.len() - 1
+    }
+
+    fn define(&mut self, register: Register, definition: Definition) -> ValueId {
+        let id = self.new_value(register, definition);
+        self.stacks.entry(register).or_default().push(id);
+        id
+    }
+
+    fn lookup(&mut self, register: Register) -> ValueId {
+        if let Some(id) = self.stacks.get(&register).and_then(|s| s.last()) {
+            return *id;
+        }
+        if let Some(id) = self.uninitialized.get(&register) {
+            return *id;
+        }
+        let id = self.new_value(register, Definition::Uninitialized);
+        self.uninitialized.insert(register, id);
+        id
+    }
+
+    fn fill_phis(&mut self, graph: &mut Graph<SsaBlock>, predecessor: usize, successors: &[usize]) {
+        for s in successors.iter() {
+            for p in 0..graph.nodes[*s].phis.len() {
+                if graph.nodes[*s].phis[p].operands.iter().any(|(b, _)| *b == predecessor) {
+                    continue;
+                }
+                let value = self.lookup(graph.nodes[*s].phis[p].register);
+                graph.nodes[*s].phis[p].operands.push((predecessor, value));
+            }
+        }
+    }
+
+    // Walks the dominator tree without recursion; huge methods would
+    // otherwise blow the stack.
+    fn rename(&mut self, graph: &mut Graph<SsaBlock>, children: &[Vec<usize>], root: usize) {
+        let mut work = vec![Visit::Enter(root)];
+        while let Some(visit) = work.pop() {
+            let b = match visit {
+                Visit::Enter(b) => b,
+                Visit::Exit(pushed) | Visit::Hide(pushed) => {
+                    for r in pushed {
+                        self.stacks.get_mut(&r).unwrap().pop();
+                    }
+                    continue;
+                }
+                Visit::Restore(ids) => {
+                    for id in ids {
+                        self.stacks.entry(self.values[id].register).or_default().push(id);
+                    }
+                    continue;
+                }
+            };
+
+            let mut pushed: Vec<Register> = Vec::new();
+            for p in 0..graph.nodes[b].phis.len() {
+                let register = graph.nodes[b].phis[p].register;
+                graph.nodes[b].phis[p].dest = self.define(register, Definition::Phi(b));
+                pushed.push(register);
+            }
+
+            let normal_successors = graph.edges.iter()
+                .filter(|(x, _)| *x == b)
+                .map(|(_, y)| *y)
+                .collect::<Vec<usize>>();
+            let exception_successors = graph.exception_successors(b).to_vec();
+
+            let count = graph.nodes[b].statements.len();
+            for s in 0..count {
+                // a throwing instruction transfers control before its own
+                // definitions happen
+                if s == count - 1 {
+                    self.fill_phis(graph, b, &exception_successors);
+                }
+
+                let kind = graph.nodes[b].statements[s].instruction.kind.clone();
+                let addr = graph.nodes[b].statements[s].instruction.addr;
+
+                let uses = statement_uses(&kind).into_iter()
+                    .map(|r| self.lookup(r))
+                    .collect::<Vec<ValueId>>();
+
+                let definition = match kind {
+                    InstructionKind::MoveException(_) => Definition::CaughtException(addr),
+                    _ => Definition::Instruction(addr),
+                };
+                let mut defs: Vec<ValueId> = Vec::new();
+                for r in statement_defs(&kind) {
+                    defs.push(self.define(r, definition.clone()));
+                    pushed.push(r);
+                }
+
+                graph.nodes[b].statements[s].uses = uses;
+                graph.nodes[b].statements[s].defs = defs;
+            }
+            if count == 0 {
+                self.fill_phis(graph, b, &exception_successors);
+            }
+            self.fill_phis(graph, b, &normal_successors);
+
+            work.push(Visit::Exit(pushed));
+            let (handlers, others): (Vec<usize>, Vec<usize>) = children[b].iter()
+                .partition(|c| exception_successors.contains(c) && !normal_successors.contains(c));
+            for c in others.iter().rev() {
+                work.push(Visit::Enter(*c));
+            }
+            let thrown = graph.nodes[b].statements.last().map(|s| s.defs.clone()).unwrap_or_default();
+            if !handlers.is_empty() && !thrown.is_empty() {
+                let registers = thrown.iter().map(|id| self.values[*id].register).collect();
+                work.push(Visit::Restore(thrown));
+                for c in handlers.iter().rev() {
+                    work.push(Visit::Enter(*c));
+                }
+                work.push(Visit::Hide(registers));
+            } else {
+                for c in handlers.iter().rev() {
+                    work.push(Visit::Enter(*c));
+                }
+            }
+        }
+    }
+}
+
+fn produces_result(kind: &InstructionKind) -> bool {
+    match kind {
+          InstructionKind::FilledNewArray(..)
+        | InstructionKind::FilledNewArrayRange(..)
+        | InstructionKind::InvokePolymorphic(..)
+        | InstructionKind::InvokePolymorphicRange(..)
+        | InstructionKind::InvokeCustom(..)
+        | InstructionKind::InvokeCustomRange(..) => true,
+        k => is_invoke(k),
+    }
+}
+
+fn statement_defs(kind: &InstructionKind) -> Vec<Register> {
+    let mut defs = kind.defined_registers();
+    if produces_result(kind) {
+        defs.push(RESULT_REGISTER);
+    }
+    defs
+}
+
+fn statement_uses(kind: &InstructionKind) -> Vec<Register> {
+    let mut uses = kind.used_registers();
+    match kind {
+          InstructionKind::MoveResult(_)
+        | InstructionKind::MoveResultWide(_)
+        | InstructionKind::MoveResultObject(_) => uses.push(RESULT_REGISTER),
+        _ => {}
+    }
+    uses
+}
+
+// Literal and index operands, which are not renamed.
+fn immediate_operand(kind: &InstructionKind) -> Option<String> {
+    match kind {
+          InstructionKind::Const4(_, x)
+        | InstructionKind::Const16(_, x)
+        | InstructionKind::Const(_, x)
+        | InstructionKind::ConstHigh16(_, x)
+        | InstructionKind::AddIntLit16(_, _, x)
+        | InstructionKind::RSubIntLit16(_, _, x)
+        | InstructionKind::MulIntLit16(_, _, x)
+        | InstructionKind::DivIntLit16(_, _, x)
+        | InstructionKind::RemIntLit16(_, _, x)
+        | InstructionKind::AndIntLit16(_, _, x)
+        | InstructionKind::OrIntLit16(_, _, x)
+        | InstructionKind::XorIntLit16(_, _, x)
+        | InstructionKind::AddIntLit8(_, _, x)
+        | InstructionKind::RSubIntLit8(_, _, x)
+        | InstructionKind::MulIntLit8(_, _, x)
+        | InstructionKind::DivIntLit8(_, _, x)
+        | InstructionKind::RemIntLit8(_, _, x)
+        | InstructionKind::AndIntLit8(_, _, x)
+        | InstructionKind::OrIntLit8(_, _, x)
+        | InstructionKind::XorIntLit8(_, _, x)
+        | InstructionKind::ShlIntLit8(_, _, x)
+        | InstructionKind::ShrIntLit8(_, _, x)
+        | InstructionKind::UShrIntLit8(_, _, x) => Some(format!("{:#x}", x)),
+          InstructionKind::ConstWide16(_, x)
+        | InstructionKind::ConstWide32(_, x)
+        | InstructionKind::ConstWide(_, x)
+        | InstructionKind::ConstWideHigh16(_, x) => Some(format!("{:#x}", x)),
+          InstructionKind::ConstString(_, x)
+        | InstructionKind::ConstStringJumbo(_, x) => Some(format!("string@{:#x}", x)),
+          InstructionKind::ConstClass(_, x)
+        | InstructionKind::CheckCast(_, x)
+        | InstructionKind::InstanceOf(_, _, x)
+        | InstructionKind::NewInstance(_, x)
+        | InstructionKind::NewArray(_, _, x)
+        | InstructionKind::FilledNewArray(_, x)
+        | InstructionKind::FilledNewArrayRange(_, _, x) => Some(format!("type@{:#x}", x)),
+          InstructionKind::InvokeVirtual(_, x)
+        | InstructionKind::InvokeSuper(_, x)
+        | InstructionKind::InvokeDirect(_, x)
+        | InstructionKind::InvokeStatic(_, x)
+        | InstructionKind::InvokeInterface(_, x)
+        | InstructionKind::InvokeVirtualRange(_, _, x)
+        | InstructionKind::InvokeSuperRange(_, _, x)
+        | InstructionKind::InvokeDirectRange(_, _, x)
+        | InstructionKind::InvokeStaticRange(_, _, x)
+        | InstructionKind::InvokeInterfaceRange(_, _, x) => Some(format!("method@{:#x}", x)),
+          InstructionKind::IGet(_, _, x)
+        | InstructionKind::IGetWide(_, _, x)
+        | InstructionKind::IGetObject(_, _, x)
+        | InstructionKind::IGetBoolean(_, _, x)
+        | InstructionKind::IGetByte(_, _, x)
+        | InstructionKind::IGetChar(_, _, x)
+        | InstructionKind::IGetShort(_, _, x)
+        | InstructionKind::IPut(_, _, x)
+        | InstructionKind::IPutWide(_, _, x)
+        | InstructionKind::IPutObject(_, _, x)
+        | InstructionKind::IPutBoolean(_, _, x)
+        | InstructionKind::IPutByte(_, _, x)
+        | InstructionKind::IPutChar(_, _, x)
+        | InstructionKind::IPutShort(_, _, x)
+        | InstructionKind::SGet(_, x)
+        | InstructionKind::SGetWide(_, x)
+        | InstructionKind::SGetObject(_, x)
+        | InstructionKind::SGetBoolean(_, x)
+        | InstructionKind::SGetByte(_, x)
+        | InstructionKind::SGetChar(_, x)
+        | InstructionKind::SGetShort(_, x)
+        | InstructionKind::SPut(_, x)
+        | InstructionKind::SPutWide(_, x)
+        | InstructionKind::SPutObject(_, x)
+        | InstructionKind::SPutBoolean(_, x)
+        | InstructionKind::SPutByte(_, x)
+        | InstructionKind::SPutChar(_, x)
+        | InstructionKind::SPutShort(_, x) => Some(format!("field@{:#x}", x)),
+        _ => None,
+    }
+}
+
+impl Display for SsaMethod<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = |ids: &[ValueId]| ids.iter().map(|id| self.value_name(*id)).collect::<Vec<String>>().join(", ");
+
+        for (b, block) in self.graph.nodes.iter().enumerate() {
+            let predecessors = self.graph.predecessors(b).iter().map(|x| format!("b{}", x)).collect::<Vec<String>>();
+            let successors = self.graph.successors(b).iter().map(|x| format!("b{}", x)).collect::<Vec<String>>();
+            writeln!(f, "  b{} {:#x} preds [{}] succs [{}]", b, block.starting_addr, predecessors.join(", "), successors.join(", "))?;
+
+            if b == 0 && !self.parameters.is_empty() {
+                writeln!(f, "    {} = parameters", names(&self.parameters))?;
+            }
+
+            for phi in block.phis.iter() {
+                let operands = phi.operands.iter()
+                    .map(|(p, v)| format!("{} b{}", self.value_name(*v), p))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                writeln!(f, "    {} = phi({})", self.value_name(phi.dest), operands)?;
+            }
+
+            for s in block.statements.iter() {
+                let mut operands = names(&s.uses);
+                if let Some(x) = immediate_operand(&s.instruction.kind) {
+                    if !operands.is_empty() {
+                        operands.push_str(", ");
+                    }
+                    operands.push_str(&x);
+                }
+                let mut operation = format!("{} {}", s.instruction.kind.mnemonic(), operands);
+                let uninitialized = s.uses.iter()
+                    .filter(|id| self.values[**id].definition == Definition::Uninitialized)
+                    .map(|id| self.value_name(*id))
+                    .collect::<Vec<String>>();
+                if !uninitialized.is_empty() {
+                    operation = format!("{:<40} ; reads uninitialized {}", operation, uninitialized.join(", "));
+                }
+                if s.defs.is_empty() {
+                    writeln!(f, "    {:#x} {}", s.instruction.addr, operation.trim_end())?;
+                } else {
+                    writeln!(f, "    {:#x} {} = {}", s.instruction.addr, names(&s.defs), operation.trim_end())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::tests::{catch_all, code_item, code_units};
+
+    fn phi_operands(method: &SsaMethod, phi: &Phi) -> Vec<(usize, String)> {
+        phi.operands.iter().map(|(b, v)| (*b, method.value_name(*v))).collect()
+    }
+
+    fn uses(method: &SsaMethod, block: usize, statement: usize) -> Vec<String> {
+        method.graph.nodes[block].statements[statement].uses.iter().map(|v| method.value_name(*v)).collect()
+    }
+
+    #[test]
+    fn diamond() {
+        let bytes = code_units(&[
+            0x0138, 4,      // if-eqz v1, +4
+            0x1012,         // const/4 v0, 1
+            0x0228,         // goto +2
+            0x2012,         // const/4 v0, 2
+            0x000f,         // return v0
+        ]);
+        let code = code_item(&bytes, 2, 1, Vec::new());
+        let method = build_ssa(&code);
+
+        assert_eq!(method.graph.nodes.len(), 5);
+        assert_eq!(method.parameters.iter().map(|v| method.value_name(*v)).collect::<Vec<String>>(), vec!["v1_0"]);
+        for b in 0..4 {
+            assert!(method.graph.nodes[b].phis.is_empty());
+        }
+
+        let phis = &method.graph.nodes[4].phis;
+        assert_eq!(phis.len(), 1);
+        assert_eq!(method.value_name(phis[0].dest), "v0_2");
+        assert_eq!(phi_operands(&method, &phis[0]), vec![(2, "v0_0".to_string()), (3, "v0_1".to_string())]);
+        assert_eq!(method.values[phis[0].dest].definition, Definition::Phi(4));
+        assert_eq!(uses(&method, 4, 0), vec!["v0_2"]);
+    }
+
+    #[test]
+    fn loop_header() {
+        let bytes = code_units(&[
+            0x0012,         // const/4 v0, 0
+            0x013d, 6,      // if-lez v1, +6
+            0x10b0,         // add-int/2addr v0, v1
+            0x01d8, 0xff01, // add-int/lit8 v1, v1, -1
+            0xfb28,         // goto -5
+            0x000f,         // return v0
+        ]);
+        let code = code_item(&bytes, 2, 1, Vec::new());
+        let method = build_ssa(&code);
+
+        // b1 const, b2 the if-lez header, b3 the body, b4 the return
+        assert_eq!(method.graph.predecessors(2), &[1, 3]);
+        let phis = &method.graph.nodes[2].phis;
+        let mut registers = phis.iter().map(|p| p.register).collect::<Vec<Register>>();
+        registers.sort();
+        assert_eq!(registers, vec![0, 1]);
+
+        for phi in phis.iter() {
+            let entry = phi.operands.iter().find(|(b, _)| *b == 1).unwrap().1;
+            let back = phi.operands.iter().find(|(b, _)| *b == 3).unwrap().1;
+            assert_eq!(phi.operands.len(), 2);
+            match phi.register {
+                0 => assert_eq!(method.values[entry].definition, Definition::Instruction(0)),
+                _ => assert_eq!(method.values[entry].definition, Definition::Parameter),
+            }
+            // the value carried around the loop is the one the body defined
+            let body_def = if phi.register == 0 { 6 } else { 8 };
+            assert_eq!(method.values[back].definition, Definition::Instruction(body_def));
+        }
+
+        // the body and the exit read the header's phis
+        let phi_name = |r: Register| method.value_name(phis.iter().find(|p| p.register == r).unwrap().dest);
+        assert_eq!(uses(&method, 3, 0), vec![phi_name(0), phi_name(1)]);
+        assert_eq!(uses(&method, 4, 0), vec![phi_name(0)]);
+    }
+
+    #[test]
+    fn try_catch() {
+        let bytes = code_units(&[
+            0x1012,         // const/4 v0, 1
+            0x20b3,         // div-int/2addr v0, v2
+            0x0428,         // goto +4
+            0x010d,         // move-exception v1
+            0x00d8, 0x0100, // add-int/lit8 v0, v0, 1
+            0x000f,         // return v0
+        ]);
+        let code = code_item(&bytes, 3, 1, vec![catch_all(1, 2, 3)]);
+        let method = build_ssa(&code);
+
+        // b1 const, b2 div, b3 goto, b4 the handler, b5 the return
+        assert_eq!(method.graph.exception_successors(2), &[4]);
+
+        let handler = &method.graph.nodes[4];
+        assert_eq!(method.values[handler.statements[0].defs[0]].definition, Definition::CaughtException(6));
+        assert_eq!(method.value_name(handler.statements[0].defs[0]), "v1_0");
+        // the div threw before writing v0, so the handler sees the constant
+        assert_eq!(uses(&method, 4, 1), vec!["v0_0"]);
+
+        let phis = &method.graph.nodes[5].phis;
+        assert_eq!(phis.len(), 1);
+        assert_eq!(phis[0].register, 0);
+        let mut operands = phis[0].operands.iter()
+            .map(|(b, v)| (*b, method.values[*v].definition.clone()))
+            .collect::<Vec<(usize, Definition)>>();
+        operands.sort_by_key(|(b, _)| *b);
+        assert_eq!(operands, vec![(3, Definition::Instruction(2)), (4, Definition::Instruction(8))]);
+    }
+}
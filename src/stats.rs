@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::analysis::{control_flow_graph, is_payload};
+use crate::dex_types::*;
+use crate::parallel;
+
+// Method, field and type indices are 16 bits wide in most instructions,
+// which is what forces large apps into multidex.
+const REFERENCE_LIMIT: usize = 65536;
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub classes: usize,
+    pub methods: usize,
+    pub fields: usize,
+    pub strings: usize,
+    pub types: usize,
+    pub methods_with_code: usize,
+    pub instructions: usize,
+    pub average_complexity: f64,
+    pub limits: Vec<ReferenceLimit>,
+    pub largest_by_instructions: Vec<MethodStats>,
+    pub largest_by_registers: Vec<MethodStats>,
+    pub most_complex: Vec<MethodStats>,
+    // most frequent first
+    pub opcodes: Vec<OpcodeCount>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReferenceLimit {
+    pub kind: &'static str,
+    pub used: usize,
+    pub limit: usize,
+    pub percent: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MethodStats {
+    pub method: String,
+    // in 16-bit code units
+    pub instructions_size: u32,
+    pub registers_size: u16,
+    pub complexity: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OpcodeCount {
+    pub mnemonic: &'static str,
+    pub count: usize,
+}
+
+// `top` is how many methods to keep in each of the largest/most complex lists.
+pub fn compute_stats(dex: &DexFile, top: usize) -> Result<Stats, String> {
+    let mut methods: Vec<MethodStats> = Vec::new();
+    let mut opcodes: BTreeMap<&'static str, usize> = BTreeMap::new();
+    let mut instructions = 0;
+
+    let per_method = parallel::map_methods(&dex.classes, |_, m| {
+        Ok(m.code_item()?.map(|code| {
+            let stats = MethodStats {
+                method: dex.method_signature(m.method_idx),
+                instructions_size: code.instructions_size,
+                registers_size: code.registers_size,
+                complexity: cyclomatic_complexity(code),
+            };
+            (stats, code)
+        }))
+    })?;
+    for (stats, code) in per_method.into_iter().flatten() {
+        for i in code.instructions.iter().filter(|i| !is_payload(&i.kind)) {
+            *opcodes.entry(i.kind.mnemonic()).or_insert(0) += 1;
+            instructions += 1;
+        }
+        methods.push(stats);
+    }
+
+    let average_complexity = if methods.is_empty() {
+        0.0
+    } else {
+        methods.iter().map(|m| m.complexity).sum::<usize>() as f64 / methods.len() as f64
+    };
+    let largest = |key: fn(&MethodStats) -> usize| {
+        let mut sorted = methods.clone();
+        sorted.sort_by(|a, b| key(b).cmp(&key(a)).then_with(|| a.method.cmp(&b.method)));
+        sorted.truncate(top);
+        sorted
+    };
+
+    let mut opcodes = opcodes.into_iter()
+        .map(|(mnemonic, count)| OpcodeCount { mnemonic, count })
+        .collect::<Vec<OpcodeCount>>();
+    opcodes.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.mnemonic.cmp(b.mnemonic)));
+
+    let limit = |kind, used: usize| ReferenceLimit {
+        kind,
+        used,
+        limit: REFERENCE_LIMIT,
+        percent: used as f64 * 100.0 / REFERENCE_LIMIT as f64,
+    };
+
+    Ok(Stats {
+        classes: dex.classes.len(),
+        methods: dex.methods.len(),
+        fields: dex.fields.len(),
+        strings: dex.strings.len(),
+        types: dex.types.len(),
+        methods_with_code: methods.len(),
+        instructions,
+        average_complexity,
+        limits: vec![
+            limit("method", dex.methods.len()),
+            limit("field", dex.fields.len()),
+            limit("type", dex.types.len()),
+        ],
+        largest_by_instructions: largest(|m| m.instructions_size as usize),
+        largest_by_registers: largest(|m| m.registers_size as usize),
+        most_complex: largest(|m| m.complexity),
+        opcodes,
+    })
+}
+
+// One more than the number of decisions: every block with n successors,
+// catch handlers included, adds n - 1 paths through the method.
+pub fn cyclomatic_complexity(code: &CodeItem) -> usize {
+    let graph = control_flow_graph(code);
+    let decisions = (0..graph.nodes.len())
+        .map(|n| graph.successors(n).len().saturating_sub(1))
+        .sum::<usize>();
+    decisions + 1
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "classes            {}", self.classes)?;
+        writeln!(f, "methods            {}", self.methods)?;
+        writeln!(f, "fields             {}", self.fields)?;
+        writeln!(f, "strings            {}", self.strings)?;
+        writeln!(f, "types              {}", self.types)?;
+        writeln!(f, "methods with code  {}", self.methods_with_code)?;
+        writeln!(f, "instructions       {}", self.instructions)?;
+        writeln!(f, "avg complexity     {:.2}", self.average_complexity)?;
+        for l in self.limits.iter() {
+            let label = format!("{} references", l.kind);
+            writeln!(f, "{:<19}{} / {} ({:.1}%)", label, l.used, l.limit, l.percent)?;
+        }
+
+        let lists = [
+            ("largest methods by code units", &self.largest_by_instructions),
+            ("largest methods by registers", &self.largest_by_registers),
+            ("most complex methods", &self.most_complex),
+        ];
+        for (title, methods) in lists.iter() {
+            writeln!(f)?;
+            writeln!(f, "{}", title)?;
+            for m in methods.iter() {
+                writeln!(f, "    {:>6} units {:>4} registers {:>4} complexity  {}", m.instructions_size, m.registers_size, m.complexity, m.method)?;
+            }
+        }
+
+        writeln!(f)?;
+        writeln!(f, "opcodes")?;
+        for o in self.opcodes.iter() {
+            writeln!(f, "    {:<24}{}", o.mnemonic, o.count)?;
+        }
+        Ok(())
+    }
+}
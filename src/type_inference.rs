@@ -0,0 +1,790 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fmt::Display;
+
+use crate::analysis::control_flow_graph;
+use crate::dex_parser::parse_type_descriptor;
+use crate::dex_types::*;
+use crate::instructions::*;
+use crate::printer::get_type_descriptor_string;
+
+const OBJECT: &str = "Ljava/lang/Object;";
+const THROWABLE: &str = "Ljava/lang/Throwable;";
+const METHOD_HANDLE: &str = "Ljava/lang/invoke/MethodHandle;";
+const METHOD_TYPE: &str = "Ljava/lang/invoke/MethodType;";
+
+// The register type lattice used by the Dalvik verifier, minus the precise
+// constant ranges. References are kept as raw descriptors.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegisterType {
+    Undefined,
+    Conflict,
+    Zero,
+    Constant,
+    ConstantLo,
+    ConstantHi,
+    Boolean,
+    Byte,
+    Short,
+    Char,
+    Integer,
+    Float,
+    LongLo,
+    LongHi,
+    DoubleLo,
+    DoubleHi,
+    Reference(String),
+    UninitializedThis(String),
+    Uninitialized(String, usize),
+}
+
+impl RegisterType {
+    fn from_descriptor(descriptor: &str) -> (RegisterType, Option<RegisterType>) {
+        match descriptor {
+            "Z" => (RegisterType::Boolean, None),
+            "B" => (RegisterType::Byte, None),
+            "S" => (RegisterType::Short, None),
+            "C" => (RegisterType::Char, None),
+            "I" => (RegisterType::Integer, None),
+            "F" => (RegisterType::Float, None),
+            "J" => (RegisterType::LongLo, Some(RegisterType::LongHi)),
+            "D" => (RegisterType::DoubleLo, Some(RegisterType::DoubleHi)),
+            "V" | "" => (RegisterType::Conflict, None),
+            d => (RegisterType::Reference(d.to_string()), None),
+        }
+    }
+
+    pub fn is_reference(&self) -> bool {
+        matches!(self, RegisterType::Reference(_) | RegisterType::Zero)
+    }
+
+    fn is_integral(&self) -> bool {
+        matches!(self,
+              RegisterType::Zero
+            | RegisterType::Constant
+            | RegisterType::Boolean
+            | RegisterType::Byte
+            | RegisterType::Short
+            | RegisterType::Char
+            | RegisterType::Integer)
+    }
+
+    // Position in the boolean < byte < short < int ordering; char sits
+    // beside short.
+    fn integral_rank(&self) -> Option<u8> {
+        match self {
+            RegisterType::Zero | RegisterType::Constant => Some(0),
+            RegisterType::Boolean => Some(1),
+            RegisterType::Byte => Some(2),
+            RegisterType::Short | RegisterType::Char => Some(3),
+            RegisterType::Integer => Some(4),
+            _ => None,
+        }
+    }
+}
+
+impl Display for RegisterType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegisterType::Undefined => write!(f, "undefined"),
+            RegisterType::Conflict => write!(f, "conflict"),
+            RegisterType::Zero => write!(f, "zero"),
+            RegisterType::Constant => write!(f, "constant"),
+            RegisterType::ConstantLo => write!(f, "wide constant"),
+            RegisterType::ConstantHi => write!(f, "wide constant (high)"),
+            RegisterType::Boolean => write!(f, "boolean"),
+            RegisterType::Byte => write!(f, "byte"),
+            RegisterType::Short => write!(f, "short"),
+            RegisterType::Char => write!(f, "char"),
+            RegisterType::Integer => write!(f, "int"),
+            RegisterType::Float => write!(f, "float"),
+            RegisterType::LongLo => write!(f, "long"),
+            RegisterType::LongHi => write!(f, "long (high)"),
+            RegisterType::DoubleLo => write!(f, "double"),
+            RegisterType::DoubleHi => write!(f, "double (high)"),
+            RegisterType::Reference(d) => write!(f, "{}", descriptor_to_string(d)),
+            RegisterType::UninitializedThis(d) => write!(f, "uninitialized this {}", descriptor_to_string(d)),
+            RegisterType::Uninitialized(d, addr) => write!(f, "uninitialized {} from {:#x}", descriptor_to_string(d), addr),
+        }
+    }
+}
+
+fn descriptor_to_string(d: &str) -> String {
+    get_type_descriptor_string(&parse_type_descriptor(d.to_string()))
+}
+
+// What the last instruction left for a move-result* to read.
+#[derive(Debug, Clone, PartialEq)]
+enum PendingResult {
+    None,
+    Value(Vec<RegisterType>),
+    // a quickened invoke, whose method is no longer known; the move-result
+    // that follows gives its category
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Frame {
+    registers: Vec<RegisterType>,
+    result: PendingResult,
+}
+
+#[derive(Debug)]
+pub struct RegisterTypes {
+    // register types on entry to each instruction, keyed by address
+    pub before: HashMap<usize, Vec<RegisterType>>,
+    // the registers each instruction touches: inputs as they were read,
+    // outputs as they were written
+    pub annotations: HashMap<usize, Vec<(Register, RegisterType)>>,
+    // instructions reading a register that holds no usable value
+    pub conflicts: Vec<(usize, Register)>,
+}
+
+pub struct TypeInference<'a> {
+    strings: &'a [String],
+    types: &'a [DexType],
+    protos: &'a [DexProto],
+    fields: &'a [DexField],
+    methods: &'a [DexMethod],
+    call_sites: &'a [Vec<EncodedValue>],
+    superclasses: HashMap<String, String>,
+}
+
+impl<'a> TypeInference<'a> {
+    pub fn new(
+        strings: &'a [String],
+        types: &'a [DexType],
+        protos: &'a [DexProto],
+        fields: &'a [DexField],
+        methods: &'a [DexMethod],
+        classes: &'a [DexClassDef],
+        call_sites: &'a [Vec<EncodedValue>],
+    ) -> TypeInference<'a> {
+        let mut superclasses: HashMap<String, String> = HashMap::new();
+        for c in classes.iter() {
+            if let (Some(class), Some(superclass)) = (types.get(c.class_idx), types.get(c.superclass_idx)) {
+                superclasses.insert(class.raw.clone(), superclass.raw.clone());
+            }
+        }
+
+        TypeInference { strings, types, protos, fields, methods, call_sites, superclasses }
+    }
+
+    // None for a method without code.
+    pub fn infer(&self, method: &EncodedMethod) -> Result<Option<RegisterTypes>, String> {
+        Ok(method.code_item()?.map(|code| self.infer_code(method, code)))
+    }
+
+    // `method`'s code, when it was decoded without caching it.
+    pub fn infer_code(&self, method: &EncodedMethod, code: &CodeItem) -> RegisterTypes {
+        let cfg = control_flow_graph(code);
+        let mut result = RegisterTypes {
+            before: HashMap::new(),
+            annotations: HashMap::new(),
+            conflicts: Vec::new(),
+        };
+        if cfg.nodes.is_empty() {
+            return result;
+        }
+
+        let mut entry_frames: Vec<Option<Frame>> = vec![None; cfg.nodes.len()];
+        entry_frames[0] = Some(self.initial_frame(method, code));
+
+        let mut worklist: VecDeque<usize> = VecDeque::new();
+        worklist.push_back(0);
+        while let Some(b) = worklist.pop_front() {
+            let mut frame = entry_frames[b].clone().unwrap();
+            let instructions = &cfg.nodes[b].instructions;
+            let exception_successors = cfg.exception_successors(b);
+
+            for (k, i) in instructions.iter().enumerate() {
+                if k == instructions.len() - 1 {
+                    for s in exception_successors.iter() {
+                        let handler_frame = Frame { registers: frame.registers.clone(), result: PendingResult::None };
+                        if self.merge_into(&mut entry_frames[*s], &handler_frame) {
+                            worklist.push_back(*s);
+                        }
+                    }
+                }
+                self.step(&mut frame, i, code);
+            }
+
+            for s in cfg.edges.iter().filter(|(x, _)| *x == b).map(|(_, y)| *y) {
+                if self.merge_into(&mut entry_frames[s], &frame) {
+                    worklist.push_back(s);
+                }
+            }
+        }
+
+        // replay each block once more to record per-instruction types
+        for (b, block) in cfg.nodes.iter().enumerate() {
+            let mut frame = match &entry_frames[b] {
+                Some(f) => f.clone(),
+                None => continue,
+            };
+            for i in block.instructions.iter() {
+                let before = frame.registers.clone();
+                self.step(&mut frame, i, code);
+
+                let defs = i.kind.defined_registers();
+                let mut annotation: Vec<(Register, RegisterType)> = Vec::new();
+                for r in i.kind.used_registers() {
+                    let t = before.get(r as usize).cloned().unwrap_or(RegisterType::Undefined);
+                    if t == RegisterType::Conflict || t == RegisterType::Undefined {
+                        result.conflicts.push((i.addr, r));
+                    }
+                    if !defs.contains(&r) && !annotation.iter().any(|(x, _)| *x == r) && !is_high_half(&t) {
+                        annotation.push((r, t));
+                    }
+                }
+                for r in defs {
+                    let t = frame.registers.get(r as usize).cloned().unwrap_or(RegisterType::Undefined);
+                    if !is_high_half(&t) {
+                        annotation.push((r, t));
+                    }
+                }
+
+                result.before.insert(i.addr, before);
+                result.annotations.insert(i.addr, annotation);
+            }
+        }
+
+        result
+    }
+
+    fn initial_frame(&self, method: &EncodedMethod, code: &CodeItem) -> Frame {
+        let mut registers = vec![RegisterType::Undefined; code.registers_size as usize];
+        let (method_id, proto) = match self.methods.get(method.method_idx).and_then(|m| Some((m, self.protos.get(m.proto_idx)?))) {
+            Some(x) => x,
+            None => return Frame { registers, result: PendingResult::None },
+        };
+
+        let mut r = code.registers_size.saturating_sub(code.ins_size) as usize;
+        if method.access_flags & MethodAccessLevel::Static as u32 == 0 {
+            let class = self.type_descriptor(method_id.class_idx);
+            let is_constructor = self.is_constructor(method.method_idx);
+            let this = if is_constructor && class != OBJECT {
+                RegisterType::UninitializedThis(class)
+            } else {
+                RegisterType::Reference(class)
+            };
+            if r < registers.len() {
+                registers[r] = this;
+            }
+            r += 1;
+        }
+
+        for p in proto.parameter_type_idx_list.iter() {
+            let (lo, hi) = RegisterType::from_descriptor(&self.type_descriptor(*p));
+            if r < registers.len() {
+                registers[r] = lo;
+            }
+            r += 1;
+            if let Some(hi) = hi {
+                if r < registers.len() {
+                    registers[r] = hi;
+                }
+                r += 1;
+            }
+        }
+
+        Frame { registers, result: PendingResult::None }
+    }
+
+    fn merge_into(&self, target: &mut Option<Frame>, incoming: &Frame) -> bool {
+        let current = match target {
+            Some(f) => f,
+            None => {
+                *target = Some(incoming.clone());
+                return true;
+            }
+        };
+
+        let mut changed = false;
+        for (r, t) in current.registers.iter_mut().enumerate() {
+            let merged = self.merge(t, &incoming.registers[r]);
+            if merged != *t {
+                *t = merged;
+                changed = true;
+            }
+        }
+        if current.result != incoming.result && current.result != PendingResult::None {
+            current.result = PendingResult::None;
+            changed = true;
+        }
+        changed
+    }
+
+    fn merge(&self, a: &RegisterType, b: &RegisterType) -> RegisterType {
+        if a == b {
+            return a.clone();
+        }
+        match (a, b) {
+            (RegisterType::Zero, x) | (x, RegisterType::Zero) if x.is_reference() || x.is_integral() || *x == RegisterType::Float => x.clone(),
+            (RegisterType::Constant, RegisterType::Float) | (RegisterType::Float, RegisterType::Constant) => RegisterType::Float,
+            (RegisterType::ConstantLo, x) | (x, RegisterType::ConstantLo) if *x == RegisterType::LongLo || *x == RegisterType::DoubleLo => x.clone(),
+            (RegisterType::ConstantHi, x) | (x, RegisterType::ConstantHi) if *x == RegisterType::LongHi || *x == RegisterType::DoubleHi => x.clone(),
+            (RegisterType::Reference(x), RegisterType::Reference(y)) => RegisterType::Reference(self.common_superclass(x, y)),
+            (x, y) if x.is_integral() && y.is_integral() => {
+                let (rx, ry) = (x.integral_rank().unwrap(), y.integral_rank().unwrap());
+                if rx == ry {
+                    RegisterType::Integer // short and char
+                } else if rx > ry {
+                    x.clone()
+                } else {
+                    y.clone()
+                }
+            }
+            _ => RegisterType::Conflict,
+        }
+    }
+
+    fn common_superclass(&self, a: &str, b: &str) -> String {
+        if a.starts_with('[') || b.starts_with('[') {
+            return OBJECT.to_string();
+        }
+
+        let chain = |start: &str| {
+            let mut result = vec![start.to_string()];
+            while let Some(s) = self.superclasses.get(result.last().unwrap()) {
+                if result.contains(s) {
+                    break; // cyclic hierarchy
+                }
+                result.push(s.clone());
+            }
+            result
+        };
+
+        let a_chain = chain(a);
+        let b_chain = chain(b);
+        a_chain.into_iter()
+            .find(|x| b_chain.contains(x))
+            .unwrap_or_else(|| OBJECT.to_string())
+    }
+
+    fn type_descriptor(&self, idx: TypeIndex) -> String {
+        match self.types.get(idx) {
+            Some(t) => t.raw.clone(),
+            None => String::new(),
+        }
+    }
+
+    fn is_constructor(&self, idx: MethodIndex) -> bool {
+        self.methods.get(idx).and_then(|m| self.strings.get(m.name_idx)).map(|x| x == "<init>").unwrap_or(false)
+    }
+
+    fn field_type(&self, idx: FieldIndex) -> String {
+        match self.fields.get(idx) {
+            Some(f) => self.type_descriptor(f.type_idx),
+            None => String::new(),
+        }
+    }
+
+    fn return_type(&self, idx: MethodIndex) -> String {
+        match self.methods.get(idx) {
+            Some(m) => self.proto_return_type(m.proto_idx),
+            None => String::new(),
+        }
+    }
+
+    fn proto_return_type(&self, idx: ProtoIndex) -> String {
+        match self.protos.get(idx) {
+            Some(p) => self.type_descriptor(p.return_type_idx),
+            None => String::new(),
+        }
+    }
+
+    // The method type a call site is linked with is its third value.
+    fn call_site_return_type(&self, idx: CallSiteIndex) -> String {
+        match self.call_sites.get(idx).and_then(|values| values.get(2)) {
+            Some(EncodedValue::MethodType(p)) => self.proto_return_type(*p),
+            _ => String::new(),
+        }
+    }
+
+    fn caught_type(&self, code: &CodeItem, addr: usize) -> String {
+        let mut caught: Vec<String> = Vec::new();
+        for t in code.tries.iter() {
+            for h in t.handlers.iter().filter(|h| h.addr == addr) {
+                caught.push(self.type_descriptor(h.type_idx));
+            }
+            if t.catch_all_addr == Some(addr) {
+                caught.push(THROWABLE.to_string());
+            }
+        }
+
+        match caught.split_first() {
+            Some((first, rest)) => rest.iter().fold(first.clone(), |acc, x| self.common_superclass(&acc, x)),
+            None => THROWABLE.to_string(),
+        }
+    }
+
+    fn step(&self, frame: &mut Frame, i: &Instruction, code: &CodeItem) {
+        let registers = &mut frame.registers;
+        let get = |registers: &Vec<RegisterType>, r: Register| registers.get(r as usize).cloned().unwrap_or(RegisterType::Conflict);
+        let set = |registers: &mut Vec<RegisterType>, r: Register, t: RegisterType| {
+            if let Some(x) = registers.get_mut(r as usize) {
+                *x = t;
+            }
+        };
+        let set_descriptor = |registers: &mut Vec<RegisterType>, r: Register, d: &str| {
+            let (lo, hi) = RegisterType::from_descriptor(d);
+            set(registers, r, lo);
+            if let Some(hi) = hi {
+                set(registers, r + 1, hi);
+            }
+        };
+
+        let mut result = PendingResult::None;
+        match &i.kind {
+              InstructionKind::Move(a, b)
+            | InstructionKind::MoveFrom16(a, b)
+            | InstructionKind::Move16(a, b)
+            | InstructionKind::MoveObject(a, b)
+            | InstructionKind::MoveObjectFrom16(a, b)
+            | InstructionKind::MoveObject16(a, b) => {
+                let t = get(registers, *b);
+                set(registers, *a, t);
+            }
+              InstructionKind::MoveWide(a, b)
+            | InstructionKind::MoveWideFrom16(a, b)
+            | InstructionKind::MoveWide16(a, b) => {
+                let (lo, hi) = (get(registers, *b), get(registers, *b + 1));
+                set(registers, *a, lo);
+                set(registers, *a + 1, hi);
+            }
+              InstructionKind::MoveResult(a)
+            | InstructionKind::MoveResultWide(a)
+            | InstructionKind::MoveResultObject(a) => {
+                let width = if let InstructionKind::MoveResultWide(_) = i.kind { 2 } else { 1 };
+                let value = match &frame.result {
+                    PendingResult::Value(v) => v.clone(),
+                    PendingResult::Unknown => match i.kind {
+                        InstructionKind::MoveResult(_) => vec![RegisterType::Constant],
+                        InstructionKind::MoveResultWide(_) => vec![RegisterType::ConstantLo, RegisterType::ConstantHi],
+                        _ => vec![RegisterType::Reference(OBJECT.to_string())],
+                    },
+                    PendingResult::None => Vec::new(),
+                };
+                for k in 0..width {
+                    let t = value.get(k).cloned().unwrap_or(RegisterType::Conflict);
+                    set(registers, *a + k as Register, t);
+                }
+            }
+            InstructionKind::MoveException(a) => {
+                let t = RegisterType::Reference(self.caught_type(code, i.addr));
+                set(registers, *a, t);
+            }
+              InstructionKind::Const4(a, x)
+            | InstructionKind::Const16(a, x)
+            | InstructionKind::Const(a, x)
+            | InstructionKind::ConstHigh16(a, x) => {
+                set(registers, *a, if *x == 0 { RegisterType::Zero } else { RegisterType::Constant });
+            }
+              InstructionKind::ConstWide16(a, _)
+            | InstructionKind::ConstWide32(a, _)
+            | InstructionKind::ConstWide(a, _)
+            | InstructionKind::ConstWideHigh16(a, _) => {
+                set(registers, *a, RegisterType::ConstantLo);
+                set(registers, *a + 1, RegisterType::ConstantHi);
+            }
+              InstructionKind::ConstString(a, _)
+            | InstructionKind::ConstStringJumbo(a, _) => set_descriptor(registers, *a, "Ljava/lang/String;"),
+            InstructionKind::ConstClass(a, _) => set_descriptor(registers, *a, "Ljava/lang/Class;"),
+            InstructionKind::ConstMethodHandle(a, _) => set_descriptor(registers, *a, METHOD_HANDLE),
+            InstructionKind::ConstMethodType(a, _) => set_descriptor(registers, *a, METHOD_TYPE),
+            InstructionKind::CheckCast(a, t) => {
+                let d = self.type_descriptor(*t);
+                set_descriptor(registers, *a, &d);
+            }
+            InstructionKind::InstanceOf(a, _, _) => set(registers, *a, RegisterType::Boolean),
+            InstructionKind::ArrayLength(a, _) => set(registers, *a, RegisterType::Integer),
+            InstructionKind::NewInstance(a, t) => {
+                let d = self.type_descriptor(*t);
+                set(registers, *a, RegisterType::Uninitialized(d, i.addr));
+            }
+            InstructionKind::NewArray(a, _, t) => {
+                let d = self.type_descriptor(*t);
+                set_descriptor(registers, *a, &d);
+            }
+              InstructionKind::FilledNewArray(_, t)
+            | InstructionKind::FilledNewArrayRange(_, _, t) => {
+                result = PendingResult::Value(vec![RegisterType::Reference(self.type_descriptor(*t))]);
+            }
+              InstructionKind::CmpLFloat(a, _, _)
+            | InstructionKind::CmpGFloat(a, _, _)
+            | InstructionKind::CmpLDouble(a, _, _)
+            | InstructionKind::CmpGDouble(a, _, _)
+            | InstructionKind::CmpLong(a, _, _) => set(registers, *a, RegisterType::Byte),
+              InstructionKind::AGet(a, b, _)
+            | InstructionKind::AGetWide(a, b, _)
+            | InstructionKind::AGetObject(a, b, _) => {
+                let component = match get(registers, *b) {
+                    RegisterType::Reference(d) if d.starts_with('[') => d[1..].to_string(),
+                    _ => match i.kind {
+                        InstructionKind::AGetWide(..) => "J".to_string(),
+                        InstructionKind::AGetObject(..) => OBJECT.to_string(),
+                        _ => "I".to_string(),
+                    },
+                };
+                set_descriptor(registers, *a, &component);
+            }
+            InstructionKind::AGetBoolean(a, _, _) => set(registers, *a, RegisterType::Boolean),
+            InstructionKind::AGetByte(a, _, _) => set(registers, *a, RegisterType::Byte),
+            InstructionKind::AGetChar(a, _, _) => set(registers, *a, RegisterType::Char),
+            InstructionKind::AGetShort(a, _, _) => set(registers, *a, RegisterType::Short),
+            // quickened accesses no longer name the field, only its kind
+            InstructionKind::IGetQuick(a, _, _) => set_descriptor(registers, *a, "I"),
+            InstructionKind::IGetWideQuick(a, _, _) => set_descriptor(registers, *a, "J"),
+            InstructionKind::IGetObjectQuick(a, _, _) => set_descriptor(registers, *a, OBJECT),
+            InstructionKind::IGetBooleanQuick(a, _, _) => set(registers, *a, RegisterType::Boolean),
+            InstructionKind::IGetByteQuick(a, _, _) => set(registers, *a, RegisterType::Byte),
+            InstructionKind::IGetCharQuick(a, _, _) => set(registers, *a, RegisterType::Char),
+            InstructionKind::IGetShortQuick(a, _, _) => set(registers, *a, RegisterType::Short),
+              InstructionKind::IGet(a, _, f)
+            | InstructionKind::IGetWide(a, _, f)
+            | InstructionKind::IGetObject(a, _, f)
+            | InstructionKind::IGetBoolean(a, _, f)
+            | InstructionKind::IGetByte(a, _, f)
+            | InstructionKind::IGetChar(a, _, f)
+            | InstructionKind::IGetShort(a, _, f)
+            | InstructionKind::SGet(a, f)
+            | InstructionKind::SGetWide(a, f)
+            | InstructionKind::SGetObject(a, f)
+            | InstructionKind::SGetBoolean(a, f)
+            | InstructionKind::SGetByte(a, f)
+            | InstructionKind::SGetChar(a, f)
+            | InstructionKind::SGetShort(a, f) => {
+                let d = self.field_type(*f);
+                set_descriptor(registers, *a, &d);
+            }
+              InstructionKind::InvokeVirtual(args, m)
+            | InstructionKind::InvokeSuper(args, m)
+            | InstructionKind::InvokeDirect(args, m)
+            | InstructionKind::InvokeStatic(args, m)
+            | InstructionKind::InvokeInterface(args, m) => {
+                self.initialize(registers, i, args.first().cloned(), *m);
+                result = self.invoke_result(*m);
+            }
+              InstructionKind::InvokeVirtualRange(first, count, m)
+            | InstructionKind::InvokeSuperRange(first, count, m)
+            | InstructionKind::InvokeDirectRange(first, count, m)
+            | InstructionKind::InvokeStaticRange(first, count, m)
+            | InstructionKind::InvokeInterfaceRange(first, count, m) => {
+                self.initialize(registers, i, Some(*first).filter(|_| *count > 0), *m);
+                result = self.invoke_result(*m);
+            }
+              InstructionKind::InvokePolymorphic(_, _, p)
+            | InstructionKind::InvokePolymorphicRange(_, _, _, p) => {
+                result = descriptor_result(&self.proto_return_type(*p));
+            }
+              InstructionKind::InvokeCustom(_, c)
+            | InstructionKind::InvokeCustomRange(_, _, c) => {
+                result = descriptor_result(&self.call_site_return_type(*c));
+            }
+              InstructionKind::InvokeVirtualQuick(..)
+            | InstructionKind::InvokeVirtualQuickRange(..) => result = PendingResult::Unknown,
+            kind => {
+                // arithmetic and conversions
+                let defs = kind.defined_registers();
+                if let Some(a) = defs.first() {
+                    set_descriptor(registers, *a, arithmetic_result(kind));
+                }
+            }
+        }
+
+        frame.result = result;
+    }
+
+    // invoke-direct of a constructor turns every copy of the uninitialized
+    // reference into an initialized one
+    fn initialize(&self, registers: &mut [RegisterType], i: &Instruction, receiver: Option<Register>, m: MethodIndex) {
+        let is_direct = matches!(i.kind, InstructionKind::InvokeDirect(..) | InstructionKind::InvokeDirectRange(..));
+        if !is_direct || !self.is_constructor(m) {
+            return;
+        }
+
+        let receiver_type = match receiver.and_then(|r| registers.get(r as usize)) {
+            Some(t) => t.clone(),
+            None => return,
+        };
+        let initialized = match &receiver_type {
+            RegisterType::UninitializedThis(d) | RegisterType::Uninitialized(d, _) => RegisterType::Reference(d.clone()),
+            _ => return,
+        };
+        for t in registers.iter_mut() {
+            if *t == receiver_type {
+                *t = initialized.clone();
+            }
+        }
+    }
+
+    fn invoke_result(&self, m: MethodIndex) -> PendingResult {
+        descriptor_result(&self.return_type(m))
+    }
+}
+
+fn descriptor_result(descriptor: &str) -> PendingResult {
+    match RegisterType::from_descriptor(descriptor) {
+        (RegisterType::Conflict, _) => PendingResult::None,
+        (lo, Some(hi)) => PendingResult::Value(vec![lo, hi]),
+        (lo, None) => PendingResult::Value(vec![lo]),
+    }
+}
+
+fn is_high_half(t: &RegisterType) -> bool {
+    matches!(t, RegisterType::LongHi | RegisterType::DoubleHi | RegisterType::ConstantHi)
+}
+
+// The type an arithmetic operation or conversion leaves in its destination.
+fn arithmetic_result(kind: &InstructionKind) -> &'static str {
+    use InstructionKind::*;
+    match kind {
+          NegLong(..) | NotLong(..) | IntToLong(..) | FloatToLong(..) | DoubleToLong(..)
+        | AddLong(..) | SubLong(..) | MulLong(..) | DivLong(..) | RemLong(..)
+        | AndLong(..) | OrLong(..) | XorLong(..) | ShlLong(..) | ShrLong(..) | UShrLong(..)
+        | AddLong2Addr(..) | SubLong2Addr(..) | MulLong2Addr(..) | DivLong2Addr(..) | RemLong2Addr(..)
+        | AndLong2Addr(..) | OrLong2Addr(..) | XorLong2Addr(..) | ShlLong2Addr(..) | ShrLong2Addr(..) | UShrLong2Addr(..) => "J",
+          NegFloat(..) | IntToFloat(..) | LongToFloat(..) | DoubleToFloat(..)
+        | AddFloat(..) | SubFloat(..) | MulFloat(..) | DivFloat(..) | RemFloat(..)
+        | AddFloat2Addr(..) | SubFloat2Addr(..) | MulFloat2Addr(..) | DivFloat2Addr(..) | RemFloat2Addr(..) => "F",
+          NegDouble(..) | IntToDouble(..) | LongToDouble(..) | FloatToDouble(..)
+        | AddDouble(..) | SubDouble(..) | MulDouble(..) | DivDouble(..) | RemDouble(..)
+        | AddDouble2Addr(..) | SubDouble2Addr(..) | MulDouble2Addr(..) | DivDouble2Addr(..) | RemDouble2Addr(..) => "D",
+        IntToByte(..) => "B",
+        IntToChar(..) => "C",
+        IntToShort(..) => "S",
+        _ => "I",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+    use crate::analysis::tests::{code_item, code_units};
+    use crate::anomaly::STRICT;
+
+    struct Fixture {
+        strings: Vec<String>,
+        types: Vec<DexType>,
+        protos: Vec<DexProto>,
+        methods: Vec<DexMethod>,
+        call_sites: Vec<Vec<EncodedValue>>,
+    }
+
+    // LFoo; extends Object with `<init>()V` and `get()I`, and LBar; and
+    // LBaz; both extend LFoo;.
+    fn fixture() -> Fixture {
+        let strings = ["LFoo;", "Ljava/lang/Object;", "I", "V", "<init>", "get", "LBar;", "LBaz;", "J"]
+            .iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        let types = [0, 1, 2, 3, 6, 7, 8].iter()
+            .map(|i| DexType { raw: strings[*i].clone(), parsed: parse_type_descriptor(strings[*i].clone()) })
+            .collect::<Vec<DexType>>();
+        let protos = vec![
+            DexProto { shorty_idx: 3, return_type_idx: 3, parameters_offset: 0, parameter_type_idx_list: Vec::new() },
+            DexProto { shorty_idx: 2, return_type_idx: 2, parameters_offset: 0, parameter_type_idx_list: Vec::new() },
+            DexProto { shorty_idx: 8, return_type_idx: 6, parameters_offset: 0, parameter_type_idx_list: Vec::new() },
+        ];
+        let methods = vec![
+            DexMethod { class_idx: 0, proto_idx: 0, name_idx: 4 },
+            DexMethod { class_idx: 0, proto_idx: 1, name_idx: 5 },
+            DexMethod { class_idx: 1, proto_idx: 0, name_idx: 4 },
+        ];
+        let call_sites = vec![vec![EncodedValue::MethodHandle(0), EncodedValue::String(5), EncodedValue::MethodType(2)]];
+        Fixture { strings, types, protos, methods, call_sites }
+    }
+
+    fn inference(f: &Fixture) -> TypeInference<'_> {
+        let mut inference = TypeInference::new(&f.strings, &f.types, &f.protos, &[], &f.methods, &[], &f.call_sites);
+        inference.superclasses.insert("LFoo;".to_string(), OBJECT.to_string());
+        inference.superclasses.insert("LBar;".to_string(), "LFoo;".to_string());
+        inference.superclasses.insert("LBaz;".to_string(), "LFoo;".to_string());
+        inference
+    }
+
+    // Register types before each instruction of a virtual method of LFoo;
+    // whose `this` is the last of `registers_size` registers.
+    fn infer(method_idx: MethodIndex, registers_size: u16, units: &[u16]) -> HashMap<usize, Vec<RegisterType>> {
+        let f = fixture();
+        let bytes = code_units(units);
+        let code = code_item(&bytes, registers_size, 1, Vec::new());
+        let method = EncodedMethod {
+            method_idx,
+            access_flags: 0,
+            code_offset: 0,
+            hiddenapi_flags: None,
+            source: DexSource { buffer: &bytes, data_base: 0, compact: None, anomalies: &STRICT, header_size: 0, field_ids_size: 0, method_ids_size: 3 },
+            code: OnceLock::new(),
+        };
+        inference(&f).infer_code(&method, &code).before
+    }
+
+    fn reference(d: &str) -> RegisterType {
+        RegisterType::Reference(d.to_string())
+    }
+
+    #[test]
+    fn merges() {
+        let f = fixture();
+        let inference = inference(&f);
+        let merge = |a: RegisterType, b: RegisterType| inference.merge(&a, &b);
+
+        assert_eq!(merge(RegisterType::Zero, reference("LFoo;")), reference("LFoo;"));
+        assert_eq!(merge(RegisterType::Zero, RegisterType::Float), RegisterType::Float);
+        assert_eq!(merge(RegisterType::Constant, RegisterType::Float), RegisterType::Float);
+        assert_eq!(merge(RegisterType::Boolean, RegisterType::Byte), RegisterType::Byte);
+        assert_eq!(merge(RegisterType::Byte, RegisterType::Short), RegisterType::Short);
+        assert_eq!(merge(RegisterType::Short, RegisterType::Char), RegisterType::Integer);
+        assert_eq!(merge(RegisterType::ConstantLo, RegisterType::DoubleLo), RegisterType::DoubleLo);
+        assert_eq!(merge(RegisterType::ConstantHi, RegisterType::LongHi), RegisterType::LongHi);
+        assert_eq!(merge(RegisterType::Integer, RegisterType::Float), RegisterType::Conflict);
+        assert_eq!(merge(RegisterType::LongLo, RegisterType::DoubleLo), RegisterType::Conflict);
+        assert_eq!(merge(RegisterType::Integer, reference("LFoo;")), RegisterType::Conflict);
+        assert_eq!(merge(reference("LBar;"), reference("LBaz;")), reference("LFoo;"));
+        assert_eq!(merge(reference("LBar;"), reference("LFoo;")), reference("LFoo;"));
+        assert_eq!(merge(reference("[I"), reference("LFoo;")), reference(OBJECT));
+    }
+
+    #[test]
+    fn constructor_initializes_this() {
+        let before = infer(0, 2, &[
+            0x1007,                 // move-object v0, v1
+            0x1070, 2, 0x0001,      // invoke-direct {v1}, Object.<init>
+            0x000e,                 // return-void
+        ]);
+
+        let uninitialized = RegisterType::UninitializedThis("LFoo;".to_string());
+        assert_eq!(before[&0], vec![RegisterType::Undefined, uninitialized.clone()]);
+        assert_eq!(before[&2], vec![uninitialized.clone(), uninitialized]);
+        // every copy of the reference is initialized, not just the receiver
+        assert_eq!(before[&8], vec![reference("LFoo;"), reference("LFoo;")]);
+    }
+
+    #[test]
+    fn move_result() {
+        let before = infer(1, 3, &[
+            0x106e, 1, 0x0002,      // invoke-virtual {v2}, LFoo;.get
+            0x000a,                 // move-result v0
+            0x10fa, 1, 0x0002, 1,   // invoke-polymorphic {v2}, LFoo;.get, ()I
+            0x010a,                 // move-result v1
+            0x00fc, 0, 0x0000,      // invoke-custom {}, call_site_0
+            0x000b,                 // move-result-wide v0
+            0x10e9, 0, 0x0002,      // invoke-virtual-quick {v2}, vtable@0
+            0x000c,                 // move-result-object v0
+            0x10e9, 0, 0x0002,      // invoke-virtual-quick {v2}, vtable@0
+            0x000a,                 // move-result v0
+            0x00fe, 0,              // const-method-handle v0, method_handle@0
+            0x01ff, 0,              // const-method-type v1, ()V
+            0x000e,                 // return-void
+        ]);
+
+        assert_eq!(before[&8][0], RegisterType::Integer);
+        assert_eq!(before[&18][1], RegisterType::Integer);
+        assert_eq!(before[&26][0..2], [RegisterType::LongLo, RegisterType::LongHi]);
+        assert_eq!(before[&34][0], reference(OBJECT));
+        assert_eq!(before[&42][0], RegisterType::Constant);
+        assert_eq!(before[&50][0..2], [reference("Ljava/lang/invoke/MethodHandle;"), reference("Ljava/lang/invoke/MethodType;")]);
+    }
+}
@@ -0,0 +1,423 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::Display;
+
+use crate::analysis::{branch_targets, control_flow_graph, falls_through, is_invoke, is_payload, reverse_postorder};
+use crate::dex_types::*;
+use crate::instructions::*;
+use crate::type_inference::{RegisterType, TypeInference};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct Diagnostic {
+    pub addr: Option<usize>,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.addr {
+            Some(addr) => write!(f, "{:#x}: {}", addr, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+// Structural checks on method bodies, modelled on the ones the ART
+// verifier performs, plus reads of registers whose inferred type is
+// undefined or conflicting.
+pub struct Verifier<'a> {
+    strings: &'a [String],
+    types: &'a [DexType],
+    protos: &'a [DexProto],
+    fields: &'a [DexField],
+    methods: &'a [DexMethod],
+    inference: TypeInference<'a>,
+}
+
+impl<'a> Verifier<'a> {
+    pub fn new(
+        strings: &'a [String],
+        types: &'a [DexType],
+        protos: &'a [DexProto],
+        fields: &'a [DexField],
+        methods: &'a [DexMethod],
+        classes: &'a [DexClassDef],
+        call_sites: &'a [Vec<EncodedValue>],
+    ) -> Verifier<'a> {
+        let inference = TypeInference::new(strings, types, protos, fields, methods, classes, call_sites);
+        Verifier { strings, types, protos, fields, methods, inference }
+    }
+
+    pub fn verify(&self, method: &EncodedMethod) -> Result<Vec<Diagnostic>, String> {
+        let mut diagnostics: Vec<Diagnostic> = Vec::new();
+        // not cached, verify-code reads every method once
+        let code = match method.decode_code_item()? {
+            Some(c) => c,
+            None => return Ok(diagnostics),
+        };
+        let code = &*code;
+
+        let mut error = |addr: Option<usize>, message: String| diagnostics.push(Diagnostic { addr, message });
+
+        if code.ins_size > code.registers_size {
+            error(None, format!("ins_size {} exceeds registers_size {}", code.ins_size, code.registers_size));
+        }
+        if let Some(expected) = self.expected_ins_size(method) {
+            if expected != code.ins_size as usize {
+                error(None, format!("ins_size {} does not match the prototype, which needs {}", code.ins_size, expected));
+            }
+        }
+
+        let instruction_starts = code.instructions.iter()
+            .filter(|i| !is_payload(&i.kind))
+            .map(|i| i.addr)
+            .collect::<HashSet<usize>>();
+
+        for i in code.instructions.iter() {
+            if is_payload(&i.kind) {
+                if i.addr % 4 != 0 {
+                    error(Some(i.addr), format!("{} payload is not 4-byte aligned", payload_name(&i.kind)));
+                }
+                continue;
+            }
+
+            if i.kind == InstructionKind::Unused {
+                error(Some(i.addr), format!("invalid opcode {:#04x}", i.bytecode[0]));
+                continue;
+            }
+
+            let registers = i.kind.used_registers().into_iter().chain(i.kind.defined_registers());
+            for r in registers.filter(|r| *r >= code.registers_size as Register).collect::<HashSet<Register>>() {
+                error(Some(i.addr), format!("register v{} out of range, registers_size is {}", r, code.registers_size));
+            }
+
+            if let Some(message) = self.check_index(&i.kind) {
+                error(Some(i.addr), message);
+            }
+
+            if is_invoke(&i.kind) {
+                let count = invoke_argument_count(&i.kind);
+                if count > code.outs_size as usize {
+                    error(Some(i.addr), format!("invoke passes {} argument registers but outs_size is {}", count, code.outs_size));
+                }
+            }
+
+            for target in branch_targets(code, i) {
+                if !instruction_starts.contains(&target) {
+                    error(Some(i.addr), format!("branch target {:#x} is not the start of an instruction", target));
+                }
+            }
+
+            if let Some((payload_addr, expected)) = payload_reference(i) {
+                let payload = code.instructions.iter().find(|x| x.addr == payload_addr);
+                match payload {
+                    Some(p) if payload_name(&p.kind) == expected => {}
+                    _ => error(Some(i.addr), format!("no {} payload at {:#x}", expected, payload_addr)),
+                }
+            }
+        }
+
+        for t in code.tries.iter() {
+            let handlers = t.handlers.iter().map(|h| h.addr).chain(t.catch_all_addr);
+            for addr in handlers {
+                if !instruction_starts.contains(&addr) {
+                    error(None, format!("exception handler {:#x} is not the start of an instruction", addr));
+                }
+            }
+            for h in t.handlers.iter().filter(|h| h.type_idx >= self.types.len()) {
+                error(None, format!("exception handler {:#x} catches invalid type index {:#x}", h.addr, h.type_idx));
+            }
+        }
+
+        let cfg = control_flow_graph(code);
+        if !cfg.nodes.is_empty() {
+            for b in reverse_postorder(&cfg, 0) {
+                let last = cfg.nodes[b].instructions.last().unwrap();
+                let next_addr = last.addr + last.bytecode.len();
+                if falls_through(&last.kind) && !instruction_starts.contains(&next_addr) {
+                    error(Some(last.addr), "execution can fall off the end of the code".to_string());
+                }
+            }
+        }
+
+        let types = self.inference.infer_code(method, code);
+        for (addr, r) in types.conflicts.iter() {
+            // out of range registers are reported above
+            let t = match types.before.get(addr).and_then(|b| b.get(*r as usize)) {
+                Some(t) => t,
+                None => continue,
+            };
+            let state = if *t == RegisterType::Undefined { "is undefined" } else { "holds conflicting types" };
+            error(Some(*addr), format!("reads v{}, which {}", r, state));
+        }
+
+        diagnostics.sort_by_key(|d| d.addr);
+        Ok(diagnostics)
+    }
+
+    fn expected_ins_size(&self, method: &EncodedMethod) -> Option<usize> {
+        let method_id = self.methods.get(method.method_idx)?;
+        let proto = self.protos.get(method_id.proto_idx)?;
+
+        let mut size = if method.access_flags & MethodAccessLevel::Static as u32 == 0 { 1 } else { 0 };
+        for p in proto.parameter_type_idx_list.iter() {
+            size += match self.types.get(*p).map(|t| t.raw.as_str()) {
+                Some("J") | Some("D") => 2,
+                _ => 1,
+            };
+        }
+        Some(size)
+    }
+
+    fn check_index(&self, kind: &InstructionKind) -> Option<String> {
+        let (name, idx, size) = match kind.index_reference()? {
+            IndexReference::String(i) => ("string", i, self.strings.len()),
+            IndexReference::Type(i) => ("type", i, self.types.len()),
+            IndexReference::Field(i) => ("field", i, self.fields.len()),
+            IndexReference::Method(i) => ("method", i, self.methods.len()),
+        };
+
+        if idx < size {
+            return None;
+        }
+        Some(format!("invalid {} index {:#x}, the table has {} entries", name, idx, size))
+    }
+}
+
+fn payload_name(kind: &InstructionKind) -> &'static str {
+    match kind {
+        InstructionKind::PackedSwitchPayload(..) => "packed-switch",
+        InstructionKind::SparseSwitchPayload(..) => "sparse-switch",
+        InstructionKind::FillArrayDataPayload(..) => "fill-array-data",
+        _ => "",
+    }
+}
+
+fn payload_reference(i: &Instruction) -> Option<(usize, &'static str)> {
+    let (offset, name) = match i.kind {
+        InstructionKind::PackedSwitch(_, o) => (o, "packed-switch"),
+        InstructionKind::SparseSwitch(_, o) => (o, "sparse-switch"),
+        InstructionKind::FillArrayData(_, o) => (o, "fill-array-data"),
+        _ => return None,
+    };
+    Some(((i.addr as i64 + offset as i64 * 2) as usize, name))
+}
+
+fn invoke_argument_count(kind: &InstructionKind) -> usize {
+    match kind {
+          InstructionKind::InvokeVirtual(args, _)
+        | InstructionKind::InvokeSuper(args, _)
+        | InstructionKind::InvokeDirect(args, _)
+        | InstructionKind::InvokeStatic(args, _)
+        | InstructionKind::InvokeInterface(args, _) => args.len(),
+        _ => kind.used_registers().len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+    use crate::analysis::tests::code_units;
+    use crate::anomaly::STRICT;
+    use crate::dex_parser::parse_type_descriptor;
+
+    const STATIC: u32 = MethodAccessLevel::Static as u32;
+    // where the instructions start: the code item is at 0x10
+    const INSNS: usize = 0x20;
+
+    // A try over `count` code units from `start`, with an optional typed
+    // handler and a catch-all, both given in code units.
+    struct Try {
+        start: u32,
+        count: u16,
+        typed: Option<(u8, u8)>,
+        catch_all: u8,
+    }
+
+    // The diagnostics for `LFoo;->run()V`, or `run(I)V` as method 1, with
+    // the given code item.
+    fn verify(method_idx: MethodIndex, access_flags: u32, sizes: (u16, u16, u16), units: &[u16], tries: Option<Try>) -> Vec<String> {
+        let (registers_size, ins_size, outs_size) = sizes;
+        let mut bytes = vec![0u8; 0x10];
+        for x in [registers_size, ins_size, outs_size, tries.is_some() as u16] {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&(units.len() as u32).to_le_bytes());
+        bytes.extend(code_units(units));
+        if let Some(t) = tries {
+            while !bytes.len().is_multiple_of(4) {
+                bytes.push(0);
+            }
+            bytes.extend_from_slice(&t.start.to_le_bytes());
+            bytes.extend_from_slice(&t.count.to_le_bytes());
+            bytes.extend_from_slice(&1u16.to_le_bytes());
+            // one handler list, its size as an sleb128: -1 for one typed
+            // handler and a catch-all, 0 for just the catch-all
+            bytes.push(1);
+            match t.typed {
+                Some((type_idx, addr)) => bytes.extend_from_slice(&[0x7f, type_idx, addr]),
+                None => bytes.push(0),
+            }
+            bytes.push(t.catch_all);
+        }
+
+        let strings = ["LFoo;", "V", "run", "I"].iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        let types = [0, 1, 3].iter()
+            .map(|i| DexType { raw: strings[*i].clone(), parsed: parse_type_descriptor(strings[*i].clone()) })
+            .collect::<Vec<DexType>>();
+        let protos = vec![
+            DexProto { shorty_idx: 1, return_type_idx: 1, parameters_offset: 0, parameter_type_idx_list: Vec::new() },
+            DexProto { shorty_idx: 1, return_type_idx: 1, parameters_offset: 0, parameter_type_idx_list: vec![2] },
+        ];
+        let methods = vec![
+            DexMethod { class_idx: 0, proto_idx: 0, name_idx: 2 },
+            DexMethod { class_idx: 0, proto_idx: 1, name_idx: 2 },
+        ];
+        let method = EncodedMethod {
+            method_idx,
+            access_flags,
+            code_offset: 0x10,
+            hiddenapi_flags: None,
+            source: DexSource { buffer: &bytes, data_base: 0, compact: None, anomalies: &STRICT, header_size: 0, field_ids_size: 0, method_ids_size: 2 },
+            code: OnceLock::new(),
+        };
+        let verifier = Verifier::new(&strings, &types, &protos, &[], &methods, &[], &[]);
+        verifier.verify(&method).unwrap().iter().map(|d| d.to_string()).collect()
+    }
+
+    fn at(units: usize, message: &str) -> String {
+        format!("{:#x}: {}", INSNS + units * 2, message)
+    }
+
+    #[test]
+    fn valid_code() {
+        let diagnostics = verify(1, STATIC, (2, 1, 1), &[
+            0x1071, 1, 0x0001,      // invoke-static {v1}, run(I)V
+            0x000e,                 // return-void
+        ], None);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn ins_size() {
+        let diagnostics = verify(0, STATIC, (0, 1, 0), &[0x000e], None);
+        assert_eq!(diagnostics, vec![
+            "ins_size 1 exceeds registers_size 0",
+            "ins_size 1 does not match the prototype, which needs 0",
+        ]);
+        // `this` counts for a virtual method
+        assert!(verify(1, 0, (2, 2, 0), &[0x000e], None).is_empty());
+    }
+
+    #[test]
+    fn payload_alignment() {
+        let diagnostics = verify(0, STATIC, (0, 0, 0), &[
+            0x000e,                 // return-void
+            0x0300, 1, 0, 0,        // fill-array-data-payload at 0x22
+        ], None);
+        assert_eq!(diagnostics, vec![at(1, "fill-array-data payload is not 4-byte aligned")]);
+    }
+
+    #[test]
+    fn invalid_opcode() {
+        let diagnostics = verify(0, STATIC, (0, 0, 0), &[0x003e, 0x000e], None);
+        assert_eq!(diagnostics, vec![at(0, "invalid opcode 0x3e")]);
+    }
+
+    #[test]
+    fn register_bounds() {
+        let diagnostics = verify(0, STATIC, (1, 0, 0), &[
+            0x0112,                 // const/4 v1, 0
+            0x000e,
+        ], None);
+        assert_eq!(diagnostics, vec![at(0, "register v1 out of range, registers_size is 1")]);
+    }
+
+    #[test]
+    fn index_bounds() {
+        let diagnostics = verify(0, STATIC, (1, 0, 0), &[
+            0x001a, 0x0063,         // const-string v0, string@0x63
+            0x000e,
+        ], None);
+        assert_eq!(diagnostics, vec![at(0, "invalid string index 0x63, the table has 4 entries")]);
+    }
+
+    #[test]
+    fn invoke_outs() {
+        let diagnostics = verify(1, STATIC, (2, 1, 0), &[
+            0x1071, 1, 0x0001,      // invoke-static {v1}, run(I)V
+            0x000e,
+        ], None);
+        assert_eq!(diagnostics, vec![at(0, "invoke passes 1 argument registers but outs_size is 0")]);
+    }
+
+    #[test]
+    fn branch_targets() {
+        let diagnostics = verify(0, STATIC, (1, 0, 0), &[
+            0x0228,                 // goto +2, into the const/16
+            0x0013, 0x0005,         // const/16 v0, 5
+            0x000e,
+        ], None);
+        assert_eq!(diagnostics, vec![at(0, &format!("branch target {:#x} is not the start of an instruction", INSNS + 4))]);
+    }
+
+    #[test]
+    fn missing_payload() {
+        let diagnostics = verify(0, STATIC, (1, 0, 0), &[
+            0x0012,                 // const/4 v0, 0
+            0x002b, 0x0003, 0x0000, // packed-switch v0, +3, which is return-void
+            0x000e,
+        ], None);
+        assert_eq!(diagnostics, vec![at(1, &format!("no packed-switch payload at {:#x}", INSNS + 8))]);
+    }
+
+    #[test]
+    fn handlers() {
+        let code = [
+            0x0000,                 // nop
+            0x0013, 0x0005,         // const/16 v0, 5
+            0x000e,
+        ];
+        let tries = Try { start: 0, count: 1, typed: Some((0x63, 3)), catch_all: 2 };
+        let diagnostics = verify(0, STATIC, (1, 0, 0), &code, Some(tries));
+        assert_eq!(diagnostics, vec![
+            format!("exception handler {:#x} is not the start of an instruction", INSNS + 4),
+            format!("exception handler {:#x} catches invalid type index 0x63", INSNS + 6),
+        ]);
+        let tries = Try { start: 0, count: 1, typed: None, catch_all: 3 };
+        assert!(verify(0, STATIC, (1, 0, 0), &code, Some(tries)).is_empty());
+    }
+
+    #[test]
+    fn falls_off_the_end() {
+        let diagnostics = verify(0, STATIC, (0, 0, 0), &[0x0000], None);
+        assert_eq!(diagnostics, vec![at(0, "execution can fall off the end of the code")]);
+    }
+
+    #[test]
+    fn undefined_register() {
+        let diagnostics = verify(0, STATIC, (1, 0, 0), &[
+            0x007b,                 // neg-int v0, v0
+            0x000e,
+        ], None);
+        assert_eq!(diagnostics, vec![at(0, "reads v0, which is undefined")]);
+    }
+
+    #[test]
+    fn conflicting_types() {
+        let diagnostics = verify(0, STATIC, (2, 0, 0), &[
+            0x0112,                 // const/4 v1, 0
+            0x0138, 0x0004,         // if-eqz v1, +4
+            0x1082,                 // int-to-float v0, v1
+            0x0328,                 // goto +3
+            0x01d8, 0x0101,         // add-int/lit8 v0, v1, 1
+            0x007b,                 // neg-int v0, v0, a float or an int
+            0x000e,
+        ], None);
+        assert_eq!(diagnostics, vec![at(7, "reads v0, which holds conflicting types")]);
+    }
+}
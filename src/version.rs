@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+
+use crate::dex_types::*;
+use crate::instructions::InstructionKind;
+
+// The dex versions whose layout this tool knows about.
+const KNOWN_VERSIONS: std::ops::RangeInclusive<u32> = 35..=41;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Feature {
+    DefaultMethods,
+    MethodHandles,
+    CallSites,
+    InvokePolymorphic,
+    InvokeCustom,
+    ConstMethodHandle,
+    ConstMethodType,
+    Container,
+}
+
+impl Feature {
+    // The first dex version allowed to contain the feature.
+    pub fn min_version(self) -> u32 {
+        match self {
+            Feature::DefaultMethods => 37,
+              Feature::MethodHandles
+            | Feature::CallSites
+            | Feature::InvokePolymorphic
+            | Feature::InvokeCustom => 38,
+              Feature::ConstMethodHandle
+            | Feature::ConstMethodType => 39,
+            Feature::Container => 40,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::DefaultMethods    => "code in interface methods",
+            Feature::MethodHandles     => "method_handle_item section",
+            Feature::CallSites         => "call_site_id_item section",
+            Feature::InvokePolymorphic => "invoke-polymorphic",
+            Feature::InvokeCustom      => "invoke-custom",
+            Feature::ConstMethodHandle => "const-method-handle",
+            Feature::ConstMethodType   => "const-method-type",
+            Feature::Container         => "container header fields",
+        }
+    }
+}
+
+pub fn supports(version: u32, feature: Feature) -> bool {
+    version >= feature.min_version()
+}
+
+// The version the parser reads a file as. Unknown versions are read like
+// 035, and compact dex, which ART converts from standard dex files, like
+// the newest one it is converted from.
+pub fn parse_version(dex_version: &str, compact: bool) -> u32 {
+    match dex_version.parse() {
+        _ if compact => 39,
+        Ok(v) if KNOWN_VERSIONS.contains(&v) => v,
+        _ => 35,
+    }
+}
+
+// Everything in the file that its version says should not be there, one
+// line per feature with where it first shows up.
+pub fn version_warnings(dex: &DexFile) -> Result<Vec<String>, String> {
+    // compact dex numbers its versions separately and is only written by
+    // ART, from dex files it has already verified
+    if dex.header.compact.is_some() {
+        return Ok(match dex.header.version() {
+            Some(1) => Vec::new(),
+            _ => vec![format!("unknown compact dex version {:?}", dex.header.dex_version)],
+        });
+    }
+
+    let version = match dex.header.version() {
+        Some(v) if KNOWN_VERSIONS.contains(&v) => v,
+        _ => return Ok(vec![format!("unknown dex version {:?}, parsing it like 035", dex.header.dex_version)]),
+    };
+
+    let mut found: BTreeMap<Feature, (usize, String)> = BTreeMap::new();
+    let mut add = |feature: Feature, location: String| {
+        found.entry(feature).or_insert((0, location)).0 += 1;
+    };
+
+    for item in dex.map.iter() {
+        match item.type_code {
+            0x0007 => add(Feature::CallSites, format!("{:#x}", item.offset)),
+            0x0008 => add(Feature::MethodHandles, format!("{:#x}", item.offset)),
+            _ => {}
+        }
+    }
+    // the parser leaves the fields out when the version has none
+    if dex.header.compact.is_none() && dex.header.header_size >= 0x78 {
+        add(Feature::Container, "the header".to_string());
+    }
+
+    for c in dex.classes.iter() {
+        let is_interface = c.access_flags & ClassAccessLevel::Interface as u32 != 0;
+        for m in c.methods()? {
+            // not cached, this runs for every file that is parsed
+            let code = match m.decode_code_item()? {
+                Some(code) => code,
+                None => continue,
+            };
+            // static initializers are the only code interfaces had before 037
+            if is_interface && dex.methods.get(m.method_idx).and_then(|x| dex.strings.get(x.name_idx)).map(|n| n.as_str()) != Some("<clinit>") {
+                add(Feature::DefaultMethods, dex.method_signature(m.method_idx));
+            }
+            for i in code.instructions.iter() {
+                let feature = match i.kind {
+                      InstructionKind::InvokePolymorphic(..)
+                    | InstructionKind::InvokePolymorphicRange(..) => Feature::InvokePolymorphic,
+                      InstructionKind::InvokeCustom(..)
+                    | InstructionKind::InvokeCustomRange(..) => Feature::InvokeCustom,
+                    InstructionKind::ConstMethodHandle(..) => Feature::ConstMethodHandle,
+                    InstructionKind::ConstMethodType(..) => Feature::ConstMethodType,
+                    _ => continue,
+                };
+                add(feature, format!("{:#x}", i.addr));
+            }
+        }
+    }
+
+    Ok(found.into_iter()
+        .filter(|(feature, _)| !supports(version, *feature))
+        .map(|(feature, (count, first))| format!(
+            "{} requires dex version {:03} but the file is version {:03} ({} found, first at {})",
+            feature.name(), feature.min_version(), version, count, first))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binary_parser::BinaryParser;
+    use crate::dex_parser::parse_header;
+
+    fn header(magic: &[u8; 8]) -> Vec<u8> {
+        let mut bytes = vec![0u8; 0x78];
+        bytes[..8].copy_from_slice(magic);
+        bytes[0x20..0x24].copy_from_slice(&0x78u32.to_le_bytes());
+        bytes[0x24..0x28].copy_from_slice(&0x78u32.to_le_bytes());
+        bytes[0x28..0x2c].copy_from_slice(&[0x78, 0x56, 0x34, 0x12]);
+        bytes[0x70..0x74].copy_from_slice(&0x78u32.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn container_fields_need_version_040() {
+        let bytes = header(b"dex\n041\0");
+        let h = parse_header(&mut BinaryParser::new(&bytes)).unwrap();
+        assert_eq!(h.container_size, Some(0x78));
+        assert_eq!(h.header_offset, Some(0));
+
+        let bytes = header(b"dex\n039\0");
+        let h = parse_header(&mut BinaryParser::new(&bytes)).unwrap();
+        assert_eq!(h.container_size, None);
+        assert_eq!(h.header_offset, None);
+    }
+
+    #[test]
+    fn versions_read_as() {
+        assert_eq!(parse_version("038", false), 38);
+        assert_eq!(parse_version("099", false), 35);
+        assert_eq!(parse_version("001", true), 39);
+        assert!(!supports(parse_version("037", false), Feature::CallSites));
+    }
+}
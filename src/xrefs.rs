@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::dex_types::*;
+use crate::instructions::*;
+use crate::parallel;
+use crate::printer::get_type_descriptor_string;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Xref {
+    // the method whose code holds the reference
+    pub method: MethodIndex,
+    pub addr: usize,
+    pub mnemonic: &'static str,
+}
+
+// Reverse index from constant pool entries to the instructions that use
+// them, built once over every code item of a dex file.
+pub struct XrefIndex {
+    references: HashMap<IndexReference, Vec<Xref>>,
+}
+
+impl XrefIndex {
+    pub fn new(classes: &[DexClassDef]) -> Result<XrefIndex, String> {
+        let per_method = parallel::map_methods(classes, |_, m| {
+            let mut found: Vec<(IndexReference, Xref)> = Vec::new();
+            let code = match m.code_item()? {
+                Some(code) => code,
+                None => return Ok(found),
+            };
+
+            for i in code.instructions.iter() {
+                if let Some(r) = i.kind.index_reference() {
+                    found.push((r, Xref { method: m.method_idx, addr: i.addr, mnemonic: i.kind.mnemonic() }));
+                }
+            }
+
+            // catch clauses reference their exception type too
+            for t in code.tries.iter() {
+                for h in t.handlers.iter() {
+                    found.push((IndexReference::Type(h.type_idx), Xref { method: m.method_idx, addr: h.addr, mnemonic: "catch" }));
+                }
+            }
+            Ok(found)
+        })?;
+
+        let mut references: HashMap<IndexReference, Vec<Xref>> = HashMap::new();
+        for (r, xref) in per_method.into_iter().flatten() {
+            references.entry(r).or_default().push(xref);
+        }
+
+        Ok(XrefIndex { references })
+    }
+
+    pub fn references_to(&self, target: IndexReference) -> &[Xref] {
+        match self.references.get(&target) {
+            Some(xrefs) => xrefs,
+            None => &[],
+        }
+    }
+}
+
+// What an xref query is looking for, before it has been resolved against
+// the tables of a particular dex file.
+pub enum XrefQuery<'a> {
+    String(&'a str),
+    Type(&'a str),
+    Field(&'a str),
+    Method(&'a str),
+}
+
+impl<'a> XrefQuery<'a> {
+    // Types match by descriptor or dotted name; fields and methods by full
+    // signature or by `Lcom/example/Foo;->name` alone.
+    pub fn resolve(&self, dex: &DexFile) -> Vec<IndexReference> {
+        match *self {
+            XrefQuery::String(s) => (0..dex.strings.len())
+                .filter(|i| dex.strings[*i] == s)
+                .map(IndexReference::String)
+                .collect(),
+            XrefQuery::Type(t) => (0..dex.types.len())
+                .filter(|i| dex.types[*i].raw == t || get_type_descriptor_string(&dex.types[*i].parsed) == t)
+                .map(IndexReference::Type)
+                .collect(),
+            XrefQuery::Field(f) => (0..dex.fields.len())
+                .filter(|i| signature_matches(&dex.field_signature(*i), f, ':'))
+                .map(IndexReference::Field)
+                .collect(),
+            XrefQuery::Method(m) => (0..dex.methods.len())
+                .filter(|i| signature_matches(&dex.method_signature(*i), m, '('))
+                .map(IndexReference::Method)
+                .collect(),
+        }
+    }
+}
+
+// A full signature, or one cut short right before `separator`.
+pub fn signature_matches(signature: &str, query: &str, separator: char) -> bool {
+    signature == query || (signature.starts_with(query) && signature[query.len()..].starts_with(separator))
+}